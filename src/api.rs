@@ -0,0 +1,623 @@
+//! Typed client for the mihomo external controller API, and the payloads it
+//! returns. Every request can be recorded in an [`ApiLog`] for inspection.
+
+use reqwest::{Client, Method, RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Treats an explicit `null` like a missing field; the core emits `null` for
+/// unset lists.
+fn null_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Tun {
+    pub enable: bool,
+    #[serde(default)]
+    pub stack: Option<String>,
+    #[serde(default)]
+    pub device: Option<String>,
+    #[serde(rename = "auto-route", default)]
+    pub auto_route: bool,
+    #[serde(rename = "auto-detect-interface", default)]
+    pub auto_detect_interface: bool,
+    #[serde(rename = "dns-hijack", default, deserialize_with = "null_default")]
+    pub dns_hijack: Vec<String>,
+    #[serde(default)]
+    pub mtu: Option<u32>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Dns {
+    #[serde(default)]
+    pub enable: bool,
+    #[serde(default, deserialize_with = "null_default")]
+    pub listen: String,
+    #[serde(default)]
+    pub ipv6: bool,
+    #[serde(rename = "enhanced-mode", default, deserialize_with = "null_default")]
+    pub enhanced_mode: String,
+    #[serde(rename = "fake-ip-range", default, deserialize_with = "null_default")]
+    pub fake_ip_range: String,
+    #[serde(default, deserialize_with = "null_default")]
+    pub nameserver: Vec<String>,
+    #[serde(default, deserialize_with = "null_default")]
+    pub fallback: Vec<String>,
+    #[serde(
+        rename = "default-nameserver",
+        default,
+        deserialize_with = "null_default"
+    )]
+    pub default_nameserver: Vec<String>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Sniffer {
+    #[serde(default)]
+    pub enable: bool,
+    #[serde(rename = "force-dns-mapping", default)]
+    pub force_dns_mapping: bool,
+    #[serde(rename = "parse-pure-ip", default)]
+    pub parse_pure_ip: bool,
+    #[serde(rename = "override-destination", default)]
+    pub override_destination: bool,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// The `/configs` payload. Fields the app doesn't model yet are kept in
+/// `extra` so the config browser can still show them.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Config {
+    pub mode: String,
+    pub tun: Tun,
+    #[serde(default)]
+    pub port: u16,
+    #[serde(rename = "socks-port", default)]
+    pub socks_port: u16,
+    #[serde(rename = "redir-port", default)]
+    pub redir_port: u16,
+    #[serde(rename = "tproxy-port", default)]
+    pub tproxy_port: u16,
+    #[serde(rename = "mixed-port")]
+    pub mixed_port: u16,
+    #[serde(rename = "log-level")]
+    pub log_level: String,
+    #[serde(rename = "allow-lan")]
+    pub allow_lan: bool,
+    #[serde(rename = "bind-address")]
+    pub bind_address: String,
+    #[serde(rename = "lan-allowed-ips", default, deserialize_with = "null_default")]
+    pub lan_allowed_ips: Vec<String>,
+    #[serde(default, deserialize_with = "null_default")]
+    pub authentication: Vec<String>,
+    /// Source prefixes that may use the inbound ports without authentication.
+    #[serde(
+        rename = "skip-auth-prefixes",
+        default,
+        deserialize_with = "null_default"
+    )]
+    pub skip_auth_prefixes: Vec<String>,
+    pub ipv6: bool,
+    #[serde(rename = "unified-delay", default)]
+    pub unified_delay: bool,
+    #[serde(rename = "tcp-concurrent", default)]
+    pub tcp_concurrent: bool,
+    #[serde(rename = "interface-name", default, deserialize_with = "null_default")]
+    pub interface_name: String,
+    #[serde(rename = "routing-mark", default)]
+    pub routing_mark: u32,
+    #[serde(
+        rename = "find-process-mode",
+        default,
+        deserialize_with = "null_default"
+    )]
+    pub find_process_mode: String,
+    #[serde(rename = "geodata-mode", default)]
+    pub geodata_mode: bool,
+    #[serde(rename = "geo-auto-update", default)]
+    pub geo_auto_update: bool,
+    #[serde(rename = "external-controller", default)]
+    pub external_controller: Option<String>,
+    #[serde(rename = "external-ui", default)]
+    pub external_ui: Option<String>,
+    /// DNS hosts overrides; values are a single address or a list of addresses.
+    #[serde(default, deserialize_with = "null_default")]
+    pub hosts: BTreeMap<String, serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dns: Option<Dns>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sniffer: Option<Sniffer>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub experimental: Option<serde_json::Map<String, serde_json::Value>>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ProxyItem {
+    pub name: Option<String>,
+    #[serde(rename = "type")]
+    pub proxy_type: Option<String>,
+    pub now: Option<String>,
+    pub all: Option<Vec<String>>,
+    /// Set by the `hidden: true` group option in the core config.
+    #[serde(default)]
+    pub hidden: bool,
+    /// The node a `url-test` or `fallback` group is pinned to; empty while
+    /// the core picks it.
+    #[serde(default, deserialize_with = "null_default")]
+    pub fixed: String,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProxiesResponse {
+    pub proxies: HashMap<String, ProxyItem>,
+}
+
+/// The `/dns/query` payload, the core's answer in DNS-over-JSON form.
+#[derive(Debug, Deserialize, Clone)]
+pub struct DnsQueryResponse {
+    /// The response code: 0 is NOERROR, 3 NXDOMAIN.
+    #[serde(rename = "Status")]
+    pub status: u16,
+    #[serde(rename = "Answer", default, deserialize_with = "null_default")]
+    pub answer: Vec<DnsRecord>,
+    #[serde(rename = "Authority", default, deserialize_with = "null_default")]
+    pub authority: Vec<DnsRecord>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct DnsRecord {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub record_type: u16,
+    #[serde(rename = "TTL")]
+    pub ttl: u32,
+    pub data: String,
+}
+
+/// Name of a DNS record type number, e.g. `AAAA` for 28.
+pub fn dns_type_name(record_type: u16) -> String {
+    match record_type {
+        1 => "A".to_string(),
+        2 => "NS".to_string(),
+        5 => "CNAME".to_string(),
+        6 => "SOA".to_string(),
+        12 => "PTR".to_string(),
+        15 => "MX".to_string(),
+        16 => "TXT".to_string(),
+        28 => "AAAA".to_string(),
+        33 => "SRV".to_string(),
+        64 => "SVCB".to_string(),
+        65 => "HTTPS".to_string(),
+        other => format!("TYPE{}", other),
+    }
+}
+
+/// Name of a DNS response code, e.g. `NXDOMAIN` for 3.
+pub fn dns_rcode_name(rcode: u16) -> String {
+    match rcode {
+        0 => "NOERROR".to_string(),
+        1 => "FORMERR".to_string(),
+        2 => "SERVFAIL".to_string(),
+        3 => "NXDOMAIN".to_string(),
+        4 => "NOTIMP".to_string(),
+        5 => "REFUSED".to_string(),
+        other => format!("RCODE{}", other),
+    }
+}
+
+/// Number of requests kept for the debug console.
+pub const API_LOG_CAPACITY: usize = 50;
+const API_LOG_BODY_LIMIT: usize = 4096;
+
+#[derive(Debug, Clone)]
+pub struct ApiLogEntry {
+    pub method: String,
+    pub url: String,
+    pub request_body: Option<String>,
+    pub status: Result<u16, String>,
+    pub response_body: String,
+    pub elapsed: Duration,
+    /// Why the body couldn't be decoded, when it couldn't.
+    pub decode_error: Option<String>,
+}
+
+pub type ApiLog = Arc<Mutex<VecDeque<ApiLogEntry>>>;
+
+pub struct ApiResponse {
+    pub status: StatusCode,
+    pub body: String,
+    url: String,
+    log: ApiLog,
+}
+
+impl ApiResponse {
+    /// Decodes the body as `T`. A mismatch is also noted on this request's
+    /// debug log entry, next to the body that caused it.
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, ApiError> {
+        decode(&self.body).map_err(|e| {
+            if let Ok(mut log) = self.log.lock()
+                && let Some(entry) = log.iter_mut().rev().find(|entry| entry.url == self.url)
+            {
+                entry.decode_error = Some(e.to_string());
+            }
+            ApiError::Decode(e)
+        })
+    }
+}
+
+/// A response body that didn't match the expected shape, located well enough
+/// to tell which field a core version changed.
+#[derive(Debug)]
+pub struct DecodeError {
+    /// Where in the document decoding stopped, e.g. `proxies.GLOBAL.all[0]`.
+    pub path: String,
+    /// The body around the failure, on one line.
+    pub snippet: String,
+    pub source: serde_json::Error,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to parse JSON")?;
+        if self.path != "." {
+            write!(f, " at `{}`", self.path)?;
+        }
+        write!(f, ": {}", self.source)?;
+        if !self.snippet.is_empty() {
+            write!(f, " near `{}`", self.snippet)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for DecodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Deserializes `body`, reporting the path of the first field that doesn't fit.
+pub fn decode<T: DeserializeOwned>(body: &str) -> Result<T, DecodeError> {
+    let deserializer = &mut serde_json::Deserializer::from_str(body);
+    serde_path_to_error::deserialize(deserializer).map_err(|e| {
+        let path = e.path().to_string();
+        let source = e.into_inner();
+        DecodeError {
+            path,
+            snippet: snippet_at(body, source.line(), source.column()),
+            source,
+        }
+    })
+}
+
+/// Up to 30 bytes either side of a 1-based line and column, newlines flattened.
+fn snippet_at(body: &str, line: usize, column: usize) -> String {
+    const RADIUS: usize = 30;
+    if line == 0 {
+        return String::new();
+    }
+    let line_start: usize = body
+        .split_inclusive('\n')
+        .take(line - 1)
+        .map(str::len)
+        .sum();
+    let at = (line_start + column).min(body.len());
+    let mut start = at.saturating_sub(RADIUS);
+    while !body.is_char_boundary(start) {
+        start -= 1;
+    }
+    let mut end = (at + RADIUS).min(body.len());
+    while !body.is_char_boundary(end) {
+        end += 1;
+    }
+    body[start..end]
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn truncate_body(body: &str) -> String {
+    if body.len() <= API_LOG_BODY_LIMIT {
+        return body.to_string();
+    }
+    let mut end = API_LOG_BODY_LIMIT;
+    while !body.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}... ({} bytes total)", &body[..end], body.len())
+}
+
+/// Sends a non-streaming request, reads the whole body and records it in the debug log.
+pub async fn send_logged(
+    client: &Client,
+    log: &ApiLog,
+    request: RequestBuilder,
+) -> Result<ApiResponse, ApiError> {
+    let request = request.build()?;
+    let method = request.method().to_string();
+    let url = request.url().to_string();
+    let request_body = request
+        .body()
+        .and_then(|b| b.as_bytes())
+        .map(|b| truncate_body(&String::from_utf8_lossy(b)));
+
+    let start = Instant::now();
+    let result = async {
+        let resp = client.execute(request).await?;
+        let status = resp.status();
+        let body = resp.text().await?;
+        Ok::<_, reqwest::Error>((status, body))
+    }
+    .await;
+
+    let entry = ApiLogEntry {
+        method,
+        url: url.clone(),
+        request_body,
+        status: match &result {
+            Ok((status, _)) => Ok(status.as_u16()),
+            Err(e) => Err(e.to_string()),
+        },
+        response_body: result
+            .as_ref()
+            .map(|(_, body)| truncate_body(body))
+            .unwrap_or_default(),
+        elapsed: start.elapsed(),
+        decode_error: None,
+    };
+    if let Ok(mut log) = log.lock() {
+        if log.len() >= API_LOG_CAPACITY {
+            log.pop_front();
+        }
+        log.push_back(entry);
+    }
+
+    let (status, body) = result?;
+    Ok(ApiResponse {
+        status,
+        body,
+        url,
+        log: log.clone(),
+    })
+}
+
+/// Why a controller request failed.
+#[derive(Debug)]
+pub enum ApiError {
+    /// The controller couldn't be reached: connection refused or timed out.
+    Unreachable(reqwest::Error),
+    /// Any other failure to send the request or read the response.
+    Transport(reqwest::Error),
+    /// The controller answered with a non-success status.
+    Status(StatusCode),
+    /// The response wasn't the JSON the endpoint is documented to return.
+    Decode(DecodeError),
+}
+
+impl ApiError {
+    /// Whether the request may succeed later unchanged, as opposed to the
+    /// controller rejecting it.
+    pub fn is_offline(&self) -> bool {
+        matches!(self, ApiError::Unreachable(_))
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Unreachable(e) | ApiError::Transport(e) => {
+                write!(f, "Failed to connect: {}", e)
+            }
+            ApiError::Status(status) => write!(f, "Server returned error: {}", status),
+            ApiError::Decode(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ApiError::Unreachable(e) | ApiError::Transport(e) => Some(e),
+            ApiError::Status(_) => None,
+            ApiError::Decode(e) => Some(e),
+        }
+    }
+}
+
+impl From<reqwest::Error> for ApiError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_connect() || e.is_timeout() {
+            ApiError::Unreachable(e)
+        } else {
+            ApiError::Transport(e)
+        }
+    }
+}
+
+impl From<DecodeError> for ApiError {
+    fn from(e: DecodeError) -> Self {
+        ApiError::Decode(e)
+    }
+}
+
+/// One controller, addressed by its base URL and secret. Cheap to clone.
+#[derive(Clone)]
+pub struct MihomoClient {
+    client: Client,
+    base_url: String,
+    secret: String,
+    log: ApiLog,
+    timeout: Option<Duration>,
+}
+
+impl MihomoClient {
+    /// `secret` may be empty for controllers without one.
+    pub fn new(client: Client, base_url: &str, secret: &str) -> Self {
+        Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            secret: secret.to_string(),
+            log: ApiLog::default(),
+            timeout: None,
+        }
+    }
+
+    /// Records requests in `log` instead of a private one.
+    pub fn with_log(mut self, log: ApiLog) -> Self {
+        self.log = log;
+        self
+    }
+
+    /// Gives up on requests that take longer than `timeout`.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    pub fn log(&self) -> &ApiLog {
+        &self.log
+    }
+
+    /// An authenticated request to `path`, e.g. `/version`, for endpoints
+    /// without a typed method.
+    pub fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let mut request = self
+            .client
+            .request(method, format!("{}{}", self.base_url, path));
+        if !self.secret.is_empty() {
+            request = request.bearer_auth(&self.secret);
+        }
+        if let Some(timeout) = self.timeout {
+            request = request.timeout(timeout);
+        }
+        request
+    }
+
+    /// The WebSocket form of a streaming endpoint such as `/traffic`, with the
+    /// secret attached. `None` for `https` controllers: the WebSocket client
+    /// is built without TLS, so those keep the chunked HTTP stream.
+    pub fn websocket_request(
+        &self,
+        path: &str,
+    ) -> Option<tokio_tungstenite::tungstenite::handshake::client::Request> {
+        use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+        let rest = self.base_url.strip_prefix("http://")?;
+        let mut request = format!("ws://{}{}", rest, path)
+            .into_client_request()
+            .ok()?;
+        if !self.secret.is_empty()
+            && let Ok(value) = format!("Bearer {}", self.secret).parse()
+        {
+            request
+                .headers_mut()
+                .insert(reqwest::header::AUTHORIZATION, value);
+        }
+        Some(request)
+    }
+
+    pub async fn send(&self, request: RequestBuilder) -> Result<ApiResponse, ApiError> {
+        send_logged(&self.client, &self.log, request).await
+    }
+
+    /// Sends `request`, treating a non-success status as an error.
+    async fn send_ok(&self, request: RequestBuilder) -> Result<ApiResponse, ApiError> {
+        let resp = self.send(request).await?;
+        if !resp.status.is_success() {
+            return Err(ApiError::Status(resp.status));
+        }
+        Ok(resp)
+    }
+
+    /// Every proxy and group the core knows, by name.
+    pub async fn proxies(&self) -> Result<HashMap<String, ProxyItem>, ApiError> {
+        let resp = self.send_ok(self.request(Method::GET, "/proxies")).await?;
+        Ok(resp.json::<ProxiesResponse>()?.proxies)
+    }
+
+    /// Switches the selector `group` to `proxy`. On `url-test` and `fallback`
+    /// groups this pins `proxy` until [`unpin_proxy`](Self::unpin_proxy).
+    pub async fn select_proxy(&self, group: &str, proxy: &str) -> Result<(), ApiError> {
+        let path = format!("/proxies/{}", urlencoding::encode(group));
+        let request = self
+            .request(Method::PUT, &path)
+            .json(&serde_json::json!({ "name": proxy }));
+        self.send_ok(request).await.map(|_| ())
+    }
+
+    /// Lets the core pick the node of a pinned `url-test` or `fallback` group again.
+    pub async fn unpin_proxy(&self, group: &str) -> Result<(), ApiError> {
+        let path = format!("/proxies/{}", urlencoding::encode(group));
+        self.send_ok(self.request(Method::DELETE, &path))
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn config(&self) -> Result<Config, ApiError> {
+        self.send_ok(self.request(Method::GET, "/configs"))
+            .await?
+            .json()
+    }
+
+    /// Resolves `name` through the core's own DNS, as its rules would.
+    pub async fn dns_query(
+        &self,
+        name: &str,
+        record_type: &str,
+    ) -> Result<DnsQueryResponse, ApiError> {
+        let path = format!(
+            "/dns/query?name={}&type={}",
+            urlencoding::encode(name),
+            urlencoding::encode(record_type)
+        );
+        self.send_ok(self.request(Method::GET, &path)).await?.json()
+    }
+
+    /// Applies a partial config such as `{"mode": "rule"}`; keys left out keep
+    /// their values.
+    pub async fn patch_config(&self, patch: &serde_json::Value) -> Result<(), ApiError> {
+        let request = self.request(Method::PATCH, "/configs").json(patch);
+        self.send_ok(request).await.map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snippet_at_flattens_the_lines_around_an_error() {
+        let body = "{\n  \"a\": 1,\n  \"b\": x\n}";
+        assert_eq!(snippet_at(body, 3, 8), "{ \"a\": 1, \"b\": x }");
+        assert_eq!(snippet_at(body, 0, 0), "");
+    }
+
+    #[test]
+    fn snippet_at_stays_on_char_boundaries() {
+        let body = "é".repeat(40);
+        assert_eq!(snippet_at(&body, 1, 1), "é".repeat(16));
+        assert_eq!(snippet_at(&body, 9, 99), "é".repeat(15));
+    }
+}
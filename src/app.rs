@@ -1,332 +1,7043 @@
 use anyhow::Result;
 use futures_util::StreamExt;
+use mihomot::api::{
+    API_LOG_CAPACITY, ApiError, ApiLog, ApiResponse, Config, DnsQueryResponse, MihomoClient,
+    ProxiesResponse, ProxyItem, send_logged,
+};
+use ratatui::style::Color;
 use ratatui::widgets::{ListState, TableState};
-use reqwest::Client;
+use reqwest::{Client, Method, RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::fs;
+use std::io::Write as _;
 use std::path::PathBuf;
-use std::time::Duration;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::sync::mpsc;
 
+use crate::changelog;
+use crate::keymap::{Action, Keys};
+use crate::theme::{Theme, ThemeName};
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Traffic {
     pub up: u64,
     pub down: u64,
 }
 
+/// One line of the core's `/logs` stream.
 #[derive(Debug, Deserialize, Clone)]
-#[allow(dead_code)]
-pub struct Tun {
-    pub enable: bool,
-    pub stack: Option<String>,
-    pub device: Option<String>,
+pub struct LogLine {
+    #[serde(rename = "type", default)]
+    pub level: String,
+    #[serde(default)]
+    pub payload: String,
+}
+
+/// Levels the Logs view can stream, cycled with `L`; each includes the ones
+/// after it.
+pub const LOG_LEVELS: [&str; 4] = ["debug", "info", "warning", "error"];
+
+/// Log lines kept for the Logs view.
+const LOG_LIMIT: usize = 1000;
+
+/// One sample of the core's `/memory` stream, in bytes.
+#[derive(Debug, Deserialize, Clone, Copy)]
+pub struct Memory {
+    #[serde(default)]
+    pub inuse: u64,
+    /// 0 when the OS imposes no limit.
+    #[serde(default)]
+    pub oslimit: u64,
 }
 
 #[derive(Debug, Deserialize, Clone)]
-pub struct Config {
-    pub mode: String,
-    pub tun: Tun,
-    #[serde(rename = "mixed-port")]
-    pub mixed_port: u16,
-    #[serde(rename = "log-level")]
-    pub log_level: String,
-    #[serde(rename = "allow-lan")]
-    pub allow_lan: bool,
-    #[serde(rename = "bind-address")]
-    pub bind_address: String,
-    pub ipv6: bool,
+pub struct ProxyProvider {
+    pub name: String,
+    #[serde(default, rename = "vehicleType")]
+    pub vehicle_type: String,
+    #[serde(default)]
+    pub proxies: Vec<ProxyItem>,
+    #[serde(default, rename = "updatedAt")]
+    pub updated_at: String,
+    #[serde(default, rename = "subscriptionInfo")]
+    pub subscription_info: Option<SubscriptionInfo>,
+}
+
+/// Traffic quota parsed by the core from the subscription's `subscription-userinfo` header.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct SubscriptionInfo {
+    #[serde(default)]
+    pub upload: u64,
+    #[serde(default)]
+    pub download: u64,
+    #[serde(default)]
+    pub total: u64,
+    /// Unix timestamp; 0 when the subscription never expires.
+    #[serde(default)]
+    pub expire: u64,
+}
+
+/// Work the provider views can ask the core to do for one provider.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProviderAction {
+    /// Re-download a proxy provider's subscription.
+    Update,
+    /// Delay-test every proxy in a proxy provider.
+    HealthCheck,
+    /// Re-download a rule provider's rule set.
+    UpdateRuleSet,
+}
+
+impl ProviderAction {
+    pub fn label(self) -> &'static str {
+        match self {
+            ProviderAction::Update | ProviderAction::UpdateRuleSet => "update",
+            ProviderAction::HealthCheck => "health check",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProxyProvidersResponse {
+    #[serde(default)]
+    pub providers: HashMap<String, ProxyProvider>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ConnectionMetadata {
+    #[serde(default)]
+    pub network: String,
+    #[serde(default, rename = "destinationIP")]
+    pub destination_ip: String,
+    #[serde(default, rename = "destinationPort")]
+    pub destination_port: String,
+    #[serde(default)]
+    pub host: String,
+    #[serde(default)]
+    pub process: String,
+    #[serde(default, rename = "processPath")]
+    pub process_path: String,
+    #[serde(default, rename = "sniffHost")]
+    pub sniff_host: String,
+    #[serde(default, rename = "sourceIP")]
+    pub source_ip: String,
+    #[serde(default, rename = "sourcePort")]
+    pub source_port: String,
+    #[serde(default, rename = "inboundName")]
+    pub inbound_name: String,
+    /// The inbound protocol, e.g. `HTTP`, `Socks5` or `Tun`.
+    #[serde(default, rename = "type")]
+    pub conn_type: String,
 }
 
 #[derive(Debug, Deserialize, Clone)]
-pub struct ProxyItem {
-    pub name: Option<String>,
-    #[serde(rename = "type")]
-    pub proxy_type: Option<String>,
-    pub now: Option<String>,
-    pub all: Option<Vec<String>>,
-    #[serde(flatten)]
-    pub extra: serde_json::Map<String, serde_json::Value>,
+pub struct Connection {
+    pub id: String,
+    #[serde(default)]
+    pub metadata: ConnectionMetadata,
+    #[serde(default)]
+    pub upload: u64,
+    #[serde(default)]
+    pub download: u64,
+    #[serde(default)]
+    pub start: String,
+    #[serde(default)]
+    pub chains: Vec<String>,
+    #[serde(default)]
+    pub rule: String,
+    #[serde(default, rename = "rulePayload")]
+    pub rule_payload: String,
+}
+
+impl Connection {
+    /// Time since the connection was opened.
+    pub fn duration(&self) -> Option<Duration> {
+        parse_rfc3339(&self.start)?.elapsed().ok()
+    }
+
+    /// Host name when sniffed/known, otherwise the destination address.
+    pub fn target(&self) -> String {
+        let host = if self.metadata.host.is_empty() {
+            &self.metadata.destination_ip
+        } else {
+            &self.metadata.host
+        };
+        format!("{}:{}", host, self.metadata.destination_port)
+    }
+
+    /// `Proxy > HK > HK-01`: the core lists the node first.
+    pub fn chain_text(&self) -> String {
+        self.chains
+            .iter()
+            .rev()
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(" > ")
+    }
+
+    /// `DomainSuffix(google.com)`, or the bare type for `Match` and the like.
+    pub fn rule_text(&self) -> String {
+        if self.rule_payload.is_empty() {
+            self.rule.clone()
+        } else {
+            format!("{}({})", self.rule, self.rule_payload)
+        }
+    }
+}
+
+/// The open connections summed up for the Overview, rebuilt from every
+/// connections snapshot.
+#[derive(Debug, Default)]
+pub struct ConnectionStats {
+    pub total: usize,
+    pub tcp: usize,
+    pub udp: usize,
+    /// Proxy chains with their connections and bytes moved, most used first.
+    pub by_chain: Vec<(String, usize, u64)>,
+    /// Matched rules with their connections, most used first.
+    pub by_rule: Vec<(String, usize)>,
+    /// Hosts by bytes moved over all their connections, most first.
+    pub top_hosts: Vec<(String, u64)>,
+}
+
+impl ConnectionStats {
+    fn new(connections: &[Connection]) -> Self {
+        let mut by_chain: HashMap<String, (usize, u64)> = HashMap::new();
+        let mut by_rule: HashMap<String, usize> = HashMap::new();
+        let mut hosts: HashMap<String, u64> = HashMap::new();
+        for conn in connections {
+            let bytes = conn.download + conn.upload;
+            let chain = by_chain.entry(conn.chain_text()).or_default();
+            chain.0 += 1;
+            chain.1 += bytes;
+            *by_rule.entry(conn.rule_text()).or_default() += 1;
+            *hosts.entry(conn.target()).or_default() += bytes;
+        }
+
+        let mut by_chain: Vec<_> = by_chain
+            .into_iter()
+            .map(|(chain, (count, bytes))| (chain, count, bytes))
+            .collect();
+        by_chain.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let mut by_rule: Vec<_> = by_rule.into_iter().collect();
+        by_rule.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let mut top_hosts: Vec<_> = hosts.into_iter().collect();
+        top_hosts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        let tcp = connections
+            .iter()
+            .filter(|c| c.metadata.network.eq_ignore_ascii_case("tcp"))
+            .count();
+        let udp = connections
+            .iter()
+            .filter(|c| c.metadata.network.eq_ignore_ascii_case("udp"))
+            .count();
+        ConnectionStats {
+            total: connections.len(),
+            tcp,
+            udp,
+            by_chain,
+            by_rule,
+            top_hosts,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
-pub struct ProxiesResponse {
-    pub proxies: HashMap<String, ProxyItem>,
+pub struct ConnectionsResponse {
+    #[serde(default, rename = "downloadTotal")]
+    pub download_total: u64,
+    #[serde(default, rename = "uploadTotal")]
+    pub upload_total: u64,
+    /// The core sends `null` rather than `[]` when there are no connections.
+    #[serde(default)]
+    pub connections: Option<Vec<Connection>>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct AppSettings {
-    #[serde(default = "default_base_url")]
-    pub base_url: String,
-    #[serde(default = "default_api_secret")]
-    pub api_secret: String,
-    #[serde(default = "default_test_url")]
-    pub test_url: String,
-    #[serde(default = "default_test_timeout")]
-    pub test_timeout: u64,
+/// The core's fake-ip pool when its config doesn't name one.
+const DEFAULT_FAKE_IP_RANGE: &str = "198.18.0.1/16";
+
+/// Fake-ip addresses seen in the open connections, with the domains they
+/// stand for and what those resolve to for real.
+#[derive(Debug)]
+pub struct FakeIpMap {
+    pub range: String,
+    /// Whether the core's DNS hands out fake-ip addresses at all.
+    pub fake_ip_mode: bool,
+    /// Sorted by address.
+    pub entries: Vec<FakeIpEntry>,
 }
 
-fn default_base_url() -> String {
-    "http://127.0.0.1:9090".to_string()
+#[derive(Debug)]
+pub struct FakeIpEntry {
+    pub ip: String,
+    /// Empty when no connection to the address carries a host name.
+    pub domain: String,
+    pub connections: usize,
+    /// Real addresses from `/dns/query`, or why the lookup failed; `None`
+    /// while it runs.
+    pub real: Option<Result<Vec<String>, String>>,
 }
 
-fn default_api_secret() -> String {
-    std::env::var("MIHOMO_SECRET").unwrap_or_else(|_| "mihomo".to_string())
+#[derive(Debug, Deserialize, Clone)]
+pub struct Rule {
+    #[serde(rename = "type")]
+    pub rule_type: String,
+    #[serde(default)]
+    pub payload: String,
+    #[serde(default)]
+    pub proxy: String,
+    /// Position in the core's rule list, which decides match precedence.
+    #[serde(skip)]
+    pub index: usize,
 }
 
-fn default_test_url() -> String {
-    "https://www.google.com".to_string()
+#[derive(Debug, Deserialize)]
+pub struct RulesResponse {
+    #[serde(default)]
+    pub rules: Vec<Rule>,
 }
 
-fn default_test_timeout() -> u64 {
-    3000
+#[derive(Debug, Deserialize, Clone)]
+pub struct RuleProvider {
+    pub name: String,
+    #[serde(default)]
+    pub behavior: String,
+    #[serde(default, rename = "vehicleType")]
+    pub vehicle_type: String,
+    #[serde(default, rename = "ruleCount")]
+    pub rule_count: u64,
+    #[serde(default, rename = "updatedAt")]
+    pub updated_at: String,
+    /// `yaml`, `text` or `mrs`, on cores that report it.
+    #[serde(default)]
+    pub format: String,
 }
 
-impl Default for AppSettings {
-    fn default() -> Self {
-        Self {
-            base_url: default_base_url(),
-            api_secret: default_api_secret(),
-            test_url: default_test_url(),
-            test_timeout: default_test_timeout(),
+/// What a rule provider's cached file contains, read from the core's directory.
+pub struct RuleSetPreview {
+    pub path: PathBuf,
+    pub total: usize,
+    pub domains: usize,
+    pub ips: usize,
+    /// The first `RULE_SET_PREVIEW_ENTRIES` entries.
+    pub entries: Vec<String>,
+}
+
+const RULE_SET_PREVIEW_ENTRIES: usize = 50;
+
+/// Where cores commonly store downloaded rule sets, relative to their directory.
+const RULE_SET_DIRS: [&str; 4] = ["ruleset", "rules", "providers/rules", ""];
+const RULE_SET_EXTENSIONS: [&str; 5] = ["yaml", "yml", "txt", "list", "mrs"];
+
+fn read_rule_set(dir: &std::path::Path, name: &str) -> Result<RuleSetPreview, String> {
+    let path = RULE_SET_DIRS
+        .iter()
+        .flat_map(|sub| {
+            RULE_SET_EXTENSIONS
+                .iter()
+                .map(move |ext| dir.join(sub).join(format!("{}.{}", name, ext)))
+        })
+        .find(|path| path.is_file())
+        .ok_or_else(|| format!("No cached file for {} under {}", name, dir.display()))?;
+    if path.extension().is_some_and(|ext| ext == "mrs") {
+        return Err(format!("{} is in binary mrs format", path.display()));
+    }
+    let text = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    // YAML rule sets list entries under `payload:`; text ones have one per line
+    let yaml = text
+        .lines()
+        .any(|line| line.trim_start().starts_with("payload:"));
+    let entries: Vec<String> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            if yaml {
+                line.strip_prefix('-')
+                    .map(|entry| entry.trim().trim_matches(['\'', '"']).to_string())
+            } else {
+                Some(line.to_string())
+            }
+        })
+        .collect();
+
+    let is_ip = |entry: &String| match entry.split_once(',') {
+        Some((kind, _)) => kind.to_ascii_uppercase().contains("IP"),
+        None => entry
+            .split('/')
+            .next()
+            .is_some_and(|addr| addr.parse::<std::net::IpAddr>().is_ok()),
+    };
+    let is_domain = |entry: &String| match entry.split_once(',') {
+        Some((kind, _)) => kind.to_ascii_uppercase().starts_with("DOMAIN"),
+        None => !is_ip(entry),
+    };
+
+    Ok(RuleSetPreview {
+        path,
+        total: entries.len(),
+        domains: entries.iter().filter(|e| is_domain(e)).count(),
+        ips: entries.iter().filter(|e| is_ip(e)).count(),
+        entries: entries.into_iter().take(RULE_SET_PREVIEW_ENTRIES).collect(),
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RuleProvidersResponse {
+    #[serde(default)]
+    pub providers: HashMap<String, RuleProvider>,
+}
+
+/// Recursively merges `patch` into `target`, the way the core applies a PATCH.
+fn merge_json(target: &mut serde_json::Value, patch: serde_json::Value) {
+    match (target, patch) {
+        (serde_json::Value::Object(target), serde_json::Value::Object(patch)) => {
+            for (key, value) in patch {
+                merge_json(target.entry(key).or_insert(serde_json::Value::Null), value);
+            }
         }
+        (target, patch) => *target = patch,
     }
 }
 
-#[derive(Clone, PartialEq, Debug)]
-pub enum RealLatencyStatus {
-    Pending,
-    Testing,
-    Success(u64),
-    Failed(String),
+/// Config maps that are staged whole: a staged one replaces the loaded one
+/// rather than merging into it, so entries can be removed as well.
+const WHOLE_CONFIG_KEYS: [&str; 1] = ["hosts"];
+
+/// Removes staged leaves equal to `current`, and objects left empty by that.
+fn prune_unchanged(staged: &mut serde_json::Value, current: &serde_json::Value) {
+    if let serde_json::Value::Object(map) = staged {
+        map.retain(|key, value| {
+            let Some(existing) = current.get(key) else {
+                return true;
+            };
+            prune_unchanged(value, existing);
+            match value {
+                serde_json::Value::Object(inner) => !inner.is_empty(),
+                other => other != existing,
+            }
+        });
+    }
 }
 
-#[derive(Clone, PartialEq)]
-pub enum Focus {
-    Groups,
-    Proxies,
-    Settings,
+fn collect_changes(
+    prefix: &str,
+    staged: &serde_json::Value,
+    current: &serde_json::Value,
+    rows: &mut Vec<(String, String, String)>,
+) {
+    let serde_json::Value::Object(map) = staged else {
+        return;
+    };
+    for (key, value) in map {
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        let existing = current.get(key).unwrap_or(&serde_json::Value::Null);
+        if value.is_object() {
+            collect_changes(&path, value, existing, rows);
+        } else {
+            rows.push((path, existing.to_string(), value.to_string()));
+        }
+    }
 }
 
-#[derive(Clone, PartialEq, Debug)]
-pub enum ConfigEntry {
-    BaseUrl,
-    ApiSecret,
-    TestUrl,
-    TestTimeout,
-    Mode,
-    Tun,
-    MixedPort,
-    LogLevel,
-    AllowLan,
-    BindAddress,
-    Ipv6,
+/// Why a delay test of one proxy failed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DelayFailure {
+    /// The proxy didn't answer within the test timeout.
+    Timeout,
+    /// The proxy's server refused the connection.
+    Refused,
+    /// The proxy's server name didn't resolve.
+    Dns,
+    /// The controller rejected the test itself, e.g. `404` for an unknown
+    /// proxy or `400` for a bad test URL.
+    Status(u16),
+    /// The controller couldn't be reached, so the proxy wasn't tested.
+    Unreachable,
+    /// Any other error, as the core worded it. Group tests only say that a
+    /// proxy failed, not why.
+    Other(String),
 }
 
-pub struct App {
-    pub proxies: HashMap<String, ProxyItem>,
-    pub config: Option<Config>,
-    pub real_latency_status: RealLatencyStatus,
-    pub client: Client,
-    pub app_settings: AppSettings,
+impl DelayFailure {
+    /// Sorts a failed `/proxies/<name>/delay` answer by the core's status and message.
+    fn classify(status: StatusCode, body: &str) -> Self {
+        let message = serde_json::from_str::<serde_json::Value>(body)
+            .ok()
+            .and_then(|json| json.get("message")?.as_str().map(str::to_string))
+            .unwrap_or_default();
+        let lower = message.to_lowercase();
+        if matches!(
+            status,
+            StatusCode::REQUEST_TIMEOUT | StatusCode::GATEWAY_TIMEOUT
+        ) || lower.contains("timeout")
+            || lower.contains("deadline")
+        {
+            DelayFailure::Timeout
+        } else if lower.contains("refused") {
+            DelayFailure::Refused
+        } else if lower.contains("no such host")
+            || lower.contains("lookup")
+            || lower.contains("dns")
+        {
+            DelayFailure::Dns
+        } else if status.is_client_error() {
+            DelayFailure::Status(status.as_u16())
+        } else if message.is_empty() {
+            DelayFailure::Other(format!("core returned {}", status))
+        } else {
+            DelayFailure::Other(message)
+        }
+    }
 
-    pub real_latency_tx: mpsc::Sender<RealLatencyStatus>,
-    pub real_latency_rx: mpsc::Receiver<RealLatencyStatus>,
+    /// A few characters for the delay column.
+    pub fn label(&self) -> String {
+        match self {
+            DelayFailure::Timeout => "timeout".to_string(),
+            DelayFailure::Refused => "refused".to_string(),
+            DelayFailure::Dns => "dns".to_string(),
+            DelayFailure::Status(code) => format!("http {}", code),
+            DelayFailure::Unreachable => "no api".to_string(),
+            DelayFailure::Other(_) => "failed".to_string(),
+        }
+    }
 
-    pub proxy_latency: HashMap<String, Option<u64>>,
-    pub proxy_test_tx: mpsc::Sender<(String, u64)>,
-    pub proxy_test_rx: mpsc::Receiver<(String, u64)>,
+    pub fn describe(&self) -> String {
+        match self {
+            DelayFailure::Timeout => "Timed out: the node may be dead or slow".to_string(),
+            DelayFailure::Refused => "Connection refused by the node's server".to_string(),
+            DelayFailure::Dns => "The node's server name didn't resolve".to_string(),
+            DelayFailure::Status(code) => format!(
+                "The controller rejected the test ({}); check the test URL",
+                code
+            ),
+            DelayFailure::Unreachable => "The controller couldn't be reached".to_string(),
+            DelayFailure::Other(message) => message.clone(),
+        }
+    }
+}
 
-    pub traffic_tx: mpsc::Sender<Traffic>,
-    pub traffic_rx: mpsc::Receiver<Traffic>,
+/// Sends a delay test and reads the `delay` the core reports, or why it failed.
+async fn measure_delay(
+    client: &Client,
+    log: &ApiLog,
+    request: RequestBuilder,
+) -> Result<u64, DelayFailure> {
+    let resp = send_logged(client, log, request)
+        .await
+        .map_err(|_| DelayFailure::Unreachable)?;
+    if !resp.status.is_success() {
+        return Err(DelayFailure::classify(resp.status, &resp.body));
+    }
+    serde_json::from_str::<serde_json::Value>(&resp.body)
+        .ok()
+        .and_then(|json| json.get("delay")?.as_u64())
+        .ok_or_else(|| DelayFailure::Other("No delay in the core's answer".to_string()))
+}
 
-    pub traffic_history_up: VecDeque<u64>,
-    pub traffic_history_down: VecDeque<u64>,
-    pub current_up: u64,
-    pub current_down: u64,
+/// How one attempt at following a streaming endpoint ended.
+enum StreamEnd {
+    /// The controller could not be reached or refused the request.
+    Unreachable,
+    /// The stream was up and then closed, e.g. because the core restarted.
+    Dropped,
+    /// The app stopped listening, so the task should exit.
+    ReceiverGone,
+}
 
-    pub group_names: Vec<String>,
-    pub group_state: ListState,
-    pub proxy_state: TableState,
-    pub focus: Focus,
-    pub previous_focus: Focus,
-    pub show_info_popup: bool,
-    pub popup_scroll: u16,
+async fn stream_websocket<T: DeserializeOwned>(
+    request: tokio_tungstenite::tungstenite::handshake::client::Request,
+    tx: &mpsc::Sender<AppEvent>,
+    wrap: fn(T) -> AppEvent,
+    on_connect: impl FnOnce(),
+) -> StreamEnd {
+    use tokio_tungstenite::tungstenite::Message;
 
-    pub settings_items: Vec<ConfigEntry>,
-    pub settings_state: TableState,
-    pub is_editing: bool,
-    pub editing_value: String,
+    let Ok((mut socket, _)) = tokio_tungstenite::connect_async(request).await else {
+        return StreamEnd::Unreachable;
+    };
+    on_connect();
+    while let Some(Ok(message)) = socket.next().await {
+        let Message::Text(text) = message else {
+            continue;
+        };
+        if let Ok(item) = serde_json::from_str::<T>(&text)
+            && tx.send(wrap(item)).await.is_err()
+        {
+            return StreamEnd::ReceiverGone;
+        }
+    }
+    StreamEnd::Dropped
+}
 
-    pub error: Option<String>,
+/// The chunked HTTP form of a streaming endpoint: one JSON object per line.
+async fn stream_http_lines<T: DeserializeOwned>(
+    request: RequestBuilder,
+    tx: &mpsc::Sender<AppEvent>,
+    wrap: fn(T) -> AppEvent,
+    on_connect: impl FnOnce(),
+) -> StreamEnd {
+    let resp = match request.send().await {
+        Ok(resp) if resp.status().is_success() => resp,
+        _ => return StreamEnd::Unreachable,
+    };
+    on_connect();
+    let mut stream = resp.bytes_stream();
+    let mut buffer = String::new();
+    while let Some(Ok(bytes)) = stream.next().await {
+        let Ok(text) = std::str::from_utf8(&bytes) else {
+            continue;
+        };
+        buffer.push_str(text);
+        while let Some(pos) = buffer.find('\n') {
+            let line: String = buffer.drain(..=pos).collect();
+            if let Ok(item) = serde_json::from_str::<T>(line.trim_end())
+                && tx.send(wrap(item)).await.is_err()
+            {
+                return StreamEnd::ReceiverGone;
+            }
+        }
+    }
+    StreamEnd::Dropped
 }
 
-impl App {
-    pub fn new(url_override: Option<String>, secret_override: Option<String>) -> Self {
-        let mut group_state = ListState::default();
-        let mut proxy_state = TableState::default();
-        group_state.select(Some(0));
-        proxy_state.select(Some(0));
+/// Parses the core's history timestamps, e.g. `2024-05-01T12:34:56.789+08:00`.
+fn parse_rfc3339(text: &str) -> Option<SystemTime> {
+    let (date, rest) = text.split_once('T')?;
+    let mut date_parts = date.splitn(3, '-').map(|p| p.parse::<i64>());
+    let (year, month, day) = (
+        date_parts.next()?.ok()?,
+        date_parts.next()?.ok()?,
+        date_parts.next()?.ok()?,
+    );
+    let offset_at = rest.find(['Z', 'z', '+', '-'])?;
+    let (time, zone) = rest.split_at(offset_at);
+    let mut time_parts = time.split(':');
+    let hour = time_parts.next()?.parse::<i64>().ok()?;
+    let minute = time_parts.next()?.parse::<i64>().ok()?;
+    let second = time_parts.next()?.split('.').next()?.parse::<i64>().ok()?;
+    let offset = match zone.as_bytes().first()? {
+        b'Z' | b'z' => 0,
+        sign => {
+            let (h, m) = zone[1..].split_once(':')?;
+            let minutes = h.parse::<i64>().ok()? * 60 + m.parse::<i64>().ok()?;
+            if *sign == b'-' { -minutes } else { minutes }
+        }
+    };
 
-        let mut settings_state = TableState::default();
-        settings_state.select(Some(0));
+    // Days since the Unix epoch for a proleptic Gregorian date
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
 
-        let settings_items = vec![
-            ConfigEntry::BaseUrl,
-            ConfigEntry::ApiSecret,
-            ConfigEntry::TestUrl,
-            ConfigEntry::TestTimeout,
-            ConfigEntry::Mode,
-            ConfigEntry::Tun,
-            ConfigEntry::MixedPort,
-            ConfigEntry::LogLevel,
-            ConfigEntry::AllowLan,
-            ConfigEntry::BindAddress,
-            ConfigEntry::Ipv6,
-        ];
+    let secs = days * 86400 + hour * 3600 + minute * 60 + second - offset * 60;
+    Some(SystemTime::UNIX_EPOCH + Duration::from_secs(u64::try_from(secs).ok()?))
+}
 
-        let mut app_settings = Self::load_app_settings();
-        if let Some(url) = url_override {
-            app_settings.base_url = url;
+/// Whether `ip` lies in a prefix such as `198.18.0.1/16`.
+fn ip_in_prefix(ip: std::net::IpAddr, prefix: &str) -> bool {
+    use std::net::IpAddr;
+
+    let Some((addr, len)) = prefix.split_once('/') else {
+        return false;
+    };
+    let (Ok(addr), Ok(len)) = (addr.parse::<IpAddr>(), len.parse::<u32>()) else {
+        return false;
+    };
+    match (ip, addr) {
+        (IpAddr::V4(ip), IpAddr::V4(addr)) if len <= 32 => {
+            let mask = u32::MAX.checked_shl(32 - len).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(addr) & mask
         }
-        if let Some(secret) = secret_override {
-            app_settings.api_secret = secret;
+        (IpAddr::V6(ip), IpAddr::V6(addr)) if len <= 128 => {
+            let mask = u128::MAX.checked_shl(128 - len).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(addr) & mask
         }
+        _ => false,
+    }
+}
 
-        let (real_latency_tx, real_latency_rx) = mpsc::channel(10);
-        let (traffic_tx, traffic_rx) = mpsc::channel(100);
-        let (proxy_test_tx, proxy_test_rx) = mpsc::channel(100);
+fn hash_body(body: &str) -> u64 {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    body.hash(&mut hasher);
+    hasher.finish()
+}
 
-        let app = Self {
-            proxies: HashMap::new(),
-            config: None,
-            real_latency_status: RealLatencyStatus::Pending,
-            client: Client::builder().build().unwrap_or_default(),
-            app_settings,
-            real_latency_tx,
-            real_latency_rx,
-            proxy_latency: HashMap::new(),
-            proxy_test_tx,
-            proxy_test_rx,
-            traffic_tx,
-            traffic_rx,
-            traffic_history_up: VecDeque::from(vec![0; 1000]),
-            traffic_history_down: VecDeque::from(vec![0; 1000]),
-            current_up: 0,
-            current_down: 0,
-            group_names: Vec::new(),
-            group_state,
-            proxy_state,
-            focus: Focus::Groups,
-            previous_focus: Focus::Groups,
-            show_info_popup: false,
-            popup_scroll: 0,
-            settings_items,
-            settings_state,
-            is_editing: false,
-            editing_value: String::new(),
-            error: None,
-        };
+/// Amounts of data, up to gigabytes, with one decimal place and `separator`
+/// as the decimal point.
+pub fn format_bytes(bytes: u64, separator: char) -> String {
+    const KB: u64 = 1024;
+    const MB: u64 = KB * 1024;
+    const GB: u64 = MB * 1024;
+    let (value, unit) = match bytes {
+        0..KB => return format!("{} B", bytes),
+        KB..MB => (bytes as f64 / KB as f64, "KB"),
+        MB..GB => (bytes as f64 / MB as f64, "MB"),
+        _ => (bytes as f64 / GB as f64, "GB"),
+    };
+    format!("{:.1} {}", value, unit).replace('.', &separator.to_string())
+}
 
-        app.start_traffic_monitor();
-        app
+/// Turns the controller's running traffic totals into rates, for the
+/// commands that poll instead of streaming `/traffic`.
+#[derive(Default)]
+pub struct TrafficRate {
+    last: Option<(Instant, u64, u64)>,
+}
+
+impl TrafficRate {
+    /// Download and upload bytes per second since the last call; `None` on
+    /// the first.
+    pub fn update(&mut self, download_total: u64, upload_total: u64) -> Option<(u64, u64)> {
+        let now = Instant::now();
+        let rates = self.last.map(|(at, down, up)| {
+            let secs = now.duration_since(at).as_secs_f64().max(f64::EPSILON);
+            let rate = |total: u64, last: u64| (total.saturating_sub(last) as f64 / secs) as u64;
+            (rate(download_total, down), rate(upload_total, up))
+        });
+        self.last = Some((now, download_total, upload_total));
+        rates
     }
+}
 
-    fn start_traffic_monitor(&self) {
-        let client = self.client.clone();
-        let base_url = self.app_settings.base_url.clone();
-        let secret = self.app_settings.api_secret.clone();
-        let tx = self.traffic_tx.clone();
+/// FNV-1a, for hashes that must not change between runs or Rust versions,
+/// which `DefaultHasher` doesn't promise.
+fn stable_hash(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
 
-        tokio::spawn(async move {
-            let url = format!("{}/traffic", base_url);
-            loop {
-                let mut request = client.get(&url);
-                if !secret.is_empty() {
-                    request = request.bearer_auth(&secret);
-                }
+/// Hash of the config as the app models it, so drift checks ignore key order.
+fn config_hash(config: &Config) -> u64 {
+    serde_json::to_string(config)
+        .map(|body| hash_body(&body))
+        .unwrap_or_default()
+}
 
-                if let Ok(resp) = request.send().await
-                    && resp.status().is_success()
-                {
-                    let mut stream = resp.bytes_stream();
-                    let mut buffer = String::new();
-
-                    while let Some(Ok(bytes)) = stream.next().await {
-                        if let Ok(text) = std::str::from_utf8(&bytes) {
-                            buffer.push_str(text);
-                            while let Some(pos) = buffer.find('\n') {
-                                let line = buffer[..pos].to_string();
-                                buffer = buffer[pos + 1..].to_string();
-
-                                if let Ok(traffic) = serde_json::from_str::<Traffic>(&line)
-                                    && tx.send(traffic).await.is_err()
-                                {
-                                    return;
-                                }
-                            }
-                        }
+/// Asks every endpoint in `PROBED_ENDPOINTS` at once what it supports.
+async fn probe(api: MihomoClient) -> Capabilities {
+    let probes = PROBED_ENDPOINTS.iter().map(|path| {
+        let request = api.request(Method::GET, path);
+        async move { (*path, request.send().await) }
+    });
+
+    let mut capabilities = Capabilities {
+        checked: true,
+        ..Default::default()
+    };
+    for (path, result) in futures_util::future::join_all(probes).await {
+        let status = match result {
+            Ok(resp) => {
+                let code = resp.status();
+                if code.is_success() {
+                    if path == "/version" {
+                        capabilities.version = resp.json::<VersionInfo>().await.ok();
                     }
+                    EndpointStatus::Available
+                } else if code == reqwest::StatusCode::NOT_FOUND
+                    || code == reqwest::StatusCode::METHOD_NOT_ALLOWED
+                {
+                    EndpointStatus::Unsupported(code.as_u16())
+                } else {
+                    EndpointStatus::Failed(code.to_string())
                 }
-                tokio::time::sleep(Duration::from_secs(3)).await;
             }
-        });
+            Err(e) if e.is_timeout() => EndpointStatus::Failed("Timeout".to_string()),
+            Err(e) if e.is_connect() => EndpointStatus::Failed("Conn Err".to_string()),
+            Err(_) => EndpointStatus::Failed("Error".to_string()),
+        };
+        capabilities.endpoints.push((path, status));
     }
+    capabilities
+}
 
-    pub fn on_traffic(&mut self, traffic: Traffic) {
-        self.current_up = traffic.up;
-        self.current_down = traffic.down;
+/// Sends a request whose response carries nothing but its status.
+async fn post_action(api: &MihomoClient, request: RequestBuilder) -> Result<()> {
+    let resp = api.send(request).await?;
+    if !resp.status.is_success() {
+        anyhow::bail!("Server returned error: {}", resp.status);
+    }
+    Ok(())
+}
+
+/// Per-node weights of each Smart group. The endpoint only exists on cores
+/// built with Smart group support, so failures are kept per group.
+async fn smart_weights(
+    api: MihomoClient,
+    groups: Vec<String>,
+) -> HashMap<String, Result<HashMap<String, f64>, String>> {
+    let mut weights = HashMap::new();
+    for group in groups {
+        let path = format!("/group/{}/weights", urlencoding::encode(&group));
+        let result = match api.send(api.request(reqwest::Method::GET, &path)).await {
+            Ok(resp) if resp.status.is_success() => parse_smart_weights(&resp.body),
+            Ok(resp) => Err(resp.status.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        weights.insert(group, result);
+    }
+    weights
+}
+
+/// Accepts either `{"weights": {node: weight}}` or a bare `{node: weight}` object.
+fn parse_smart_weights(body: &str) -> Result<HashMap<String, f64>, String> {
+    let value: serde_json::Value = serde_json::from_str(body).map_err(|e| e.to_string())?;
+    let map = value
+        .get("weights")
+        .unwrap_or(&value)
+        .as_object()
+        .ok_or_else(|| "unexpected response".to_string())?;
+    Ok(map
+        .iter()
+        .filter_map(|(node, weight)| Some((node.clone(), weight.as_f64()?)))
+        .collect())
+}
+
+/// A named preset of process/host patterns, toggled with number keys in the
+/// Connections view.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ConnectionFilter {
+    pub name: String,
+    pub patterns: Vec<String>,
+}
+
+impl ConnectionFilter {
+    /// Case-insensitive substring match against process name/path, host and destination IP.
+    pub fn matches(&self, conn: &Connection) -> bool {
+        let fields = [
+            &conn.metadata.process,
+            &conn.metadata.process_path,
+            &conn.metadata.host,
+            &conn.metadata.destination_ip,
+        ];
+        self.patterns.iter().any(|pattern| {
+            let pattern = pattern.to_lowercase();
+            fields
+                .iter()
+                .any(|field| !field.is_empty() && field.to_lowercase().contains(&pattern))
+        })
+    }
+}
+
+/// A column the Connections table can show.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConnectionColumn {
+    Host,
+    Process,
+    Network,
+    Chain,
+    Rule,
+    /// The host name the core sniffed from the traffic.
+    Sniff,
+    Source,
+    Inbound,
+    Type,
+    DownloadSpeed,
+    UploadSpeed,
+    Download,
+    Upload,
+    Time,
+}
+
+impl ConnectionColumn {
+    pub fn title(self) -> &'static str {
+        match self {
+            ConnectionColumn::Host => "Host",
+            ConnectionColumn::Process => "Process",
+            ConnectionColumn::Network => "Net",
+            ConnectionColumn::Chain => "Chain",
+            ConnectionColumn::Rule => "Rule",
+            ConnectionColumn::Sniff => "Sniffed",
+            ConnectionColumn::Source => "Source",
+            ConnectionColumn::Inbound => "Inbound",
+            ConnectionColumn::Type => "Type",
+            ConnectionColumn::DownloadSpeed => "↓/s",
+            ConnectionColumn::UploadSpeed => "↑/s",
+            ConnectionColumn::Download => "↓",
+            ConnectionColumn::Upload => "↑",
+            ConnectionColumn::Time => "Time",
+        }
+    }
+}
+
+/// Columns of the Connections table when no set is configured.
+pub const DEFAULT_CONNECTION_COLUMNS: [ConnectionColumn; 8] = [
+    ConnectionColumn::Host,
+    ConnectionColumn::Process,
+    ConnectionColumn::Network,
+    ConnectionColumn::Chain,
+    ConnectionColumn::Rule,
+    ConnectionColumn::Download,
+    ConnectionColumn::Upload,
+    ConnectionColumn::Time,
+];
+
+/// A named set of Connections columns, cycled with `v` in that view.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ConnectionColumns {
+    pub name: String,
+    pub columns: Vec<ConnectionColumn>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AppSettings {
+    #[serde(default = "default_base_url")]
+    pub base_url: String,
+    #[serde(default = "default_api_secret")]
+    pub api_secret: String,
+    #[serde(default = "default_test_url")]
+    pub test_url: String,
+    #[serde(default = "default_test_timeout")]
+    pub test_timeout: u64,
+    /// Maximum number of proxy delay tests in flight at once.
+    #[serde(default = "default_delay_test_concurrency")]
+    pub delay_test_concurrency: usize,
+    /// Per-proxy delay test timeout passed to the core, in milliseconds.
+    #[serde(default = "default_delay_test_timeout")]
+    pub delay_test_timeout: u64,
+    #[serde(default = "default_connection_filters")]
+    pub connection_filters: Vec<ConnectionFilter>,
+    #[serde(default = "default_connection_columns")]
+    pub connection_columns: Vec<ConnectionColumns>,
+    /// Groups listed first, in this order.
+    #[serde(default)]
+    pub pinned_groups: Vec<String>,
+    /// Groups left out of the Groups list unless hidden groups are shown.
+    #[serde(default)]
+    pub hidden_groups: Vec<String>,
+    /// Hours between updates the app itself asks for, per proxy provider,
+    /// regardless of the core's own `interval`.
+    #[serde(default)]
+    pub provider_auto_update: BTreeMap<String, u64>,
+    /// Groups bound to function keys, by key number: `1` is `F1`.
+    #[serde(default)]
+    pub group_hotkeys: BTreeMap<u8, String>,
+    /// Groups whose proxy is only switched after a confirmation.
+    #[serde(default)]
+    pub locked_groups: Vec<String>,
+    /// What runs at launch; empty for nothing.
+    #[serde(default = "default_startup_actions")]
+    pub startup_actions: Vec<StartupAction>,
+    /// Steps replayed once the startup fetch is in, such as
+    /// `test_group:Proxy`; see `StartStep`.
+    #[serde(default)]
+    pub on_start: Vec<String>,
+    /// Starts a chord in the main views: this key, then one of `leader_chords`.
+    #[serde(default = "default_leader_key")]
+    pub leader_key: char,
+    #[serde(default = "default_leader_chords")]
+    pub leader_chords: BTreeMap<char, LeaderAction>,
+    /// Groups and Proxies keys by action, e.g. `"down": "j Down"`; actions
+    /// left out keep their default keys.
+    #[serde(default)]
+    pub keymap: BTreeMap<Action, String>,
+    #[serde(default)]
+    pub low_power: LowPowerMode,
+    /// Seconds between automatic refreshes of proxies, providers and the
+    /// config; 0 leaves refreshing to `r`.
+    #[serde(default)]
+    pub auto_refresh: u64,
+    /// How critical events get attention beyond the status bar.
+    #[serde(default)]
+    pub alerts: AlertStyle,
+    /// Seconds after which delay results are shown as stale; 0 never ages them.
+    #[serde(default = "default_latency_max_age")]
+    pub latency_max_age: u64,
+    /// Re-test the selected group when its results go stale.
+    #[serde(default)]
+    pub auto_retest_stale: bool,
+    /// Test each group the first time it is selected in a session.
+    #[serde(default)]
+    pub auto_test_groups: bool,
+    /// How delay results are shown in the proxy table and group summaries.
+    #[serde(default)]
+    pub latency_format: LatencyFormat,
+    #[serde(default)]
+    pub layout: LayoutPreset,
+    #[serde(default)]
+    pub theme: ThemeName,
+    /// Delays are rounded to a multiple of this many milliseconds in the `ms` format.
+    #[serde(default = "default_latency_precision")]
+    pub latency_precision: u64,
+    /// Delays below this many milliseconds count as fast: green, grade `A`.
+    #[serde(default = "default_latency_good")]
+    pub latency_good: u64,
+    /// Delays below this count as medium; slower ones as slow.
+    #[serde(default = "default_latency_medium")]
+    pub latency_medium: u64,
+    /// The Overview's test gauge is full at this delay or faster...
+    #[serde(default = "default_latency_gauge_min")]
+    pub latency_gauge_min: u64,
+    /// ...and empty at this one or slower.
+    #[serde(default = "default_latency_gauge_max")]
+    pub latency_gauge_max: u64,
+    /// Accent color per controller URL, as a color name or `#rrggbb`.
+    #[serde(default)]
+    pub accent_colors: BTreeMap<String, String>,
+    /// Free-form scratchpad per controller URL.
+    #[serde(default)]
+    pub notes: BTreeMap<String, String>,
+    /// The core's working directory, used to preview cached rule sets.
+    /// Defaults to `~/.config/mihomo`.
+    #[serde(default)]
+    pub core_dir: Option<String>,
+    /// Saved controllers to switch between with `S`.
+    #[serde(default)]
+    pub profiles: Vec<Profile>,
+    /// Locale for numbers such as `de_DE`. Defaults to the `LC_ALL`,
+    /// `LC_NUMERIC` or `LANG` environment variable.
+    #[serde(default)]
+    pub locale: Option<String>,
+    /// Where delay history and other data that isn't settings is kept.
+    /// Defaults to `$XDG_DATA_HOME/mihomot` or `~/.local/share/mihomot`.
+    #[serde(default)]
+    pub data_dir: Option<String>,
+}
+
+/// A saved controller. `base_url` and `api_secret` above hold the one in use;
+/// its pinned and hidden groups are copied back here when switching away.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Profile {
+    pub name: String,
+    pub base_url: String,
+    #[serde(default)]
+    pub api_secret: String,
+    #[serde(default)]
+    pub pinned_groups: Vec<String>,
+    #[serde(default)]
+    pub hidden_groups: Vec<String>,
+    #[serde(default)]
+    pub group_hotkeys: BTreeMap<u8, String>,
+    #[serde(default)]
+    pub locked_groups: Vec<String>,
+}
+
+/// Work done once at launch; see `AppSettings::startup_actions`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StartupAction {
+    /// Proxies and proxy providers.
+    FetchProxies,
+    FetchConfig,
+    ConnectivityTest,
+    /// Delay-test the first group; implies fetching proxies.
+    GroupTest,
+}
+
+impl StartupAction {
+    pub const ALL: [StartupAction; 4] = [
+        StartupAction::FetchProxies,
+        StartupAction::FetchConfig,
+        StartupAction::ConnectivityTest,
+        StartupAction::GroupTest,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            StartupAction::FetchProxies => "fetch_proxies",
+            StartupAction::FetchConfig => "fetch_config",
+            StartupAction::ConnectivityTest => "connectivity_test",
+            StartupAction::GroupTest => "group_test",
+        }
+    }
+}
+
+/// One step of `AppSettings::on_start`, written `name` or `name:argument`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StartStep {
+    /// `group:<name>` shows the group's proxies.
+    Group(String),
+    /// `test_group[:<name>]` delay-tests the group, else the selected one.
+    TestGroup(Option<String>),
+    /// `open_tab:<view>`, e.g. `open_tab:connections`.
+    OpenTab(Focus),
+    /// `command:<line>` runs a command palette line.
+    Command(String),
+    /// Any other leader chord action by its name, e.g. `test_all`.
+    Leader(LeaderAction),
+}
+
+impl StartStep {
+    pub fn parse(step: &str) -> Result<Self, String> {
+        let (name, argument) = match step.split_once(':') {
+            Some((name, argument)) => (name.trim(), Some(argument.trim())),
+            None => (step.trim(), None),
+        };
+        let argument = argument.filter(|a| !a.is_empty());
+        let step = match (name, argument) {
+            ("group", Some(group)) => StartStep::Group(group.to_string()),
+            ("test_group", group) => StartStep::TestGroup(group.map(str::to_string)),
+            ("open_tab", Some(view)) => StartStep::OpenTab(match view {
+                "groups" | "proxies" => Focus::Groups,
+                "connections" => Focus::Connections,
+                "rules" => Focus::Rules,
+                "logs" => Focus::Logs,
+                "providers" | "rule_providers" => Focus::RuleProviders,
+                "proxy_providers" => Focus::ProxyProviders,
+                "overview" => Focus::Overview,
+                _ => return Err(format!("Unknown tab {:?}", view)),
+            }),
+            ("command", Some(line)) => StartStep::Command(line.to_string()),
+            ("group" | "open_tab" | "command", None) => {
+                return Err(format!("{} needs an argument, e.g. {}:...", name, name));
+            }
+            (name, None) => StartStep::Leader(
+                LeaderAction::ALL
+                    .into_iter()
+                    .find(|a| a.name() == name)
+                    .ok_or_else(|| format!("Unknown step {:?}", name))?,
+            ),
+            (name, Some(_)) => return Err(format!("{} takes no argument", name)),
+        };
+        Ok(step)
+    }
+
+    /// Splits the comma-separated list the Settings view edits. A comma only
+    /// starts a new step when a step name follows it, so `command:` lines
+    /// and group names can contain commas.
+    pub fn split_list(text: &str) -> Vec<String> {
+        let is_name = |name: &str| {
+            matches!(
+                name,
+                "group" | "test_group" | "open_tab" | "command" | "none"
+            ) || LeaderAction::ALL.into_iter().any(|a| a.name() == name)
+        };
+        let mut steps: Vec<String> = Vec::new();
+        for piece in text.split(',') {
+            if piece.trim().is_empty() {
+                continue;
+            }
+            let name = piece.split(':').next().unwrap_or_default().trim();
+            match steps.last_mut() {
+                Some(step) if !is_name(name) => {
+                    step.push(',');
+                    step.push_str(piece);
+                }
+                _ => steps.push(piece.to_string()),
+            }
+        }
+        steps
+            .into_iter()
+            .map(|step| step.trim().to_string())
+            .filter(|step| step != "none")
+            .collect()
+    }
+}
+
+/// What a leader chord runs; see `AppSettings::leader_chords`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LeaderAction {
+    /// Delay-test every group.
+    TestAll,
+    TestGroup,
+    ConnectivityTest,
+    Refresh,
+    Groups,
+    Connections,
+    Rules,
+    RuleProviders,
+    ProxyProviders,
+    Logs,
+    Overview,
+    Settings,
+    Servers,
+    Palette,
+    Notes,
+    Debug,
+    Dns,
+    TrafficHistory,
+    Notifications,
+}
+
+impl LeaderAction {
+    pub const ALL: [LeaderAction; 19] = [
+        LeaderAction::TestAll,
+        LeaderAction::TestGroup,
+        LeaderAction::ConnectivityTest,
+        LeaderAction::Refresh,
+        LeaderAction::Groups,
+        LeaderAction::Connections,
+        LeaderAction::Rules,
+        LeaderAction::RuleProviders,
+        LeaderAction::ProxyProviders,
+        LeaderAction::Logs,
+        LeaderAction::Overview,
+        LeaderAction::Settings,
+        LeaderAction::Servers,
+        LeaderAction::Palette,
+        LeaderAction::Notes,
+        LeaderAction::Debug,
+        LeaderAction::Dns,
+        LeaderAction::TrafficHistory,
+        LeaderAction::Notifications,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LeaderAction::TestAll => "test_all",
+            LeaderAction::TestGroup => "test_group",
+            LeaderAction::ConnectivityTest => "connectivity_test",
+            LeaderAction::Refresh => "refresh",
+            LeaderAction::Groups => "groups",
+            LeaderAction::Connections => "connections",
+            LeaderAction::Rules => "rules",
+            LeaderAction::RuleProviders => "rule_providers",
+            LeaderAction::ProxyProviders => "proxy_providers",
+            LeaderAction::Logs => "logs",
+            LeaderAction::Overview => "overview",
+            LeaderAction::Settings => "settings",
+            LeaderAction::Servers => "servers",
+            LeaderAction::Palette => "palette",
+            LeaderAction::Notes => "notes",
+            LeaderAction::Debug => "debug",
+            LeaderAction::Dns => "dns",
+            LeaderAction::TrafficHistory => "traffic_history",
+            LeaderAction::Notifications => "notifications",
+        }
+    }
+
+    /// Short label for the status bar while a chord is pending.
+    pub fn label(self) -> &'static str {
+        match self {
+            LeaderAction::TestAll => "Test all",
+            LeaderAction::TestGroup => "Test group",
+            LeaderAction::ConnectivityTest => "Connectivity",
+            LeaderAction::Refresh => "Refresh",
+            LeaderAction::Groups => "Groups",
+            LeaderAction::Connections => "Connections",
+            LeaderAction::Rules => "Rules",
+            LeaderAction::RuleProviders => "Rule providers",
+            LeaderAction::ProxyProviders => "Proxy providers",
+            LeaderAction::Logs => "Logs",
+            LeaderAction::Overview => "Overview",
+            LeaderAction::Settings => "Settings",
+            LeaderAction::Servers => "Servers",
+            LeaderAction::Palette => "Palette",
+            LeaderAction::Notes => "Notes",
+            LeaderAction::Debug => "Debug",
+            LeaderAction::Dns => "DNS query",
+            LeaderAction::TrafficHistory => "Traffic history",
+            LeaderAction::Notifications => "Notifications",
+        }
+    }
+}
+
+fn default_leader_key() -> char {
+    ' '
+}
+
+fn default_leader_chords() -> BTreeMap<char, LeaderAction> {
+    BTreeMap::from([
+        ('t', LeaderAction::TestAll),
+        ('g', LeaderAction::TestGroup),
+        ('n', LeaderAction::ConnectivityTest),
+        ('r', LeaderAction::Refresh),
+        ('p', LeaderAction::Groups),
+        ('c', LeaderAction::Connections),
+        ('l', LeaderAction::Rules),
+        ('s', LeaderAction::Settings),
+        ('S', LeaderAction::Servers),
+        (':', LeaderAction::Palette),
+        ('d', LeaderAction::Dns),
+        ('h', LeaderAction::TrafficHistory),
+        ('N', LeaderAction::Notifications),
+    ])
+}
+
+/// How a leader key is written in settings and hints; the default is `space`.
+pub fn leader_key_name(key: char) -> String {
+    match key {
+        ' ' => "space".to_string(),
+        key => key.to_string(),
+    }
+}
+
+fn default_startup_actions() -> Vec<StartupAction> {
+    vec![
+        StartupAction::FetchProxies,
+        StartupAction::FetchConfig,
+        StartupAction::ConnectivityTest,
+    ]
+}
+
+/// Slows redraws and polling, and pauses streams while idle. `Auto` enables it
+/// for SSH sessions.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LowPowerMode {
+    #[default]
+    Auto,
+    On,
+    Off,
+}
+
+impl LowPowerMode {
+    pub fn next(self) -> Self {
+        match self {
+            LowPowerMode::Auto => LowPowerMode::On,
+            LowPowerMode::On => LowPowerMode::Off,
+            LowPowerMode::Off => LowPowerMode::Auto,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            LowPowerMode::Auto => "auto",
+            LowPowerMode::On => "on",
+            LowPowerMode::Off => "off",
+        }
+    }
+}
+
+/// What a critical event does besides showing in the status bar: the
+/// controller going down, the connectivity test failing or a core restart
+/// or upgrade failing.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertStyle {
+    #[default]
+    Off,
+    /// The terminal bell, which tmux and most terminals flag on the window.
+    Bell,
+    /// Inverts the screen for a moment.
+    Flash,
+    Both,
+}
+
+impl AlertStyle {
+    pub fn next(self) -> Self {
+        match self {
+            AlertStyle::Off => AlertStyle::Bell,
+            AlertStyle::Bell => AlertStyle::Flash,
+            AlertStyle::Flash => AlertStyle::Both,
+            AlertStyle::Both => AlertStyle::Off,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            AlertStyle::Off => "off",
+            AlertStyle::Bell => "bell",
+            AlertStyle::Flash => "flash",
+            AlertStyle::Both => "both",
+        }
+    }
+}
+
+/// How long the screen stays inverted for a flash alert.
+const ALERT_FLASH: Duration = Duration::from_millis(300);
+
+/// Notifications kept for the history popup.
+const NOTIFICATION_LIMIT: usize = 200;
+/// Toasts shown at once; older ones stay in the history.
+pub const TOAST_LIMIT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NotificationLevel {
+    Info,
+    Warn,
+    Error,
+}
+
+impl NotificationLevel {
+    pub fn label(self) -> &'static str {
+        match self {
+            NotificationLevel::Info => "info",
+            NotificationLevel::Warn => "warn",
+            NotificationLevel::Error => "error",
+        }
+    }
+
+    /// How long the toast stays up; errors linger so they can be read.
+    fn toast_time(self) -> Duration {
+        match self {
+            NotificationLevel::Info => Duration::from_secs(4),
+            NotificationLevel::Warn => Duration::from_secs(6),
+            NotificationLevel::Error => Duration::from_secs(10),
+        }
+    }
+}
+
+/// A message shown as a toast, then kept in the history popup.
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub level: NotificationLevel,
+    pub message: String,
+    pub at: SystemTime,
+    /// How often it came in a row; repeats refresh the toast instead of
+    /// stacking up.
+    pub count: u32,
+    /// When the toast goes away.
+    pub until: Instant,
+    /// Increases with every notification, repeats included.
+    pub seq: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LatencyTier {
+    Fast,
+    Medium,
+    Slow,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LatencyFormat {
+    /// `123 ms`, rounded to `latency_precision`.
+    #[default]
+    Ms,
+    /// A bar scaled to the slowest result shown alongside it.
+    Bar,
+    /// `A` below `latency_good`, `B` below `latency_medium`, `C` otherwise.
+    Grade,
+}
+
+impl LatencyFormat {
+    pub fn next(self) -> Self {
+        match self {
+            LatencyFormat::Ms => LatencyFormat::Bar,
+            LatencyFormat::Bar => LatencyFormat::Grade,
+            LatencyFormat::Grade => LatencyFormat::Ms,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            LatencyFormat::Ms => "ms",
+            LatencyFormat::Bar => "bar",
+            LatencyFormat::Grade => "grade",
+        }
+    }
+}
+
+/// Arrangement of the main view.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LayoutPreset {
+    /// Groups and Proxies side by side; the Overview has its own tab.
+    #[default]
+    Classic,
+    /// Groups above Proxies on the left; the Overview and a connections
+    /// summary share the right half, so they stay in view. Suits wide
+    /// terminals.
+    Wide,
+}
+
+impl LayoutPreset {
+    pub fn next(self) -> Self {
+        match self {
+            LayoutPreset::Classic => LayoutPreset::Wide,
+            LayoutPreset::Wide => LayoutPreset::Classic,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            LayoutPreset::Classic => "classic",
+            LayoutPreset::Wide => "wide",
+        }
+    }
+}
+
+/// Order of the Proxies table; the group's own order is kept in `group.all`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ProxySort {
+    #[default]
+    Original,
+    /// Fastest first; untested and failed proxies go last.
+    Latency,
+    Name,
+    /// By protocol (`Shadowsocks`, `Vmess`, ...), then name.
+    Type,
+}
+
+impl ProxySort {
+    pub fn next(self) -> Self {
+        match self {
+            ProxySort::Original => ProxySort::Latency,
+            ProxySort::Latency => ProxySort::Name,
+            ProxySort::Name => ProxySort::Type,
+            ProxySort::Type => ProxySort::Original,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ProxySort::Original => "original",
+            ProxySort::Latency => "latency",
+            ProxySort::Name => "name",
+            ProxySort::Type => "type",
+        }
+    }
+}
+
+fn default_base_url() -> String {
+    "http://127.0.0.1:9090".to_string()
+}
+
+fn default_api_secret() -> String {
+    std::env::var("MIHOMO_SECRET").unwrap_or_else(|_| "mihomo".to_string())
+}
+
+fn default_test_url() -> String {
+    "https://www.google.com".to_string()
+}
+
+fn default_test_timeout() -> u64 {
+    3000
+}
+
+fn default_delay_test_concurrency() -> usize {
+    8
+}
+
+fn default_delay_test_timeout() -> u64 {
+    5000
+}
+
+fn default_latency_max_age() -> u64 {
+    600
+}
+
+fn default_latency_precision() -> u64 {
+    1
+}
+
+fn default_latency_good() -> u64 {
+    200
+}
+
+fn default_latency_medium() -> u64 {
+    500
+}
+
+fn default_latency_gauge_min() -> u64 {
+    20
+}
+
+fn default_latency_gauge_max() -> u64 {
+    1000
+}
+
+fn default_connection_filters() -> Vec<ConnectionFilter> {
+    let preset = |name: &str, patterns: &[&str]| ConnectionFilter {
+        name: name.to_string(),
+        patterns: patterns.iter().map(|p| p.to_string()).collect(),
+    };
+    vec![
+        preset(
+            "browser",
+            &["chrome", "firefox", "safari", "msedge", "brave"],
+        ),
+        preset(
+            "torrent",
+            &["qbittorrent", "transmission", "aria2", "deluge"],
+        ),
+    ]
+}
+
+fn default_connection_columns() -> Vec<ConnectionColumns> {
+    use ConnectionColumn::*;
+    let set = |name: &str, columns: &[ConnectionColumn]| ConnectionColumns {
+        name: name.to_string(),
+        columns: columns.to_vec(),
+    };
+    vec![
+        set("default", &DEFAULT_CONNECTION_COLUMNS),
+        set(
+            "debug",
+            &[Host, Sniff, Process, Chain, Rule, Inbound, Type, Source],
+        ),
+        set(
+            "traffic",
+            &[
+                Host,
+                Chain,
+                DownloadSpeed,
+                UploadSpeed,
+                Download,
+                Upload,
+                Time,
+            ],
+        ),
+    ]
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        Self {
+            base_url: default_base_url(),
+            api_secret: default_api_secret(),
+            test_url: default_test_url(),
+            test_timeout: default_test_timeout(),
+            delay_test_concurrency: default_delay_test_concurrency(),
+            delay_test_timeout: default_delay_test_timeout(),
+            connection_filters: default_connection_filters(),
+            connection_columns: default_connection_columns(),
+            pinned_groups: Vec::new(),
+            hidden_groups: Vec::new(),
+            group_hotkeys: BTreeMap::new(),
+            locked_groups: Vec::new(),
+            provider_auto_update: BTreeMap::new(),
+            startup_actions: default_startup_actions(),
+            on_start: Vec::new(),
+            leader_key: default_leader_key(),
+            leader_chords: default_leader_chords(),
+            keymap: BTreeMap::new(),
+            low_power: LowPowerMode::default(),
+            auto_refresh: 0,
+            alerts: AlertStyle::default(),
+            latency_max_age: default_latency_max_age(),
+            auto_retest_stale: false,
+            auto_test_groups: false,
+            latency_format: LatencyFormat::default(),
+            layout: LayoutPreset::default(),
+            theme: ThemeName::default(),
+            latency_precision: default_latency_precision(),
+            latency_good: default_latency_good(),
+            latency_medium: default_latency_medium(),
+            latency_gauge_min: default_latency_gauge_min(),
+            latency_gauge_max: default_latency_gauge_max(),
+            accent_colors: BTreeMap::new(),
+            notes: BTreeMap::new(),
+            core_dir: None,
+            profiles: Vec::new(),
+            locale: None,
+            data_dir: None,
+        }
+    }
+}
+
+/// Endpoints the app relies on, probed once per connection.
+pub const PROBED_ENDPOINTS: [&str; 5] = [
+    "/version",
+    "/proxies",
+    "/configs",
+    "/connections",
+    "/traffic",
+];
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum EndpointStatus {
+    Available,
+    /// The controller answered 404/405, so the endpoint does not exist on this core.
+    Unsupported(u16),
+    /// Any other failure (auth, network, 5xx); the feature stays enabled.
+    Failed(String),
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct VersionInfo {
+    pub version: String,
+    #[serde(default)]
+    pub meta: bool,
+    /// Set by the closed-source Clash Premium core.
+    #[serde(default)]
+    pub premium: bool,
+}
+
+impl VersionInfo {
+    /// The version with the core's flavor, e.g. `v1.19.0 (Meta)`.
+    pub fn describe(&self) -> String {
+        if self.meta {
+            format!("{} (Meta)", self.version)
+        } else if self.premium {
+            format!("{} (Premium)", self.version)
+        } else {
+            self.version.clone()
+        }
+    }
+}
+
+/// The newest mihomo release, compared against the connected core.
+const CORE_RELEASES_URL: &str = "https://api.github.com/repos/MetaCubeX/mihomo/releases/latest";
+
+/// How long a looked-up core release stays fresh; GitHub limits anonymous
+/// API calls to 60 an hour.
+const CORE_RELEASE_MAX_AGE: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, Clone, Default)]
+pub struct Capabilities {
+    pub checked: bool,
+    pub version: Option<VersionInfo>,
+    pub endpoints: Vec<(&'static str, EndpointStatus)>,
+}
+
+impl Capabilities {
+    /// Only endpoints the core explicitly rejected count as unsupported.
+    pub fn supports(&self, path: &str) -> bool {
+        !self
+            .endpoints
+            .iter()
+            .any(|(p, status)| *p == path && matches!(status, EndpointStatus::Unsupported(_)))
+    }
+
+    pub fn core_version(&self) -> Option<(u32, u32, u32)> {
+        self.version
+            .as_ref()
+            .and_then(|v| parse_version(&v.version))
+    }
+
+    /// Unknown or unparsable versions (e.g. alpha builds) are assumed to be recent.
+    pub fn supports_feature(&self, feature: CoreFeature) -> bool {
+        self.core_version()
+            .is_none_or(|version| version >= feature.min_version())
+    }
+
+    pub fn unsupported_features(&self) -> Vec<CoreFeature> {
+        CoreFeature::ALL
+            .into_iter()
+            .filter(|f| !self.supports_feature(*f))
+            .collect()
+    }
+}
+
+/// Controller features gated on a minimum mihomo version.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CoreFeature {
+    GroupDelay,
+    DnsQuery,
+    Upgrade,
+}
+
+impl CoreFeature {
+    pub const ALL: [CoreFeature; 3] = [
+        CoreFeature::GroupDelay,
+        CoreFeature::DnsQuery,
+        CoreFeature::Upgrade,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            CoreFeature::GroupDelay => "group delay test",
+            CoreFeature::DnsQuery => "DNS query",
+            CoreFeature::Upgrade => "core upgrade",
+        }
+    }
+
+    pub fn min_version(&self) -> (u32, u32, u32) {
+        match self {
+            CoreFeature::GroupDelay => (1, 14, 0),
+            CoreFeature::DnsQuery => (1, 15, 0),
+            CoreFeature::Upgrade => (1, 17, 0),
+        }
+    }
+}
+
+/// Parses `v1.18.1`, `1.18` or `Meta v1.18.1-foo` into a comparable triple.
+pub fn parse_version(raw: &str) -> Option<(u32, u32, u32)> {
+    let start = raw.find(|c: char| c.is_ascii_digit())?;
+    if start > 0 && !raw[..start].trim_end().ends_with(['v', 'V']) {
+        return None;
+    }
+    let numeric: String = raw[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '.')
+        .collect();
+    let mut parts = numeric.split('.').map(|p| p.parse::<u32>().ok());
+    let major = parts.next()??;
+    let minor = parts.next().flatten().unwrap_or(0);
+    let patch = parts.next().flatten().unwrap_or(0);
+    Some((major, minor, patch))
+}
+
+/// A state-changing controller request that can be replayed later.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mutation {
+    SelectProxy { group: String, proxy: String },
+    PatchConfig(serde_json::Value),
+}
+
+impl Mutation {
+    pub fn describe(&self) -> String {
+        match self {
+            Mutation::SelectProxy { group, proxy } => format!("select {} -> {}", group, proxy),
+            Mutation::PatchConfig(body) => format!("config {}", body),
+        }
+    }
+
+    pub async fn send(&self, api: &MihomoClient) -> Result<(), ApiError> {
+        match self {
+            Mutation::SelectProxy { group, proxy } => api.select_proxy(group, proxy).await,
+            Mutation::PatchConfig(body) => api.patch_config(body).await,
+        }
+    }
+}
+
+/// A mutation that failed because the controller was unreachable.
+#[derive(Debug, Clone)]
+pub struct QueuedMutation {
+    pub mutation: Mutation,
+    pub error: String,
+    pub attempts: u32,
+}
+
+/// A proxy switch sent to the controller but not yet confirmed.
+#[derive(Debug, Clone)]
+pub struct PendingSwitch {
+    pub proxy: String,
+    /// The group's `now` before the optimistic update, restored on failure.
+    pub previous: Option<String>,
+    /// Reverts don't add to the selection history, so repeated undos walk back.
+    pub revert: bool,
+}
+
+/// Rounds run by the proxy comparison popup.
+pub const COMPARE_ROUNDS: usize = 10;
+
+/// Two marked proxies delay-tested side by side, one pair of requests per round.
+pub struct Comparison {
+    pub proxies: [String; 2],
+    /// Each finished round's delay per proxy; `None` for a timeout or error.
+    pub results: [Vec<Option<u64>>; 2],
+}
+
+/// Summary of one side of a `Comparison`, over the rounds that succeeded.
+pub struct DelayStats {
+    pub min: u64,
+    pub avg: u64,
+    pub median: u64,
+    pub max: u64,
+    /// Standard deviation, in milliseconds.
+    pub jitter: u64,
+}
+
+impl Comparison {
+    pub fn rounds_done(&self) -> usize {
+        self.results[0].len().min(self.results[1].len())
+    }
+
+    pub fn stats(&self, side: usize) -> Option<DelayStats> {
+        let mut delays: Vec<u64> = self.results[side].iter().flatten().copied().collect();
+        if delays.is_empty() {
+            return None;
+        }
+        delays.sort_unstable();
+        let n = delays.len() as f64;
+        let mean = delays.iter().sum::<u64>() as f64 / n;
+        let variance = delays
+            .iter()
+            .map(|d| (*d as f64 - mean).powi(2))
+            .sum::<f64>()
+            / n;
+        Some(DelayStats {
+            min: delays[0],
+            avg: mean.round() as u64,
+            median: delays[delays.len() / 2],
+            max: delays[delays.len() - 1],
+            jitter: variance.sqrt().round() as u64,
+        })
+    }
+}
+
+/// Intervals offered for provider auto-updates, in hours.
+pub const PROVIDER_AUTO_UPDATE_HOURS: [u64; 4] = [1, 6, 12, 24];
+/// How long a failed provider auto-update waits before trying again.
+const PROVIDER_RETRY_AFTER: Duration = Duration::from_secs(15 * 60);
+
+/// Function keys that can be bound to groups; F12 opens the debug console.
+pub const GROUP_HOTKEY_LIMIT: u8 = 11;
+
+/// Delay results kept per proxy for its trend.
+const LATENCY_HISTORY_LIMIT: usize = 16;
+
+/// File in the data directory keeping delay history across sessions.
+const LATENCY_HISTORY_FILE: &str = "latency_history.json";
+
+/// File in the data directory keeping traffic totals across sessions, one
+/// line per minute: unix seconds, bytes up, bytes down and controller URL,
+/// separated by tabs.
+const TRAFFIC_HISTORY_FILE: &str = "traffic_history.tsv";
+
+/// File in the data directory holding the version that last ran.
+const LAST_VERSION_FILE: &str = "last_version";
+
+/// Traffic minutes older than this are dropped at startup.
+const TRAFFIC_HISTORY_KEEP: Duration = Duration::from_secs(8 * 24 * 3600);
+
+/// Delay history as saved: controller URL, then proxy, then `(unix seconds, ms)`.
+type StoredLatencyHistory = BTreeMap<String, BTreeMap<String, Vec<(u64, u64)>>>;
+
+/// How a proxy's latest delay compares with its earlier ones.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LatencyTrend {
+    Faster,
+    Steady,
+    Slower,
+    /// Some of the recent tests failed.
+    Flaky,
+}
+
+/// Accents offered when cycling, and picked from by URL when none is set.
+pub const ACCENT_PALETTE: [Color; 6] = [
+    Color::Yellow,
+    Color::Cyan,
+    Color::Magenta,
+    Color::Green,
+    Color::LightRed,
+    Color::LightBlue,
+];
+
+/// Type-ahead ends after this long without a keystroke.
+const TYPE_AHEAD_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Key repeats closer together than this count as holding the key down.
+const SCROLL_REPEAT_WINDOW: Duration = Duration::from_millis(150);
+
+/// Previous selections kept per group for reverting.
+const SELECTION_HISTORY_LIMIT: usize = 20;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TrafficEventKind {
+    ProxySwitch,
+    ModeChange,
+    /// A restart or upgrade started from mihomot that the core came back from.
+    CoreRestart,
+    /// The `/traffic` stream dropped and was opened again, for whatever reason.
+    Reconnect,
+    LatencyAlert,
+}
+
+/// Something that happened at a point on the traffic chart timeline.
+#[derive(Clone, Debug)]
+pub struct TrafficEvent {
+    /// Value of `App::traffic_samples` when the event happened.
+    pub sample: u64,
+    pub kind: TrafficEventKind,
+    pub label: String,
+}
+
+const TRAFFIC_EVENT_LIMIT: usize = 100;
+
+/// Connectivity test results at or above this are marked on the chart.
+const LATENCY_ALERT_MS: u64 = 1000;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum RealLatencyStatus {
+    Pending,
+    Testing,
+    Success(u64),
+    Failed(String),
+}
+
+/// Panels that show their own load error when they have nothing else to show.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Panel {
+    Proxies,
+    Connections,
+    Rules,
+    RuleProviders,
+    ProxyProviders,
+}
+
+/// What one `GET` fetches from the controller, for `App::load` and `App::fetch`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Load {
+    Proxies,
+    Config,
+    Version,
+    Connections,
+    Rules,
+    RuleProviders,
+    ProxyProviders,
+}
+
+impl Load {
+    fn path(self) -> &'static str {
+        match self {
+            Load::Proxies => "/proxies",
+            Load::Config => "/configs",
+            Load::Version => "/version",
+            Load::Connections => "/connections",
+            Load::Rules => "/rules",
+            Load::RuleProviders => "/providers/rules",
+            Load::ProxyProviders => "/providers/proxies",
+        }
+    }
+
+    /// What failed, for the status bar.
+    fn label(self) -> &'static str {
+        match self {
+            Load::Proxies => "Fetch proxies",
+            Load::Config => "Fetch config",
+            Load::Version => "Fetch version",
+            Load::Connections => "Fetch connections",
+            Load::Rules => "Fetch rules",
+            Load::RuleProviders => "Fetch rule providers",
+            Load::ProxyProviders => "Fetch providers",
+        }
+    }
+}
+
+/// Controller calls beyond plain `Load`s that run in the background; see
+/// `App::spawn_job`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Job {
+    Probe,
+    ApplyConfig,
+    CheckConfig,
+    UpdateProviders,
+    Retry,
+    CloseConnections,
+    Flush,
+    DnsQuery,
+    Request,
+    OpenProvider,
+}
+
+impl Job {
+    /// What is running, for the status bar; checks the run loop starts on
+    /// its own have none.
+    pub fn label(self) -> Option<&'static str> {
+        match self {
+            Job::Probe => Some("Probing the controller"),
+            Job::ApplyConfig => Some("Applying settings"),
+            Job::Retry => Some("Replaying queued changes"),
+            Job::CloseConnections => Some("Closing connections"),
+            Job::Flush => Some("Flushing"),
+            Job::DnsQuery => Some("Querying DNS"),
+            Job::Request => Some("Sending request"),
+            Job::OpenProvider => Some("Loading rule providers"),
+            Job::CheckConfig | Job::UpdateProviders => None,
+        }
+    }
+}
+
+/// Everything the run loop waits on, sent through `App::events_tx` by the
+/// input reader, background requests and streams. `base_url` is the
+/// controller a load was asked of.
+pub enum AppEvent {
+    Input(crossterm::event::Event),
+    Loaded {
+        load: Load,
+        base_url: String,
+        response: Result<ApiResponse, ApiError>,
+    },
+    SmartWeights {
+        base_url: String,
+        weights: HashMap<String, Result<HashMap<String, f64>, String>>,
+    },
+    Unpinned {
+        group: String,
+        result: Result<(), ApiError>,
+    },
+    SwitchResult {
+        group: String,
+        result: Result<(), ApiError>,
+    },
+    ProviderResult {
+        name: String,
+        action: ProviderAction,
+        result: Result<(), String>,
+    },
+    ProxyDelay {
+        name: String,
+        result: Result<u64, DelayFailure>,
+    },
+    /// The core's group delay test failed as a whole, so no proxy got a result.
+    GroupTestFailed {
+        group: String,
+        error: ApiError,
+    },
+    /// One side's result of comparison round `run`.
+    Compare {
+        run: u64,
+        side: usize,
+        delay: Option<u64>,
+    },
+    RealLatency(RealLatencyStatus),
+    ControllerRtt(Result<u64, String>),
+    CoreOperation(CoreOperationEvent),
+    LatestCoreRelease(String),
+    Traffic(Traffic),
+    Memory(Memory),
+    Log(LogLine),
+    Connections(ConnectionsResponse),
+    /// The real addresses behind one fake-ip address of the map.
+    FakeIpLookup {
+        ip: String,
+        real: Result<Vec<String>, String>,
+    },
+    /// A `spawn_job` task finished; `apply` takes its answer in.
+    JobDone {
+        job: Job,
+        base_url: String,
+        apply: Box<dyn FnOnce(&mut App) + Send>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Focus {
+    Groups,
+    Proxies,
+    Settings,
+    Debug,
+    Connections,
+    Rules,
+    RuleProviders,
+    ProxyProviders,
+    Logs,
+    Overview,
+}
+
+/// Entries of the tab bar, in order; the number keys pick them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tab {
+    Proxies,
+    Connections,
+    Rules,
+    Logs,
+    /// Rule providers and proxy providers, one view each.
+    Providers,
+    Overview,
+}
+
+impl Tab {
+    pub const ALL: [Tab; 6] = [
+        Tab::Proxies,
+        Tab::Connections,
+        Tab::Rules,
+        Tab::Logs,
+        Tab::Providers,
+        Tab::Overview,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Tab::Proxies => "Proxies",
+            Tab::Connections => "Connections",
+            Tab::Rules => "Rules",
+            Tab::Logs => "Logs",
+            Tab::Providers => "Providers",
+            Tab::Overview => "Overview",
+        }
+    }
+
+    /// The tab a view belongs to; Settings and the debug console have none.
+    pub fn of(focus: &Focus) -> Option<Tab> {
+        match focus {
+            Focus::Groups | Focus::Proxies => Some(Tab::Proxies),
+            Focus::Connections => Some(Tab::Connections),
+            Focus::Rules => Some(Tab::Rules),
+            Focus::Logs => Some(Tab::Logs),
+            Focus::RuleProviders | Focus::ProxyProviders => Some(Tab::Providers),
+            Focus::Overview => Some(Tab::Overview),
+            Focus::Settings | Focus::Debug => None,
+        }
+    }
+}
+
+/// One visible line of the read-only config browser.
+pub struct ConfigTreeRow {
+    pub path: String,
+    pub depth: usize,
+    pub key: String,
+    /// `None` for objects and arrays, which can be expanded.
+    pub value: Option<String>,
+    pub expanded: bool,
+}
+
+fn push_tree_rows(
+    value: &serde_json::Value,
+    prefix: &str,
+    depth: usize,
+    expanded: &BTreeSet<String>,
+    rows: &mut Vec<ConfigTreeRow>,
+) {
+    let children: Vec<(String, &serde_json::Value)> = match value {
+        serde_json::Value::Object(map) => map.iter().map(|(k, v)| (k.clone(), v)).collect(),
+        serde_json::Value::Array(items) => items
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (format!("[{}]", i), v))
+            .collect(),
+        _ => return,
+    };
+    for (key, child) in children {
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        let is_branch = matches!(
+            child,
+            serde_json::Value::Object(map) if !map.is_empty()
+        ) || matches!(child, serde_json::Value::Array(items) if !items.is_empty());
+        let is_expanded = is_branch && expanded.contains(&path);
+        rows.push(ConfigTreeRow {
+            path: path.clone(),
+            depth,
+            key,
+            value: (!is_branch).then(|| child.to_string()),
+            expanded: is_expanded,
+        });
+        if is_expanded {
+            push_tree_rows(child, &path, depth + 1, expanded, rows);
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum ConfigEntry {
+    BaseUrl,
+    ApiSecret,
+    TestUrl,
+    TestTimeout,
+    DelayTestConcurrency,
+    DelayTestTimeout,
+    LatencyMaxAge,
+    AutoRetestStale,
+    AutoTestGroups,
+    LatencyFormat,
+    LatencyPrecision,
+    LatencyGood,
+    LatencyMedium,
+    LatencyGaugeMin,
+    LatencyGaugeMax,
+    Layout,
+    Theme,
+    StartupActions,
+    OnStart,
+    LeaderKey,
+    LeaderChords,
+    AutoRefresh,
+    LowPower,
+    Alerts,
+    Accent,
+    Mode,
+    Tun,
+    MixedPort,
+    LogLevel,
+    AllowLan,
+    Authentication,
+    SkipAuthPrefixes,
+    BindAddress,
+    Ipv6,
+    Hosts,
+    DnsQuery,
+    CoreRestart,
+    CoreUpgrade,
+}
+
+impl ConfigEntry {
+    /// Top-level `/configs` key this entry edits, if it belongs to the core.
+    pub fn config_key(&self) -> Option<&'static str> {
+        match self {
+            ConfigEntry::Mode => Some("mode"),
+            ConfigEntry::Tun => Some("tun"),
+            ConfigEntry::MixedPort => Some("mixed-port"),
+            ConfigEntry::LogLevel => Some("log-level"),
+            ConfigEntry::AllowLan => Some("allow-lan"),
+            ConfigEntry::Authentication => Some("authentication"),
+            ConfigEntry::SkipAuthPrefixes => Some("skip-auth-prefixes"),
+            ConfigEntry::BindAddress => Some("bind-address"),
+            ConfigEntry::Ipv6 => Some("ipv6"),
+            _ => None,
+        }
+    }
+
+    /// How much one +/- press changes a numeric entry; `None` for the rest.
+    pub fn step(&self) -> Option<u64> {
+        match self {
+            ConfigEntry::MixedPort
+            | ConfigEntry::DelayTestConcurrency
+            | ConfigEntry::LatencyPrecision => Some(1),
+            ConfigEntry::TestTimeout | ConfigEntry::DelayTestTimeout => Some(500),
+            ConfigEntry::LatencyGood | ConfigEntry::LatencyMedium => Some(50),
+            ConfigEntry::LatencyGaugeMin => Some(10),
+            ConfigEntry::LatencyGaugeMax => Some(100),
+            ConfigEntry::LatencyMaxAge => Some(60),
+            ConfigEntry::AutoRefresh => Some(10),
+            _ => None,
+        }
+    }
+}
+
+/// Command palette commands, completed with Tab.
+const PALETTE_COMMANDS: [&str; 7] = [
+    "select ",
+    "bulk ",
+    "restart",
+    "flush fakeip",
+    "flush dns",
+    "request ",
+    "dns ",
+];
+
+/// Something hard to undo, held until the user confirms it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfirmAction {
+    CloseConnection { id: String, target: String },
+    CloseAllConnections,
+    RestartCore,
+    UpgradeCore,
+    FlushFakeIp,
+    FlushDns,
+    FlushSmartGroup(String),
+    SwitchProxy { group: String, proxy: String },
+    RevertGroup(String),
+    UnpinGroup(String),
+}
+
+impl ConfirmAction {
+    /// What failed, for the status bar.
+    pub fn label(&self) -> &'static str {
+        match self {
+            ConfirmAction::CloseConnection { .. } => "Close connection",
+            ConfirmAction::CloseAllConnections => "Close connections",
+            ConfirmAction::RestartCore => "Restart core",
+            ConfirmAction::UpgradeCore => "Upgrade core",
+            ConfirmAction::FlushFakeIp => "Flush fake-ip cache",
+            ConfirmAction::FlushDns => "Flush DNS cache",
+            ConfirmAction::FlushSmartGroup(_) => "Flush Smart group",
+            ConfirmAction::SwitchProxy { .. } | ConfirmAction::RevertGroup(_) => "Switch proxy",
+            ConfirmAction::UnpinGroup(_) => "Unpin group",
+        }
+    }
+
+    pub fn prompt(&self) -> String {
+        match self {
+            ConfirmAction::CloseConnection { target, .. } => format!("Close {}?", target),
+            ConfirmAction::CloseAllConnections => "Close all connections?".to_string(),
+            ConfirmAction::RestartCore => {
+                "Restart the core? Every connection will drop.".to_string()
+            }
+            ConfirmAction::UpgradeCore => {
+                "Upgrade the core binary and restart it? Every connection will drop.".to_string()
+            }
+            ConfirmAction::FlushFakeIp => "Flush the fake-ip cache?".to_string(),
+            ConfirmAction::FlushDns => "Flush the DNS cache?".to_string(),
+            ConfirmAction::FlushSmartGroup(group) => {
+                format!("Flush what Smart group {} has learned?", group)
+            }
+            ConfirmAction::SwitchProxy { group, proxy } => {
+                format!("{} is locked. Switch it to {}?", group, proxy)
+            }
+            ConfirmAction::RevertGroup(group) => {
+                format!("{} is locked. Revert it to its previous proxy?", group)
+            }
+            ConfirmAction::UnpinGroup(group) => {
+                format!("{} is locked. Let the core pick its node again?", group)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CoreOperationKind {
+    Restart,
+    Upgrade,
+}
+
+impl CoreOperationKind {
+    fn path(self) -> &'static str {
+        match self {
+            CoreOperationKind::Restart => "/restart",
+            CoreOperationKind::Upgrade => "/upgrade",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            CoreOperationKind::Restart => "Restart",
+            CoreOperationKind::Upgrade => "Upgrade",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CoreOperationStatus {
+    /// The request is out; upgrades answer only once the download is done.
+    Sending,
+    /// Accepted; polling `/version` until the core answers again.
+    Waiting {
+        went_down: bool,
+    },
+    /// Back up, with the version it reported.
+    Done {
+        version: String,
+        took: Duration,
+    },
+    Failed(String),
+}
+
+/// A restart or upgrade started from Settings, followed until the core is back.
+#[derive(Debug, Clone)]
+pub struct CoreOperation {
+    pub kind: CoreOperationKind,
+    pub started: Instant,
+    pub version_before: Option<String>,
+    pub status: CoreOperationStatus,
+}
+
+/// Progress of the running `CoreOperation`, sent from its tasks.
+pub enum CoreOperationEvent {
+    Sent(Result<(), String>),
+    /// A `/version` poll: the version, or `None` while the core is down.
+    Polled(Option<String>),
+}
+
+/// How long a core may stay unreachable before the operation counts as failed.
+const CORE_COMEBACK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Popups that take the keyboard until answered or closed; one at a time.
+#[derive(Debug, Default)]
+pub enum Modal {
+    #[default]
+    None,
+    /// The `?` keybinding reference, scrolled with `popup_scroll`.
+    Help,
+    /// Details of the selected proxy, scrolled with `popup_scroll`.
+    Info,
+    Capabilities,
+    /// Changes waiting for the controller to come back.
+    Queue,
+    /// Staged core edits, applied on a yes.
+    ConfigReview,
+    ConfigTree,
+    Hosts,
+    Profiles,
+    /// `yes` is the highlighted answer; it starts on No.
+    Confirm {
+        action: ConfirmAction,
+        yes: bool,
+    },
+    Response(RawResponse),
+    Dns(DnsTool),
+    TrafficHistory(TrafficHistory),
+    BulkSelect(BulkSelection),
+    Pin(PinChoice),
+    /// Every notification still kept, newest first.
+    Notifications,
+    /// What's new since this version, shown once after an upgrade.
+    Changelog(String),
+    /// Fake-ip addresses the core handed out, with their real lookups.
+    FakeIp(FakeIpMap),
+}
+
+/// Enter on a member of a `url-test` or `fallback` group: pin that node, or
+/// hand the choice back to the core.
+#[derive(Debug)]
+pub struct PinChoice {
+    pub group: String,
+    pub proxy: String,
+    pub kind: &'static str,
+    /// The node the group is pinned to now, if any.
+    pub pinned: Option<String>,
+    /// The highlighted answer; starts on returning to auto when `proxy` is
+    /// already the pinned node.
+    pub unpin: bool,
+}
+
+/// A `bulk` palette command: in every group matching one pattern, the fastest
+/// member matching the other, previewed before anything is switched.
+#[derive(Debug)]
+pub struct BulkSelection {
+    pub groups: String,
+    pub proxies: String,
+    pub changes: Vec<BulkChange>,
+}
+
+impl BulkSelection {
+    pub fn switches(&self) -> usize {
+        self.changes
+            .iter()
+            .filter(|c| matches!(c.outcome, BulkOutcome::Switch { .. }))
+            .count()
+    }
+}
+
+#[derive(Debug)]
+pub struct BulkChange {
+    pub group: String,
+    pub now: Option<String>,
+    pub locked: bool,
+    pub outcome: BulkOutcome,
+}
+
+#[derive(Debug)]
+pub enum BulkOutcome {
+    Switch {
+        proxy: String,
+        delay: u64,
+    },
+    /// The fastest match is already selected.
+    Unchanged {
+        delay: u64,
+    },
+    Skip(String),
+}
+
+/// Record types offered by the DNS tool, cycled with Tab.
+pub const DNS_QUERY_TYPES: [&str; 6] = ["A", "AAAA", "CNAME", "MX", "TXT", "HTTPS"];
+
+/// The DNS query popup: a name to look up through the core, and the last answer.
+#[derive(Debug, Default)]
+pub struct DnsTool {
+    pub input: String,
+    /// Index into `DNS_QUERY_TYPES`.
+    pub record_type: usize,
+    pub lookup: Option<DnsLookup>,
+}
+
+#[derive(Debug)]
+pub struct DnsLookup {
+    pub name: String,
+    pub record_type: &'static str,
+    pub answer: Result<DnsQueryResponse, String>,
+    pub elapsed: Duration,
+    /// What probably answered, worked out from the core's DNS config.
+    pub resolver: String,
+}
+
+/// How long a request typed in the debug console may take. Longer than the
+/// delay test timeout, since some endpoints (provider updates, upgrades)
+/// are slow on purpose.
+const RAW_REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Spans offered by the traffic history popup. There is no time zone data to
+/// find midnight with, so "today" and "this week" are rolling windows.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TrafficRange {
+    Hour,
+    Day,
+    Week,
+}
+
+impl TrafficRange {
+    pub const ALL: [TrafficRange; 3] = [TrafficRange::Hour, TrafficRange::Day, TrafficRange::Week];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            TrafficRange::Hour => "Last hour",
+            TrafficRange::Day => "Last 24 hours",
+            TrafficRange::Week => "Last 7 days",
+        }
+    }
+
+    pub fn span(self) -> Duration {
+        match self {
+            TrafficRange::Hour => Duration::from_secs(3600),
+            TrafficRange::Day => Duration::from_secs(24 * 3600),
+            TrafficRange::Week => Duration::from_secs(7 * 24 * 3600),
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            TrafficRange::Hour => TrafficRange::Day,
+            TrafficRange::Day => TrafficRange::Week,
+            TrafficRange::Week => TrafficRange::Hour,
+        }
+    }
+
+    pub fn previous(self) -> Self {
+        match self {
+            TrafficRange::Hour => TrafficRange::Week,
+            TrafficRange::Day => TrafficRange::Hour,
+            TrafficRange::Week => TrafficRange::Day,
+        }
+    }
+}
+
+/// Bytes moved during one minute, as stored in `TRAFFIC_HISTORY_FILE`.
+#[derive(Clone, Copy, Debug)]
+pub struct TrafficMinute {
+    /// Unix seconds at the start of the minute.
+    pub start: u64,
+    pub up: u64,
+    pub down: u64,
+}
+
+/// The traffic history popup: the stored minutes of the current controller
+/// within `range`.
+#[derive(Debug)]
+pub struct TrafficHistory {
+    pub range: TrafficRange,
+    /// Unix seconds where the range begins.
+    pub since: u64,
+    pub minutes: Vec<TrafficMinute>,
+}
+
+impl TrafficHistory {
+    /// Total bytes up and down.
+    pub fn totals(&self) -> (u64, u64) {
+        self.minutes
+            .iter()
+            .fold((0, 0), |(up, down), m| (up + m.up, down + m.down))
+    }
+
+    /// Bytes up and down in `count` equal slices of the range, oldest first.
+    pub fn buckets(&self, count: usize) -> (Vec<u64>, Vec<u64>) {
+        let count = count.max(1);
+        let span = self.range.span().as_secs();
+        let mut up = vec![0; count];
+        let mut down = vec![0; count];
+        for minute in &self.minutes {
+            let offset = minute.start.saturating_sub(self.since);
+            let i = ((offset * count as u64) / span).min(count as u64 - 1) as usize;
+            up[i] += minute.up;
+            down[i] += minute.down;
+        }
+        (up, down)
+    }
+}
+
+/// The answer to a `request` typed in the command palette.
+#[derive(Debug)]
+pub struct RawResponse {
+    /// The line as typed, e.g. `GET /version`.
+    pub request: String,
+    /// HTTP status, or why no answer arrived.
+    pub status: Result<u16, String>,
+    pub elapsed: Duration,
+    pub body: String,
+}
+
+pub struct App {
+    pub proxies: HashMap<String, ProxyItem>,
+    pub config: Option<Config>,
+    config_hash: Option<u64>,
+    /// Set when the core's config no longer matches `config`.
+    pub config_drift: bool,
+    /// Why each panel's last load failed; cleared by the next successful load.
+    pub load_errors: HashMap<Panel, String>,
+    /// Background loads still waiting on the controller.
+    pub loading: HashSet<Load>,
+    /// Background jobs still running, once per run.
+    pub jobs: Vec<Job>,
+    /// Everything the run loop waits on: input, API results and streams.
+    pub events_tx: mpsc::Sender<AppEvent>,
+    pub events_rx: mpsc::Receiver<AppEvent>,
+    /// Core config edits made in Settings, sent as one PATCH once confirmed.
+    pub staged_config: serde_json::Map<String, serde_json::Value>,
+    pub config_tree_state: ListState,
+    config_tree_expanded: BTreeSet<String>,
+    pub real_latency_status: RealLatencyStatus,
+    pub client: Client,
+    pub app_settings: AppSettings,
+    /// Decimal separator of the user's locale, for sizes and speeds.
+    pub decimal_separator: char,
+
+    /// Round trip of the last `/version` ping, in ms, or why it failed. Tells
+    /// a slow link to the controller apart from slow proxies.
+    pub controller_rtt: Option<Result<u64, String>>,
+    /// The last restart or upgrade started from Settings.
+    pub core_operation: Option<CoreOperation>,
+    /// Tag of the newest mihomo release, once looked up.
+    pub latest_core_release: Option<String>,
+    latest_core_release_checked: Option<Instant>,
+
+    pub proxy_latency: HashMap<String, Option<u64>>,
+    /// When each `proxy_latency` result was measured.
+    pub proxy_latency_at: HashMap<String, SystemTime>,
+    /// Why the last delay test of each proxy failed, if it did.
+    pub delay_failures: HashMap<String, DelayFailure>,
+    /// Recent delay results per proxy, oldest first; `0` is a failed test.
+    pub latency_history: HashMap<String, VecDeque<(SystemTime, u64)>>,
+    /// Per-node weights of Smart groups, or why they couldn't be loaded.
+    pub smart_weights: HashMap<String, Result<HashMap<String, f64>, String>>,
+    /// Provider (subscription) each proxy was loaded from, keyed by proxy name.
+    pub proxy_origin: HashMap<String, String>,
+    /// Proxy providers other than the core's implicit `default`, sorted by name.
+    pub proxy_providers: Vec<ProxyProvider>,
+    pub proxy_providers_state: TableState,
+    /// Proxy provider actions still in flight, keyed by provider name.
+    pub provider_actions: HashMap<String, ProviderAction>,
+    /// Rule providers being updated.
+    pub rule_provider_updates: HashSet<String>,
+    /// When each proxy provider last finished an update, and how it went.
+    pub provider_update_results: HashMap<String, (SystemTime, Result<(), String>)>,
+    /// Groups already tested by `auto_test_groups` this session.
+    auto_tested_groups: HashSet<String>,
+    /// Proxies marked for comparison, at most two.
+    pub marked_proxies: Vec<String>,
+    /// The open comparison popup.
+    pub comparison: Option<Comparison>,
+    comparison_task: Option<tokio::task::JoinHandle<()>>,
+    /// Counts comparisons started, to tell their results apart.
+    comparison_run: u64,
+
+    pub pending_switches: HashMap<String, PendingSwitch>,
+    /// Earlier selections of each group, most recent last.
+    pub selection_history: HashMap<String, Vec<String>>,
+    /// The node each automatic group used before the core last moved it, and
+    /// when that change was noticed.
+    pub auto_switches: HashMap<String, (String, SystemTime)>,
+
+    /// Mutations that failed while the controller was unreachable.
+    pub mutation_queue: Vec<QueuedMutation>,
+    pub queue_state: ListState,
+    pub auto_retry: bool,
+
+    traffic_stream: Option<tokio::task::JoinHandle<()>>,
+    /// Latest core memory usage, once `/memory` has reported.
+    pub memory: Option<Memory>,
+    memory_stream: Option<tokio::task::JoinHandle<()>>,
+    /// Newest last, up to `LOG_LIMIT`.
+    pub logs: VecDeque<LogLine>,
+    /// Nothing selected means following the newest line.
+    pub logs_state: ListState,
+    /// Index into `LOG_LEVELS`.
+    pub logs_level: usize,
+    /// While paused, new lines wait in `logs_held` and the view stands still.
+    pub logs_paused: bool,
+    /// Lines that arrived while paused, up to `LOG_LIMIT`.
+    pub logs_held: VecDeque<LogLine>,
+    /// Held lines pushed out by newer ones during this pause.
+    pub logs_dropped: usize,
+    logs_stream: Option<tokio::task::JoinHandle<()>>,
+    /// Lookups of the fake-ip map being filled in.
+    fake_ip_lookups: Option<tokio::task::JoinHandle<()>>,
+    /// Whether this looks like an SSH session, for `LowPowerMode::Auto`.
+    remote_session: bool,
+    /// Set by the main loop while low-power mode has paused streaming.
+    pub idle: bool,
+    /// A bell the main loop still has to ring, per `alerts`.
+    pub bell_pending: bool,
+    /// While set and in the future, the screen is drawn inverted.
+    pub flash_until: Option<Instant>,
+    /// Render the compact `--mini` layout instead of the full UI.
+    pub mini: bool,
+    /// Characters typed since `'` in the Groups/Proxies lists, and when the last arrived.
+    type_ahead: Option<(String, Instant)>,
+    /// Query typed after `/` in the Proxies list; while set, only matching proxies are shown.
+    pub proxy_search: Option<String>,
+    /// The proxy selected when the search started, restored when it is cancelled.
+    proxy_search_origin: Option<String>,
+    /// When the last j/k arrived and how many came in quick succession.
+    scroll_streak: Option<(Instant, u32)>,
+    connections_stream: Option<tokio::task::JoinHandle<()>>,
+    /// True while the `/connections` WebSocket is connected; polling is the fallback.
+    pub connections_live: Arc<AtomicBool>,
+
+    pub traffic_history_up: VecDeque<u64>,
+    pub traffic_history_down: VecDeque<u64>,
+    /// Number of traffic samples received, used to place chart events.
+    pub traffic_samples: u64,
+    pub traffic_events: VecDeque<TrafficEvent>,
+    /// Bytes of the minute in progress, written out once it's over.
+    traffic_minute: Option<TrafficMinute>,
+    /// Set by the traffic monitor when its stream comes back after dropping.
+    traffic_reconnected: Arc<AtomicBool>,
+    pub current_up: u64,
+    pub current_down: u64,
+
+    pub connections: Vec<Connection>,
+    pub connection_stats: ConnectionStats,
+    pub connections_state: TableState,
+    pub active_connection_filters: BTreeSet<usize>,
+    /// Index into `connection_columns` of the set shown.
+    pub connection_columns: usize,
+    /// Per-connection download and upload rates since the previous snapshot.
+    pub connection_rates: HashMap<String, (u64, u64)>,
+    connections_at: Option<Instant>,
+    pub download_total: u64,
+    pub upload_total: u64,
+
+    /// Connection shown in the detail popup, with its sampled throughput (bytes/s).
+    pub detail_connection_id: Option<String>,
+    pub connection_rate_down: VecDeque<u64>,
+    pub connection_rate_up: VecDeque<u64>,
+    last_connection_sample: Option<(u64, u64, Instant)>,
+
+    pub rules: Vec<Rule>,
+    pub rules_state: TableState,
+    pub rules_query: String,
+    pub rule_providers: Vec<RuleProvider>,
+    pub rule_providers_state: TableState,
+    /// Preview of the selected rule provider's contents, keyed by provider name.
+    pub rule_provider_preview: Option<(String, Result<RuleSetPreview, String>)>,
+
+    pub group_names: Vec<String>,
+    pub group_state: ListState,
+    /// Groups opened through with Enter to reach the selected one, outermost
+    /// first; Esc in the Proxies list climbs back up.
+    pub group_trail: Vec<String>,
+    pub show_hidden_groups: bool,
+    /// Limits the proxy list to nodes from this provider.
+    pub provider_filter: Option<String>,
+    pub proxy_sort: ProxySort,
+    pub proxy_state: TableState,
+    pub focus: Focus,
+    pub previous_focus: Focus,
+    pub modal: Modal,
+    /// The notes popup is open and holds this controller's notes being edited.
+    pub notes_draft: Option<String>,
+    pub popup_scroll: u16,
+
+    pub capabilities: Capabilities,
+
+    pub api_log: ApiLog,
+    pub debug_state: TableState,
+
+    pub settings_items: Vec<ConfigEntry>,
+    pub settings_state: TableState,
+    pub is_editing: bool,
+    pub editing_value: String,
+
+    pub command_palette: bool,
+    /// The leader key was pressed; the next key picks a chord.
+    pub leader_pending: bool,
+    /// Groups and Proxies keys, from `keymap`.
+    pub keys: Keys,
+
+    pub hosts_state: ListState,
+    /// The hosts entry being edited, so renaming it drops the old domain.
+    pub editing_host: Option<String>,
+    pub profiles_state: ListState,
+
+    /// Oldest first, up to `NOTIFICATION_LIMIT`; the newest show as toasts.
+    pub notifications: VecDeque<Notification>,
+    notification_seq: u64,
+}
+
+impl App {
+    pub fn new(url_override: Option<String>, secret_override: Option<String>) -> Self {
+        let mut group_state = ListState::default();
+        let mut proxy_state = TableState::default();
+        group_state.select(Some(0));
+        proxy_state.select(Some(0));
+
+        let mut settings_state = TableState::default();
+        settings_state.select(Some(0));
+
+        let settings_items = vec![
+            ConfigEntry::BaseUrl,
+            ConfigEntry::ApiSecret,
+            ConfigEntry::TestUrl,
+            ConfigEntry::TestTimeout,
+            ConfigEntry::DelayTestConcurrency,
+            ConfigEntry::DelayTestTimeout,
+            ConfigEntry::LatencyMaxAge,
+            ConfigEntry::AutoRetestStale,
+            ConfigEntry::AutoTestGroups,
+            ConfigEntry::LatencyFormat,
+            ConfigEntry::LatencyPrecision,
+            ConfigEntry::LatencyGood,
+            ConfigEntry::LatencyMedium,
+            ConfigEntry::LatencyGaugeMin,
+            ConfigEntry::LatencyGaugeMax,
+            ConfigEntry::Layout,
+            ConfigEntry::Theme,
+            ConfigEntry::StartupActions,
+            ConfigEntry::OnStart,
+            ConfigEntry::LeaderKey,
+            ConfigEntry::LeaderChords,
+            ConfigEntry::AutoRefresh,
+            ConfigEntry::LowPower,
+            ConfigEntry::Alerts,
+            ConfigEntry::Accent,
+            ConfigEntry::Mode,
+            ConfigEntry::Tun,
+            ConfigEntry::MixedPort,
+            ConfigEntry::LogLevel,
+            ConfigEntry::AllowLan,
+            ConfigEntry::Authentication,
+            ConfigEntry::SkipAuthPrefixes,
+            ConfigEntry::BindAddress,
+            ConfigEntry::Ipv6,
+            ConfigEntry::Hosts,
+            ConfigEntry::DnsQuery,
+            ConfigEntry::CoreRestart,
+            ConfigEntry::CoreUpgrade,
+        ];
+
+        let mut app_settings = Self::load_app_settings();
+        if let Some(url) = url_override {
+            app_settings.base_url = url;
+        }
+        if let Some(secret) = secret_override {
+            app_settings.api_secret = secret;
+        }
+
+        let (events_tx, events_rx) = mpsc::channel(256);
+
+        let locale = app_settings.locale.clone().or_else(|| {
+            ["LC_ALL", "LC_NUMERIC", "LANG"]
+                .iter()
+                .filter_map(|var| std::env::var(var).ok())
+                .find(|value| !value.is_empty())
+        });
+        let decimal_separator = decimal_separator_for(locale.as_deref().unwrap_or("C"));
+
+        Self {
+            proxies: HashMap::new(),
+            config: None,
+            real_latency_status: RealLatencyStatus::Pending,
+            client: Client::builder().build().unwrap_or_default(),
+            app_settings,
+            decimal_separator,
+            controller_rtt: None,
+            core_operation: None,
+            latest_core_release: None,
+            latest_core_release_checked: None,
+            proxy_latency: HashMap::new(),
+            proxy_latency_at: HashMap::new(),
+            latency_history: HashMap::new(),
+            delay_failures: HashMap::new(),
+            config_hash: None,
+            config_drift: false,
+            load_errors: HashMap::new(),
+            loading: HashSet::new(),
+            jobs: Vec::new(),
+            events_tx,
+            events_rx,
+            staged_config: serde_json::Map::new(),
+            config_tree_state: ListState::default(),
+            config_tree_expanded: BTreeSet::new(),
+            smart_weights: HashMap::new(),
+            proxy_origin: HashMap::new(),
+            auto_tested_groups: HashSet::new(),
+            marked_proxies: Vec::new(),
+            comparison: None,
+            comparison_run: 0,
+            comparison_task: None,
+            pending_switches: HashMap::new(),
+            selection_history: HashMap::new(),
+            auto_switches: HashMap::new(),
+            proxy_providers: Vec::new(),
+            proxy_providers_state: TableState::default(),
+            provider_actions: HashMap::new(),
+            rule_provider_updates: HashSet::new(),
+            provider_update_results: HashMap::new(),
+            mutation_queue: Vec::new(),
+            queue_state: ListState::default(),
+            auto_retry: true,
+            traffic_stream: None,
+            memory: None,
+            memory_stream: None,
+            logs: VecDeque::new(),
+            logs_state: ListState::default(),
+            logs_level: 1,
+            logs_paused: false,
+            logs_held: VecDeque::new(),
+            logs_dropped: 0,
+            logs_stream: None,
+            fake_ip_lookups: None,
+            remote_session: std::env::var_os("SSH_CONNECTION").is_some()
+                || std::env::var_os("SSH_TTY").is_some(),
+            idle: false,
+            bell_pending: false,
+            flash_until: None,
+            mini: false,
+            type_ahead: None,
+            scroll_streak: None,
+            connections_stream: None,
+            connections_live: Arc::new(AtomicBool::new(false)),
+            traffic_history_up: VecDeque::from(vec![0; 1000]),
+            traffic_history_down: VecDeque::from(vec![0; 1000]),
+            traffic_minute: None,
+            traffic_samples: 0,
+            traffic_events: VecDeque::new(),
+            traffic_reconnected: Arc::new(AtomicBool::new(false)),
+            current_up: 0,
+            current_down: 0,
+            connections: Vec::new(),
+            connection_stats: ConnectionStats::default(),
+            connections_state: TableState::default(),
+            active_connection_filters: BTreeSet::new(),
+            connection_columns: 0,
+            connection_rates: HashMap::new(),
+            connections_at: None,
+            download_total: 0,
+            upload_total: 0,
+            detail_connection_id: None,
+            connection_rate_down: VecDeque::new(),
+            connection_rate_up: VecDeque::new(),
+            last_connection_sample: None,
+            rules: Vec::new(),
+            rules_state: TableState::default(),
+            rules_query: String::new(),
+            rule_providers: Vec::new(),
+            rule_providers_state: TableState::default(),
+            rule_provider_preview: None,
+            group_names: Vec::new(),
+            group_state,
+            group_trail: Vec::new(),
+            show_hidden_groups: false,
+            provider_filter: None,
+            proxy_sort: ProxySort::default(),
+            proxy_search: None,
+            proxy_search_origin: None,
+            proxy_state,
+            focus: Focus::Groups,
+            previous_focus: Focus::Groups,
+            modal: Modal::None,
+            notes_draft: None,
+            popup_scroll: 0,
+            capabilities: Capabilities::default(),
+            api_log: Arc::new(Mutex::new(VecDeque::with_capacity(API_LOG_CAPACITY))),
+            debug_state: TableState::default(),
+            settings_items,
+            settings_state,
+            is_editing: false,
+            editing_value: String::new(),
+            command_palette: false,
+            leader_pending: false,
+            keys: Keys::default(),
+            hosts_state: ListState::default(),
+            editing_host: None,
+            profiles_state: ListState::default(),
+            notifications: VecDeque::new(),
+            notification_seq: 0,
+        }
+    }
+
+    /// Probes every endpoint in `PROBED_ENDPOINTS` in the background. Once
+    /// the answers are in, the traffic monitor is started and `then` loaded,
+    /// so neither is decided by what the previous controller supported.
+    pub fn probe_capabilities(&mut self, then: Vec<Load>) {
+        let api = self.api().with_timeout(Duration::from_secs(3));
+        self.spawn_job(Job::Probe, probe(api), move |app, capabilities| {
+            app.capabilities = capabilities;
+            app.start_traffic_monitor();
+            for load in then {
+                app.load(load);
+            }
+        });
+    }
+
+    /// Starts following `/traffic` and `/memory`, unless already running.
+    pub fn start_traffic_monitor(&mut self) {
+        if self.traffic_stream.is_none() && self.capabilities.supports("/traffic") {
+            let reconnected = self.traffic_reconnected.clone();
+            let stream = self.spawn_stream("/traffic", AppEvent::Traffic, Some(reconnected));
+            self.traffic_stream = Some(stream);
+        }
+        if self.memory_stream.is_none() {
+            self.memory_stream = Some(self.spawn_stream("/memory", AppEvent::Memory, None));
+        }
+    }
+
+    /// Follows one of the core's streaming endpoints, sending each item as
+    /// `wrap(item)` and reconnecting with backoff until the receiver goes away.
+    /// `reconnected` is raised when the stream comes back after dropping.
+    fn spawn_stream<T: DeserializeOwned + Send + 'static>(
+        &self,
+        path: &str,
+        wrap: fn(T) -> AppEvent,
+        reconnected: Option<Arc<AtomicBool>>,
+    ) -> tokio::task::JoinHandle<()> {
+        let tx = self.events_tx.clone();
+        let api = self.api();
+        let path = path.to_string();
+
+        tokio::spawn(async move {
+            let mut backoff = Duration::from_secs(1);
+            let mut dropped = false;
+            loop {
+                let on_connect = || {
+                    if dropped && let Some(reconnected) = &reconnected {
+                        reconnected.store(true, Ordering::Relaxed);
+                    }
+                };
+                let end = match api.websocket_request(&path) {
+                    Some(request) => stream_websocket(request, &tx, wrap, on_connect).await,
+                    None => {
+                        let request = api.request(Method::GET, &path);
+                        stream_http_lines(request, &tx, wrap, on_connect).await
+                    }
+                };
+                match end {
+                    StreamEnd::ReceiverGone => return,
+                    StreamEnd::Dropped => {
+                        dropped = true;
+                        backoff = Duration::from_secs(1);
+                    }
+                    StreamEnd::Unreachable => {}
+                }
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(Duration::from_secs(30));
+            }
+        })
+    }
+
+    pub fn push_traffic_event(&mut self, kind: TrafficEventKind, label: String) {
+        if self.traffic_events.len() >= TRAFFIC_EVENT_LIMIT {
+            self.traffic_events.pop_front();
+        }
+        self.traffic_events.push_back(TrafficEvent {
+            sample: self.traffic_samples,
+            kind,
+            label,
+        });
+    }
+
+    pub fn on_real_latency(&mut self, status: RealLatencyStatus) {
+        match &status {
+            RealLatencyStatus::Success(ms) if *ms >= LATENCY_ALERT_MS => {
+                self.push_traffic_event(TrafficEventKind::LatencyAlert, format!("{} ms", ms));
+            }
+            RealLatencyStatus::Failed(msg) => {
+                self.push_traffic_event(TrafficEventKind::LatencyAlert, msg.clone());
+                self.raise_alert(format!("Connectivity test failed: {}", msg));
+            }
+            _ => {}
+        }
+        self.real_latency_status = status;
+    }
+
+    /// Records a controller ping. The controller going down is critical,
+    /// unless a restart or upgrade is expected to take it down.
+    pub fn on_controller_rtt(&mut self, rtt: Result<u64, String>) {
+        if let Err(e) = &rtt
+            && matches!(self.controller_rtt, Some(Ok(_)))
+            && !self.core_operation_waiting()
+        {
+            self.raise_alert(format!("Controller down: {}", e));
+        }
+        self.controller_rtt = Some(rtt);
+    }
+
+    pub fn info(&mut self, message: impl Into<String>) {
+        self.notify(NotificationLevel::Info, message.into());
+    }
+
+    pub fn warn(&mut self, message: impl Into<String>) {
+        self.notify(NotificationLevel::Warn, message.into());
+    }
+
+    pub fn error(&mut self, message: impl Into<String>) {
+        self.notify(NotificationLevel::Error, message.into());
+    }
+
+    fn notify(&mut self, level: NotificationLevel, message: String) {
+        self.notification_seq += 1;
+        let until = Instant::now() + level.toast_time();
+        if let Some(last) = self.notifications.back_mut()
+            && last.level == level
+            && last.message == message
+        {
+            last.count += 1;
+            last.at = SystemTime::now();
+            last.until = until;
+            last.seq = self.notification_seq;
+            return;
+        }
+        if self.notifications.len() == NOTIFICATION_LIMIT {
+            self.notifications.pop_front();
+        }
+        self.notifications.push_back(Notification {
+            level,
+            message,
+            at: SystemTime::now(),
+            count: 1,
+            until,
+            seq: self.notification_seq,
+        });
+    }
+
+    /// Notifications whose toast is still up, newest first.
+    pub fn toasts(&self) -> impl Iterator<Item = &Notification> {
+        let now = Instant::now();
+        self.notifications
+            .iter()
+            .rev()
+            .filter(move |n| n.until > now)
+            .take(TOAST_LIMIT)
+    }
+
+    /// Takes every toast down; they stay in the history.
+    pub fn dismiss_toasts(&mut self) {
+        let now = Instant::now();
+        for notification in &mut self.notifications {
+            notification.until = notification.until.min(now);
+        }
+    }
+
+    /// The history popup; its toasts are taken down, since it lists them.
+    pub fn open_notifications(&mut self) {
+        self.dismiss_toasts();
+        self.popup_scroll = 0;
+        self.modal = Modal::Notifications;
+    }
+
+    pub fn last_error(&self) -> Option<&str> {
+        self.notifications
+            .iter()
+            .rev()
+            .find(|n| n.level == NotificationLevel::Error)
+            .map(|n| n.message.as_str())
+    }
+
+    /// The sequence number of the newest notification, to find later ones
+    /// with `notifications_since`.
+    pub fn notification_mark(&self) -> u64 {
+        self.notification_seq
+    }
+
+    pub fn notifications_since(&self, mark: u64) -> impl Iterator<Item = &Notification> {
+        self.notifications.iter().filter(move |n| n.seq > mark)
+    }
+
+    /// Shows a critical event as an error and rings or flashes as the
+    /// `alerts` setting asks.
+    pub fn raise_alert(&mut self, message: String) {
+        self.error(message);
+        let style = self.app_settings.alerts;
+        if matches!(style, AlertStyle::Bell | AlertStyle::Both) {
+            self.bell_pending = true;
+        }
+        if matches!(style, AlertStyle::Flash | AlertStyle::Both) {
+            self.flash_until = Some(Instant::now() + ALERT_FLASH);
+        }
+    }
+
+    /// Follows `/logs` at the chosen level, unless already running.
+    pub fn start_logs_stream(&mut self) {
+        if self.logs_stream.is_none() && self.capabilities.supports("/logs") {
+            let path = format!("/logs?level={}", LOG_LEVELS[self.logs_level]);
+            self.logs_stream = Some(self.spawn_stream(&path, AppEvent::Log, None));
+        }
+    }
+
+    pub fn stop_logs_stream(&mut self) {
+        if let Some(handle) = self.logs_stream.take() {
+            handle.abort();
+        }
+    }
+
+    pub fn on_log(&mut self, line: LogLine) {
+        if self.logs_paused {
+            if self.logs_held.len() == LOG_LIMIT {
+                self.logs_held.pop_front();
+                self.logs_dropped += 1;
+            }
+            self.logs_held.push_back(line);
+            return;
+        }
+        if self.logs.len() == LOG_LIMIT {
+            self.logs.pop_front();
+            // Keep a scrolled-back selection on the same line
+            if let Some(i) = self.logs_state.selected() {
+                self.logs_state.select(Some(i.saturating_sub(1)));
+            }
+        }
+        self.logs.push_back(line);
+    }
+
+    /// Pauses the view, or resumes it with the lines held meanwhile.
+    pub fn toggle_logs_paused(&mut self) {
+        self.logs_paused = !self.logs_paused;
+        self.logs_dropped = 0;
+        if !self.logs_paused {
+            for line in std::mem::take(&mut self.logs_held) {
+                self.on_log(line);
+            }
+        }
+    }
+
+    /// Streams the next level; the main loop reconnects with it.
+    pub fn cycle_logs_level(&mut self) {
+        self.logs_level = (self.logs_level + 1) % LOG_LEVELS.len();
+        self.stop_logs_stream();
+        self.info(format!("Logs: {} and above", LOG_LEVELS[self.logs_level]));
+    }
+
+    pub fn next_log(&mut self) {
+        if let Some(i) = self.logs_state.selected() {
+            if i + 1 >= self.logs.len() {
+                self.logs_state.select(None);
+            } else {
+                self.logs_state.select(Some(i + 1));
+            }
+        }
+    }
+
+    pub fn previous_log(&mut self) {
+        let i = match self.logs_state.selected() {
+            Some(i) => i.saturating_sub(1),
+            None => self.logs.len().saturating_sub(1),
+        };
+        if !self.logs.is_empty() {
+            self.logs_state.select(Some(i));
+        }
+    }
+
+    pub fn stop_traffic_monitor(&mut self) {
+        if let Some(handle) = self.traffic_stream.take() {
+            handle.abort();
+        }
+        if let Some(handle) = self.memory_stream.take() {
+            handle.abort();
+        }
+    }
+
+    /// The colors of the theme picked in Settings.
+    pub fn theme(&self) -> &'static Theme {
+        self.app_settings.theme.theme()
+    }
+
+    /// How full the Overview's test gauge is for a delay, scaled linearly
+    /// between `latency_gauge_min` (full) and `latency_gauge_max` (empty).
+    pub fn latency_gauge_percent(&self, ms: u64) -> u16 {
+        let min = self.app_settings.latency_gauge_min;
+        let max = self.app_settings.latency_gauge_max.max(min + 1);
+        let ms = ms.clamp(min, max);
+        ((max - ms) * 100 / (max - min)) as u16
+    }
+
+    /// Where a delay falls against the `latency_good` and `latency_medium`
+    /// cutoffs; every view colors and grades delays by this.
+    pub fn latency_tier(&self, ms: u64) -> LatencyTier {
+        if ms < self.app_settings.latency_good {
+            LatencyTier::Fast
+        } else if ms < self.app_settings.latency_medium {
+            LatencyTier::Medium
+        } else {
+            LatencyTier::Slow
+        }
+    }
+
+    /// Border and status bar accent for the current controller, so different
+    /// backends are easy to tell apart.
+    pub fn accent(&self) -> Color {
+        self.accent_for(&self.app_settings.base_url)
+    }
+
+    /// The accent of any controller URL, e.g. for listing saved servers.
+    pub fn accent_for(&self, base_url: &str) -> Color {
+        if let Some(color) = self
+            .app_settings
+            .accent_colors
+            .get(base_url)
+            .and_then(|c| c.parse().ok())
+        {
+            return color;
+        }
+        let index = (stable_hash(base_url) % ACCENT_PALETTE.len() as u64) as usize;
+        ACCENT_PALETTE[index]
+    }
+
+    pub fn cycle_accent(&mut self) -> Result<()> {
+        let current = self.accent();
+        let index = ACCENT_PALETTE
+            .iter()
+            .position(|c| *c == current)
+            .map_or(0, |i| (i + 1) % ACCENT_PALETTE.len());
+        self.app_settings.accent_colors.insert(
+            self.app_settings.base_url.clone(),
+            ACCENT_PALETTE[index].to_string(),
+        );
+        self.save_app_settings()
+    }
+
+    pub fn open_notes(&mut self) {
+        let notes = self.app_settings.notes.get(&self.app_settings.base_url);
+        self.notes_draft = Some(notes.cloned().unwrap_or_default());
+    }
+
+    /// Closes the notes popup, saving the draft for the current controller.
+    pub fn close_notes(&mut self) -> Result<()> {
+        let Some(draft) = self.notes_draft.take() else {
+            return Ok(());
+        };
+        let url = self.app_settings.base_url.clone();
+        if draft.trim().is_empty() {
+            self.app_settings.notes.remove(&url);
+        } else {
+            self.app_settings.notes.insert(url, draft);
+        }
+        self.save_app_settings()
+    }
+
+    pub fn startup_actions_text(&self) -> String {
+        if self.app_settings.startup_actions.is_empty() {
+            return "none".to_string();
+        }
+        self.app_settings
+            .startup_actions
+            .iter()
+            .map(|a| a.name())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    pub fn on_start_text(&self) -> String {
+        if self.app_settings.on_start.is_empty() {
+            return "none".to_string();
+        }
+        self.app_settings.on_start.join(", ")
+    }
+
+    pub fn leader_chords_text(&self) -> String {
+        if self.app_settings.leader_chords.is_empty() {
+            return "none".to_string();
+        }
+        self.app_settings
+            .leader_chords
+            .iter()
+            .map(|(key, action)| format!("{}={}", key, action.name()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Whether one of the tabbed views has the keyboard, with no popup,
+    /// search or type-ahead taking the keys. Only then do the leader key and
+    /// the tab keys work.
+    pub fn view_has_keys(&self) -> bool {
+        Tab::of(&self.focus).is_some()
+            && matches!(self.modal, Modal::None)
+            && self.comparison.is_none()
+            && self.detail_connection_id.is_none()
+            && self.type_ahead_text().is_none()
+            && self.proxy_search.is_none()
+    }
+
+    /// Delay-tests every group in the Groups list.
+    pub fn test_all_groups(&self) {
+        for group in &self.group_names {
+            self.test_group(group);
+        }
+    }
+
+    pub fn low_power_active(&self) -> bool {
+        match self.app_settings.low_power {
+            LowPowerMode::Auto => self.remote_session,
+            LowPowerMode::On => true,
+            LowPowerMode::Off => false,
+        }
+    }
+
+    /// Subscribes to the `/connections` WebSocket, which pushes a snapshot every
+    /// second, reconnecting with backoff until stopped.
+    pub fn start_connections_stream(&mut self) {
+        if self.connections_stream.is_some() || !self.capabilities.supports("/connections") {
+            return;
+        }
+        let api = self.api();
+        if api.websocket_request("/connections").is_none() {
+            return;
+        }
+
+        let tx = self.events_tx.clone();
+        let live = self.connections_live.clone();
+
+        self.connections_stream = Some(tokio::spawn(async move {
+            use tokio_tungstenite::tungstenite::Message;
+
+            let mut backoff = Duration::from_secs(1);
+            loop {
+                let Some(request) = api.websocket_request("/connections") else {
+                    return;
+                };
+
+                if let Ok((mut socket, _)) = tokio_tungstenite::connect_async(request).await {
+                    live.store(true, Ordering::Relaxed);
+                    backoff = Duration::from_secs(1);
+                    while let Some(Ok(message)) = socket.next().await {
+                        let Message::Text(text) = message else {
+                            continue;
+                        };
+                        if let Ok(data) = serde_json::from_str::<ConnectionsResponse>(&text)
+                            && tx.send(AppEvent::Connections(data)).await.is_err()
+                        {
+                            live.store(false, Ordering::Relaxed);
+                            return;
+                        }
+                    }
+                    live.store(false, Ordering::Relaxed);
+                }
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(Duration::from_secs(30));
+            }
+        }));
+    }
+
+    /// The saved profile for the controller in use, if any.
+    pub fn active_profile(&self) -> Option<&Profile> {
+        self.app_settings
+            .profiles
+            .iter()
+            .find(|p| p.base_url == self.app_settings.base_url)
+    }
+
+    pub fn open_profiles(&mut self) {
+        self.modal = Modal::Profiles;
+        let active = self
+            .app_settings
+            .profiles
+            .iter()
+            .position(|p| p.base_url == self.app_settings.base_url);
+        let first = (!self.app_settings.profiles.is_empty()).then_some(0);
+        self.profiles_state.select(active.or(first));
+    }
+
+    /// Saves the controller in use as a profile called `name`, or renames its
+    /// existing profile.
+    pub fn save_current_profile(&mut self, name: &str) -> Result<()> {
+        let name = name.trim();
+        if name.is_empty() {
+            anyhow::bail!("Profile name is empty");
+        }
+        let settings = &mut self.app_settings;
+        match settings
+            .profiles
+            .iter_mut()
+            .find(|p| p.base_url == settings.base_url)
+        {
+            Some(profile) => {
+                profile.name = name.to_string();
+                profile.api_secret = settings.api_secret.clone();
+            }
+            None => settings.profiles.push(Profile {
+                name: name.to_string(),
+                base_url: settings.base_url.clone(),
+                api_secret: settings.api_secret.clone(),
+                pinned_groups: settings.pinned_groups.clone(),
+                hidden_groups: settings.hidden_groups.clone(),
+                group_hotkeys: settings.group_hotkeys.clone(),
+                locked_groups: settings.locked_groups.clone(),
+            }),
+        }
+        let index = settings
+            .profiles
+            .iter()
+            .position(|p| p.base_url == settings.base_url);
+        self.profiles_state.select(index);
+        self.save_app_settings()
+    }
+
+    pub fn remove_selected_profile(&mut self) -> Result<()> {
+        let Some(index) = self
+            .profiles_state
+            .selected()
+            .filter(|i| *i < self.app_settings.profiles.len())
+        else {
+            return Ok(());
+        };
+        self.app_settings.profiles.remove(index);
+        let len = self.app_settings.profiles.len();
+        self.profiles_state
+            .select(len.checked_sub(1).map(|last| index.min(last)));
+        self.save_app_settings()
+    }
+
+    pub fn next_profile(&mut self) {
+        let len = self.app_settings.profiles.len();
+        if len == 0 {
+            return;
+        }
+        let i = match self.profiles_state.selected() {
+            Some(i) if i + 1 < len => i + 1,
+            _ => 0,
+        };
+        self.profiles_state.select(Some(i));
+    }
+
+    pub fn previous_profile(&mut self) {
+        let len = self.app_settings.profiles.len();
+        if len == 0 {
+            return;
+        }
+        let i = match self.profiles_state.selected() {
+            Some(0) | None => len - 1,
+            Some(i) => i - 1,
+        };
+        self.profiles_state.select(Some(i));
+    }
+
+    /// Points the app at the selected profile's controller. Everything loaded
+    /// from the previous one is dropped, including changes still queued for it;
+    /// its pinned and hidden groups are kept with its own profile.
+    pub fn switch_to_selected_profile(&mut self) -> Result<()> {
+        let Some(profile) = self
+            .profiles_state
+            .selected()
+            .and_then(|i| self.app_settings.profiles.get(i))
+            .cloned()
+        else {
+            return Ok(());
+        };
+        if profile.base_url == self.app_settings.base_url
+            && profile.api_secret == self.app_settings.api_secret
+        {
+            return Ok(());
+        }
+        self.save_latency_history()?;
+        self.flush_traffic_history()?;
+        let settings = &mut self.app_settings;
+        if let Some(current) = settings
+            .profiles
+            .iter_mut()
+            .find(|p| p.base_url == settings.base_url)
+        {
+            current.pinned_groups = settings.pinned_groups.clone();
+            current.hidden_groups = settings.hidden_groups.clone();
+            current.group_hotkeys = settings.group_hotkeys.clone();
+            current.locked_groups = settings.locked_groups.clone();
+        }
+        settings.base_url = profile.base_url;
+        settings.api_secret = profile.api_secret;
+        settings.pinned_groups = profile.pinned_groups;
+        settings.hidden_groups = profile.hidden_groups;
+        settings.group_hotkeys = profile.group_hotkeys;
+        settings.locked_groups = profile.locked_groups;
+        self.save_app_settings()?;
+
+        self.stop_connections_stream();
+        self.stop_traffic_monitor();
+        self.stop_logs_stream();
+        self.logs.clear();
+        self.logs_state.select(None);
+        self.stop_comparison();
+        self.proxies.clear();
+        self.group_names.clear();
+        self.group_state.select(None);
+        self.proxy_state.select(None);
+        self.provider_filter = None;
+        self.proxy_latency.clear();
+        self.proxy_latency_at.clear();
+        self.latency_history.clear();
+        self.delay_failures.clear();
+        self.load_latency_history();
+        self.proxy_origin.clear();
+        self.smart_weights.clear();
+        self.auto_tested_groups.clear();
+        self.marked_proxies.clear();
+        self.pending_switches.clear();
+        self.selection_history.clear();
+        self.mutation_queue.clear();
+        self.config = None;
+        self.config_hash = None;
+        self.config_drift = false;
+        self.staged_config.clear();
+        self.load_errors.clear();
+        self.connections.clear();
+        self.connection_stats = ConnectionStats::default();
+        self.rules.clear();
+        self.rule_providers.clear();
+        self.proxy_providers.clear();
+        self.memory = None;
+        self.controller_rtt = None;
+        self.core_operation = None;
+        self.traffic_history_up = VecDeque::from(vec![0; 1000]);
+        self.traffic_history_down = VecDeque::from(vec![0; 1000]);
+        self.traffic_samples = 0;
+        self.traffic_events.clear();
+        self.dismiss_toasts();
+        self.focus = Focus::Groups;
+        self.info(format!("Switched to {}", profile.name));
+
+        self.probe_capabilities(vec![Load::Proxies, Load::ProxyProviders, Load::Config]);
+        Ok(())
+    }
+
+    pub fn stop_connections_stream(&mut self) {
+        if let Some(handle) = self.connections_stream.take() {
+            handle.abort();
+        }
+        self.connections_live.store(false, Ordering::Relaxed);
+    }
+
+    pub fn on_traffic(&mut self, traffic: Traffic) {
+        if self.traffic_reconnected.swap(false, Ordering::Relaxed) {
+            // A drop may be the network as much as the core, so it is not
+            // called a restart
+            self.push_traffic_event(TrafficEventKind::Reconnect, "reconnect".to_string());
+        }
+        self.traffic_samples += 1;
+        self.current_up = traffic.up;
+        self.current_down = traffic.down;
+
+        self.traffic_history_up.pop_front();
+        self.traffic_history_up.push_back(traffic.up);
+
+        self.traffic_history_down.pop_front();
+        self.traffic_history_down.push_back(traffic.down);
+
+        // The core reports bytes per second, once a second
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let start = now - now % 60;
+        match &mut self.traffic_minute {
+            Some(minute) if minute.start == start => {
+                minute.up += traffic.up;
+                minute.down += traffic.down;
+            }
+            _ => {
+                let done = self.traffic_minute.replace(TrafficMinute {
+                    start,
+                    up: traffic.up,
+                    down: traffic.down,
+                });
+                if let Some(done) = done
+                    && let Err(e) = self.append_traffic_minute(done)
+                {
+                    self.error(format!("Save traffic history: {:#}", e));
+                }
+            }
+        }
+    }
+
+    fn append_traffic_minute(&self, minute: TrafficMinute) -> Result<()> {
+        let Some(dir) = self.data_dir() else {
+            return Ok(());
+        };
+        if minute.up == 0 && minute.down == 0 {
+            return Ok(());
+        }
+        fs::create_dir_all(&dir)?;
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(TRAFFIC_HISTORY_FILE))?;
+        writeln!(
+            file,
+            "{}\t{}\t{}\t{}",
+            minute.start, minute.up, minute.down, self.app_settings.base_url
+        )?;
+        Ok(())
+    }
+
+    /// Writes out the minute in progress, e.g. before quitting.
+    pub fn flush_traffic_history(&mut self) -> Result<()> {
+        match self.traffic_minute.take() {
+            Some(minute) => self.append_traffic_minute(minute),
+            None => Ok(()),
+        }
+    }
+
+    /// Stored minutes of every controller, oldest first. Lines that don't
+    /// parse are skipped.
+    fn read_traffic_history(&self) -> Vec<(TrafficMinute, String)> {
+        let Some(content) = self
+            .data_dir()
+            .and_then(|dir| fs::read_to_string(dir.join(TRAFFIC_HISTORY_FILE)).ok())
+        else {
+            return Vec::new();
+        };
+        content
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.splitn(4, '\t');
+                let minute = TrafficMinute {
+                    start: fields.next()?.parse().ok()?,
+                    up: fields.next()?.parse().ok()?,
+                    down: fields.next()?.parse().ok()?,
+                };
+                Some((minute, fields.next()?.to_string()))
+            })
+            .collect()
+    }
+
+    /// Drops minutes older than `TRAFFIC_HISTORY_KEEP`, rewriting the file
+    /// only when there are any.
+    pub fn prune_traffic_history(&self) -> Result<()> {
+        let Some(dir) = self.data_dir() else {
+            return Ok(());
+        };
+        let cutoff = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs())
+            .saturating_sub(TRAFFIC_HISTORY_KEEP.as_secs());
+        let stored = self.read_traffic_history();
+        if stored.iter().all(|(minute, _)| minute.start >= cutoff) {
+            return Ok(());
+        }
+        let mut content = String::new();
+        for (minute, url) in stored.iter().filter(|(minute, _)| minute.start >= cutoff) {
+            content.push_str(&format!(
+                "{}\t{}\t{}\t{}\n",
+                minute.start, minute.up, minute.down, url
+            ));
+        }
+        fs::write(dir.join(TRAFFIC_HISTORY_FILE), content)?;
+        Ok(())
+    }
+
+    /// Notes the running version in the data directory and, when another
+    /// version ran last, shows what changed since. A first run has nothing to
+    /// compare with, so it shows nothing.
+    pub fn check_upgrade(&mut self) -> Result<()> {
+        let Some(dir) = self.data_dir() else {
+            return Ok(());
+        };
+        let path = dir.join(LAST_VERSION_FILE);
+        let current = env!("CARGO_PKG_VERSION");
+        let previous = fs::read_to_string(&path).ok();
+        let previous = previous.as_deref().map(str::trim);
+        if previous == Some(current) {
+            return Ok(());
+        }
+        fs::create_dir_all(&dir)?;
+        fs::write(&path, current)?;
+        if let Some(previous) = previous
+            && !changelog::since(previous).is_empty()
+        {
+            self.popup_scroll = 0;
+            self.modal = Modal::Changelog(previous.to_string());
+        }
+        Ok(())
+    }
+
+    /// Opens the traffic history popup on `range`, or switches it there.
+    pub fn open_traffic_history(&mut self, range: TrafficRange) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let since = now.saturating_sub(range.span().as_secs());
+        let mut minutes: Vec<TrafficMinute> = self
+            .read_traffic_history()
+            .into_iter()
+            .filter(|(minute, url)| *url == self.app_settings.base_url && minute.start >= since)
+            .map(|(minute, _)| minute)
+            .collect();
+        minutes.extend(self.traffic_minute);
+        self.modal = Modal::TrafficHistory(TrafficHistory {
+            range,
+            since,
+            minutes,
+        });
+    }
+
+    fn get_config_path() -> Option<PathBuf> {
+        if let Ok(home) = std::env::var("HOME") {
+            let mut path = PathBuf::from(home);
+            path.push(".config");
+            path.push("mihomot");
+            let _ = fs::create_dir_all(&path);
+            path.push("settings.json");
+            Some(path)
+        } else {
+            None
+        }
+    }
+
+    /// `data_dir` if set, else `$XDG_DATA_HOME/mihomot`, else
+    /// `~/.local/share/mihomot`.
+    pub fn data_dir(&self) -> Option<PathBuf> {
+        if let Some(dir) = &self.app_settings.data_dir {
+            return Some(PathBuf::from(dir));
+        }
+        let base = match std::env::var_os("XDG_DATA_HOME").filter(|dir| !dir.is_empty()) {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(std::env::var_os("HOME")?)
+                .join(".local")
+                .join("share"),
+        };
+        Some(base.join("mihomot"))
+    }
+
+    fn read_stored_latency_history(&self) -> StoredLatencyHistory {
+        self.data_dir()
+            .and_then(|dir| fs::read_to_string(dir.join(LATENCY_HISTORY_FILE)).ok())
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    /// Restores the delay history saved for the current controller.
+    pub fn load_latency_history(&mut self) {
+        let Some(proxies) = self
+            .read_stored_latency_history()
+            .remove(&self.app_settings.base_url)
+        else {
+            return;
+        };
+        self.latency_history = proxies
+            .into_iter()
+            .map(|(name, entries)| {
+                let entries = entries
+                    .into_iter()
+                    .map(|(secs, delay)| (UNIX_EPOCH + Duration::from_secs(secs), delay))
+                    .collect();
+                (name, entries)
+            })
+            .collect();
+    }
+
+    /// Saves the current controller's delay history, keeping other controllers'.
+    pub fn save_latency_history(&self) -> Result<()> {
+        let Some(dir) = self.data_dir() else {
+            return Ok(());
+        };
+        if self.latency_history.is_empty() {
+            return Ok(());
+        }
+        let mut stored = self.read_stored_latency_history();
+        let proxies = self
+            .latency_history
+            .iter()
+            .map(|(name, entries)| {
+                let entries = entries
+                    .iter()
+                    .map(|(at, delay)| {
+                        let secs = at.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+                        (secs, *delay)
+                    })
+                    .collect();
+                (name.clone(), entries)
+            })
+            .collect();
+        stored.insert(self.app_settings.base_url.clone(), proxies);
+        fs::create_dir_all(&dir)?;
+        fs::write(
+            dir.join(LATENCY_HISTORY_FILE),
+            serde_json::to_string(&stored)?,
+        )?;
+        Ok(())
+    }
+
+    fn load_app_settings() -> AppSettings {
+        if let Some(path) = Self::get_config_path()
+            && path.exists()
+            && let Ok(content) = fs::read_to_string(path)
+        {
+            return serde_json::from_str(&content).unwrap_or_default();
+        }
+        AppSettings::default()
+    }
+
+    /// Applies the `keymap` setting, warning about keys that don't parse.
+    pub fn load_keymap(&mut self) {
+        let (keys, problems) = Keys::new(&self.app_settings.keymap);
+        self.keys = keys;
+        for problem in problems {
+            self.warn(problem);
+        }
+    }
+
+    pub fn save_app_settings(&self) -> Result<()> {
+        if let Some(path) = Self::get_config_path() {
+            let json = serde_json::to_string_pretty(&self.app_settings)?;
+            fs::write(path, json)?;
+        }
+        Ok(())
+    }
+
+    pub fn scroll_popup_down(&mut self) {
+        self.popup_scroll = self.popup_scroll.saturating_add(1);
+    }
+
+    pub fn scroll_popup_up(&mut self) {
+        self.popup_scroll = self.popup_scroll.saturating_sub(1);
+    }
+
+    /// Nudges the selected numeric entry by one step up or down. App settings
+    /// are saved right away; the mixed port is staged like any core edit.
+    pub fn step_selected_setting(&mut self, up: bool) -> Result<()> {
+        let Some(entry) = self
+            .settings_state
+            .selected()
+            .and_then(|i| self.settings_entries().get(i).cloned())
+        else {
+            return Ok(());
+        };
+        let Some(step) = entry.step() else {
+            return Ok(());
+        };
+        let nudge = |value: u64, min: u64| {
+            if up {
+                value.saturating_add(step)
+            } else {
+                value.saturating_sub(step).max(min)
+            }
+        };
+        let settings = &mut self.app_settings;
+        match entry {
+            ConfigEntry::MixedPort => {
+                let port = self
+                    .effective_config()
+                    .ok_or_else(|| anyhow::anyhow!("Config not loaded"))?
+                    .mixed_port;
+                let port = nudge(port as u64, 1).min(u16::MAX as u64);
+                self.stage_config(serde_json::json!({ "mixed-port": port }));
+                return Ok(());
+            }
+            ConfigEntry::TestTimeout => settings.test_timeout = nudge(settings.test_timeout, step),
+            ConfigEntry::DelayTestConcurrency => {
+                settings.delay_test_concurrency = nudge(settings.delay_test_concurrency as u64, 1)
+                    .try_into()
+                    .unwrap_or(usize::MAX);
+            }
+            ConfigEntry::DelayTestTimeout => {
+                settings.delay_test_timeout = nudge(settings.delay_test_timeout, step)
+            }
+            ConfigEntry::LatencyMaxAge => {
+                settings.latency_max_age = nudge(settings.latency_max_age, 0)
+            }
+            ConfigEntry::LatencyPrecision => {
+                settings.latency_precision = nudge(settings.latency_precision, 1)
+            }
+            // The cutoffs can't pass each other
+            ConfigEntry::LatencyGood => {
+                settings.latency_good =
+                    nudge(settings.latency_good, 1).min(settings.latency_medium.saturating_sub(1))
+            }
+            ConfigEntry::LatencyMedium => {
+                settings.latency_medium = nudge(settings.latency_medium, settings.latency_good + 1)
+            }
+            ConfigEntry::LatencyGaugeMin => {
+                settings.latency_gauge_min = nudge(settings.latency_gauge_min, 0)
+                    .min(settings.latency_gauge_max.saturating_sub(1))
+            }
+            ConfigEntry::LatencyGaugeMax => {
+                settings.latency_gauge_max =
+                    nudge(settings.latency_gauge_max, settings.latency_gauge_min + 1)
+            }
+            ConfigEntry::AutoRefresh => settings.auto_refresh = nudge(settings.auto_refresh, 0),
+            _ => return Ok(()),
+        }
+        self.save_app_settings()
+    }
+
+    /// The Settings rows to show: LAN-only entries need `allow-lan`, and
+    /// tools the core is too old for are left out.
+    pub fn settings_entries(&self) -> Vec<ConfigEntry> {
+        let allow_lan = self.effective_config().is_some_and(|c| c.allow_lan);
+        self.settings_items
+            .iter()
+            .filter(|entry| match entry {
+                ConfigEntry::Authentication | ConfigEntry::SkipAuthPrefixes => allow_lan,
+                ConfigEntry::DnsQuery => self.capabilities.supports_feature(CoreFeature::DnsQuery),
+                ConfigEntry::CoreUpgrade => {
+                    self.capabilities.supports_feature(CoreFeature::Upgrade)
+                }
+                _ => true,
+            })
+            .cloned()
+            .collect()
+    }
+
+    pub fn next_setting(&mut self) {
+        let len = self.settings_entries().len();
+        let i = match self.settings_state.selected() {
+            Some(i) => {
+                if i >= len - 1 {
+                    0
+                } else {
+                    i + 1
+                }
+            }
+            None => 0,
+        };
+        self.settings_state.select(Some(i));
+    }
+
+    pub fn previous_setting(&mut self) {
+        let i = match self.settings_state.selected() {
+            Some(i) => {
+                if i == 0 {
+                    self.settings_entries().len() - 1
+                } else {
+                    i - 1
+                }
+            }
+            None => 0,
+        };
+        self.settings_state.select(Some(i));
+    }
+
+    pub async fn update_config(&mut self, json_body: serde_json::Value) -> Result<()> {
+        if !self.capabilities.supports("/configs") {
+            return Ok(());
+        }
+        let mutation = Mutation::PatchConfig(json_body);
+        match mutation.send(&self.api()).await {
+            Ok(()) => {}
+            Err(e) if e.is_offline() => {
+                self.queue_mutation(mutation, e.to_string());
+                return Ok(());
+            }
+            Err(e) => return Err(e.into()),
+        }
+        // Fetch updated config to sync UI
+        self.fetch_config().await?;
+        Ok(())
+    }
+
+    /// Merges `patch` into the staged edits. Values that end up matching the
+    /// loaded config are dropped, so toggling twice leaves nothing to apply.
+    pub fn stage_config(&mut self, patch: serde_json::Value) {
+        let serde_json::Value::Object(mut patch) = patch else {
+            return;
+        };
+        let mut staged = std::mem::take(&mut self.staged_config);
+        let mut whole = serde_json::Map::new();
+        for key in WHOLE_CONFIG_KEYS {
+            if let Some(value) = patch.remove(key).or_else(|| staged.remove(key)) {
+                whole.insert(key.to_string(), value);
+            }
+        }
+        let mut staged = serde_json::Value::Object(staged);
+        merge_json(&mut staged, serde_json::Value::Object(patch));
+        let current = self
+            .config
+            .as_ref()
+            .and_then(|c| serde_json::to_value(c).ok());
+        if let Some(current) = &current {
+            prune_unchanged(&mut staged, current);
+        }
+        if let serde_json::Value::Object(mut staged) = staged {
+            for (key, value) in whole {
+                if current.as_ref().and_then(|c| c.get(&key)) != Some(&value) {
+                    staged.insert(key, value);
+                }
+            }
+            self.staged_config = staged;
+        }
+    }
+
+    /// The loaded config with staged edits applied, for display and cycling.
+    pub fn effective_config(&self) -> Option<Config> {
+        let config = self.config.as_ref()?;
+        if self.staged_config.is_empty() {
+            return Some(config.clone());
+        }
+        let mut value = serde_json::to_value(config).ok()?;
+        if let serde_json::Value::Object(map) = &mut value {
+            map.retain(|key, _| {
+                !(WHOLE_CONFIG_KEYS.contains(&key.as_str()) && self.staged_config.contains_key(key))
+            });
+        }
+        merge_json(
+            &mut value,
+            serde_json::Value::Object(self.staged_config.clone()),
+        );
+        serde_json::from_value(value).ok()
+    }
+
+    /// Staged edits as `(key, current, new)` rows for the review popup.
+    pub fn staged_changes(&self) -> Vec<(String, String, String)> {
+        let current = self
+            .config
+            .as_ref()
+            .and_then(|c| serde_json::to_value(c).ok())
+            .unwrap_or_default();
+        let mut rows = Vec::new();
+        collect_changes(
+            "",
+            &serde_json::Value::Object(self.staged_config.clone()),
+            &current,
+            &mut rows,
+        );
+        // Entries a whole map leaves out are removed on apply
+        for key in WHOLE_CONFIG_KEYS {
+            let (Some(staged), Some(serde_json::Value::Object(existing))) =
+                (self.staged_config.get(key), current.get(key))
+            else {
+                continue;
+            };
+            for (entry, value) in existing {
+                if staged.get(entry).is_none() {
+                    rows.push((
+                        format!("{}.{}", key, entry),
+                        value.to_string(),
+                        "-".to_string(),
+                    ));
+                }
+            }
+        }
+        rows
+    }
+
+    /// Sends the staged edits in the background, then reads the config back.
+    /// Edits the controller rejects stay staged; unreachable ones are queued.
+    pub fn apply_staged_config(&mut self) {
+        if self.staged_config.is_empty() || self.jobs.contains(&Job::ApplyConfig) {
+            return;
+        }
+        let patch = std::mem::take(&mut self.staged_config);
+        if !self.capabilities.supports("/configs") {
+            return;
+        }
+        let mutation = Mutation::PatchConfig(serde_json::Value::Object(patch.clone()));
+        let api = self.api();
+        let work = async move {
+            let result = match mutation.send(&api).await {
+                Ok(()) => Ok(api.config().await),
+                Err(e) => Err(e),
+            };
+            (mutation, result)
+        };
+        self.spawn_job(Job::ApplyConfig, work, move |app, (mutation, result)| {
+            match result.map(|config| app.apply_config(config)) {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => {
+                    app.error(format!("Fetch config: {:#}", e));
+                    return;
+                }
+                Err(e) if e.is_offline() => {
+                    app.queue_mutation(mutation, e.to_string());
+                    return;
+                }
+                Err(e) => {
+                    // Keep the edits so they can be reviewed and retried,
+                    // behind any staged since
+                    for (key, value) in patch {
+                        app.staged_config.entry(key).or_insert(value);
+                    }
+                    app.error(format!("Apply settings: {}", e));
+                    return;
+                }
+            }
+            // Not every core takes hosts over the API, so check the fresh config
+            if let Some(hosts) = patch.get("hosts")
+                && let Some(config) = &app.config
+                && serde_json::to_value(&config.hosts).ok().as_ref() != Some(hosts)
+            {
+                app.warn("The core didn't take the hosts change; set it in its config file");
+            }
+        });
+    }
+
+    /// Visible rows of the config browser; objects start collapsed.
+    pub fn config_tree_rows(&self) -> Vec<ConfigTreeRow> {
+        let mut rows = Vec::new();
+        if let Some(value) = self
+            .config
+            .as_ref()
+            .and_then(|c| serde_json::to_value(c).ok())
+        {
+            push_tree_rows(&value, "", 0, &self.config_tree_expanded, &mut rows);
+        }
+        rows
+    }
+
+    pub fn open_config_tree(&mut self) {
+        self.modal = Modal::ConfigTree;
+        if self.config_tree_state.selected().is_none() {
+            self.config_tree_state.select(Some(0));
+        }
+    }
+
+    pub fn toggle_config_tree_node(&mut self) {
+        let rows = self.config_tree_rows();
+        let Some(row) = self.config_tree_state.selected().and_then(|i| rows.get(i)) else {
+            return;
+        };
+        if row.value.is_some() {
+            return;
+        }
+        if !self.config_tree_expanded.remove(&row.path) {
+            self.config_tree_expanded.insert(row.path.clone());
+        }
+    }
+
+    pub fn next_config_tree_row(&mut self) {
+        let len = self.config_tree_rows().len();
+        if len == 0 {
+            return;
+        }
+        let i = match self.config_tree_state.selected() {
+            Some(i) => (i + 1) % len,
+            None => 0,
+        };
+        self.config_tree_state.select(Some(i));
+    }
+
+    pub fn previous_config_tree_row(&mut self) {
+        let len = self.config_tree_rows().len();
+        if len == 0 {
+            return;
+        }
+        let i = match self.config_tree_state.selected() {
+            Some(0) | None => len - 1,
+            Some(i) => i - 1,
+        };
+        self.config_tree_state.select(Some(i));
+    }
+
+    /// The core serves the configured external UI under `/ui` on the controller.
+    pub fn dashboard_url(&self) -> Option<String> {
+        self.config
+            .as_ref()
+            .and_then(|c| c.external_ui.as_ref())
+            .filter(|ui| !ui.is_empty())
+            .map(|_| format!("{}/ui/", self.app_settings.base_url.trim_end_matches('/')))
+    }
+
+    /// Hosts overrides with staged edits applied.
+    pub fn host_entries(&self) -> Vec<(String, String)> {
+        let Some(config) = self.effective_config() else {
+            return Vec::new();
+        };
+        config
+            .hosts
+            .iter()
+            .map(|(domain, value)| {
+                let target = match value {
+                    serde_json::Value::String(ip) => ip.clone(),
+                    serde_json::Value::Array(ips) => ips
+                        .iter()
+                        .filter_map(|ip| ip.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    other => other.to_string(),
+                };
+                (domain.clone(), target)
+            })
+            .collect()
+    }
+
+    /// Stages a new or edited hosts entry from `domain=ip[,ip...]`. An edit
+    /// that changes the domain replaces the old entry.
+    pub fn add_host(&mut self, line: &str) -> Result<()> {
+        let renamed = self.editing_host.take();
+        let Some((domain, ips)) = line.split_once('=') else {
+            self.error("Expected domain=ip".to_string());
+            return Ok(());
+        };
+        let domain = domain.trim();
+        let ips: Vec<&str> = ips
+            .split(',')
+            .map(str::trim)
+            .filter(|ip| !ip.is_empty())
+            .collect();
+        let value = match ips.as_slice() {
+            [] => {
+                self.error("Expected domain=ip".to_string());
+                return Ok(());
+            }
+            [ip] => serde_json::json!(ip),
+            _ => serde_json::json!(ips),
+        };
+
+        let mut hosts = self.effective_config().map(|c| c.hosts).unwrap_or_default();
+        if let Some(old) = renamed {
+            hosts.remove(&old);
+        }
+        hosts.insert(domain.to_string(), value);
+        self.stage_config(serde_json::json!({ "hosts": hosts }));
+        Ok(())
+    }
+
+    pub fn remove_selected_host(&mut self) -> Result<()> {
+        let Some(mut hosts) = self.effective_config().map(|c| c.hosts) else {
+            return Ok(());
+        };
+        let Some(domain) = self
+            .hosts_state
+            .selected()
+            .and_then(|i| hosts.keys().nth(i).cloned())
+        else {
+            return Ok(());
+        };
+        hosts.remove(&domain);
+        let len = hosts.len();
+        self.stage_config(serde_json::json!({ "hosts": hosts }));
+
+        self.hosts_state.select(
+            len.checked_sub(1)
+                .map(|last| self.hosts_state.selected().unwrap_or(0).min(last)),
+        );
+        Ok(())
+    }
+
+    pub fn next_host(&mut self) {
+        let len = self.host_entries().len();
+        if len == 0 {
+            return;
+        }
+        let i = match self.hosts_state.selected() {
+            Some(i) if i + 1 < len => i + 1,
+            _ => 0,
+        };
+        self.hosts_state.select(Some(i));
+    }
+
+    pub fn previous_host(&mut self) {
+        let len = self.host_entries().len();
+        if len == 0 {
+            return;
+        }
+        let i = match self.hosts_state.selected() {
+            Some(0) | None => len - 1,
+            Some(i) => i - 1,
+        };
+        self.hosts_state.select(Some(i));
+    }
+
+    pub async fn fetch_proxies(&mut self) -> Result<()> {
+        self.fetch(Load::Proxies).await?;
+        self.fetch_smart_weights().await;
+        Ok(())
+    }
+
+    /// Takes in a `/proxies` answer. The proxy cursor stays on the same
+    /// proxy, since groups may have gained or lost members.
+    fn apply_proxies(&mut self, proxies: HashMap<String, ProxyItem>) {
+        let selected_proxy = self.get_selected_proxy_name();
+        // Automatic groups move on their own; note where they came from
+        for (name, item) in &proxies {
+            if item
+                .proxy_type
+                .as_deref()
+                .and_then(auto_group_kind)
+                .is_none()
+            {
+                continue;
+            }
+            let previous = self.proxies.get(name).and_then(|p| p.now.clone());
+            if let (Some(previous), Some(now)) = (previous, &item.now)
+                && previous != *now
+            {
+                self.push_traffic_event(
+                    TrafficEventKind::ProxySwitch,
+                    format!("{} → {} (auto)", name, now),
+                );
+                self.auto_switches
+                    .insert(name.clone(), (previous, SystemTime::now()));
+            }
+        }
+        self.proxies = proxies;
+
+        // Keep optimistic selections until their requests finish
+        for (group, pending) in &self.pending_switches {
+            if let Some(item) = self.proxies.get_mut(group) {
+                item.now = Some(pending.proxy.clone());
+            }
+        }
+
+        // Populate latency from history
+        let mut measured_by_core = Vec::new();
+        for (name, item) in &self.proxies {
+            let Some(history) = item.extra.get("history").and_then(|h| h.as_array()) else {
+                continue;
+            };
+            for entry in history {
+                if let Some(at) = entry
+                    .get("time")
+                    .and_then(|t| t.as_str())
+                    .and_then(parse_rfc3339)
+                    && let Some(delay) = entry.get("delay").and_then(|d| d.as_u64())
+                {
+                    measured_by_core.push((name.clone(), at, delay));
+                }
+            }
+            if let Some(last) = history.last()
+                && let Some(delay) = last.get("delay").and_then(|d| d.as_u64())
+                && delay > 0
+            {
+                let measured = last
+                    .get("time")
+                    .and_then(|t| t.as_str())
+                    .and_then(parse_rfc3339)
+                    .unwrap_or_else(SystemTime::now);
+                self.proxy_latency.insert(name.clone(), Some(delay));
+                self.proxy_latency_at.insert(name.clone(), measured);
+            }
+        }
+        for (name, at, delay) in measured_by_core {
+            self.push_latency_history(&name, at, delay);
+        }
+
+        self.rebuild_group_names();
+        if let Some(index) =
+            selected_proxy.and_then(|name| self.visible_proxies().iter().position(|p| *p == name))
+        {
+            self.proxy_state.select(Some(index));
+        }
+    }
+
+    /// Loads per-node weights for every Smart group. The endpoint only exists on
+    /// cores built with Smart group support, so failures are kept per group.
+    async fn fetch_smart_weights(&mut self) {
+        self.smart_weights = smart_weights(self.api(), self.smart_groups()).await;
+    }
+
+    /// `fetch_smart_weights` in the background, answered through `events_rx`.
+    fn load_smart_weights(&self) {
+        let groups = self.smart_groups();
+        if groups.is_empty() {
+            return;
+        }
+        let api = self.api();
+        let tx = self.events_tx.clone();
+        let base_url = self.app_settings.base_url.clone();
+        tokio::spawn(async move {
+            let weights = smart_weights(api, groups).await;
+            let _ = tx.send(AppEvent::SmartWeights { base_url, weights }).await;
+        });
+    }
+
+    fn smart_groups(&self) -> Vec<String> {
+        self.proxies
+            .values()
+            .filter(|p| p.proxy_type.as_deref() == Some("Smart"))
+            .filter_map(|p| p.name.clone())
+            .collect()
+    }
+
+    /// Asks to clear the learned statistics of the selected Smart group.
+    pub fn confirm_flush_smart_group(&mut self) {
+        let Some(group) = self
+            .get_selected_group_name()
+            .filter(|name| {
+                self.proxies
+                    .get(*name)
+                    .is_some_and(|p| p.proxy_type.as_deref() == Some("Smart"))
+            })
+            .cloned()
+        else {
+            self.info("Selected group is not a Smart group".to_string());
+            return;
+        };
+        self.confirm(ConfirmAction::FlushSmartGroup(group));
+    }
+
+    fn flush_smart_group(&mut self, context: &'static str, group: String) {
+        let path = format!("/cache/smart/flush/{}", urlencoding::encode(&group));
+        let request = self.api().request(Method::POST, &path);
+        self.spawn_action(Job::Flush, context, request, move |app| {
+            app.info(format!("Flushed Smart group {}", group));
+            app.load_smart_weights();
+        });
+    }
+
+    /// Awaits `load` in place, for callers that need the data before going on.
+    async fn fetch(&mut self, load: Load) -> Result<()> {
+        if !self.capabilities.supports(load.path()) {
+            return Ok(());
+        }
+        let api = self.api();
+        let response = api
+            .send(api.request(reqwest::Method::GET, load.path()))
+            .await;
+        self.apply_load(load, response)
+    }
+
+    /// Starts `load` in the background, unless it is running already, so a
+    /// slow controller doesn't hold up the keyboard. Its panel shows a
+    /// spinner until `on_event` applies the answer.
+    pub fn load(&mut self, load: Load) {
+        if !self.capabilities.supports(load.path()) || !self.loading.insert(load) {
+            return;
+        }
+        let api = self.api();
+        let tx = self.events_tx.clone();
+        let base_url = self.app_settings.base_url.clone();
+        tokio::spawn(async move {
+            let response = api
+                .send(api.request(reqwest::Method::GET, load.path()))
+                .await;
+            let _ = tx
+                .send(AppEvent::Loaded {
+                    load,
+                    base_url,
+                    response,
+                })
+                .await;
+        });
+    }
+
+    /// Runs `work` in the background as `job` and hands its answer to `apply`
+    /// in the run loop, the way `load` does for plain fetches.
+    fn spawn_job<T: Send + 'static>(
+        &mut self,
+        job: Job,
+        work: impl Future<Output = T> + Send + 'static,
+        apply: impl FnOnce(&mut App, T) + Send + 'static,
+    ) {
+        self.jobs.push(job);
+        let tx = self.events_tx.clone();
+        let base_url = self.app_settings.base_url.clone();
+        tokio::spawn(async move {
+            let output = work.await;
+            let apply = Box::new(move |app: &mut App| apply(app, output));
+            let _ = tx
+                .send(AppEvent::JobDone {
+                    job,
+                    base_url,
+                    apply,
+                })
+                .await;
+        });
+    }
+
+    /// `load` as `job`, running `then` once the answer is applied.
+    fn load_then(&mut self, job: Job, load: Load, then: impl FnOnce(&mut App) + Send + 'static) {
+        let api = self.api();
+        let work = async move { api.send(api.request(Method::GET, load.path())).await };
+        self.spawn_job(job, work, move |app, response| {
+            match app.apply_load(load, response) {
+                Ok(()) => then(app),
+                Err(e) => app.error(format!("{}: {:#}", load.label(), e)),
+            }
+        });
+    }
+
+    /// Sends `request`, whose response carries nothing but its status, as
+    /// `job`. `done` runs once it succeeded; failures are put down to `context`.
+    fn spawn_action(
+        &mut self,
+        job: Job,
+        context: &'static str,
+        request: RequestBuilder,
+        done: impl FnOnce(&mut App) + Send + 'static,
+    ) {
+        let api = self.api();
+        let work = async move { post_action(&api, request).await };
+        self.spawn_job(job, work, move |app, result| match result {
+            Ok(()) => done(app),
+            Err(e) => app.error(format!("{}: {:#}", context, e)),
+        });
+    }
+
+    /// Applies what a background task sent. Answers from a controller that
+    /// was switched away from meanwhile are dropped. Input is the run loop's.
+    pub fn on_event(&mut self, event: AppEvent) {
+        match event {
+            AppEvent::Input(_) => {}
+            AppEvent::Loaded {
+                load,
+                base_url,
+                response,
+            } => {
+                self.loading.remove(&load);
+                if base_url != self.app_settings.base_url {
+                    return;
+                }
+                match self.apply_load(load, response) {
+                    Ok(()) if load == Load::Proxies => self.load_smart_weights(),
+                    Ok(()) => {}
+                    Err(e) => self.error(format!("{}: {:#}", load.label(), e)),
+                }
+            }
+            AppEvent::SmartWeights { base_url, weights } => {
+                if base_url == self.app_settings.base_url {
+                    self.smart_weights = weights;
+                }
+            }
+            AppEvent::Unpinned { group, result } => {
+                match result {
+                    Ok(()) => self.info(format!("{} picks its node again", group)),
+                    Err(e) => self.error(format!("Unpin {}: {}", group, e)),
+                }
+                self.load(Load::Proxies);
+            }
+            AppEvent::SwitchResult { group, result } => self.on_switch_result(group, result),
+            AppEvent::ProviderResult {
+                name,
+                action,
+                result,
+            } => self.on_provider_result(name, action, result),
+            AppEvent::ProxyDelay { name, result } => self.on_proxy_latencies(vec![(name, result)]),
+            AppEvent::GroupTestFailed { group, error } => {
+                self.error(format!("Test {}: {}", group, error));
+            }
+            AppEvent::Compare { run, side, delay } => self.on_compare_result(run, side, delay),
+            AppEvent::RealLatency(status) => self.on_real_latency(status),
+            AppEvent::ControllerRtt(rtt) => self.on_controller_rtt(rtt),
+            AppEvent::CoreOperation(event) => self.on_core_operation_event(event),
+            AppEvent::LatestCoreRelease(release) => self.latest_core_release = Some(release),
+            AppEvent::Traffic(traffic) => self.on_traffic(traffic),
+            AppEvent::Memory(memory) => self.memory = Some(memory),
+            AppEvent::Log(line) => self.on_log(line),
+            AppEvent::Connections(data) => self.on_connections(data),
+            AppEvent::FakeIpLookup { ip, real } => {
+                if let Modal::FakeIp(map) = &mut self.modal
+                    && let Some(entry) = map.entries.iter_mut().find(|e| e.ip == ip)
+                {
+                    entry.real = Some(real);
+                }
+            }
+            AppEvent::JobDone {
+                job,
+                base_url,
+                apply,
+            } => {
+                if let Some(index) = self.jobs.iter().position(|j| *j == job) {
+                    self.jobs.remove(index);
+                }
+                if base_url == self.app_settings.base_url {
+                    apply(self);
+                }
+            }
+        }
+    }
+
+    fn apply_load(&mut self, load: Load, response: Result<ApiResponse, ApiError>) -> Result<()> {
+        let response = response.and_then(|resp| {
+            if resp.status.is_success() {
+                Ok(resp)
+            } else {
+                Err(ApiError::Status(resp.status))
+            }
+        });
+        match load {
+            Load::Proxies => {
+                let result = response
+                    .and_then(|resp| resp.json::<ProxiesResponse>())
+                    .map(|data| self.apply_proxies(data.proxies))
+                    .map_err(Into::into);
+                self.record_load(Panel::Proxies, result)
+            }
+            Load::Config => self.apply_config(response.and_then(|resp| resp.json())),
+            Load::Version => {
+                self.capabilities.version = Some(response?.json()?);
+                Ok(())
+            }
+            Load::Connections => {
+                let result = response
+                    .and_then(|resp| resp.json::<ConnectionsResponse>())
+                    .map(|data| self.on_connections(data))
+                    .map_err(Into::into);
+                self.record_load(Panel::Connections, result)
+            }
+            Load::Rules => {
+                let result = response
+                    .map_err(Into::into)
+                    .and_then(|resp| self.apply_rules(resp));
+                self.record_load(Panel::Rules, result)
+            }
+            Load::RuleProviders => {
+                let result = response
+                    .map_err(Into::into)
+                    .and_then(|resp| self.apply_rule_providers(resp));
+                self.record_load(Panel::RuleProviders, result)
+            }
+            Load::ProxyProviders => {
+                let result = response
+                    .map_err(Into::into)
+                    .and_then(|resp| self.apply_proxy_providers(resp));
+                self.record_load(Panel::ProxyProviders, result)
+            }
+        }
+    }
+
+    fn record_load(&mut self, panel: Panel, result: Result<()>) -> Result<()> {
+        match &result {
+            Ok(()) => self.load_errors.remove(&panel),
+            Err(e) => self.load_errors.insert(panel, format!("{:#}", e)),
+        };
+        result
+    }
+
+    pub async fn fetch_connections(&mut self) -> Result<()> {
+        self.fetch(Load::Connections).await
+    }
+
+    pub fn on_connections(&mut self, data: ConnectionsResponse) {
+        self.load_errors.remove(&Panel::Connections);
+        let mut connections = data.connections.unwrap_or_default();
+        // Newest first; RFC 3339 timestamps sort lexicographically
+        connections.sort_by(|a, b| b.start.cmp(&a.start));
+
+        // Keep the cursor on the same connection across refreshes
+        let selected_id = self.selected_connection().map(|c| c.id.clone());
+        let selected = self.connections_state.selected().unwrap_or(0);
+        if let Some(at) = self.connections_at {
+            let secs = at.elapsed().as_secs_f64().max(0.001);
+            let before: HashMap<&str, (u64, u64)> = self
+                .connections
+                .iter()
+                .map(|c| (c.id.as_str(), (c.download, c.upload)))
+                .collect();
+            let rate = |now: u64, last: u64| (now.saturating_sub(last) as f64 / secs) as u64;
+            self.connection_rates = connections
+                .iter()
+                .filter_map(|c| {
+                    let (down, up) = before.get(c.id.as_str())?;
+                    Some((c.id.clone(), (rate(c.download, *down), rate(c.upload, *up))))
+                })
+                .collect();
+        }
+        self.connections_at = Some(Instant::now());
+        self.connection_stats = ConnectionStats::new(&connections);
+        self.connections = connections;
+        self.download_total = data.download_total;
+        self.upload_total = data.upload_total;
+
+        let visible = self.visible_connections();
+        let index = selected_id
+            .and_then(|id| visible.iter().position(|c| c.id == id))
+            .or_else(|| visible.len().checked_sub(1).map(|last| selected.min(last)));
+        self.connections_state.select(index);
+        self.sample_detail_connection();
+    }
+
+    pub fn confirm_close_selected_connection(&mut self) {
+        let Some(conn) = self.selected_connection() else {
+            return;
+        };
+        self.confirm(ConfirmAction::CloseConnection {
+            id: conn.id.clone(),
+            target: conn.target(),
+        });
+    }
+
+    /// Closes one connection through `DELETE /connections/:id`.
+    fn close_connection(&mut self, context: &'static str, id: String, target: String) {
+        let path = format!("/connections/{}", urlencoding::encode(&id));
+        let request = self.api().request(Method::DELETE, &path);
+        self.spawn_action(Job::CloseConnections, context, request, move |app| {
+            // Drop it right away rather than waiting for the next snapshot
+            app.connections.retain(|c| c.id != id);
+            let len = app.visible_connections().len();
+            if let Some(selected) = app.connections_state.selected() {
+                app.connections_state
+                    .select(len.checked_sub(1).map(|last| selected.min(last)));
+            }
+            app.info(format!("Closed {}", target));
+        });
+    }
+
+    fn close_all_connections(&mut self, context: &'static str) {
+        let request = self.api().request(Method::DELETE, "/connections");
+        self.spawn_action(Job::CloseConnections, context, request, |app| {
+            app.info(format!("Closed {} connections", app.connections.len()));
+            app.connections.clear();
+            app.connection_stats = ConnectionStats::default();
+            app.connections_state.select(None);
+        });
+    }
+
+    /// Asks the core to restart or upgrade itself, then follows it until it
+    /// answers again. Upgrades only reply once the new binary is downloaded,
+    /// so the request runs in the background and reports on `events_rx`.
+    pub fn start_core_operation(&mut self, kind: CoreOperationKind) {
+        if self.core_operation_running() {
+            self.error("A core restart or upgrade is already running".to_string());
+            return;
+        }
+        let request = self
+            .api()
+            .with_timeout(Duration::from_secs(300))
+            .request(Method::POST, kind.path());
+        self.core_operation = Some(CoreOperation {
+            kind,
+            started: Instant::now(),
+            version_before: self
+                .capabilities
+                .version
+                .as_ref()
+                .map(|v| v.version.clone()),
+            status: CoreOperationStatus::Sending,
+        });
+
+        let tx = self.events_tx.clone();
+        let client = self.client.clone();
+        let log = self.api_log.clone();
+        tokio::spawn(async move {
+            let result = match send_logged(&client, &log, request).await {
+                Ok(resp) if resp.status.is_success() => Ok(()),
+                Ok(resp) => Err(serde_json::from_str::<serde_json::Value>(&resp.body)
+                    .ok()
+                    .and_then(|json| json.get("message")?.as_str().map(str::to_string))
+                    .unwrap_or_else(|| format!("Server returned error: {}", resp.status))),
+                Err(e) => Err(e.to_string()),
+            };
+            let _ = tx
+                .send(AppEvent::CoreOperation(CoreOperationEvent::Sent(result)))
+                .await;
+        });
+    }
+
+    pub fn core_operation_running(&self) -> bool {
+        self.core_operation.as_ref().is_some_and(|op| {
+            matches!(
+                op.status,
+                CoreOperationStatus::Sending | CoreOperationStatus::Waiting { .. }
+            )
+        })
+    }
+
+    /// Whether `/version` should be polled for the core coming back.
+    pub fn core_operation_waiting(&self) -> bool {
+        self.core_operation
+            .as_ref()
+            .is_some_and(|op| matches!(op.status, CoreOperationStatus::Waiting { .. }))
+    }
+
+    /// Looks up the newest mihomo release in the background, unless that was
+    /// done within `CORE_RELEASE_MAX_AGE`. Failures leave the last answer.
+    pub fn check_latest_core_release(&mut self) {
+        if self
+            .latest_core_release_checked
+            .is_some_and(|at| at.elapsed() < CORE_RELEASE_MAX_AGE)
+        {
+            return;
+        }
+        self.latest_core_release_checked = Some(Instant::now());
+
+        #[derive(Deserialize)]
+        struct Release {
+            tag_name: String,
+        }
+        let request = self
+            .client
+            .get(CORE_RELEASES_URL)
+            .header(
+                reqwest::header::USER_AGENT,
+                concat!("mihomot/", env!("CARGO_PKG_VERSION")),
+            )
+            .timeout(Duration::from_secs(10));
+        let tx = self.events_tx.clone();
+        tokio::spawn(async move {
+            let Ok(resp) = request.send().await else {
+                return;
+            };
+            if let Ok(release) = resp.json::<Release>().await {
+                let _ = tx.send(AppEvent::LatestCoreRelease(release.tag_name)).await;
+            }
+        });
+    }
+
+    /// Whether the connected core is older than the newest release; `None`
+    /// while either version is unknown or unparsable.
+    pub fn core_outdated(&self) -> Option<bool> {
+        let latest = parse_version(self.latest_core_release.as_ref()?)?;
+        Some(self.capabilities.core_version()? < latest)
+    }
+
+    /// Asks `/version` whether the core is back; the answer arrives as
+    /// `CoreOperationEvent::Polled`.
+    pub fn poll_core_version(&self) {
+        let request = self
+            .api()
+            .with_timeout(Duration::from_secs(2))
+            .request(Method::GET, "/version");
+        let tx = self.events_tx.clone();
+        tokio::spawn(async move {
+            let version = match request.send().await {
+                Ok(resp) if resp.status().is_success() => {
+                    resp.json::<VersionInfo>().await.ok().map(|v| v.version)
+                }
+                _ => None,
+            };
+            let _ = tx
+                .send(AppEvent::CoreOperation(CoreOperationEvent::Polled(version)))
+                .await;
+        });
+    }
+
+    /// Advances the running operation. Once the core is back, everything
+    /// loaded from it is fetched again, since a restart may have changed it.
+    pub fn on_core_operation_event(&mut self, event: CoreOperationEvent) {
+        let Some(op) = self.core_operation.as_mut() else {
+            return;
+        };
+        match (event, &op.status) {
+            (CoreOperationEvent::Sent(Ok(())), CoreOperationStatus::Sending) => {
+                let message = format!("{} started; waiting for the core", op.kind.label());
+                op.status = CoreOperationStatus::Waiting { went_down: false };
+                self.info(message);
+            }
+            (CoreOperationEvent::Sent(Err(e)), CoreOperationStatus::Sending) => {
+                let message = format!("{} core: {}", op.kind.label(), e);
+                op.status = CoreOperationStatus::Failed(e);
+                self.raise_alert(message);
+            }
+            (CoreOperationEvent::Polled(None), CoreOperationStatus::Waiting { .. }) => {
+                if op.started.elapsed() >= CORE_COMEBACK_TIMEOUT {
+                    let e = format!("No answer within {}s", CORE_COMEBACK_TIMEOUT.as_secs());
+                    let message = format!("{} core: {}", op.kind.label(), e);
+                    op.status = CoreOperationStatus::Failed(e);
+                    self.raise_alert(message);
+                } else {
+                    op.status = CoreOperationStatus::Waiting { went_down: true };
+                }
+            }
+            (
+                CoreOperationEvent::Polled(Some(version)),
+                CoreOperationStatus::Waiting { went_down },
+            ) => {
+                // A quick restart can come back between two polls, so after a
+                // while an answer counts even if it was never seen down
+                let changed = op.version_before.as_ref() != Some(&version);
+                if !went_down && !changed && op.started.elapsed() < Duration::from_secs(5) {
+                    return;
+                }
+                let took = op.started.elapsed();
+                let message = match &op.version_before {
+                    Some(before) if *before != version => {
+                        format!("Core is back: {} -> {}", before, version)
+                    }
+                    _ => format!("Core is back ({})", version),
+                };
+                let label = format!("core {}", op.kind.label().to_lowercase());
+                op.status = CoreOperationStatus::Done { version, took };
+                self.push_traffic_event(TrafficEventKind::CoreRestart, label);
+                self.info(message);
+                self.reload_after_core_operation();
+            }
+            _ => {}
+        }
+    }
+
+    fn reload_after_core_operation(&mut self) {
+        // The run loop opens it again against the new process
+        self.stop_connections_stream();
+        // The new core may support a different set of endpoints
+        let mut loads = vec![Load::Proxies, Load::Config];
+        if !self.proxy_providers.is_empty() {
+            loads.push(Load::ProxyProviders);
+        }
+        self.probe_capabilities(loads);
+    }
+
+    /// Empties one of the core's caches, `fakeip` or `dns`.
+    fn flush_cache(&mut self, context: &'static str, cache: &str, label: &'static str) {
+        let path = format!("/cache/{}/flush", cache);
+        let request = self.api().request(Method::POST, &path);
+        self.spawn_action(Job::Flush, context, request, move |app| {
+            app.info(format!("Flushed the {} cache", label));
+        });
+    }
+
+    /// Opens the confirmation popup for `action`.
+    pub fn confirm(&mut self, action: ConfirmAction) {
+        self.modal = Modal::Confirm { action, yes: false };
+    }
+
+    /// Closes the confirmation popup, running its action on a yes.
+    pub fn answer_confirm(&mut self, yes: bool) {
+        let Modal::Confirm { action, .. } = std::mem::take(&mut self.modal) else {
+            return;
+        };
+        if !yes {
+            return;
+        }
+        let context = action.label();
+        match action {
+            ConfirmAction::CloseConnection { id, target } => {
+                self.close_connection(context, id, target)
+            }
+            ConfirmAction::CloseAllConnections => self.close_all_connections(context),
+            ConfirmAction::RestartCore => self.start_core_operation(CoreOperationKind::Restart),
+            ConfirmAction::UpgradeCore => self.start_core_operation(CoreOperationKind::Upgrade),
+            ConfirmAction::FlushFakeIp => self.flush_cache(context, "fakeip", "fake-ip"),
+            ConfirmAction::FlushDns => self.flush_cache(context, "dns", "DNS"),
+            ConfirmAction::FlushSmartGroup(group) => self.flush_smart_group(context, group),
+            ConfirmAction::SwitchProxy { group, proxy } => self.switch_proxy(&group, &proxy, false),
+            ConfirmAction::RevertGroup(group) => self.revert_group(group),
+            ConfirmAction::UnpinGroup(group) => self.unpin_group(group),
+        }
+    }
+
+    pub fn open_connection_detail(&mut self) {
+        let Some(conn) = self.selected_connection() else {
+            return;
+        };
+        let sample = (conn.download, conn.upload, Instant::now());
+        self.detail_connection_id = Some(conn.id.clone());
+        self.last_connection_sample = Some(sample);
+        self.connection_rate_down.clear();
+        self.connection_rate_up.clear();
+    }
+
+    pub fn close_connection_detail(&mut self) {
+        self.detail_connection_id = None;
+        self.last_connection_sample = None;
+    }
+
+    pub fn detail_connection(&self) -> Option<&Connection> {
+        let id = self.detail_connection_id.as_ref()?;
+        self.connections.iter().find(|c| &c.id == id)
+    }
+
+    /// Records the detail connection's throughput since the previous refresh.
+    fn sample_detail_connection(&mut self) {
+        let Some((down, up)) = self.detail_connection().map(|c| (c.download, c.upload)) else {
+            return;
+        };
+        if let Some((last_down, last_up, at)) = self.last_connection_sample {
+            let secs = at.elapsed().as_secs_f64().max(0.001);
+            let rate = |now: u64, last: u64| (now.saturating_sub(last) as f64 / secs) as u64;
+            self.connection_rate_down.push_back(rate(down, last_down));
+            self.connection_rate_up.push_back(rate(up, last_up));
+            while self.connection_rate_down.len() > 300 {
+                self.connection_rate_down.pop_front();
+                self.connection_rate_up.pop_front();
+            }
+        }
+        self.last_connection_sample = Some((down, up, Instant::now()));
+    }
+
+    /// Maps the fake-ip destinations of the open connections back to their
+    /// domains, and looks each domain up through the core for its real
+    /// addresses, a few at a time.
+    pub fn open_fake_ip_map(&mut self) {
+        let dns = self.config.as_ref().and_then(|config| config.dns.as_ref());
+        let fake_ip_mode = dns.is_some_and(|dns| dns.enable && dns.enhanced_mode == "fake-ip");
+        let range = dns
+            .map(|dns| dns.fake_ip_range.clone())
+            .filter(|range| !range.is_empty())
+            .unwrap_or_else(|| DEFAULT_FAKE_IP_RANGE.to_string());
+
+        // Address -> (domain, connections)
+        let mut seen: HashMap<std::net::IpAddr, (String, usize)> = HashMap::new();
+        for conn in &self.connections {
+            let Ok(ip) = conn.metadata.destination_ip.parse() else {
+                continue;
+            };
+            if !ip_in_prefix(ip, &range) {
+                continue;
+            }
+            let host = if conn.metadata.host.is_empty() {
+                &conn.metadata.sniff_host
+            } else {
+                &conn.metadata.host
+            };
+            let entry = seen.entry(ip).or_default();
+            if entry.0.is_empty() {
+                entry.0.clone_from(host);
+            }
+            entry.1 += 1;
+        }
+        let mut seen: Vec<_> = seen.into_iter().collect();
+        seen.sort_by_key(|(ip, _)| *ip);
+
+        // The popup opens at once and fills in as the lookups come back
+        let entries = seen
+            .iter()
+            .map(|(ip, (domain, connections))| FakeIpEntry {
+                ip: ip.to_string(),
+                domain: domain.clone(),
+                connections: *connections,
+                real: None,
+            })
+            .collect();
+        self.modal = Modal::FakeIp(FakeIpMap {
+            range: range.clone(),
+            fake_ip_mode,
+            entries,
+        });
+
+        if let Some(task) = self.fake_ip_lookups.take() {
+            task.abort();
+        }
+        let api = self
+            .api()
+            .with_timeout(Duration::from_millis(self.app_settings.test_timeout));
+        let tx = self.events_tx.clone();
+        self.fake_ip_lookups = Some(tokio::spawn(async move {
+            let lookups = seen.into_iter().map(|(ip, (domain, _))| {
+                let (record_type, wanted) = if ip.is_ipv4() { ("A", 1) } else { ("AAAA", 28) };
+                let (api, range) = (&api, &range);
+                async move {
+                    if domain.is_empty() {
+                        return (ip, Err("no host name to look up".to_string()));
+                    }
+                    let real = match api.dns_query(&domain, record_type).await {
+                        // Asked through the core, a fake-ip answer is only the pool again
+                        Ok(answer) => Ok(answer
+                            .answer
+                            .into_iter()
+                            .filter(|record| record.record_type == wanted)
+                            .map(|record| record.data)
+                            .filter(|data| data.parse().is_ok_and(|ip| !ip_in_prefix(ip, range)))
+                            .collect()),
+                        Err(ApiError::Status(StatusCode::NOT_FOUND)) => {
+                            Err("the core has no DNS query API".to_string())
+                        }
+                        Err(e) => Err(e.to_string()),
+                    };
+                    (ip, real)
+                }
+            });
+            let mut results = futures_util::stream::iter(lookups).buffer_unordered(8);
+            while let Some((ip, real)) = results.next().await {
+                let ip = ip.to_string();
+                if tx.send(AppEvent::FakeIpLookup { ip, real }).await.is_err() {
+                    return;
+                }
+            }
+        }));
+        self.popup_scroll = 0;
+    }
+
+    /// Connections matching any active filter preset, or all when none is active.
+    pub fn visible_connections(&self) -> Vec<&Connection> {
+        let active: Vec<&ConnectionFilter> = self
+            .active_connection_filters
+            .iter()
+            .filter_map(|i| self.app_settings.connection_filters.get(*i))
+            .collect();
+        self.connections
+            .iter()
+            .filter(|conn| active.is_empty() || active.iter().any(|f| f.matches(conn)))
+            .collect()
+    }
+
+    pub fn selected_connection(&self) -> Option<&Connection> {
+        self.connections_state
+            .selected()
+            .and_then(|i| self.visible_connections().get(i).copied())
+    }
+
+    pub fn toggle_connection_filter(&mut self, index: usize) {
+        if index >= self.app_settings.connection_filters.len() {
+            return;
+        }
+        if !self.active_connection_filters.remove(&index) {
+            self.active_connection_filters.insert(index);
+        }
+        let len = self.visible_connections().len();
+        self.connections_state
+            .select(if len == 0 { None } else { Some(0) });
+    }
+
+    /// The name and columns of the set in use; the built-in columns when
+    /// none are configured.
+    pub fn current_connection_columns(&self) -> (&str, &[ConnectionColumn]) {
+        match self
+            .app_settings
+            .connection_columns
+            .get(self.connection_columns)
+        {
+            Some(set) if !set.columns.is_empty() => (&set.name, &set.columns),
+            _ => ("default", &DEFAULT_CONNECTION_COLUMNS),
+        }
+    }
+
+    pub fn cycle_connection_columns(&mut self) {
+        let count = self.app_settings.connection_columns.len();
+        if count == 0 {
+            self.info("No column sets in connection_columns".to_string());
+            return;
+        }
+        self.connection_columns = (self.connection_columns + 1) % count;
+        self.info(format!("Columns: {}", self.current_connection_columns().0));
+    }
+
+    pub fn next_connection(&mut self) {
+        let len = self.visible_connections().len();
+        if len == 0 {
+            return;
+        }
+        let i = match self.connections_state.selected() {
+            Some(i) if i + 1 < len => i + 1,
+            _ => 0,
+        };
+        self.connections_state.select(Some(i));
+    }
+
+    pub fn previous_connection(&mut self) {
+        let len = self.visible_connections().len();
+        if len == 0 {
+            return;
+        }
+        let i = match self.connections_state.selected() {
+            Some(0) | None => len - 1,
+            Some(i) => i - 1,
+        };
+        self.connections_state.select(Some(i));
+    }
+
+    fn apply_rules(&mut self, resp: ApiResponse) -> Result<()> {
+        self.rules = resp.json::<RulesResponse>()?.rules;
+        for (index, rule) in self.rules.iter_mut().enumerate() {
+            rule.index = index;
+        }
+        let len = self.visible_rules().len();
+        let selected = self.rules_state.selected().unwrap_or(0);
+        self.rules_state
+            .select(len.checked_sub(1).map(|last| selected.min(last)));
+        Ok(())
+    }
+
+    /// Rules whose type, payload or target contains the search query.
+    pub fn visible_rules(&self) -> Vec<&Rule> {
+        let query = self.rules_query.to_lowercase();
+        self.rules
+            .iter()
+            .filter(|rule| {
+                query.is_empty()
+                    || rule.rule_type.to_lowercase().contains(&query)
+                    || rule.payload.to_lowercase().contains(&query)
+                    || rule.proxy.to_lowercase().contains(&query)
+            })
+            .collect()
+    }
+
+    pub fn set_rules_query(&mut self, query: &str) {
+        self.rules_query = query.trim().to_string();
+        let len = self.visible_rules().len();
+        self.rules_state
+            .select(if len == 0 { None } else { Some(0) });
+    }
+
+    pub fn next_rule(&mut self) {
+        let len = self.visible_rules().len();
+        if len == 0 {
+            return;
+        }
+        let i = match self.rules_state.selected() {
+            Some(i) if i + 1 < len => i + 1,
+            _ => 0,
+        };
+        self.rules_state.select(Some(i));
+    }
+
+    pub fn previous_rule(&mut self) {
+        let len = self.visible_rules().len();
+        if len == 0 {
+            return;
+        }
+        let i = match self.rules_state.selected() {
+            Some(0) | None => len - 1,
+            Some(i) => i - 1,
+        };
+        self.rules_state.select(Some(i));
+    }
+
+    fn apply_rule_providers(&mut self, resp: ApiResponse) -> Result<()> {
+        let data = resp.json::<RuleProvidersResponse>()?;
+        let mut providers: Vec<RuleProvider> = data.providers.into_values().collect();
+        providers.sort_by(|a, b| a.name.cmp(&b.name));
+        self.rule_providers = providers;
+        if self.rule_providers_state.selected().is_none() && !self.rule_providers.is_empty() {
+            self.rule_providers_state.select(Some(0));
+        }
+        // Refetching may mean the files were updated
+        self.rule_provider_preview = None;
+        self.load_rule_provider_preview();
+        Ok(())
+    }
+
+    /// Switches to the Providers view with the selected RULE-SET rule's provider selected.
+    pub fn jump_to_rule_provider(&mut self) {
+        let Some(provider) = self
+            .rules_state
+            .selected()
+            .and_then(|i| self.visible_rules().get(i).copied())
+            .filter(|rule| rule.rule_type.eq_ignore_ascii_case("RuleSet"))
+            .map(|rule| rule.payload.clone())
+        else {
+            self.info("Selected rule is not a RULE-SET".to_string());
+            return;
+        };
+
+        self.load_then(Job::OpenProvider, Load::RuleProviders, move |app| match app
+            .rule_providers
+            .iter()
+            .position(|p| p.name == provider)
+        {
+            Some(index) => {
+                app.rule_providers_state.select(Some(index));
+                app.load_rule_provider_preview();
+                app.focus = Focus::RuleProviders;
+            }
+            None => app.info(format!("Rule provider {} not found", provider)),
+        });
+    }
+
+    /// Reads the selected provider's cached file, unless it is already loaded.
+    pub fn load_rule_provider_preview(&mut self) {
+        let Some(provider) = self
+            .rule_providers_state
+            .selected()
+            .and_then(|i| self.rule_providers.get(i))
+        else {
+            self.rule_provider_preview = None;
+            return;
+        };
+        if self
+            .rule_provider_preview
+            .as_ref()
+            .is_some_and(|(name, _)| *name == provider.name)
+        {
+            return;
+        }
+        let dir = match &self.app_settings.core_dir {
+            Some(dir) => PathBuf::from(dir),
+            None => {
+                let Some(home) = std::env::var_os("HOME") else {
+                    self.rule_provider_preview =
+                        Some((provider.name.clone(), Err("HOME is not set".to_string())));
+                    return;
+                };
+                PathBuf::from(home).join(".config").join("mihomo")
+            }
+        };
+        let preview = if provider.vehicle_type.eq_ignore_ascii_case("Inline") {
+            Err("Inline rule sets live in the core config".to_string())
+        } else if provider.format.eq_ignore_ascii_case("mrs") {
+            Err("mrs rule sets are binary and can't be previewed".to_string())
+        } else {
+            read_rule_set(&dir, &provider.name)
+        };
+        self.rule_provider_preview = Some((provider.name.clone(), preview));
+    }
+
+    pub fn next_rule_provider(&mut self) {
+        let len = self.rule_providers.len();
+        if len == 0 {
+            return;
+        }
+        let i = match self.rule_providers_state.selected() {
+            Some(i) if i + 1 < len => i + 1,
+            _ => 0,
+        };
+        self.rule_providers_state.select(Some(i));
+        self.load_rule_provider_preview();
+    }
+
+    pub fn previous_rule_provider(&mut self) {
+        let len = self.rule_providers.len();
+        if len == 0 {
+            return;
+        }
+        let i = match self.rule_providers_state.selected() {
+            Some(0) | None => len - 1,
+            Some(i) => i - 1,
+        };
+        self.rule_providers_state.select(Some(i));
+        self.load_rule_provider_preview();
+    }
+
+    fn apply_proxy_providers(&mut self, resp: ApiResponse) -> Result<()> {
+        let data = resp.json::<ProxyProvidersResponse>()?;
+        let mut providers: Vec<ProxyProvider> = data
+            .providers
+            .into_values()
+            // "Compatible" is the core's implicit provider for proxies defined inline
+            .filter(|provider| provider.vehicle_type != "Compatible")
+            .collect();
+        providers.sort_by(|a, b| a.name.cmp(&b.name));
+        self.proxy_origin = providers
+            .iter()
+            .flat_map(|provider| {
+                provider
+                    .proxies
+                    .iter()
+                    .filter_map(|proxy| Some((proxy.name.clone()?, provider.name.clone())))
+            })
+            .collect();
+        self.proxy_providers = providers;
+        let len = self.proxy_providers.len();
+        let selected = self.proxy_providers_state.selected().unwrap_or(0);
+        self.proxy_providers_state
+            .select(len.checked_sub(1).map(|last| selected.min(last)));
+        Ok(())
+    }
+
+    pub fn next_proxy_provider(&mut self) {
+        let len = self.proxy_providers.len();
+        if len == 0 {
+            return;
+        }
+        let i = match self.proxy_providers_state.selected() {
+            Some(i) if i + 1 < len => i + 1,
+            _ => 0,
+        };
+        self.proxy_providers_state.select(Some(i));
+    }
+
+    pub fn previous_proxy_provider(&mut self) {
+        let len = self.proxy_providers.len();
+        if len == 0 {
+            return;
+        }
+        let i = match self.proxy_providers_state.selected() {
+            Some(0) | None => len - 1,
+            Some(i) => i - 1,
+        };
+        self.proxy_providers_state.select(Some(i));
+    }
+
+    /// Asks the core to update or health-check the selected provider in the
+    /// background; the result arrives through `events_rx`.
+    pub fn start_provider_action(&mut self, action: ProviderAction) {
+        let rule_set = action == ProviderAction::UpdateRuleSet;
+        let selected = if rule_set {
+            self.rule_providers_state
+                .selected()
+                .and_then(|i| self.rule_providers.get(i))
+                .map(|provider| provider.name.clone())
+        } else {
+            self.proxy_providers_state
+                .selected()
+                .and_then(|i| self.proxy_providers.get(i))
+                .map(|provider| provider.name.clone())
+        };
+        if let Some(name) = selected {
+            self.run_provider_action(name, action);
+        }
+    }
+
+    fn run_provider_action(&mut self, name: String, action: ProviderAction) {
+        let rule_set = action == ProviderAction::UpdateRuleSet;
+        let running = if rule_set {
+            self.rule_provider_updates.contains(&name).then_some(action)
+        } else {
+            self.provider_actions.get(&name).copied()
+        };
+        if let Some(running) = running {
+            self.warn(format!("{} is already running a {}", name, running.label()));
+            return;
+        }
+
+        let path = format!(
+            "/providers/{}/{}",
+            if rule_set { "rules" } else { "proxies" },
+            urlencoding::encode(&name)
+        );
+        let api = self.api();
+        let request = match action {
+            ProviderAction::Update | ProviderAction::UpdateRuleSet => {
+                api.request(Method::PUT, &path)
+            }
+            ProviderAction::HealthCheck => {
+                api.request(Method::GET, &format!("{}/healthcheck", path))
+            }
+        };
+
+        if rule_set {
+            self.rule_provider_updates.insert(name.clone());
+        } else {
+            self.provider_actions.insert(name.clone(), action);
+        }
+        let client = self.client.clone();
+        let log = self.api_log.clone();
+        let tx = self.events_tx.clone();
+        tokio::spawn(async move {
+            let result = match send_logged(&client, &log, request).await {
+                Ok(resp) if resp.status.is_success() => Ok(()),
+                Ok(resp) => Err(format!("Server returned error: {}", resp.status)),
+                Err(e) => Err(format!("Failed to connect: {:#}", e)),
+            };
+            let _ = tx
+                .send(AppEvent::ProviderResult {
+                    name,
+                    action,
+                    result,
+                })
+                .await;
+        });
+    }
+
+    /// Cycles the selected proxy provider's auto-update interval through
+    /// `PROVIDER_AUTO_UPDATE_HOURS`, then off.
+    pub fn cycle_provider_auto_update(&mut self) -> Result<()> {
+        let Some(provider) = self
+            .proxy_providers_state
+            .selected()
+            .and_then(|i| self.proxy_providers.get(i))
+        else {
+            return Ok(());
+        };
+        let name = provider.name.clone();
+        if provider.vehicle_type != "HTTP" {
+            self.warn(format!("{} isn't downloaded, so it can't be updated", name));
+            return Ok(());
+        }
+        let schedule = &mut self.app_settings.provider_auto_update;
+        let next = match schedule.get(&name) {
+            None => PROVIDER_AUTO_UPDATE_HOURS.first(),
+            Some(hours) => PROVIDER_AUTO_UPDATE_HOURS.iter().find(|h| *h > hours),
+        };
+        match next {
+            Some(hours) => {
+                schedule.insert(name.clone(), *hours);
+                self.info(format!("{} updates every {}h", name, hours));
+            }
+            None => {
+                schedule.remove(&name);
+                self.info(format!("{} auto-update off", name));
+            }
+        }
+        self.save_app_settings()
+    }
+
+    /// Updates every scheduled proxy provider whose subscription is older
+    /// than its interval. Failed ones are retried after
+    /// `PROVIDER_RETRY_AFTER`, not on every check.
+    pub fn update_due_providers(&mut self) {
+        if self.app_settings.provider_auto_update.is_empty()
+            || !self.capabilities.supports(Load::ProxyProviders.path())
+            || self.jobs.contains(&Job::UpdateProviders)
+        {
+            return;
+        }
+        self.load_then(
+            Job::UpdateProviders,
+            Load::ProxyProviders,
+            App::start_due_provider_updates,
+        );
+    }
+
+    fn start_due_provider_updates(&mut self) {
+        let due: Vec<String> = self
+            .proxy_providers
+            .iter()
+            .filter(|provider| {
+                let Some(hours) = self.app_settings.provider_auto_update.get(&provider.name) else {
+                    return false;
+                };
+                let age = parse_rfc3339(&provider.updated_at)
+                    .and_then(|at| at.elapsed().ok())
+                    .unwrap_or(Duration::MAX);
+                let retry_due = self
+                    .provider_update_results
+                    .get(&provider.name)
+                    .and_then(|(at, _)| at.elapsed().ok())
+                    .is_none_or(|since| since >= PROVIDER_RETRY_AFTER);
+                age >= Duration::from_secs(hours * 3600)
+                    && retry_due
+                    && !self.provider_actions.contains_key(&provider.name)
+            })
+            .map(|provider| provider.name.clone())
+            .collect();
+        for name in due {
+            self.run_provider_action(name, ProviderAction::Update);
+        }
+    }
+
+    /// Reports a finished provider action and reloads what it changed.
+    pub fn on_provider_result(
+        &mut self,
+        name: String,
+        action: ProviderAction,
+        result: Result<(), String>,
+    ) {
+        if action == ProviderAction::UpdateRuleSet {
+            self.rule_provider_updates.remove(&name);
+        } else {
+            self.provider_actions.remove(&name);
+        }
+        if action == ProviderAction::Update {
+            self.provider_update_results
+                .insert(name.clone(), (SystemTime::now(), result.clone()));
+        }
+        match result {
+            Ok(()) if action == ProviderAction::UpdateRuleSet => {
+                self.info(format!("{}: {} done", name, action.label()));
+                self.load(Load::RuleProviders);
+            }
+            Ok(()) => {
+                self.info(format!("{}: {} done", name, action.label()));
+                // Health checks refresh the proxies' delay history
+                self.load(Load::Proxies);
+                self.load(Load::ProxyProviders);
+            }
+            Err(e) => self.error(format!("{} {}: {}", name, action.label(), e)),
+        }
+    }
+
+    pub async fn fetch_config(&mut self) -> Result<()> {
+        self.fetch(Load::Config).await
+    }
+
+    pub async fn fetch_version(&mut self) -> Result<()> {
+        self.fetch(Load::Version).await
+    }
+
+    /// Takes in a `/configs` answer; a refused read keeps the config as it was.
+    fn apply_config(&mut self, result: Result<Config, ApiError>) -> Result<()> {
+        let config = match result {
+            Ok(config) => config,
+            Err(ApiError::Status(_)) => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        if let Some(previous) = &self.config
+            && previous.mode != config.mode
+        {
+            let label = format!("mode {}", config.mode);
+            self.push_traffic_event(TrafficEventKind::ModeChange, label);
+        }
+        self.config_hash = Some(config_hash(&config));
+        self.config = Some(config);
+        self.config_drift = false;
+        Ok(())
+    }
+
+    /// The `auto_refresh` tick: reloads proxies and proxy providers, and the
+    /// rules or rule providers while their view is open. The config is left
+    /// alone while core edits are staged, so the review still compares them
+    /// with what they were made against.
+    pub fn auto_refresh(&mut self) {
+        self.load(Load::Proxies);
+        self.load(Load::ProxyProviders);
+        match self.current_view() {
+            Focus::Rules => self.load(Load::Rules),
+            Focus::RuleProviders => self.load(Load::RuleProviders),
+            _ => {}
+        }
+        if self.staged_config.is_empty() {
+            self.load(Load::Config);
+        }
+    }
+
+    /// Compares the core's current `/configs` against the cached copy without
+    /// replacing it, so edits from other dashboards are flagged instead of
+    /// silently overwritten by stale Settings toggles.
+    pub fn check_config_drift(&mut self) {
+        if self.config_hash.is_none()
+            || !self.capabilities.supports("/configs")
+            || self.jobs.contains(&Job::CheckConfig)
+        {
+            return;
+        }
+        let api = self.api();
+        let work = async move { api.config().await };
+        self.spawn_job(Job::CheckConfig, work, |app, result| match result {
+            // Compared with the cache as it is now, which may be newer
+            Ok(config) => {
+                if app
+                    .config_hash
+                    .is_some_and(|cached| cached != config_hash(&config))
+                {
+                    app.config_drift = true;
+                }
+            }
+            // A refused read says nothing about drift
+            Err(ApiError::Status(_)) => {}
+            Err(e) => app.error(format!("Check config: {}", e)),
+        });
+    }
+
+    pub fn trigger_latency_test(&mut self) {
+        let client = self.client.clone();
+        let url = self.app_settings.test_url.clone();
+        let timeout = self.app_settings.test_timeout;
+        let tx = self.events_tx.clone();
+
+        self.real_latency_status = RealLatencyStatus::Testing;
+
+        tokio::spawn(async move {
+            use std::time::Instant;
+            let start = Instant::now();
+
+            let status = match client
+                .head(&url)
+                .timeout(Duration::from_millis(timeout))
+                .send()
+                .await
+            {
+                Ok(resp) => {
+                    if resp.status().is_success() || resp.status().is_redirection() {
+                        RealLatencyStatus::Success(start.elapsed().as_millis() as u64)
+                    } else {
+                        RealLatencyStatus::Failed(format!("Status: {}", resp.status()))
+                    }
+                }
+                Err(e) => {
+                    let msg = if e.is_timeout() {
+                        "Timeout".to_string()
+                    } else if e.is_connect() {
+                        "Conn Err".to_string()
+                    } else {
+                        "Error".to_string()
+                    };
+                    RealLatencyStatus::Failed(msg)
+                }
+            };
+            let _ = tx.send(AppEvent::RealLatency(status)).await;
+        });
+    }
+
+    /// Times a `GET /version` to the controller in the background. Not written
+    /// to the debug console, which it would otherwise flood.
+    pub fn ping_controller(&self) {
+        let request = self
+            .api()
+            .with_timeout(Duration::from_secs(5))
+            .request(Method::GET, "/version");
+        let tx = self.events_tx.clone();
+        tokio::spawn(async move {
+            let start = Instant::now();
+            let result = match request.send().await {
+                Ok(resp) if resp.status().is_success() => Ok(start.elapsed().as_millis() as u64),
+                Ok(resp) => Err(format!("HTTP {}", resp.status().as_u16())),
+                Err(e) if e.is_timeout() => Err("Timeout".to_string()),
+                Err(_) => Err("Unreachable".to_string()),
+            };
+            let _ = tx.send(AppEvent::ControllerRtt(result)).await;
+        });
+    }
+
+    /// Delay-tests every proxy in the selected group. Cores with the group delay
+    /// API test the whole group in one request; older ones get a request per proxy.
+    pub fn trigger_group_latency_test(&self) {
+        if let Some(group_name) = self.get_selected_group_name() {
+            self.test_group(group_name);
+        }
+    }
+
+    /// Starts a delay test of `group_name`, whose results arrive on
+    /// `events_rx`. The handle finishes once every result has been sent.
+    pub fn test_group(&self, group_name: &str) -> Option<tokio::task::JoinHandle<()>> {
+        if !self.capabilities.supports("/proxies") {
+            return None;
+        }
+        let all = self.proxies.get(group_name).and_then(|g| g.all.as_ref())?;
+        let batched = self.capabilities.supports_feature(CoreFeature::GroupDelay);
+
+        let timeout = self.app_settings.delay_test_timeout;
+        let path = format!(
+            "/group/{}/delay?url={}&timeout={}",
+            urlencoding::encode(group_name),
+            urlencoding::encode(&self.app_settings.test_url),
+            timeout
+        );
+        // The core tests the group's proxies in parallel, so this takes about one timeout
+        let batch = self
+            .api()
+            .with_timeout(Duration::from_millis(timeout + 2000))
+            .request(Method::GET, &path);
+        let singles: Vec<(String, RequestBuilder)> = all
+            .iter()
+            .map(|proxy| (proxy.clone(), self.delay_request(proxy)))
+            .collect();
+        let concurrency = self.app_settings.delay_test_concurrency.max(1);
+        let tx = self.events_tx.clone();
+        let client = self.client.clone();
+        let log = self.api_log.clone();
+        let group = group_name.to_string();
+
+        Some(tokio::spawn(async move {
+            // Older cores are tested proxy by proxy straight away
+            if batched {
+                let error = match send_logged(&client, &log, batch).await {
+                    Ok(resp) if resp.status.is_success() => {
+                        match resp.json::<HashMap<String, u64>>() {
+                            Ok(delays) => {
+                                // Proxies that failed are left out of the result
+                                for name in singles.into_iter().map(|(name, _)| name) {
+                                    let result = delays.get(&name).copied().ok_or_else(|| {
+                                        DelayFailure::Other("Failed in the group test".to_string())
+                                    });
+                                    if tx
+                                        .send(AppEvent::ProxyDelay { name, result })
+                                        .await
+                                        .is_err()
+                                    {
+                                        return;
+                                    }
+                                }
+                                return;
+                            }
+                            Err(e) => Some(e),
+                        }
+                    }
+                    Ok(resp)
+                        if matches!(
+                            resp.status,
+                            StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED
+                        ) =>
+                    {
+                        // No group endpoint after all: test proxy by proxy below
+                        None
+                    }
+                    Ok(resp) => Some(ApiError::Status(resp.status)),
+                    Err(e) => Some(e),
+                };
+                if let Some(error) = error {
+                    let _ = tx.send(AppEvent::GroupTestFailed { group, error }).await;
+                    return;
+                }
+            }
+
+            futures_util::stream::iter(singles)
+                .for_each_concurrent(concurrency, |(name, request)| {
+                    let (client, log, tx) = (&client, &log, &tx);
+                    async move {
+                        let result = measure_delay(client, log, request).await;
+                        let _ = tx.send(AppEvent::ProxyDelay { name, result }).await;
+                    }
+                })
+                .await;
+        }))
+    }
+
+    /// Tests the selected group the first time it is shown this session, so its
+    /// delay column is filled in by the time a proxy is picked.
+    pub fn auto_test_selected_group(&mut self) {
+        if !self.app_settings.auto_test_groups {
+            return;
+        }
+        let Some(group) = self.get_selected_group_name().cloned() else {
+            return;
+        };
+        if self.proxies.contains_key(&group) && self.auto_tested_groups.insert(group) {
+            self.trigger_group_latency_test();
+        }
+    }
+
+    /// A single delay test of `proxy` against the configured test URL.
+    fn delay_request(&self, proxy: &str) -> RequestBuilder {
+        let timeout = self.app_settings.delay_test_timeout;
+        let path = format!(
+            "/proxies/{}/delay?url={}&timeout={}",
+            urlencoding::encode(proxy),
+            urlencoding::encode(&self.app_settings.test_url),
+            timeout
+        );
+        // Give the core a little longer than its own test timeout to answer
+        self.api()
+            .with_timeout(Duration::from_millis(timeout + 2000))
+            .request(Method::GET, &path)
+    }
+
+    /// Marks or unmarks the selected proxy for comparison; a third mark
+    /// replaces the oldest.
+    pub fn toggle_compare_mark(&mut self) {
+        let Some(proxy) = self.get_selected_proxy_name() else {
+            return;
+        };
+        if let Some(index) = self.marked_proxies.iter().position(|p| *p == proxy) {
+            self.marked_proxies.remove(index);
+            return;
+        }
+        if self.marked_proxies.len() == 2 {
+            self.marked_proxies.remove(0);
+        }
+        self.marked_proxies.push(proxy);
+    }
+
+    /// Opens the comparison popup and tests both marked proxies
+    /// `COMPARE_ROUNDS` times, each round sending both requests at once.
+    pub fn start_comparison(&mut self) {
+        let [a, b] = self.marked_proxies.as_slice() else {
+            self.info("Mark two proxies with m to compare them".to_string());
+            return;
+        };
+        let proxies = [a.clone(), b.clone()];
+        self.stop_comparison();
+        // Results of an earlier run may still be queued
+        self.comparison_run += 1;
+        let run = self.comparison_run;
+
+        let requests = [
+            self.delay_request(&proxies[0]),
+            self.delay_request(&proxies[1]),
+        ];
+        let client = self.client.clone();
+        let log = self.api_log.clone();
+        let tx = self.events_tx.clone();
+        self.comparison = Some(Comparison {
+            proxies,
+            results: [Vec::new(), Vec::new()],
+        });
+        self.comparison_task = Some(tokio::spawn(async move {
+            for _ in 0..COMPARE_ROUNDS {
+                let [Some(a), Some(b)] = requests.each_ref().map(|r| r.try_clone()) else {
+                    return;
+                };
+                let (a, b) = futures_util::future::join(
+                    measure_delay(&client, &log, a),
+                    measure_delay(&client, &log, b),
+                )
+                .await;
+                let (a, b) = (a.ok(), b.ok());
+                for (side, delay) in [(0, a), (1, b)] {
+                    let result = AppEvent::Compare { run, side, delay };
+                    if tx.send(result).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        }));
+    }
+
+    pub fn on_compare_result(&mut self, run: u64, side: usize, delay: Option<u64>) {
+        if run != self.comparison_run {
+            return;
+        }
+        if let Some(comparison) = &mut self.comparison {
+            comparison.results[side].push(delay);
+        }
+    }
+
+    /// Closes the comparison popup, cancelling rounds still to run.
+    pub fn stop_comparison(&mut self) {
+        if let Some(task) = self.comparison_task.take() {
+            task.abort();
+        }
+        self.comparison = None;
+    }
+
+    /// Switches a group's proxy without blocking the UI. The new selection is shown
+    /// immediately and marked pending until the controller answers; the result
+    /// arrives through `events_rx` and is handled by `on_switch_result`.
+    pub fn select_proxy(&mut self, group_name: &str, proxy_name: &str) {
+        if let Some(kind) = self.auto_group_kind(group_name) {
+            if !can_pin(kind) {
+                self.info(format!(
+                    "{} is a {} group; the core picks its node",
+                    group_name, kind
+                ));
+                return;
+            }
+            let pinned = self.pinned_proxy(group_name).map(str::to_string);
+            self.modal = Modal::Pin(PinChoice {
+                group: group_name.to_string(),
+                proxy: proxy_name.to_string(),
+                kind,
+                unpin: pinned.as_deref() == Some(proxy_name),
+                pinned,
+            });
+            return;
+        }
+        self.pin_proxy(group_name, proxy_name);
+    }
+
+    /// Answers the pin popup.
+    pub fn answer_pin(&mut self, unpin: bool) {
+        let Modal::Pin(choice) = std::mem::take(&mut self.modal) else {
+            return;
+        };
+        if !unpin {
+            self.pin_proxy(&choice.group, &choice.proxy);
+        } else if choice.pinned.is_none() {
+            self.info(format!("{} already picks its node itself", choice.group));
+        } else if self.is_group_locked(&choice.group) {
+            self.confirm(ConfirmAction::UnpinGroup(choice.group));
+        } else {
+            self.unpin_group(choice.group);
+        }
+    }
+
+    /// Switches a selector, or pins the node of a `url-test` or `fallback`
+    /// group, asking first when the group is locked.
+    fn pin_proxy(&mut self, group_name: &str, proxy_name: &str) {
+        if self.is_group_locked(group_name) {
+            self.confirm(ConfirmAction::SwitchProxy {
+                group: group_name.to_string(),
+                proxy: proxy_name.to_string(),
+            });
+            return;
+        }
+        self.switch_proxy(group_name, proxy_name, false);
+    }
+
+    /// Hands the node choice of `group` back to the core. The answer arrives
+    /// through `events_rx`.
+    fn unpin_group(&mut self, group: String) {
+        if !self.capabilities.supports("/proxies") {
+            return;
+        }
+        if let Some(item) = self.proxies.get_mut(&group) {
+            item.fixed.clear();
+        }
+        let api = self.api();
+        let tx = self.events_tx.clone();
+        tokio::spawn(async move {
+            let result = api.unpin_proxy(&group).await;
+            let _ = tx.send(AppEvent::Unpinned { group, result }).await;
+        });
+    }
+
+    /// The node `group` is pinned to, while the core isn't picking it.
+    pub fn pinned_proxy(&self, group: &str) -> Option<&str> {
+        self.proxies
+            .get(group)
+            .map(|item| item.fixed.as_str())
+            .filter(|fixed| !fixed.is_empty())
+    }
+
+    /// Switches `group_name` to `proxy_name` and waits for the controller to
+    /// confirm, for callers without an event loop to reconcile the result.
+    pub async fn select_proxy_now(&self, group_name: &str, proxy_name: &str) -> Result<()> {
+        Ok(self.api().select_proxy(group_name, proxy_name).await?)
+    }
+
+    /// Switches the selected group back to the proxy it used before the last switch.
+    pub fn revert_selected_group(&mut self) {
+        let Some(group) = self.get_selected_group_name().cloned() else {
+            return;
+        };
+        if self.is_group_locked(&group) {
+            self.confirm(ConfirmAction::RevertGroup(group));
+            return;
+        }
+        self.revert_group(group);
+    }
+
+    fn revert_group(&mut self, group: String) {
+        let Some(previous) = self
+            .selection_history
+            .get_mut(&group)
+            .and_then(|history| history.pop())
+        else {
+            self.info(format!("No earlier selection for {}", group));
+            return;
+        };
+        self.info(format!("Reverted {} to {}", group, previous));
+        self.switch_proxy(&group, &previous, true);
+    }
+
+    fn switch_proxy(&mut self, group_name: &str, proxy_name: &str, revert: bool) {
+        if !self.capabilities.supports("/proxies") {
+            return;
+        }
+        let previous = self
+            .proxies
+            .get_mut(group_name)
+            .and_then(|group| group.now.replace(proxy_name.to_string()));
+        self.pending_switches.insert(
+            group_name.to_string(),
+            PendingSwitch {
+                proxy: proxy_name.to_string(),
+                previous,
+                revert,
+            },
+        );
+
+        let api = self.api();
+        let tx = self.events_tx.clone();
+        let group = group_name.to_string();
+        let proxy = proxy_name.to_string();
+        tokio::spawn(async move {
+            let result = api.select_proxy(&group, &proxy).await;
+            let _ = tx.send(AppEvent::SwitchResult { group, result }).await;
+        });
+    }
+
+    /// Reconciles an optimistic switch: refetch on success, roll back on failure
+    /// and queue the switch if the controller was unreachable.
+    pub fn on_switch_result(&mut self, group_name: String, result: Result<(), ApiError>) {
+        let Some(pending) = self.pending_switches.remove(&group_name) else {
+            return;
+        };
+        match result {
+            Ok(()) => {
+                self.push_traffic_event(
+                    TrafficEventKind::ProxySwitch,
+                    format!("{} → {}", group_name, pending.proxy),
+                );
+                if !pending.revert
+                    && let Some(previous) = pending.previous.filter(|p| *p != pending.proxy)
+                {
+                    let history = self.selection_history.entry(group_name).or_default();
+                    history.push(previous);
+                    if history.len() > SELECTION_HISTORY_LIMIT {
+                        history.remove(0);
+                    }
+                }
+                self.load(Load::Proxies);
+            }
+            Err(e) => {
+                if let Some(group) = self.proxies.get_mut(&group_name)
+                    && group.now.as_deref() == Some(pending.proxy.as_str())
+                {
+                    group.now = pending.previous;
+                }
+                if e.is_offline() {
+                    let mutation = Mutation::SelectProxy {
+                        group: group_name,
+                        proxy: pending.proxy,
+                    };
+                    self.queue_mutation(mutation, e.to_string());
+                } else {
+                    if pending.revert {
+                        // Keep the entry so the revert can be retried
+                        self.selection_history
+                            .entry(group_name)
+                            .or_default()
+                            .push(pending.proxy);
+                    }
+                    self.error(e.to_string());
+                }
+            }
+        }
+    }
+
+    /// A newer switch for the same group supersedes a queued one.
+    fn queue_mutation(&mut self, mutation: Mutation, error: String) {
+        if let Mutation::SelectProxy { group, .. } = &mutation {
+            self.mutation_queue.retain(|queued| {
+                !matches!(&queued.mutation, Mutation::SelectProxy { group: g, .. } if g == group)
+            });
+        }
+        self.warn(format!(
+            "Controller unreachable, queued: {}",
+            mutation.describe()
+        ));
+        self.mutation_queue.push(QueuedMutation {
+            mutation,
+            error,
+            attempts: 1,
+        });
+    }
+
+    /// Replays queued mutations in order in the background, stopping at the
+    /// first one that still can't reach the controller. Mutations the
+    /// controller rejects are dropped.
+    pub fn retry_queued(&mut self) {
+        if self.mutation_queue.is_empty() || self.jobs.contains(&Job::Retry) {
+            return;
+        }
+        let queue = std::mem::take(&mut self.mutation_queue);
+        let api = self.api().with_timeout(Duration::from_secs(5));
+        let work = async move {
+            let mut results = Vec::new();
+            let mut offline = false;
+            for queued in queue {
+                let result = if offline {
+                    None
+                } else {
+                    let result = queued.mutation.send(&api).await;
+                    offline = result.as_ref().is_err_and(ApiError::is_offline);
+                    Some(result)
+                };
+                results.push((queued, result));
+            }
+            results
+        };
+        self.spawn_job(Job::Retry, work, App::on_queue_retried);
+    }
+
+    /// Takes in what `retry_queued` sent; `None` is a mutation left untried.
+    fn on_queue_retried(&mut self, results: Vec<(QueuedMutation, Option<Result<(), ApiError>>)>) {
+        let mut remaining = Vec::new();
+        let mut applied = 0;
+        for (mut queued, result) in results {
+            match result {
+                None => remaining.push(queued),
+                Some(Ok(())) => applied += 1,
+                Some(Err(e)) if e.is_offline() => {
+                    queued.attempts += 1;
+                    queued.error = e.to_string();
+                    remaining.push(queued);
+                }
+                Some(Err(e)) => {
+                    self.error(format!(
+                        "Dropped queued change ({}): {}",
+                        queued.mutation.describe(),
+                        e
+                    ));
+                }
+            }
+        }
+        // Changes queued meanwhile go after the older ones
+        remaining.append(&mut self.mutation_queue);
+        self.mutation_queue = remaining;
+        let len = self.mutation_queue.len();
+        self.queue_state.select(
+            len.checked_sub(1)
+                .map(|last| self.queue_state.selected().unwrap_or(0).min(last)),
+        );
+
+        if applied > 0 {
+            self.info(format!("Applied {} queued change(s)", applied));
+            self.load(Load::Proxies);
+            self.load(Load::Config);
+        }
+    }
+
+    pub fn drop_selected_queued(&mut self) {
+        let Some(index) = self.queue_state.selected() else {
+            return;
+        };
+        if index < self.mutation_queue.len() {
+            self.mutation_queue.remove(index);
+        }
+        let len = self.mutation_queue.len();
+        self.queue_state
+            .select(len.checked_sub(1).map(|last| index.min(last)));
+    }
+
+    pub fn next_queued(&mut self) {
+        let len = self.mutation_queue.len();
+        if len == 0 {
+            return;
+        }
+        let i = match self.queue_state.selected() {
+            Some(i) if i + 1 < len => i + 1,
+            _ => 0,
+        };
+        self.queue_state.select(Some(i));
+    }
+
+    pub fn previous_queued(&mut self) {
+        let len = self.mutation_queue.len();
+        if len == 0 {
+            return;
+        }
+        let i = match self.queue_state.selected() {
+            Some(0) | None => len - 1,
+            Some(i) => i - 1,
+        };
+        self.queue_state.select(Some(i));
+    }
+
+    pub fn is_switch_pending(&self, group_name: &str, proxy_name: &str) -> bool {
+        self.pending_switches
+            .get(group_name)
+            .is_some_and(|p| p.proxy == proxy_name)
+    }
+
+    /// Splits `<group> <proxy>` where both names may contain spaces, preferring the
+    /// longest group name that prefixes the input.
+    fn split_group_arg<'a>(&self, rest: &'a str) -> Option<(&'a str, &'a str)> {
+        self.group_names
+            .iter()
+            .filter(|g| {
+                rest.strip_prefix(g.as_str())
+                    .is_some_and(|tail| tail.starts_with(' '))
+            })
+            .max_by_key(|g| g.len())
+            .map(|g| (&rest[..g.len()], &rest[g.len() + 1..]))
+    }
+
+    /// Completes the command palette input from live data. Returns the completed
+    /// input and the remaining candidates when the completion is ambiguous.
+    pub fn complete_command(&self, input: &str) -> (String, Vec<String>) {
+        let Some(rest) = input.strip_prefix("select ") else {
+            let matches: Vec<String> = PALETTE_COMMANDS
+                .iter()
+                .filter(|c| c.starts_with(input))
+                .map(|c| c.to_string())
+                .collect();
+            return match matches.as_slice() {
+                [only] => (only.clone(), Vec::new()),
+                _ => (input.to_string(), matches),
+            };
+        };
+
+        let (prefix, partial, candidates, suffix): (String, &str, Vec<String>, &str) =
+            match self.split_group_arg(rest) {
+                Some((group, partial)) => {
+                    let all = self
+                        .proxies
+                        .get(group)
+                        .and_then(|g| g.all.clone())
+                        .unwrap_or_default();
+                    (format!("select {} ", group), partial, all, "")
+                }
+                None => ("select ".to_string(), rest, self.group_names.clone(), " "),
+            };
+
+        let matches: Vec<String> = candidates
+            .into_iter()
+            .filter(|c| c.starts_with(partial))
+            .collect();
+        match matches.as_slice() {
+            [] => (input.to_string(), Vec::new()),
+            [only] => (format!("{}{}{}", prefix, only, suffix), Vec::new()),
+            _ => {
+                let mut common = matches[0].clone();
+                for m in &matches[1..] {
+                    while !m.starts_with(common.as_str()) {
+                        common.pop();
+                    }
+                }
+                (format!("{}{}", prefix, common), matches)
+            }
+        }
+    }
+
+    /// Executes a command palette line: `select <group> <proxy>`, `bulk
+    /// <groups> <proxies>`, `restart`, `flush fakeip` or `flush dns`. The last
+    /// three ask for confirmation; `bulk` shows a preview first.
+    pub fn run_command(&mut self, input: &str) -> Result<()> {
+        let input = input.trim();
+        let action = match input {
+            "restart" => Some(ConfirmAction::RestartCore),
+            "flush fakeip" => Some(ConfirmAction::FlushFakeIp),
+            "flush dns" => Some(ConfirmAction::FlushDns),
+            _ => None,
+        };
+        if let Some(action) = action {
+            self.confirm(action);
+            return Ok(());
+        }
+        if let Some(line) = input.strip_prefix("request ") {
+            return self.run_raw_request(line);
+        }
+        if let Some(args) = input.strip_prefix("bulk ") {
+            let Some((groups, proxies)) = args.trim().split_once(char::is_whitespace) else {
+                self.error("Usage: bulk <groups> <proxies>, e.g. bulk Stream* HK");
+                return Ok(());
+            };
+            self.modal = Modal::BulkSelect(self.plan_bulk_selection(groups, proxies.trim()));
+            return Ok(());
+        }
+        if let Some(args) = input.strip_prefix("dns ") {
+            let mut args = args.split_whitespace();
+            let mut tool = DnsTool {
+                input: args.next().unwrap_or_default().to_string(),
+                ..Default::default()
+            };
+            if let Some(record_type) = args.next() {
+                tool.record_type = DNS_QUERY_TYPES
+                    .iter()
+                    .position(|t| t.eq_ignore_ascii_case(record_type))
+                    .ok_or_else(|| anyhow::anyhow!("Unknown record type {}", record_type))?;
+            }
+            self.modal = Modal::Dns(tool);
+            self.run_dns_query();
+            return Ok(());
+        }
+        let Some(rest) = input.strip_prefix("select ") else {
+            self.error(format!("Unknown command: {}", input));
+            return Ok(());
+        };
+        let Some((group, proxy)) = self.split_group_arg(rest) else {
+            self.error(format!("Unknown group in: {}", rest));
+            return Ok(());
+        };
+        let (group, proxy) = (group.to_string(), proxy.to_string());
+        let is_member = self
+            .proxies
+            .get(&group)
+            .and_then(|g| g.all.as_ref())
+            .is_some_and(|all| all.contains(&proxy));
+        if !is_member {
+            self.error(format!("{} is not a member of {}", proxy, group));
+            return Ok(());
+        }
+
+        // Naming the node is answer enough for url-test and fallback groups
+        if let Some(kind) = self.auto_group_kind(&group)
+            && !can_pin(kind)
+        {
+            self.error(format!(
+                "{} is a {} group; the core picks its node",
+                group, kind
+            ));
+            return Ok(());
+        }
+
+        self.pin_proxy(&group, &proxy);
+        if !self.is_group_locked(&group) {
+            self.info(format!("{} -> {}", group, proxy));
+        }
+        Ok(())
+    }
+
+    /// Picks, in every group whose name matches `groups`, the member matching
+    /// `proxies` with the lowest known delay. Untested members are passed over.
+    pub fn plan_bulk_selection(&self, groups: &str, proxies: &str) -> BulkSelection {
+        let changes = self
+            .group_names
+            .iter()
+            .filter(|name| glob_match(groups, name))
+            .map(|name| {
+                let group = self.proxies.get(name);
+                let now = group.and_then(|g| g.now.clone());
+                let outcome = if let Some(kind) = self.auto_group_kind(name) {
+                    BulkOutcome::Skip(format!("{} group; the core picks its node", kind))
+                } else {
+                    let members: Vec<&String> = group
+                        .and_then(|g| g.all.as_ref())
+                        .into_iter()
+                        .flatten()
+                        .filter(|member| glob_match(proxies, member))
+                        .collect();
+                    let fastest = members
+                        .iter()
+                        .filter_map(|member| {
+                            let delay = self
+                                .proxy_latency
+                                .get(member.as_str())
+                                .copied()
+                                .flatten()
+                                .or_else(|| self.group_latency(member))?;
+                            Some((*member, delay))
+                        })
+                        .min_by_key(|(_, delay)| *delay);
+                    match fastest {
+                        Some((proxy, delay)) if now.as_ref() == Some(proxy) => {
+                            BulkOutcome::Unchanged { delay }
+                        }
+                        Some((proxy, delay)) => BulkOutcome::Switch {
+                            proxy: proxy.clone(),
+                            delay,
+                        },
+                        None if members.is_empty() => {
+                            BulkOutcome::Skip("no member matches".to_string())
+                        }
+                        None => BulkOutcome::Skip(
+                            "no matching member has a delay result; test the group first"
+                                .to_string(),
+                        ),
+                    }
+                };
+                BulkChange {
+                    group: name.clone(),
+                    now,
+                    locked: self.is_group_locked(name),
+                    outcome,
+                }
+            })
+            .collect();
+        BulkSelection {
+            groups: groups.to_string(),
+            proxies: proxies.to_string(),
+            changes,
+        }
+    }
+
+    /// Switches every group the open bulk preview plans to. Confirming the
+    /// preview covers locked groups too; they are marked in it.
+    pub fn apply_bulk_selection(&mut self) {
+        let Modal::BulkSelect(selection) = std::mem::take(&mut self.modal) else {
+            return;
+        };
+        self.popup_scroll = 0;
+        let mut switched = 0;
+        for change in &selection.changes {
+            if let BulkOutcome::Switch { proxy, .. } = &change.outcome {
+                self.switch_proxy(&change.group, proxy, false);
+                switched += 1;
+            }
+        }
+        self.info(match switched {
+            0 => "Nothing to switch".to_string(),
+            1 => "Switching 1 group".to_string(),
+            n => format!("Switching {} groups", n),
+        });
+    }
+
+    /// A client for the controller in use, recording into the debug console.
+    pub fn api(&self) -> MihomoClient {
+        MihomoClient::new(
+            self.client.clone(),
+            &self.app_settings.base_url,
+            &self.app_settings.api_secret,
+        )
+        .with_log(self.api_log.clone())
+    }
+
+    /// Runs a request typed in the debug console; see `raw_request`.
+    pub fn run_debug_request(&mut self, line: &str) {
+        match self.raw_request(line) {
+            // Failures are recorded in the log itself
+            Ok(request) => {
+                let api = self.api();
+                let work = async move {
+                    let _ = api.send(request).await;
+                };
+                self.spawn_job(Job::Request, work, |app, ()| {
+                    app.debug_state.select(Some(0));
+                });
+            }
+            Err(e) => self.error(format!("{:#}", e)),
+        }
+    }
+
+    /// Runs a `request` from the command palette and shows the answer in a
+    /// popup, unless another one was opened meanwhile.
+    pub fn run_raw_request(&mut self, line: &str) -> Result<()> {
+        let request = self.raw_request(line)?;
+        let api = self.api();
+        let line = line.trim().to_string();
+        let work = async move {
+            let start = Instant::now();
+            let (status, body) = match api.send(request).await {
+                Ok(resp) => {
+                    // Pretty-print JSON; anything else is shown as it came
+                    let body = serde_json::from_str::<serde_json::Value>(&resp.body)
+                        .ok()
+                        .and_then(|json| serde_json::to_string_pretty(&json).ok())
+                        .unwrap_or(resp.body);
+                    (Ok(resp.status.as_u16()), body)
+                }
+                Err(e) => (Err(e.to_string()), String::new()),
+            };
+            RawResponse {
+                request: line,
+                status,
+                elapsed: start.elapsed(),
+                body,
+            }
+        };
+        self.spawn_job(Job::Request, work, |app, response| {
+            if matches!(app.modal, Modal::None) {
+                app.modal = Modal::Response(response);
+                app.popup_scroll = 0;
+            } else {
+                match response.status {
+                    Ok(code) => app.info(format!("{}: {}", response.request, code)),
+                    Err(e) => app.error(format!("{}: {}", response.request, e)),
+                }
+            }
+        });
+        Ok(())
+    }
+
+    pub fn open_dns_tool(&mut self) {
+        if !self.capabilities.supports_feature(CoreFeature::DnsQuery) {
+            self.warn("The DNS query tool needs mihomo 1.15 or later");
+            return;
+        }
+        self.modal = Modal::Dns(DnsTool::default());
+    }
+
+    /// Looks up the name typed in the DNS tool through the core, in the
+    /// background; Enter does nothing while a lookup is running.
+    pub fn run_dns_query(&mut self) {
+        let Modal::Dns(tool) = &self.modal else {
+            return;
+        };
+        let name = tool.input.trim().to_string();
+        if name.is_empty() || self.jobs.contains(&Job::DnsQuery) {
+            return;
+        }
+        let record_type = DNS_QUERY_TYPES[tool.record_type];
+
+        let api = self
+            .api()
+            .with_timeout(Duration::from_millis(self.app_settings.test_timeout));
+        let query = name.clone();
+        let work = async move {
+            let start = Instant::now();
+            let answer = api
+                .dns_query(&query, record_type)
+                .await
+                .map_err(|e| match e {
+                    ApiError::Status(StatusCode::NOT_FOUND) => {
+                        "The core has no DNS query API".to_string()
+                    }
+                    e => e.to_string(),
+                });
+            (answer, start.elapsed())
+        };
+        self.spawn_job(Job::DnsQuery, work, move |app, (answer, elapsed)| {
+            let resolver = match &answer {
+                Ok(answer) => app.dns_resolver_hint(&name, answer),
+                Err(_) => String::new(),
+            };
+            if let Modal::Dns(tool) = &mut app.modal {
+                tool.lookup = Some(DnsLookup {
+                    name,
+                    record_type,
+                    answer,
+                    elapsed,
+                    resolver,
+                });
+            }
+        });
+    }
+
+    /// What most likely answered `name`. `/dns/query` doesn't say, so this
+    /// follows the core's DNS config: hosts, then fake-ip, then
+    /// `nameserver-policy`, then the plain nameservers.
+    fn dns_resolver_hint(&self, name: &str, answer: &DnsQueryResponse) -> String {
+        let Some(config) = &self.config else {
+            return "unknown: the core config isn't loaded".to_string();
+        };
+        let name = name.trim_end_matches('.').to_lowercase();
+        if let Some(pattern) = config
+            .hosts
+            .keys()
+            .find(|pattern| domain_matches(pattern, &name))
+        {
+            return format!("hosts entry {}", pattern);
+        }
+        let Some(dns) = &config.dns else {
+            return "the core's nameservers (its config doesn't list them)".to_string();
+        };
+        if !dns.enable {
+            return "the system resolver (the core's DNS is off)".to_string();
+        }
+        let fake_ip = dns.enhanced_mode == "fake-ip"
+            && answer.answer.iter().any(|record| {
+                record
+                    .data
+                    .parse()
+                    .is_ok_and(|ip| ip_in_prefix(ip, &dns.fake_ip_range))
+            });
+        if fake_ip {
+            return format!("the fake-ip pool {}", dns.fake_ip_range);
+        }
+        let policy = dns
+            .extra
+            .get("nameserver-policy")
+            .and_then(|policy| policy.as_object());
+        for (patterns, servers) in policy.into_iter().flatten() {
+            if let Some(pattern) = patterns
+                .split(',')
+                .map(str::trim)
+                .find(|p| domain_matches(p, &name))
+            {
+                let servers = match servers {
+                    serde_json::Value::Array(list) => list
+                        .iter()
+                        .filter_map(|s| s.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    other => other.as_str().unwrap_or_default().to_string(),
+                };
+                return format!("nameserver-policy {}: {}", pattern, servers);
+            }
+        }
+        if dns.nameserver.is_empty() {
+            return "the core's default nameservers".to_string();
+        }
+        let mut hint = format!("nameserver: {}", dns.nameserver.join(", "));
+        if !dns.fallback.is_empty() {
+            hint.push_str(&format!(" (fallback: {})", dns.fallback.join(", ")));
+        }
+        hint
+    }
+
+    /// Builds a request from a line like `GET /version` or
+    /// `PATCH /configs {"mode":"rule"}`, with the controller's secret applied.
+    /// A bare path defaults to GET; a body must be valid JSON. Full URLs are
+    /// only accepted on the controller itself, so the secret never leaves it.
+    fn raw_request(&self, line: &str) -> Result<RequestBuilder> {
+        let line = line.trim();
+        let (method, rest) = match line.split_once(' ') {
+            Some((m, rest)) if !m.starts_with('/') && !m.starts_with("http") => {
+                (m.to_uppercase(), rest.trim())
+            }
+            _ if line.starts_with('/') || line.starts_with("http") => ("GET".to_string(), line),
+            _ => (line.to_uppercase(), "/"),
+        };
+        let (path, body) = match rest.split_once(' ') {
+            Some((path, body)) => (path, Some(body.trim().to_string())),
+            None => (rest, None),
+        };
+
+        let path = if path.starts_with("http") {
+            let base = self.app_settings.base_url.trim_end_matches('/');
+            match path.strip_prefix(base) {
+                Some(rest) if rest.is_empty() || rest.starts_with(['/', '?']) => rest,
+                _ => anyhow::bail!("Only requests to {} are allowed", base),
+            }
+        } else {
+            path
+        };
+        let Ok(method) = Method::from_bytes(method.as_bytes()) else {
+            anyhow::bail!("Invalid HTTP method: {}", method);
+        };
+
+        let mut request = self
+            .api()
+            .with_timeout(RAW_REQUEST_TIMEOUT)
+            .request(method, path);
+        if let Some(body) = body {
+            if let Err(e) = serde_json::from_str::<serde_json::Value>(&body) {
+                anyhow::bail!("The body is not valid JSON: {}", e);
+            }
+            request = request
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body);
+        }
+        Ok(request)
+    }
+
+    pub fn next_debug_entry(&mut self) {
+        let len = self.api_log.lock().map(|log| log.len()).unwrap_or(0);
+        if len == 0 {
+            return;
+        }
+        let i = match self.debug_state.selected() {
+            Some(i) if i + 1 < len => i + 1,
+            _ => 0,
+        };
+        self.debug_state.select(Some(i));
+    }
+
+    pub fn previous_debug_entry(&mut self) {
+        let len = self.api_log.lock().map(|log| log.len()).unwrap_or(0);
+        if len == 0 {
+            return;
+        }
+        let i = match self.debug_state.selected() {
+            Some(0) | None => len - 1,
+            Some(i) => i - 1,
+        };
+        self.debug_state.select(Some(i));
+    }
+
+    /// Rebuilds the Groups list: pinned groups first in their saved order, the
+    /// rest alphabetically, leaving out hidden groups unless they are shown.
+    /// The cursor stays on the same group when it is still listed.
+    pub fn rebuild_group_names(&mut self) {
+        let selected = self.get_selected_group_name().cloned();
+        let settings = &self.app_settings;
+
+        let mut names: Vec<String> = self
+            .proxies
+            .values()
+            .filter(|p| {
+                matches!(p.proxy_type.as_deref(), Some("Selector" | "Smart"))
+                    || p.proxy_type.as_deref().and_then(auto_group_kind).is_some()
+            })
+            .filter(|p| self.show_hidden_groups || !p.hidden)
+            .filter_map(|p| p.name.clone())
+            .filter(|name| self.show_hidden_groups || !settings.hidden_groups.contains(name))
+            .collect();
+        names.sort();
+        names.sort_by_key(|name| {
+            settings
+                .pinned_groups
+                .iter()
+                .position(|p| p == name)
+                .unwrap_or(usize::MAX)
+        });
+        self.group_names = names;
+
+        if let Some(index) = selected.and_then(|s| self.group_names.iter().position(|n| *n == s)) {
+            self.group_state.select(Some(index));
+        } else if self
+            .group_state
+            .selected()
+            .is_none_or(|i| i >= self.group_names.len())
+        {
+            // The list loses its selection when drawn empty, as it is while
+            // the first load runs
+            self.group_state.select(Some(0));
+        }
+    }
+
+    pub fn toggle_pin_selected_group(&mut self) -> Result<()> {
+        let Some(name) = self.get_selected_group_name().cloned() else {
+            return Ok(());
+        };
+        let pinned = &mut self.app_settings.pinned_groups;
+        if let Some(index) = pinned.iter().position(|p| *p == name) {
+            pinned.remove(index);
+        } else {
+            pinned.push(name);
+        }
+        self.rebuild_group_names();
+        self.save_app_settings()
+    }
+
+    /// Moves the selected group up or down among the pinned groups, pinning it first if needed.
+    pub fn move_selected_group(&mut self, up: bool) -> Result<()> {
+        let Some(name) = self.get_selected_group_name().cloned() else {
+            return Ok(());
+        };
+        let pinned = &mut self.app_settings.pinned_groups;
+        let index = match pinned.iter().position(|p| *p == name) {
+            Some(index) => index,
+            None => {
+                pinned.push(name);
+                pinned.len() - 1
+            }
+        };
+        if up && index > 0 {
+            pinned.swap(index, index - 1);
+        } else if !up && index + 1 < pinned.len() {
+            pinned.swap(index, index + 1);
+        }
+        self.rebuild_group_names();
+        self.save_app_settings()
+    }
+
+    pub fn toggle_hide_selected_group(&mut self) -> Result<()> {
+        let Some(name) = self.get_selected_group_name().cloned() else {
+            return Ok(());
+        };
+        let hidden = &mut self.app_settings.hidden_groups;
+        if let Some(index) = hidden.iter().position(|h| *h == name) {
+            hidden.remove(index);
+        } else {
+            hidden.push(name);
+        }
+        self.rebuild_group_names();
+        self.select_current_proxy();
+        self.save_app_settings()
+    }
+
+    /// Binds the selected group to the first free key of F1–F11, or unbinds it.
+    pub fn toggle_hotkey_selected_group(&mut self) -> Result<()> {
+        let Some(name) = self.get_selected_group_name().cloned() else {
+            return Ok(());
+        };
+        let hotkeys = &mut self.app_settings.group_hotkeys;
+        if let Some(key) = hotkeys.iter().find(|(_, g)| **g == name).map(|(k, _)| *k) {
+            hotkeys.remove(&key);
+            self.info(format!("F{} unbound", key));
+        } else if let Some(key) = (1..=GROUP_HOTKEY_LIMIT).find(|k| !hotkeys.contains_key(k)) {
+            hotkeys.insert(key, name.clone());
+            self.info(format!("F{} jumps to {}", key, name));
+        } else {
+            self.info(format!("F1–F{} are all bound", GROUP_HOTKEY_LIMIT));
+            return Ok(());
+        }
+        self.save_app_settings()
+    }
+
+    pub fn group_hotkey(&self, name: &str) -> Option<u8> {
+        self.app_settings
+            .group_hotkeys
+            .iter()
+            .find(|(_, g)| *g == name)
+            .map(|(k, _)| *k)
+    }
+
+    /// Selects the group bound to F`key` and focuses its proxies.
+    pub fn jump_to_group_hotkey(&mut self, key: u8) {
+        let Some(name) = self.app_settings.group_hotkeys.get(&key).cloned() else {
+            self.info(format!("F{} isn't bound; press B on a group", key));
+            return;
+        };
+        if !self.jump_to_group(&name) {
+            self.info(format!("{} isn't listed", name));
+        }
+    }
+
+    /// Shows `name`'s proxies in the main view; false when it isn't listed.
+    pub fn jump_to_group(&mut self, name: &str) -> bool {
+        let Some(index) = self.group_names.iter().position(|g| g == name) else {
+            return false;
+        };
+        self.group_trail.clear();
+        self.group_state.select(Some(index));
+        self.select_current_proxy();
+        self.focus = Focus::Proxies;
+        true
+    }
+
+    /// Hidden either locally or by the core's `hidden` group flag.
+    pub fn is_group_hidden(&self, name: &str) -> bool {
+        self.app_settings.hidden_groups.iter().any(|h| h == name)
+            || self.proxies.get(name).is_some_and(|p| p.hidden)
+    }
+
+    /// The config name of `name`'s group type when the core picks its node
+    /// itself, e.g. `url-test`.
+    pub fn auto_group_kind(&self, name: &str) -> Option<&'static str> {
+        self.proxies
+            .get(name)
+            .and_then(|p| p.proxy_type.as_deref())
+            .and_then(auto_group_kind)
+    }
+
+    pub fn toggle_lock_selected_group(&mut self) -> Result<()> {
+        let Some(name) = self.get_selected_group_name().cloned() else {
+            return Ok(());
+        };
+        let locked = &mut self.app_settings.locked_groups;
+        let message = if let Some(index) = locked.iter().position(|l| *l == name) {
+            locked.remove(index);
+            format!("Unlocked {}", name)
+        } else {
+            locked.push(name.clone());
+            format!("Locked {}; switching it now asks first", name)
+        };
+        self.info(message);
+        self.save_app_settings()
+    }
+
+    pub fn is_group_locked(&self, name: &str) -> bool {
+        self.app_settings.locked_groups.iter().any(|l| l == name)
+    }
+
+    pub fn is_group_pinned(&self, name: &str) -> bool {
+        self.app_settings.pinned_groups.iter().any(|p| p == name)
+    }
+
+    // Navigation Helpers
+    pub fn start_type_ahead(&mut self) {
+        self.type_ahead = Some((String::new(), Instant::now()));
+    }
 
-        self.traffic_history_up.pop_front();
-        self.traffic_history_up.push_back(traffic.up);
+    pub fn stop_type_ahead(&mut self) {
+        self.type_ahead = None;
+    }
 
-        self.traffic_history_down.pop_front();
-        self.traffic_history_down.push_back(traffic.down);
+    /// The pending type-ahead text, while it hasn't timed out.
+    pub fn type_ahead_text(&self) -> Option<&str> {
+        self.type_ahead
+            .as_ref()
+            .filter(|(_, at)| at.elapsed() < TYPE_AHEAD_TIMEOUT)
+            .map(|(text, _)| text.as_str())
     }
 
-    fn get_config_path() -> Option<PathBuf> {
-        if let Ok(home) = std::env::var("HOME") {
-            let mut path = PathBuf::from(home);
-            path.push(".config");
-            path.push("mihomot");
-            let _ = fs::create_dir_all(&path);
-            path.push("settings.json");
-            Some(path)
-        } else {
-            None
+    /// Extends the type-ahead text and jumps to the first entry of the focused
+    /// list starting with it, or failing that, containing it.
+    pub fn type_ahead_push(&mut self, c: Option<char>) {
+        let mut text = self.type_ahead_text().unwrap_or_default().to_string();
+        match c {
+            Some(c) => text.push(c),
+            None => {
+                text.pop();
+            }
+        }
+        let needle = text.to_lowercase();
+        self.type_ahead = Some((text, Instant::now()));
+        if needle.is_empty() {
+            return;
         }
-    }
 
-    fn load_app_settings() -> AppSettings {
-        if let Some(path) = Self::get_config_path()
-            && path.exists()
-            && let Ok(content) = fs::read_to_string(path)
-        {
-            return serde_json::from_str(&content).unwrap_or_default();
+        let names = match self.focus {
+            Focus::Groups => self.group_names.clone(),
+            Focus::Proxies => self.visible_proxies(),
+            _ => return,
+        };
+        let lowered: Vec<String> = names.iter().map(|n| n.to_lowercase()).collect();
+        let Some(index) = lowered
+            .iter()
+            .position(|n| n.starts_with(&needle))
+            .or_else(|| lowered.iter().position(|n| n.contains(&needle)))
+        else {
+            return;
+        };
+        match self.focus {
+            Focus::Groups => {
+                self.group_state.select(Some(index));
+                self.select_current_proxy();
+            }
+            _ => self.proxy_state.select(Some(index)),
         }
-        AppSettings::default()
     }
 
-    pub fn save_app_settings(&self) -> Result<()> {
-        if let Some(path) = Self::get_config_path() {
-            let json = serde_json::to_string_pretty(&self.app_settings)?;
-            fs::write(path, json)?;
+    pub fn start_proxy_search(&mut self) {
+        self.proxy_search_origin = self.get_selected_proxy_name();
+        self.proxy_search = Some(String::new());
+    }
+
+    /// Extends or shortens the search query and puts the cursor on the first match.
+    pub fn proxy_search_push(&mut self, c: Option<char>) {
+        let Some(query) = self.proxy_search.as_mut() else {
+            return;
+        };
+        match c {
+            Some(c) => query.push(c),
+            None => {
+                query.pop();
+            }
         }
-        Ok(())
+        let any = !self.visible_proxies().is_empty();
+        self.proxy_state.select(any.then_some(0));
     }
 
-    pub fn scroll_popup_down(&mut self) {
-        self.popup_scroll = self.popup_scroll.saturating_add(1);
+    /// Ends the search. With `jump`, the cursor stays on the selected match in
+    /// the full list; otherwise it goes back to where the search started.
+    pub fn finish_proxy_search(&mut self, jump: bool) {
+        let matched = self.get_selected_proxy_name();
+        let origin = self.proxy_search_origin.take();
+        self.proxy_search = None;
+        let target = if jump { matched.or(origin) } else { origin };
+        match target.and_then(|name| self.visible_proxies().iter().position(|p| *p == name)) {
+            Some(index) => self.proxy_state.select(Some(index)),
+            None => self.select_current_proxy(),
+        }
     }
 
-    pub fn scroll_popup_up(&mut self) {
-        self.popup_scroll = self.popup_scroll.saturating_sub(1);
+    /// How many rows a j/k press should move: 1 normally, more the longer
+    /// the key is held.
+    pub fn scroll_step(&mut self) -> usize {
+        let streak = match self.scroll_streak {
+            Some((at, streak)) if at.elapsed() < SCROLL_REPEAT_WINDOW => streak + 1,
+            _ => 0,
+        };
+        self.scroll_streak = Some((Instant::now(), streak));
+        match streak {
+            0..=5 => 1,
+            6..=15 => 3,
+            _ => 8,
+        }
     }
 
-    pub fn next_setting(&mut self) {
-        let i = match self.settings_state.selected() {
+    pub fn next_group(&mut self) {
+        if self.group_names.is_empty() {
+            return;
+        }
+        let i = match self.group_state.selected() {
             Some(i) => {
-                if i >= self.settings_items.len() - 1 {
+                if i >= self.group_names.len() - 1 {
                     0
                 } else {
                     i + 1
@@ -334,203 +7045,186 @@ impl App {
             }
             None => 0,
         };
-        self.settings_state.select(Some(i));
+        self.group_trail.clear();
+        self.group_state.select(Some(i));
+        self.select_current_proxy();
     }
 
-    pub fn previous_setting(&mut self) {
-        let i = match self.settings_state.selected() {
+    pub fn previous_group(&mut self) {
+        if self.group_names.is_empty() {
+            return;
+        }
+        let i = match self.group_state.selected() {
             Some(i) => {
                 if i == 0 {
-                    self.settings_items.len() - 1
+                    self.group_names.len() - 1
                 } else {
                     i - 1
                 }
             }
             None => 0,
         };
-        self.settings_state.select(Some(i));
+        self.group_trail.clear();
+        self.group_state.select(Some(i));
+        self.select_current_proxy();
     }
 
-    pub async fn update_config(&mut self, json_body: serde_json::Value) -> Result<()> {
-        let url = format!("{}/configs", self.app_settings.base_url);
-        let mut request = self.client.patch(&url).json(&json_body);
-
-        if !self.app_settings.api_secret.is_empty() {
-            request = request.bearer_auth(&self.app_settings.api_secret);
+    /// Enter on the member a group currently uses, when that member is a
+    /// group itself: shows its proxies and remembers the way back. False for
+    /// any other member, which Enter switches to as usual.
+    pub fn enter_nested_group(&mut self) -> bool {
+        let (Some(group), Some(member)) = (
+            self.get_selected_group_name().cloned(),
+            self.get_selected_proxy_name(),
+        ) else {
+            return false;
+        };
+        let current = self.proxies.get(&group).and_then(|g| g.now.as_deref());
+        let nested = self.proxies.get(&member).is_some_and(|m| m.all.is_some());
+        if current != Some(member.as_str()) || !nested {
+            return false;
         }
-
-        request.send().await?;
-        // Fetch updated config to sync UI
-        self.fetch_config().await?;
-        Ok(())
-    }
-
-    pub async fn fetch_proxies(&mut self) -> Result<()> {
-        let url = format!("{}/proxies", self.app_settings.base_url);
-        let mut request = self.client.get(&url);
-
-        if !self.app_settings.api_secret.is_empty() {
-            request = request.bearer_auth(&self.app_settings.api_secret);
+        let mut trail = std::mem::take(&mut self.group_trail);
+        if self.jump_to_group(&member) {
+            trail.push(group);
+        } else {
+            self.info(format!("{} is hidden; H shows hidden groups", member));
         }
+        self.group_trail = trail;
+        true
+    }
 
-        match request.send().await {
-            Ok(resp) => {
-                if resp.status().is_success() {
-                    match resp.json::<ProxiesResponse>().await {
-                        Ok(data) => {
-                            self.proxies = data.proxies;
-
-                            // Populate latency from history
-                            for (name, item) in &self.proxies {
-                                if let Some(history) =
-                                    item.extra.get("history").and_then(|h| h.as_array())
-                                    && let Some(last) = history.last()
-                                    && let Some(delay) = last.get("delay").and_then(|d| d.as_u64())
-                                    && delay > 0
-                                {
-                                    self.proxy_latency.insert(name.clone(), Some(delay));
-                                }
-                            }
-
-                            self.group_names = self
-                                .proxies
-                                .values()
-                                .filter(|p| p.proxy_type.as_deref() == Some("Selector"))
-                                .filter_map(|p| p.name.clone())
-                                .collect();
-                            self.group_names.sort();
-                            self.error = None;
-                        }
-                        Err(e) => self.error = Some(format!("Failed to parse JSON: {}", e)),
-                    }
-                } else {
-                    self.error = Some(format!("Server returned error: {}", resp.status()));
-                }
-            }
-            Err(e) => self.error = Some(format!("Failed to connect: {}", e)),
+    /// Back to the group the selected one was opened from.
+    pub fn leave_nested_group(&mut self) {
+        let mut trail = std::mem::take(&mut self.group_trail);
+        if let Some(parent) = trail.pop()
+            && self.jump_to_group(&parent)
+        {
+            self.group_trail = trail;
         }
-        Ok(())
     }
 
-    pub async fn fetch_config(&mut self) -> Result<()> {
-        let url = format!("{}/configs", self.app_settings.base_url);
-        let mut request = self.client.get(&url);
-        if !self.app_settings.api_secret.is_empty() {
-            request = request.bearer_auth(&self.app_settings.api_secret);
+    /// `PROXY > HK > HK-01`: the groups opened through, the selected group
+    /// and the member it uses. `None` unless a group was opened through.
+    pub fn group_breadcrumbs(&self) -> Option<String> {
+        if self.group_trail.is_empty() {
+            return None;
         }
-        let resp = request.send().await?;
-        if resp.status().is_success() {
-            self.config = Some(resp.json::<Config>().await?);
+        let group = self.get_selected_group_name()?;
+        let mut crumbs = self.group_trail.clone();
+        crumbs.push(group.clone());
+        if let Some(now) = self.proxies.get(group).and_then(|g| g.now.clone()) {
+            crumbs.push(now);
         }
-        Ok(())
+        Some(crumbs.join(" > "))
     }
 
-    pub fn trigger_latency_test(&mut self) {
-        let client = self.client.clone();
-        let url = self.app_settings.test_url.clone();
-        let timeout = self.app_settings.test_timeout;
-        let tx = self.real_latency_tx.clone();
-
-        self.real_latency_status = RealLatencyStatus::Testing;
-
-        tokio::spawn(async move {
-            use std::time::Instant;
-            let start = Instant::now();
+    /// Moves the proxy cursor onto the group's active (`now`) proxy, or the first row.
+    pub fn select_current_proxy(&mut self) {
+        let now = self
+            .get_selected_group_name()
+            .and_then(|name| self.proxies.get(name))
+            .and_then(|group| group.now.clone());
+        let index = now
+            .and_then(|now| self.visible_proxies().iter().position(|p| *p == now))
+            .unwrap_or(0);
+        self.proxy_state.select(Some(index));
+    }
 
-            match client
-                .head(&url)
-                .timeout(Duration::from_millis(timeout))
-                .send()
-                .await
-            {
-                Ok(resp) => {
-                    if resp.status().is_success() || resp.status().is_redirection() {
-                        let delay = start.elapsed().as_millis() as u64;
-                        let _ = tx.send(RealLatencyStatus::Success(delay)).await;
-                    } else {
-                        let _ = tx
-                            .send(RealLatencyStatus::Failed(format!(
-                                "Status: {}",
-                                resp.status()
-                            )))
-                            .await;
-                    }
-                }
-                Err(e) => {
-                    let msg = if e.is_timeout() {
-                        "Timeout".to_string()
-                    } else if e.is_connect() {
-                        "Conn Err".to_string()
-                    } else {
-                        "Error".to_string()
-                    };
-                    let _ = tx.send(RealLatencyStatus::Failed(msg)).await;
-                }
-            }
-        });
+    /// Members of the selected group, limited to the provider filter and the
+    /// search query when they are set, in the order picked with `o`.
+    pub fn visible_proxies(&self) -> Vec<String> {
+        let Some(all) = self
+            .get_selected_group_name()
+            .and_then(|name| self.proxies.get(name))
+            .and_then(|group| group.all.as_ref())
+        else {
+            return Vec::new();
+        };
+        let mut names: Vec<String> = all
+            .iter()
+            .filter(|name| {
+                self.provider_filter
+                    .as_ref()
+                    .is_none_or(|provider| self.proxy_origin.get(*name) == Some(provider))
+            })
+            .filter(|name| {
+                self.proxy_search
+                    .as_ref()
+                    .is_none_or(|query| fuzzy_match(name, query).is_some())
+            })
+            .cloned()
+            .collect();
+        match self.proxy_sort {
+            ProxySort::Original => {}
+            ProxySort::Latency => names.sort_by_key(|name| {
+                self.proxy_latency
+                    .get(name)
+                    .copied()
+                    .flatten()
+                    .unwrap_or(u64::MAX)
+            }),
+            ProxySort::Name => names.sort_by_key(|name| name.to_lowercase()),
+            ProxySort::Type => names.sort_by_key(|name| {
+                let proxy_type = self
+                    .proxies
+                    .get(name)
+                    .and_then(|item| item.proxy_type.clone())
+                    .unwrap_or_default();
+                (proxy_type, name.to_lowercase())
+            }),
+        }
+        names
     }
 
-    pub fn trigger_group_latency_test(&self) {
-        if let Some(group_name) = self.get_selected_group_name()
-            && let Some(group) = self.proxies.get(group_name)
-            && let Some(all) = &group.all
+    /// Switches to the next sort order, keeping the cursor on the same proxy.
+    pub fn cycle_proxy_sort(&mut self) {
+        let selected = self.get_selected_proxy_name();
+        self.proxy_sort = self.proxy_sort.next();
+        if let Some(index) =
+            selected.and_then(|name| self.visible_proxies().iter().position(|p| *p == name))
         {
-            let base_url = self.app_settings.base_url.clone();
-            let secret = self.app_settings.api_secret.clone();
-            let test_url = self.app_settings.test_url.clone();
-            let timeout = self.app_settings.test_timeout;
-            let tx = self.proxy_test_tx.clone();
-            let client = self.client.clone();
-
-            for proxy_name in all {
-                let p_name = proxy_name.clone();
-                let my_url = format!(
-                    "{}/proxies/{}/delay?url={}&timeout={}",
-                    base_url,
-                    urlencoding::encode(&p_name),
-                    urlencoding::encode(&test_url),
-                    timeout
-                );
-                let my_client = client.clone();
-                let my_secret = secret.clone();
-                let my_tx = tx.clone();
-
-                tokio::spawn(async move {
-                    let mut req = my_client.get(&my_url);
-                    if !my_secret.is_empty() {
-                        req = req.bearer_auth(&my_secret);
-                    }
-
-                    if let Ok(resp) = req.send().await
-                        && resp.status().is_success()
-                        && let Ok(json) = resp.json::<serde_json::Value>().await
-                        && let Some(delay) = json.get("delay").and_then(|v| v.as_u64())
-                    {
-                        let _ = my_tx.send((p_name, delay)).await;
-                    }
-                });
-            }
+            self.proxy_state.select(Some(index));
         }
+        self.info(format!("Proxies sorted by {}", self.proxy_sort.label()));
     }
 
-    pub async fn select_proxy(&self, group_name: &str, proxy_name: &str) -> Result<()> {
-        let url = format!("{}/proxies/{}", self.app_settings.base_url, group_name);
-        let body = serde_json::json!({ "name": proxy_name });
-        let mut request = self.client.put(&url).json(&body);
+    /// Cycles the provider filter through the providers present in the selected group.
+    pub fn cycle_provider_filter(&mut self) {
+        let mut providers: Vec<String> = self
+            .get_selected_group_name()
+            .and_then(|name| self.proxies.get(name))
+            .and_then(|group| group.all.as_ref())
+            .map(|all| {
+                all.iter()
+                    .filter_map(|name| self.proxy_origin.get(name).cloned())
+                    .collect()
+            })
+            .unwrap_or_default();
+        providers.sort();
+        providers.dedup();
 
-        if !self.app_settings.api_secret.is_empty() {
-            request = request.bearer_auth(&self.app_settings.api_secret);
+        self.provider_filter = match &self.provider_filter {
+            None => providers.first().cloned(),
+            Some(current) => providers
+                .iter()
+                .position(|p| p == current)
+                .and_then(|i| providers.get(i + 1).cloned()),
+        };
+        if self.provider_filter.is_none() && providers.is_empty() {
+            self.info("No providers in this group".to_string());
         }
-
-        request.send().await?;
-        Ok(())
+        self.select_current_proxy();
     }
 
-    // Navigation Helpers
-    pub fn next_group(&mut self) {
-        let i = match self.group_state.selected() {
+    pub fn next_proxy(&mut self) {
+        let len = self.visible_proxies().len();
+        if len == 0 {
+            return;
+        }
+        let i = match self.proxy_state.selected() {
             Some(i) => {
-                if i >= self.group_names.len() - 1 {
+                if i >= len - 1 {
                     0
                 } else {
                     i + 1
@@ -538,62 +7232,161 @@ impl App {
             }
             None => 0,
         };
-        self.group_state.select(Some(i));
-        self.proxy_state.select(Some(0)); // Reset proxy selection
+        self.proxy_state.select(Some(i));
     }
 
-    pub fn previous_group(&mut self) {
-        let i = match self.group_state.selected() {
+    pub fn previous_proxy(&mut self) {
+        let len = self.visible_proxies().len();
+        if len == 0 {
+            return;
+        }
+        let i = match self.proxy_state.selected() {
             Some(i) => {
                 if i == 0 {
-                    self.group_names.len() - 1
+                    len - 1
                 } else {
                     i - 1
                 }
             }
             None => 0,
         };
-        self.group_state.select(Some(i));
-        self.proxy_state.select(Some(0));
+        self.proxy_state.select(Some(i));
     }
 
-    pub fn next_proxy(&mut self) {
-        if let Some(group_idx) = self.group_state.selected()
-            && let Some(group_name) = self.group_names.get(group_idx)
-            && let Some(group) = self.proxies.get(group_name)
-            && let Some(all) = &group.all
-        {
-            let i = match self.proxy_state.selected() {
-                Some(i) => {
-                    if i >= all.len() - 1 {
-                        0
-                    } else {
-                        i + 1
-                    }
-                }
-                None => 0,
-            };
-            self.proxy_state.select(Some(i));
+    /// Last known latency of the node a group currently routes through,
+    /// following nested groups via their `now` field.
+    pub fn group_latency(&self, group_name: &str) -> Option<u64> {
+        let proxy = self.resolve_group_proxy(group_name)?;
+        self.proxy_latency.get(proxy).copied().flatten()
+    }
+
+    /// Follows nested groups' `now` down to the proxy actually in use.
+    pub fn resolve_group_proxy<'a>(&'a self, group_name: &'a str) -> Option<&'a str> {
+        let mut name = group_name;
+        for _ in 0..8 {
+            let now = self.proxies.get(name)?.now.as_deref()?;
+            match self.proxies.get(now) {
+                Some(item) if item.now.is_some() => name = now,
+                _ => return Some(now),
+            }
         }
+        None
     }
 
-    pub fn previous_proxy(&mut self) {
-        if let Some(group_idx) = self.group_state.selected()
-            && let Some(group_name) = self.group_names.get(group_idx)
-            && let Some(group) = self.proxies.get(group_name)
-            && let Some(all) = &group.all
+    /// Records a batch of delay results. Sorted by latency, rows move as
+    /// results arrive, so the cursor follows the proxy it was on.
+    pub fn on_proxy_latencies(&mut self, results: Vec<(String, Result<u64, DelayFailure>)>) {
+        let selected = (self.proxy_sort == ProxySort::Latency)
+            .then(|| self.get_selected_proxy_name())
+            .flatten();
+        for (name, result) in results {
+            match result {
+                Ok(latency) => self.record_latency(name, latency),
+                Err(failure) => self.record_delay_failure(name, failure),
+            }
+        }
+        if let Some(index) =
+            selected.and_then(|name| self.visible_proxies().iter().position(|p| *p == name))
         {
-            let i = match self.proxy_state.selected() {
-                Some(i) => {
-                    if i == 0 {
-                        all.len() - 1
-                    } else {
-                        i - 1
-                    }
-                }
-                None => 0,
-            };
-            self.proxy_state.select(Some(i));
+            self.proxy_state.select(Some(index));
+        }
+    }
+
+    pub fn record_latency(&mut self, name: String, latency: u64) {
+        let now = SystemTime::now();
+        self.delay_failures.remove(&name);
+        self.push_latency_history(&name, now, latency);
+        self.proxy_latency_at.insert(name.clone(), now);
+        self.proxy_latency.insert(name, Some(latency));
+    }
+
+    /// Clears the proxy's delay and keeps why the test failed. An unreachable
+    /// controller says nothing about the proxy, so that leaves it untouched.
+    pub fn record_delay_failure(&mut self, name: String, failure: DelayFailure) {
+        if failure == DelayFailure::Unreachable {
+            return;
+        }
+        let now = SystemTime::now();
+        self.push_latency_history(&name, now, 0);
+        self.proxy_latency_at.insert(name.clone(), now);
+        self.proxy_latency.insert(name.clone(), None);
+        self.delay_failures.insert(name, failure);
+    }
+
+    /// Adds a result unless it is older than the latest one kept, so the
+    /// core's history can be merged again on every refresh.
+    fn push_latency_history(&mut self, name: &str, at: SystemTime, delay: u64) {
+        let history = self.latency_history.entry(name.to_string()).or_default();
+        if history.back().is_some_and(|(last, _)| *last >= at) {
+            return;
+        }
+        if history.len() >= LATENCY_HISTORY_LIMIT {
+            history.pop_front();
+        }
+        history.push_back((at, delay));
+    }
+
+    /// The latest result against the average of the earlier ones, once there
+    /// are at least three.
+    pub fn latency_trend(&self, name: &str) -> Option<LatencyTrend> {
+        let history = self.latency_history.get(name)?;
+        if history.len() < 3 {
+            return None;
+        }
+        if history.iter().any(|(_, delay)| *delay == 0) {
+            return Some(LatencyTrend::Flaky);
+        }
+        let (_, last) = history.back()?;
+        let earlier = history.len() as u64 - 1;
+        let average = history.iter().rev().skip(1).map(|(_, d)| d).sum::<u64>() / earlier;
+        // Within 20% either way is noise
+        Some(if *last * 5 > average * 6 {
+            LatencyTrend::Slower
+        } else if *last * 5 < average * 4 {
+            LatencyTrend::Faster
+        } else {
+            LatencyTrend::Steady
+        })
+    }
+
+    pub fn is_latency_stale(&self, name: &str) -> bool {
+        let max_age = self.app_settings.latency_max_age;
+        max_age > 0
+            && self
+                .proxy_latency_at
+                .get(name)
+                .and_then(|at| at.elapsed().ok())
+                .is_some_and(|age| age.as_secs() >= max_age)
+    }
+
+    pub fn is_group_latency_stale(&self, group_name: &str) -> bool {
+        self.resolve_group_proxy(group_name)
+            .is_some_and(|proxy| self.is_latency_stale(proxy))
+    }
+
+    /// Whether any proxy shown for the selected group has a stale result.
+    pub fn selected_group_needs_retest(&self) -> bool {
+        self.visible_proxies()
+            .iter()
+            .any(|name| self.is_latency_stale(name))
+    }
+
+    /// Whether anything on screen shows connections: their view, or the
+    /// summary of the Overview and the wide layout.
+    pub fn shows_connections(&self) -> bool {
+        match self.current_view() {
+            Focus::Connections | Focus::Overview => true,
+            Focus::Groups | Focus::Proxies => self.app_settings.layout == LayoutPreset::Wide,
+            _ => false,
+        }
+    }
+
+    /// The view drawn underneath; overlays keep the one they were opened from.
+    pub fn current_view(&self) -> Focus {
+        if matches!(self.focus, Focus::Settings | Focus::Debug) {
+            self.previous_focus.clone()
+        } else {
+            self.focus.clone()
         }
     }
 
@@ -604,15 +7397,312 @@ impl App {
     }
 
     pub fn get_selected_proxy_name(&self) -> Option<String> {
-        if let Some(group_name) = self.get_selected_group_name()
-            && let Some(group) = self.proxies.get(group_name)
-            && let Some(all) = &group.all
-        {
-            return self
-                .proxy_state
-                .selected()
-                .and_then(|i| all.get(i).cloned());
+        self.proxy_state
+            .selected()
+            .and_then(|i| self.visible_proxies().get(i).cloned())
+    }
+}
+
+/// Decimal separator for a POSIX locale name such as `de_DE.UTF-8`.
+fn decimal_separator_for(locale: &str) -> char {
+    // Languages that write 1,5 rather than 1.5
+    const COMMA: &[&str] = &[
+        "bg", "ca", "cs", "da", "de", "el", "es", "et", "fi", "fr", "hr", "hu", "id", "it", "lt",
+        "lv", "nb", "nl", "nn", "no", "pl", "pt", "ro", "ru", "sk", "sl", "sr", "sv", "tr", "uk",
+        "vi",
+    ];
+    let language = locale
+        .split(['_', '-', '.', '@'])
+        .next()
+        .unwrap_or_default();
+    if COMMA.contains(&language.to_lowercase().as_str()) {
+        ','
+    } else {
+        '.'
+    }
+}
+
+/// Whether a group of `kind` (from `auto_group_kind`) takes a pinned node.
+/// `load-balance` spreads connections over all of them instead.
+pub fn can_pin(kind: &str) -> bool {
+    kind != "load-balance"
+}
+
+/// The config name of a group type whose node the core picks by itself.
+pub fn auto_group_kind(proxy_type: &str) -> Option<&'static str> {
+    match proxy_type {
+        "URLTest" => Some("url-test"),
+        "Fallback" => Some("fallback"),
+        "LoadBalance" => Some("load-balance"),
+        _ => None,
+    }
+}
+
+/// Whether `name` matches a mihomo domain pattern: `+.example.com` covers the
+/// domain and its subdomains, `*.example.com` one level of subdomains and
+/// `.example.com` any. `geosite:` and `rule-set:` patterns can't be checked here.
+fn domain_matches(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.trim().to_lowercase();
+    if let Some(domain) = pattern.strip_prefix("+.") {
+        name == domain || name.ends_with(&format!(".{}", domain))
+    } else if let Some(domain) = pattern.strip_prefix("*.") {
+        name.strip_suffix(&format!(".{}", domain))
+            .is_some_and(|sub| !sub.is_empty() && !sub.contains('.'))
+    } else if pattern.starts_with('.') {
+        name.ends_with(&pattern)
+    } else {
+        !pattern.contains(':') && name == pattern
+    }
+}
+
+/// Case-insensitive match of a `bulk` pattern: `*` stands for any run of
+/// characters and `?` for one. Without either, the pattern may appear anywhere.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let fold = |s: &str| -> Vec<char> { s.chars().flat_map(char::to_lowercase).collect() };
+    let (pattern, name) = (fold(pattern), fold(name));
+    if !pattern.iter().any(|c| matches!(c, '*' | '?')) {
+        return pattern.is_empty() || name.windows(pattern.len()).any(|w| w == pattern.as_slice());
+    }
+    // Backtracks to the last `*` on a mismatch
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
         }
-        None
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Case-insensitive match of `query` in `text`, as a substring or failing
+/// that as a subsequence. Returns the char positions of `text` that matched.
+pub fn fuzzy_match(text: &str, query: &str) -> Option<Vec<usize>> {
+    // One char per char, so positions line up with the original text
+    let fold = |s: &str| -> Vec<char> {
+        s.chars()
+            .map(|c| c.to_lowercase().next().unwrap_or(c))
+            .collect()
+    };
+    let (text, query) = (fold(text), fold(query));
+    if query.is_empty() {
+        return Some(Vec::new());
+    }
+    if let Some(start) = text
+        .windows(query.len())
+        .position(|w| w == query.as_slice())
+    {
+        return Some((start..start + query.len()).collect());
+    }
+    let mut positions = Vec::with_capacity(query.len());
+    let mut wanted = query.iter().peekable();
+    for (i, c) in text.iter().enumerate() {
+        if wanted.peek() == Some(&c) {
+            positions.push(i);
+            wanted.next();
+        }
+    }
+    wanted.peek().is_none().then_some(positions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parse_version_reads_core_version_strings() {
+        assert_eq!(parse_version("v1.18.1"), Some((1, 18, 1)));
+        assert_eq!(parse_version("1.18"), Some((1, 18, 0)));
+        assert_eq!(parse_version("Meta v1.18.1-foo"), Some((1, 18, 1)));
+        assert_eq!(parse_version("alpha-3f2a1c"), None);
+        assert_eq!(parse_version("unknown"), None);
+    }
+
+    #[test]
+    fn merge_json_patches_nested_objects() {
+        let mut config = json!({"dns": {"enable": true, "ipv6": false}, "mode": "rule"});
+        merge_json(
+            &mut config,
+            json!({"dns": {"ipv6": true}, "mode": "global", "tun": {}}),
+        );
+        assert_eq!(
+            config,
+            json!({"dns": {"enable": true, "ipv6": true}, "mode": "global", "tun": {}})
+        );
+
+        // Anything but an object replaces what was there
+        merge_json(&mut config, json!({"dns": null}));
+        assert_eq!(config["dns"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn prune_unchanged_keeps_only_real_changes() {
+        let current = json!({"dns": {"enable": true, "ipv6": false}, "mode": "rule", "tun": {"enable": false}});
+        let mut staged = json!({
+            "dns": {"enable": true, "ipv6": true},
+            "mode": "rule",
+            "tun": {"enable": false},
+            "ipv6": true
+        });
+        prune_unchanged(&mut staged, &current);
+        assert_eq!(staged, json!({"dns": {"ipv6": true}, "ipv6": true}));
+    }
+
+    #[test]
+    fn decimal_separator_follows_the_language() {
+        assert_eq!(decimal_separator_for("de_DE.UTF-8"), ',');
+        assert_eq!(decimal_separator_for("pt-BR"), ',');
+        assert_eq!(decimal_separator_for("FR"), ',');
+        assert_eq!(decimal_separator_for("en_US.UTF-8"), '.');
+        assert_eq!(decimal_separator_for("C"), '.');
+        assert_eq!(decimal_separator_for(""), '.');
+    }
+
+    #[test]
+    fn parse_rfc3339_reads_offsets_and_fractions() {
+        let at = |secs| Some(SystemTime::UNIX_EPOCH + Duration::from_secs(secs));
+        assert_eq!(parse_rfc3339("1970-01-01T00:00:00Z"), at(0));
+        assert_eq!(parse_rfc3339("2000-02-29T00:00:00z"), at(951_782_400));
+        assert_eq!(
+            parse_rfc3339("2024-05-01T12:34:56.789+08:00"),
+            at(1_714_538_096)
+        );
+        assert_eq!(
+            parse_rfc3339("2024-05-01T12:34:56-01:30"),
+            at(1_714_572_296)
+        );
+    }
+
+    #[test]
+    fn parse_rfc3339_rejects_other_text() {
+        assert_eq!(parse_rfc3339(""), None);
+        assert_eq!(parse_rfc3339("2024-05-01"), None);
+        assert_eq!(parse_rfc3339("2024-05-01T12:34:56"), None);
+        assert_eq!(parse_rfc3339("1969-12-31T23:59:59Z"), None);
+    }
+
+    #[test]
+    fn start_step_parse_reads_each_kind() {
+        assert_eq!(
+            StartStep::parse("group: Proxy "),
+            Ok(StartStep::Group("Proxy".to_string()))
+        );
+        assert_eq!(
+            StartStep::parse("test_group"),
+            Ok(StartStep::TestGroup(None))
+        );
+        assert_eq!(
+            StartStep::parse("test_group:"),
+            Ok(StartStep::TestGroup(None))
+        );
+        assert_eq!(
+            StartStep::parse("open_tab:connections"),
+            Ok(StartStep::OpenTab(Focus::Connections))
+        );
+        assert_eq!(
+            StartStep::parse("command:request GET /proxies?a=b:c"),
+            Ok(StartStep::Command("request GET /proxies?a=b:c".to_string()))
+        );
+        assert_eq!(
+            StartStep::parse("test_all"),
+            Ok(StartStep::Leader(LeaderAction::TestAll))
+        );
+    }
+
+    #[test]
+    fn start_step_parse_rejects_bad_steps() {
+        assert!(StartStep::parse("group").is_err());
+        assert!(StartStep::parse("open_tab:nowhere").is_err());
+        assert!(StartStep::parse("test_all:Proxy").is_err());
+        assert!(StartStep::parse("bogus").is_err());
+    }
+
+    #[test]
+    fn stable_hash_is_fnv1a() {
+        assert_eq!(stable_hash(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(stable_hash("a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn format_bytes_picks_a_unit() {
+        assert_eq!(format_bytes(512, '.'), "512 B");
+        assert_eq!(format_bytes(1536, '.'), "1.5 KB");
+        assert_eq!(format_bytes(5 * 1024 * 1024, ','), "5,0 MB");
+        assert_eq!(format_bytes(3 << 30, '.'), "3.0 GB");
+    }
+
+    #[test]
+    fn start_step_split_list_keeps_commas_inside_steps() {
+        assert_eq!(
+            StartStep::split_list("group:Proxy, command:bulk Stream*,Video HK ,test_all"),
+            ["group:Proxy", "command:bulk Stream*,Video HK", "test_all"]
+        );
+        assert!(StartStep::split_list("none").is_empty());
+        assert!(StartStep::split_list(" , ").is_empty());
+    }
+
+    /// An app showing one group of `results`, each tested that many seconds ago.
+    fn app_with_results(results: &[(&str, u64)]) -> App {
+        let mut app = App::new(None, None);
+        let all: Vec<&str> = results.iter().map(|(name, _)| *name).collect();
+        let group: ProxyItem = serde_json::from_value(json!({"all": all})).unwrap();
+        app.proxies.insert("Proxy".to_string(), group);
+        app.group_names = vec!["Proxy".to_string()];
+        for (name, age) in results {
+            let at = SystemTime::now() - Duration::from_secs(*age);
+            app.proxy_latency_at.insert(name.to_string(), at);
+        }
+        app
+    }
+
+    #[test]
+    fn latency_goes_stale_at_max_age() {
+        let mut app = app_with_results(&[("fresh", 30), ("old", 60)]);
+        app.app_settings.latency_max_age = 60;
+        assert!(!app.is_latency_stale("fresh"));
+        assert!(app.is_latency_stale("old"));
+        assert!(!app.is_latency_stale("untested"));
+        assert!(app.selected_group_needs_retest());
+    }
+
+    #[test]
+    fn latency_from_the_future_is_not_stale() {
+        let mut app = app_with_results(&[]);
+        app.app_settings.latency_max_age = 1;
+        let later = SystemTime::now() + Duration::from_secs(3600);
+        app.proxy_latency_at.insert("skewed".to_string(), later);
+        assert!(!app.is_latency_stale("skewed"));
+    }
+
+    #[test]
+    fn zero_max_age_never_ages_results() {
+        let mut app = app_with_results(&[("old", 86_400)]);
+        app.app_settings.latency_max_age = 0;
+        assert!(!app.is_latency_stale("old"));
+        assert!(!app.selected_group_needs_retest());
+    }
+
+    #[test]
+    fn retest_looks_only_at_the_shown_proxies() {
+        let mut app = app_with_results(&[("HK 01", 10), ("US 01", 600)]);
+        app.app_settings.latency_max_age = 60;
+        assert!(app.selected_group_needs_retest());
+        app.proxy_search = Some("HK".to_string());
+        assert!(!app.selected_group_needs_retest());
     }
 }
@@ -0,0 +1,240 @@
+//! What each release brought, compiled in. After an upgrade the app shows the
+//! releases since the version it last ran as, once. Add an entry, newest
+//! first, with every release that has something worth telling.
+
+use crate::app::parse_version;
+use crate::keymap::{Binding, KEYMAP, KeyContext, bind};
+
+pub struct Release {
+    pub version: &'static str,
+    pub features: &'static [&'static str],
+    /// The default key bindings of the release before, which this one's
+    /// key changes are worked out against.
+    pub previous_keys: &'static [Binding],
+}
+
+pub struct KeyChange {
+    pub context: KeyContext,
+    pub action: &'static str,
+    /// `None` when the binding is new.
+    pub before: Option<&'static str>,
+    /// `None` when the binding is gone.
+    pub after: Option<&'static str>,
+    /// What the same keys did before, when they now do something else.
+    pub was: Option<&'static str>,
+}
+
+impl Release {
+    /// Default key bindings this release added, moved, removed or gave a
+    /// new meaning, in keymap order.
+    pub fn key_changes(&self) -> Vec<KeyChange> {
+        // What shipped is the next release's starting point, or the keymap
+        // for the newest one
+        let shipped = RELEASES
+            .iter()
+            .position(|release| release.version == self.version)
+            .and_then(|index| index.checked_sub(1))
+            .map_or(KEYMAP, |newer| RELEASES[newer].previous_keys);
+        key_diff(self.previous_keys, shipped)
+    }
+}
+
+/// Bindings are the same binding when their context and action match.
+fn key_diff(before: &'static [Binding], after: &'static [Binding]) -> Vec<KeyChange> {
+    let find = |list: &'static [Binding], binding: &Binding| {
+        list.iter()
+            .find(|b| b.context == binding.context && b.action == binding.action)
+    };
+    let mut changes: Vec<KeyChange> = after
+        .iter()
+        .filter_map(|binding| match find(before, binding) {
+            Some(old) if old.keys == binding.keys => None,
+            old => Some(KeyChange {
+                context: binding.context,
+                action: binding.action,
+                before: old.map(|old| old.keys),
+                after: Some(binding.keys),
+                was: None,
+            }),
+        })
+        .collect();
+    for old in before.iter().filter(|old| find(after, old).is_none()) {
+        // The same keys on a new action changed meaning rather than went away
+        if let Some(change) = changes.iter_mut().find(|change| {
+            change.context == old.context
+                && change.before.is_none()
+                && change.after == Some(old.keys)
+        }) {
+            change.before = Some(old.keys);
+            change.was = Some(old.action);
+            continue;
+        }
+        changes.push(KeyChange {
+            context: old.context,
+            action: old.action,
+            before: Some(old.keys),
+            after: None,
+            was: None,
+        });
+    }
+    changes
+}
+
+use KeyContext::*;
+
+/// The defaults of 0.1.0, worded like `KEYMAP` where the action stayed.
+const KEYS_0_1_0: &[Binding] = &[
+    bind(Global, "q", "Quit (or close the open popup)", None),
+    bind(Global, "j / Down", "Next item; hold to scroll faster", None),
+    bind(Global, "k / Up", "Previous item", None),
+    bind(Main, "h / Left / Esc", "Focus the Groups list", None),
+    bind(Main, "l / Right", "Focus the Proxies list", None),
+    bind(
+        Main,
+        "Enter",
+        "Select group, or switch to the selected proxy; on the group's current choice that is a group itself, open it",
+        None,
+    ),
+    bind(
+        Main,
+        "r",
+        "Refresh; in the Proxies list also delay-test the group",
+        None,
+    ),
+    bind(Main, "t", "Connectivity test", None),
+    bind(Main, "i", "Proxy info", None),
+    bind(Main, "s", "Open Settings", None),
+    bind(Settings, "Esc / q / s", "Close Settings", None),
+    bind(Settings, "j / k", "Navigate", None),
+    bind(Settings, "Enter", "Edit or toggle", None),
+    bind(Editing, "Enter", "Save", None),
+    bind(Editing, "Esc", "Cancel", None),
+];
+
+pub const RELEASES: &[Release] = &[Release {
+    version: "0.2.0",
+    features: &[
+        "A tab bar with an Overview, and Connections, Rules, Logs, Rule Providers and Proxy Providers views",
+        "Connections stream live, with detail and throughput, filter presets, column sets, closing and a fake-ip map",
+        "Rules can be searched and a RULE-SET rule leads to its provider, whose cached contents can be previewed",
+        "Providers can be updated and health-checked, proxy providers on an app-managed schedule",
+        "Core logs stream into a Logs view with a level filter, pause and resume",
+        "Traffic, memory and connections stream over WebSockets and reconnect on their own",
+        "Traffic is kept per minute on disk and shown over selectable ranges, with peak and average speeds",
+        "The traffic chart marks switches, mode changes, restarts and latency alerts",
+        "The status bar shows the traffic rate and the controller's round-trip time",
+        "The Overview shows the controller, external UI, core memory, a connection summary and a test gauge",
+        "Groups are tested with the core's group delay API; d tests only the selected group",
+        "Delay results age, keep a history with a trend, say why a test failed and can be re-tested when stale",
+        "Delays show as ms, bars or grades, with configurable rounding, color cutoffs and gauge range",
+        "Groups can be pinned, reordered, hidden, locked, bound to F1-F11 and opened when nested",
+        "url-test and fallback groups can be pinned to a node and handed back to the core",
+        "Proxy switches apply at once with a spinner, can be reverted with z and are queued while offline",
+        "The proxy list can be searched, sorted, filtered by provider and jumped through by typing",
+        "Two marked proxies can be compared side by side",
+        "Groups are colored by their active node's delay, with its sparkline alongside",
+        "Proxies show the provider they come from, and the cursor starts on the active one",
+        "Smart groups show their node weights and can be flushed",
+        "Several controllers can be saved and switched between, each with its own accent and notes",
+        "Core settings are staged and applied as one change, including DNS hosts and LAN authentication",
+        "The full core config can be browsed, and changes made outside the TUI are flagged",
+        "The core can be restarted and upgraded, and its flavor and newest release are shown",
+        "Features the core is too old for are hidden or explained",
+        "A command palette, a leader key with chords and an on_start macro run common steps",
+        "A hidden debug console logs every request and sends ad-hoc ones",
+        "A DNS query tool and a bulk proxy selection command",
+        "Risky actions ask for confirmation first",
+        "Messages pop up as toasts and are kept in a notification history",
+        "The terminal bell or a screen flash can mark critical events",
+        "Color themes, a wide layout preset, a --mini display and a low-power mode for SSH",
+        "keymap rebinds the Groups and Proxies keys by action name, and ? lists every key",
+        "Panels load in the background with a spinner and show load errors with a retry hint",
+        "Launch-time fetches, tests and auto-refresh are configurable, and numbers adjust in place with +/-",
+        "API errors name the field and show the part of the response that failed",
+        "c reports which endpoints the controller supports",
+        "Ctrl+Z suspends to the shell",
+        "Scripting subcommands, a unix control socket, statsd and InfluxDB export and a status page server",
+        "A self-update subcommand, and a redacted crash report on panic",
+    ],
+    previous_keys: KEYS_0_1_0,
+}];
+
+/// The releases after `previous` up to the running version, newest first.
+pub fn since(previous: &str) -> Vec<&'static Release> {
+    let (Some(previous), Some(current)) = (
+        parse_version(previous),
+        parse_version(env!("CARGO_PKG_VERSION")),
+    ) else {
+        return Vec::new();
+    };
+    RELEASES
+        .iter()
+        .filter(|release| {
+            parse_version(release.version)
+                .is_some_and(|version| previous < version && version <= current)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn since_lists_releases_after_the_previous_version() {
+        let versions = |previous| {
+            since(previous)
+                .iter()
+                .map(|release| release.version)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(versions("0.1.0"), ["0.2.0"]);
+        assert_eq!(versions("v0.1.9"), ["0.2.0"]);
+        assert!(versions(env!("CARGO_PKG_VERSION")).is_empty());
+        assert!(versions("9.0.0").is_empty());
+        assert!(versions("not a version").is_empty());
+    }
+
+    #[test]
+    fn key_diff_finds_new_moved_removed_and_repurposed_keys() {
+        const BEFORE: &[Binding] = &[
+            bind(Global, "q", "Quit", None),
+            bind(Main, "h / Esc", "Focus the Groups list", None),
+            bind(Main, "t", "Connectivity test", None),
+            bind(Main, "x", "Gone", None),
+        ];
+        const AFTER: &[Binding] = &[
+            bind(Global, "q", "Quit", None),
+            bind(Global, "?", "Show this help", None),
+            bind(Main, "h", "Focus the Groups list", None),
+            bind(Main, "t", "Test the group", None),
+        ];
+        let changes: Vec<_> = key_diff(BEFORE, AFTER)
+            .into_iter()
+            .map(|c| (c.action, c.before, c.after, c.was))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                ("Show this help", None, Some("?"), None),
+                ("Focus the Groups list", Some("h / Esc"), Some("h"), None),
+                (
+                    "Test the group",
+                    Some("t"),
+                    Some("t"),
+                    Some("Connectivity test")
+                ),
+                ("Gone", Some("x"), None, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn the_running_version_has_an_entry() {
+        assert!(
+            RELEASES
+                .iter()
+                .any(|release| release.version == env!("CARGO_PKG_VERSION"))
+        );
+    }
+}
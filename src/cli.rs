@@ -0,0 +1,252 @@
+use anyhow::{Result, anyhow, bail};
+use clap::Subcommand;
+use mihomot::api::ProxyItem;
+use std::collections::BTreeMap;
+
+use crate::app::{App, AppEvent, DelayFailure};
+
+#[derive(Subcommand, Debug)]
+pub enum ProxiesCommand {
+    /// List selectable groups with their current proxy, or the members of GROUP
+    List {
+        group: Option<String>,
+        /// Print JSON instead of tab-separated text
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Print the core config, or one key of it such as `mode` or `dns.enable`
+    Get { key: Option<String> },
+    /// Change one key, e.g. `mode rule` or `tun.enable true`. VALUE is read as
+    /// JSON when it parses, otherwise as a string
+    Set { key: String, value: String },
+}
+
+pub async fn proxies(
+    url: Option<String>,
+    secret: Option<String>,
+    command: ProxiesCommand,
+) -> Result<()> {
+    let ProxiesCommand::List { group, json } = command;
+    let app = connect(url, secret).await?;
+    let delay = |name: &str| app.proxy_latency.get(name).copied().flatten();
+
+    match group {
+        None => {
+            let groups: Vec<_> = app
+                .group_names
+                .iter()
+                .map(|name| {
+                    let item = &app.proxies[name];
+                    serde_json::json!({
+                        "group": name,
+                        "type": item.proxy_type,
+                        "now": item.now,
+                        "delay": app.group_latency(name),
+                    })
+                })
+                .collect();
+            if json {
+                println!("{}", serde_json::to_string_pretty(&groups)?);
+            } else {
+                for name in &app.group_names {
+                    let now = app.proxies[name].now.as_deref().unwrap_or("-");
+                    println!(
+                        "{}\t{}\t{}",
+                        name,
+                        now,
+                        format_delay(app.group_latency(name))
+                    );
+                }
+            }
+        }
+        Some(group) => {
+            let item = group_item(&app, &group)?;
+            let members = item.all.clone().unwrap_or_default();
+            let now = item.now.clone();
+            let rows: Vec<_> = members
+                .iter()
+                .map(|name| {
+                    let proxy_type = app.proxies.get(name).and_then(|p| p.proxy_type.clone());
+                    (name, proxy_type, delay(name), now.as_ref() == Some(name))
+                })
+                .collect();
+            if json {
+                let rows: Vec<_> = rows
+                    .iter()
+                    .map(|(name, proxy_type, delay, selected)| {
+                        serde_json::json!({
+                            "name": name,
+                            "type": proxy_type,
+                            "delay": delay,
+                            "selected": selected,
+                        })
+                    })
+                    .collect();
+                println!("{}", serde_json::to_string_pretty(&rows)?);
+            } else {
+                for (name, proxy_type, delay, selected) in rows {
+                    println!(
+                        "{}\t{}\t{}\t{}",
+                        name,
+                        proxy_type.unwrap_or_default(),
+                        format_delay(delay),
+                        if selected { "*" } else { "" }
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn select(
+    url: Option<String>,
+    secret: Option<String>,
+    group: &str,
+    proxy: &str,
+) -> Result<()> {
+    let app = connect(url, secret).await?;
+    let item = group_item(&app, group)?;
+    if let Some(kind) = app.auto_group_kind(group) {
+        bail!("{:?} is a {} group; the core picks its node", group, kind);
+    }
+    if !item
+        .all
+        .as_ref()
+        .is_some_and(|all| all.iter().any(|p| p == proxy))
+    {
+        bail!("{:?} is not a member of {:?}", proxy, group);
+    }
+    app.select_proxy_now(group, proxy).await?;
+    println!("{} -> {}", group, proxy);
+    Ok(())
+}
+
+/// Delay-tests every member of `group` and prints the results fastest first.
+pub async fn test(
+    url: Option<String>,
+    secret: Option<String>,
+    group: &str,
+    json: bool,
+) -> Result<()> {
+    let mut app = connect(url, secret).await?;
+    let members = group_item(&app, group)?.all.clone().unwrap_or_default();
+    let Some(mut task) = app.test_group(group) else {
+        bail!("The core doesn't support delay tests");
+    };
+
+    let mut delays: BTreeMap<String, Result<u64, DelayFailure>> = BTreeMap::new();
+    let mut record = |event| match event {
+        AppEvent::ProxyDelay { name, result } => {
+            delays.insert(name, result);
+            Ok(())
+        }
+        AppEvent::GroupTestFailed { group, error } => Err(anyhow!("Test {}: {}", group, error)),
+        _ => Ok(()),
+    };
+    loop {
+        tokio::select! {
+            Some(event) = app.events_rx.recv() => record(event)?,
+            _ = &mut task => break,
+        }
+    }
+    while let Ok(event) = app.events_rx.try_recv() {
+        record(event)?;
+    }
+
+    if json {
+        let results: BTreeMap<&String, Option<u64>> = members
+            .iter()
+            .map(|name| (name, delays.get(name).and_then(|d| d.clone().ok())))
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&results)?);
+    } else {
+        let mut rows: Vec<(&String, Option<&Result<u64, DelayFailure>>)> = members
+            .iter()
+            .map(|name| (name, delays.get(name)))
+            .collect();
+        rows.sort_by_key(|(_, delay)| match delay {
+            Some(Ok(ms)) => *ms,
+            _ => u64::MAX,
+        });
+        for (name, delay) in rows {
+            let delay = match delay {
+                Some(Ok(ms)) => ms.to_string(),
+                Some(Err(failure)) => failure.label(),
+                None => "timeout".to_string(),
+            };
+            println!("{}\t{}", name, delay);
+        }
+    }
+    Ok(())
+}
+
+pub async fn config(
+    url: Option<String>,
+    secret: Option<String>,
+    command: ConfigCommand,
+) -> Result<()> {
+    let mut app = App::new(url, secret);
+    app.fetch_config().await?;
+    let config = app.config.as_ref().ok_or_else(|| {
+        anyhow!(
+            "Failed to load the config from {}",
+            app.app_settings.base_url
+        )
+    })?;
+
+    match command {
+        ConfigCommand::Get { key } => {
+            let mut value = serde_json::to_value(config)?;
+            if let Some(key) = &key {
+                for part in key.split('.') {
+                    value = value
+                        .get(part)
+                        .cloned()
+                        .ok_or_else(|| anyhow!("No config key {:?}", key))?;
+                }
+            }
+            // Bare strings are easier to use from shell scripts
+            match value {
+                serde_json::Value::String(s) => println!("{}", s),
+                value => println!("{}", serde_json::to_string_pretty(&value)?),
+            }
+        }
+        ConfigCommand::Set { key, value } => {
+            let value = serde_json::from_str(&value).unwrap_or(serde_json::Value::String(value));
+            let patch = key
+                .rsplit('.')
+                .fold(value, |inner, part| serde_json::json!({ part: inner }));
+            app.update_config(patch).await?;
+            // Unreachable controllers get the change queued, which is useless here
+            if let Some(queued) = app.mutation_queue.first() {
+                bail!("{}", queued.error);
+            }
+            println!("{} updated", key);
+        }
+    }
+    Ok(())
+}
+
+/// An app with proxies loaded, hidden groups included.
+async fn connect(url: Option<String>, secret: Option<String>) -> Result<App> {
+    let mut app = App::new(url, secret);
+    app.show_hidden_groups = true;
+    app.fetch_proxies().await?;
+    Ok(app)
+}
+
+fn group_item<'a>(app: &'a App, group: &str) -> Result<&'a ProxyItem> {
+    app.proxies
+        .get(group)
+        .filter(|item| item.all.is_some())
+        .ok_or_else(|| anyhow!("No group named {:?}", group))
+}
+
+fn format_delay(delay: Option<u64>) -> String {
+    delay.map_or("-".to_string(), |ms| ms.to_string())
+}
@@ -0,0 +1,209 @@
+//! Crash reports. The run loop keeps a small snapshot of the app up to date;
+//! when something panics, the snapshot is written to the data directory with
+//! the API secret scrubbed out, and its path is printed once the terminal is
+//! back to normal.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use mihomot::api::ApiLog;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::panic::PanicHookInfo;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::app::{App, Modal};
+
+/// Key presses kept for the report.
+const ACTION_LIMIT: usize = 20;
+/// Failed API requests kept for the report.
+const API_ERROR_LIMIT: usize = 10;
+
+#[derive(Default)]
+struct Snapshot {
+    view: String,
+    group: Option<String>,
+    base_url: String,
+    core_version: Option<String>,
+    error: Option<String>,
+    data_dir: Option<PathBuf>,
+    api_log: Option<ApiLog>,
+    actions: VecDeque<String>,
+    /// Only kept to scrub it from everything else.
+    secret: String,
+}
+
+static SNAPSHOT: Mutex<Option<Snapshot>> = Mutex::new(None);
+
+/// Writes a report on panic. Call before `ratatui::init`: its hook wraps this
+/// one, but restores raw mode through its own crossterm, which never saw the
+/// terminal as it was. So this one restores it again before printing.
+pub fn install() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let _ = crossterm::terminal::disable_raw_mode();
+        let _ = crossterm::execute!(std::io::stdout(), crossterm::terminal::LeaveAlternateScreen);
+        let report = write_report(info);
+        previous(info);
+        match report {
+            Ok(Some(path)) => eprintln!(
+                "mihomot crashed. A report without your API secret was written to\n  {}\n\
+                 Please attach it when filing a bug.",
+                path.display()
+            ),
+            Ok(None) => {}
+            Err(e) => eprintln!("mihomot crashed and couldn't write a report: {}", e),
+        }
+    }));
+}
+
+/// Records what the app shows now; called once per frame.
+pub fn update(app: &App) {
+    let Ok(mut snapshot) = SNAPSHOT.lock() else {
+        return;
+    };
+    let snapshot = snapshot.get_or_insert_with(Snapshot::default);
+    snapshot.view = format!("{:?}", app.focus);
+    snapshot.group = app.get_selected_group_name().cloned();
+    snapshot.base_url.clone_from(&app.app_settings.base_url);
+    snapshot.core_version = app.capabilities.version.as_ref().map(|v| v.version.clone());
+    snapshot.error = app.last_error().map(str::to_string);
+    snapshot.data_dir = app.data_dir();
+    if snapshot.api_log.is_none() {
+        snapshot.api_log = Some(app.api_log.clone());
+    }
+    snapshot.secret.clone_from(&app.app_settings.api_secret);
+}
+
+/// Notes a key press. Typed text is left out, since it may be a password.
+pub fn record_key(app: &App, key: &KeyEvent) {
+    let typing = app.is_editing
+        || app.notes_draft.is_some()
+        || app.proxy_search.is_some()
+        || matches!(app.modal, Modal::Dns(_))
+        || app.type_ahead_text().is_some();
+    let key_name = match key.code {
+        KeyCode::Char(_) if typing => "<text>".to_string(),
+        KeyCode::Char(c) if key.modifiers.contains(KeyModifiers::CONTROL) => format!("Ctrl+{}", c),
+        KeyCode::Char(c) => c.to_string(),
+        code => format!("{:?}", code),
+    };
+    let Ok(mut snapshot) = SNAPSHOT.lock() else {
+        return;
+    };
+    let actions = &mut snapshot.get_or_insert_with(Snapshot::default).actions;
+    if actions.len() == ACTION_LIMIT {
+        actions.pop_front();
+    }
+    actions.push_back(format!("{:?}: {}", app.focus, key_name));
+}
+
+/// `None` when the panic came before the TUI started.
+fn write_report(info: &PanicHookInfo) -> std::io::Result<Option<PathBuf>> {
+    // The panic may have happened while the snapshot was locked
+    let guard = match SNAPSHOT.try_lock() {
+        Ok(guard) => guard,
+        Err(std::sync::TryLockError::Poisoned(e)) => e.into_inner(),
+        Err(std::sync::TryLockError::WouldBlock) => return Ok(None),
+    };
+    let Some(snapshot) = guard.as_ref() else {
+        return Ok(None);
+    };
+    let Some(dir) = &snapshot.data_dir else {
+        return Ok(None);
+    };
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let message = info
+        .payload()
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| info.payload().downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "(no message)".to_string());
+
+    // Only what came from the user or the controller is scrubbed; the short
+    // default secret would otherwise mangle names and paths. The header form
+    // goes first so it is caught whole, then the secret as it appears in URLs
+    let scrub = |text: &str| {
+        let secret = &snapshot.secret;
+        if secret.is_empty() {
+            return text.to_string();
+        }
+        text.replace(&format!("Bearer {}", secret), "Bearer <secret>")
+            .replace(urlencoding::encode(secret).as_ref(), "<secret>")
+            .replace(secret.as_str(), "<secret>")
+    };
+
+    let mut report = String::new();
+    let _ = writeln!(
+        report,
+        "mihomot {} crash report ({} {})",
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    );
+    let _ = writeln!(report, "Time: {} (unix)", now);
+    let _ = writeln!(report, "Panic: {}", scrub(&message));
+    if let Some(location) = info.location() {
+        let _ = writeln!(report, "At: {}:{}", location.file(), location.line());
+    }
+    let _ = writeln!(report);
+    let _ = writeln!(report, "View: {}", snapshot.view);
+    if let Some(group) = &snapshot.group {
+        let _ = writeln!(report, "Group: {}", scrub(group));
+    }
+    let _ = writeln!(
+        report,
+        "Controller: {} (core {})",
+        scrub(&snapshot.base_url),
+        snapshot.core_version.as_deref().unwrap_or("unknown")
+    );
+    if let Some(error) = &snapshot.error {
+        let _ = writeln!(report, "Last error: {}", scrub(error));
+    }
+
+    let _ = writeln!(report, "\nLast keys, oldest first:");
+    for action in &snapshot.actions {
+        let _ = writeln!(report, "  {}", action);
+    }
+
+    // Bodies stay out: config edits can carry LAN passwords
+    let _ = writeln!(report, "\nLast failed API requests, newest first:");
+    if let Some(log) = snapshot
+        .api_log
+        .as_ref()
+        .and_then(|log| log.try_lock().ok())
+    {
+        let failed = log.iter().rev().filter(|entry| match &entry.status {
+            Ok(status) => *status >= 400,
+            Err(_) => true,
+        });
+        for entry in failed.take(API_ERROR_LIMIT) {
+            let status = match &entry.status {
+                Ok(status) => status.to_string(),
+                Err(e) => scrub(e),
+            };
+            let _ = writeln!(
+                report,
+                "  {} {} -> {} ({} ms)",
+                entry.method,
+                scrub(&entry.url),
+                status,
+                entry.elapsed.as_millis()
+            );
+        }
+    }
+
+    let _ = writeln!(
+        report,
+        "\nBacktrace:\n{}",
+        std::backtrace::Backtrace::force_capture()
+    );
+
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(format!("crash-{}.txt", now));
+    std::fs::write(&path, report)?;
+    Ok(Some(path))
+}
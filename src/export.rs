@@ -0,0 +1,206 @@
+use anyhow::{Context, Result, bail};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::net::UdpSocket;
+
+use crate::app::{App, TrafficRate};
+
+/// Where `mihomot export` pushes its samples.
+enum Sink {
+    /// Plain-text gauges over UDP, one datagram per push.
+    Statsd(UdpSocket),
+    /// InfluxDB line protocol POSTed to a v1 `/write` or v2 `/api/v2/write` URL.
+    Influx { url: String, token: Option<String> },
+}
+
+/// One round of readings from the controller.
+struct Sample {
+    /// Bytes per second since the previous sample; unknown on the first one.
+    rates: Option<(u64, u64)>,
+    download_total: u64,
+    upload_total: u64,
+    connections: usize,
+    /// Delay of each group's current proxy, as `(group, proxy, ms)`.
+    latencies: Vec<(String, String, u64)>,
+}
+
+/// Polls the controller every `interval` seconds and pushes traffic and delay
+/// metrics to `target`, which is `statsd://host:port` or an InfluxDB write URL.
+pub async fn run(
+    url: Option<String>,
+    secret: Option<String>,
+    target: &str,
+    token: Option<String>,
+    interval: u64,
+) -> Result<()> {
+    let sink = if let Some(addr) = target.strip_prefix("statsd://") {
+        let socket = UdpSocket::bind("0.0.0.0:0").await?;
+        socket
+            .connect(addr)
+            .await
+            .with_context(|| format!("Failed to resolve statsd address {}", addr))?;
+        Sink::Statsd(socket)
+    } else if target.starts_with("http://") || target.starts_with("https://") {
+        Sink::Influx {
+            url: target.to_string(),
+            token,
+        }
+    } else {
+        bail!(
+            "Unsupported export target {:?}; use statsd://host:port or an InfluxDB write URL",
+            target
+        );
+    };
+
+    let mut app = App::new(url, secret);
+    let mut ticker = tokio::time::interval(Duration::from_secs(interval.max(1)));
+    let mut rate = TrafficRate::default();
+    println!(
+        "Exporting metrics from {} to {} every {}s",
+        app.app_settings.base_url,
+        target,
+        interval.max(1)
+    );
+
+    loop {
+        ticker.tick().await;
+        // A failed round is reported and skipped; the next tick tries again
+        let result = async {
+            let sample = collect(&mut app, &mut rate).await?;
+            push(&app, &sink, &sample).await
+        }
+        .await;
+        if let Err(e) = result {
+            eprintln!("export: {:#}", e);
+        }
+    }
+}
+
+async fn collect(app: &mut App, rate: &mut TrafficRate) -> Result<Sample> {
+    app.fetch_proxies().await?;
+    app.fetch_connections().await?;
+
+    let rates = rate.update(app.download_total, app.upload_total);
+
+    let latencies = app
+        .group_names
+        .iter()
+        .filter_map(|group| {
+            let proxy = app.proxies.get(group)?.now.clone()?;
+            Some((group.clone(), proxy, app.group_latency(group)?))
+        })
+        .collect();
+
+    Ok(Sample {
+        rates,
+        download_total: app.download_total,
+        upload_total: app.upload_total,
+        connections: app.connections.len(),
+        latencies,
+    })
+}
+
+async fn push(app: &App, sink: &Sink, sample: &Sample) -> Result<()> {
+    match sink {
+        Sink::Statsd(socket) => {
+            socket.send(statsd_lines(sample).as_bytes()).await?;
+        }
+        Sink::Influx { url, token } => {
+            let mut request = app.client.post(url).body(influx_lines(sample));
+            if let Some(token) = token {
+                request = request.header("Authorization", format!("Token {}", token));
+            }
+            let resp = request.send().await?;
+            if !resp.status().is_success() {
+                bail!(
+                    "InfluxDB returned {}: {}",
+                    resp.status(),
+                    resp.text().await.unwrap_or_default()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Gauges named `mihomo.<metric>`, newline-separated so one datagram carries them all.
+fn statsd_lines(sample: &Sample) -> String {
+    let mut lines = vec![
+        format!("mihomo.traffic.download_total:{}|g", sample.download_total),
+        format!("mihomo.traffic.upload_total:{}|g", sample.upload_total),
+        format!("mihomo.connections:{}|g", sample.connections),
+    ];
+    if let Some((down, up)) = sample.rates {
+        lines.push(format!("mihomo.traffic.download:{}|g", down));
+        lines.push(format!("mihomo.traffic.upload:{}|g", up));
+    }
+    for (group, _, ms) in &sample.latencies {
+        let name = statsd_name(group);
+        if !name.is_empty() {
+            lines.push(format!("mihomo.latency.{}:{}|g", name, ms));
+        }
+    }
+    lines.join("\n")
+}
+
+/// Statsd treats `.`, `:` and `|` as syntax, and many servers choke on non-ASCII.
+fn statsd_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    name.trim_matches('_').to_string()
+}
+
+fn influx_lines(sample: &Sample) -> String {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    let mut traffic = format!(
+        "download_total={}i,upload_total={}i,connections={}i",
+        sample.download_total, sample.upload_total, sample.connections
+    );
+    if let Some((down, up)) = sample.rates {
+        traffic.push_str(&format!(",download={}i,upload={}i", down, up));
+    }
+    let mut lines = vec![format!("mihomo_traffic {} {}", traffic, timestamp)];
+    for (group, proxy, ms) in &sample.latencies {
+        lines.push(format!(
+            "mihomo_latency,group={},proxy={} delay={}i {}",
+            influx_tag(group),
+            influx_tag(proxy),
+            ms,
+            timestamp
+        ));
+    }
+    lines.join("\n")
+}
+
+/// Tag values escape commas, equals signs and spaces with a backslash.
+fn influx_tag(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, ',' | '=' | ' ' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn influx_tag_escapes_syntax() {
+        assert_eq!(influx_tag("HK 01,a=b\\"), "HK\\ 01\\,a\\=b\\\\");
+        assert_eq!(influx_tag("🚀 Proxy"), "🚀\\ Proxy");
+    }
+
+    #[test]
+    fn statsd_name_keeps_ascii_alphanumerics() {
+        assert_eq!(statsd_name("🚀 Proxy.HK:1|x"), "Proxy_HK_1_x");
+        assert_eq!(statsd_name("plain"), "plain");
+    }
+}
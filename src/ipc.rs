@@ -0,0 +1,142 @@
+//! Remote control of a running TUI over a unix socket, so a second `mihomot`
+//! or a script drives it instead of starting its own. Each connection carries
+//! one command line and gets one reply line, `ok <message>` or `error: <message>`.
+
+use anyhow::{Result, anyhow, bail};
+use std::path::PathBuf;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+/// A command from `mihomot ctl`, answered through `reply`.
+pub struct Request {
+    pub line: String,
+    pub reply: oneshot::Sender<String>,
+}
+
+/// `$XDG_RUNTIME_DIR/mihomot.sock`, else one in a per-user directory under
+/// the temp directory.
+pub fn socket_path() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir).join("mihomot.sock"),
+        None => temp_socket_dir().join("mihomot.sock"),
+    }
+}
+
+fn temp_socket_dir() -> PathBuf {
+    let user = std::env::var("USER").unwrap_or_default();
+    std::env::temp_dir().join(format!("mihomot-{}", user))
+}
+
+/// Creates `dir` readable by us alone, or checks that the one already there
+/// is. The temp directory is shared with other users, and the socket would
+/// be reachable for a moment between binding it and restricting it.
+#[cfg(unix)]
+fn private_dir(dir: &std::path::Path) -> Result<()> {
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+    match std::fs::DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            let meta = std::fs::symlink_metadata(dir)?;
+            if !meta.is_dir() || meta.permissions().mode() & 0o077 != 0 {
+                bail!("{} is not a private directory", dir.display());
+            }
+            Ok(())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Sends one command to the running instance and returns its message.
+pub async fn send(line: &str) -> Result<String> {
+    #[cfg(unix)]
+    {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let path = socket_path();
+        let stream = tokio::net::UnixStream::connect(&path)
+            .await
+            .map_err(|e| anyhow!("No running mihomot at {}: {}", path.display(), e))?;
+        let (read, mut write) = stream.into_split();
+        write.write_all(format!("{}\n", line).as_bytes()).await?;
+
+        let mut reply = String::new();
+        BufReader::new(read).read_line(&mut reply).await?;
+        let reply = reply.trim_end();
+        if let Some(error) = reply.strip_prefix("error: ") {
+            bail!("{}", error);
+        }
+        Ok(reply.strip_prefix("ok").unwrap_or(reply).trim().to_string())
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = line;
+        bail!("Remote control needs unix sockets")
+    }
+}
+
+/// Accepts commands until dropped; the socket file goes with it.
+pub struct Server {
+    pub rx: mpsc::UnboundedReceiver<Request>,
+    path: PathBuf,
+    task: JoinHandle<()>,
+}
+
+impl Server {
+    /// Only call this after `send` found nobody listening: a socket file that
+    /// is still there was left behind by an instance that crashed.
+    pub fn bind() -> Result<Self> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let path = socket_path();
+            if path.parent() == Some(temp_socket_dir().as_path()) {
+                private_dir(&temp_socket_dir())?;
+            }
+            let _ = std::fs::remove_file(&path);
+            let listener = tokio::net::UnixListener::bind(&path)?;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+
+            let (tx, rx) = mpsc::unbounded_channel();
+            let task = tokio::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    tokio::spawn(serve(stream, tx.clone()));
+                }
+            });
+            Ok(Server { rx, path, task })
+        }
+        #[cfg(not(unix))]
+        bail!("Remote control needs unix sockets")
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.task.abort();
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(unix)]
+async fn serve(stream: tokio::net::UnixStream, tx: mpsc::UnboundedSender<Request>) {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let (read, mut write) = stream.into_split();
+    let mut line = String::new();
+    if BufReader::new(read).read_line(&mut line).await.is_err() {
+        return;
+    }
+    let (reply_tx, reply_rx) = oneshot::channel();
+    let request = Request {
+        line: line.trim().to_string(),
+        reply: reply_tx,
+    };
+    if tx.send(request).is_err() {
+        return;
+    }
+    let reply = reply_rx
+        .await
+        .unwrap_or_else(|_| "error: mihomot is shutting down".to_string());
+    let _ = write.write_all(format!("{}\n", reply).as_bytes()).await;
+}
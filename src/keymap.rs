@@ -0,0 +1,1026 @@
+//! Every key binding in one table. The `?` help overlay lists all of them and
+//! the status bar shows the ones with a short hint. The Groups and Proxies
+//! keys can also be rebound through `Action` and the `keymap` setting; their
+//! status bar hints follow it, while the table keeps the defaults.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Where a binding applies; also the sections of the help overlay, in order.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyContext {
+    Global,
+    Main,
+    Groups,
+    ProxySearch,
+    Connections,
+    Rules,
+    Logs,
+    RuleProviders,
+    ProxyProviders,
+    Overview,
+    Settings,
+    ConfigReview,
+    ConfigTree,
+    Hosts,
+    Servers,
+    Queue,
+    Comparison,
+    FakeIp,
+    Notes,
+    Debug,
+    Editing,
+    Confirm,
+    Response,
+    Dns,
+    TrafficHistory,
+    BulkSelect,
+    Pin,
+    Notifications,
+    Changelog,
+}
+
+impl KeyContext {
+    pub const ALL: [KeyContext; 29] = [
+        KeyContext::Global,
+        KeyContext::Main,
+        KeyContext::Groups,
+        KeyContext::ProxySearch,
+        KeyContext::Connections,
+        KeyContext::Rules,
+        KeyContext::Logs,
+        KeyContext::RuleProviders,
+        KeyContext::ProxyProviders,
+        KeyContext::Overview,
+        KeyContext::Settings,
+        KeyContext::ConfigReview,
+        KeyContext::ConfigTree,
+        KeyContext::Hosts,
+        KeyContext::Servers,
+        KeyContext::Queue,
+        KeyContext::Comparison,
+        KeyContext::FakeIp,
+        KeyContext::Notes,
+        KeyContext::Debug,
+        KeyContext::Editing,
+        KeyContext::Confirm,
+        KeyContext::Response,
+        KeyContext::Dns,
+        KeyContext::TrafficHistory,
+        KeyContext::BulkSelect,
+        KeyContext::Pin,
+        KeyContext::Notifications,
+        KeyContext::Changelog,
+    ];
+
+    pub fn title(self) -> &'static str {
+        match self {
+            KeyContext::Global => "Everywhere",
+            KeyContext::Main => "Main View",
+            KeyContext::Groups => "Groups List",
+            KeyContext::ProxySearch => "Proxy Search",
+            KeyContext::Connections => "Connections View",
+            KeyContext::Rules => "Rules View",
+            KeyContext::Logs => "Logs View",
+            KeyContext::RuleProviders => "Rule Providers View",
+            KeyContext::ProxyProviders => "Proxy Providers View",
+            KeyContext::Overview => "Overview",
+            KeyContext::Settings => "Settings View",
+            KeyContext::ConfigReview => "Apply Changes",
+            KeyContext::ConfigTree => "Config Browser",
+            KeyContext::Hosts => "DNS Hosts",
+            KeyContext::Servers => "Servers",
+            KeyContext::Queue => "Queued Changes",
+            KeyContext::Comparison => "Proxy Comparison",
+            KeyContext::FakeIp => "Fake-IP Map",
+            KeyContext::Notes => "Notes",
+            KeyContext::Debug => "Debug Console",
+            KeyContext::Editing => "Editing",
+            KeyContext::Confirm => "Confirmation",
+            KeyContext::Response => "Request Response",
+            KeyContext::Dns => "DNS Query",
+            KeyContext::TrafficHistory => "Traffic History",
+            KeyContext::BulkSelect => "Bulk Selection",
+            KeyContext::Pin => "Pin Node",
+            KeyContext::Notifications => "Notifications",
+            KeyContext::Changelog => "What's New",
+        }
+    }
+}
+
+pub struct Binding {
+    pub context: KeyContext,
+    /// The keys as listed in the help overlay.
+    pub keys: &'static str,
+    pub action: &'static str,
+    /// Compact keys and label for the status bar; `None` leaves the binding
+    /// to the help overlay.
+    pub hint: Option<(&'static str, &'static str)>,
+}
+
+pub const fn bind(
+    context: KeyContext,
+    keys: &'static str,
+    action: &'static str,
+    hint: Option<(&'static str, &'static str)>,
+) -> Binding {
+    Binding {
+        context,
+        keys,
+        action,
+        hint,
+    }
+}
+
+use KeyContext::*;
+
+pub const KEYMAP: &[Binding] = &[
+    bind(
+        Global,
+        "q",
+        "Quit (or close the open popup)",
+        Some(("q", "Quit")),
+    ),
+    bind(
+        Global,
+        "j / Down",
+        "Next item; hold to scroll faster",
+        Some(("j/k", "Nav")),
+    ),
+    bind(Global, "k / Up", "Previous item", None),
+    bind(Global, "?", "Show this help", Some(("?", "Help"))),
+    bind(
+        Global,
+        "1-6",
+        "Open a tab; 5 again switches between the provider views",
+        Some(("1-6", "Tabs")),
+    ),
+    bind(
+        Global,
+        "Tab / Shift+Tab",
+        "Next/previous view in tab order",
+        None,
+    ),
+    bind(
+        Global,
+        "Ctrl+Z",
+        "Suspend to the shell (resume with fg)",
+        None,
+    ),
+    bind(
+        Main,
+        "[ / ]",
+        "Previous/next group, also from the Proxies list",
+        Some(("[/]", "Group")),
+    ),
+    bind(Main, "h / Left", "Focus the Groups list", None),
+    bind(Main, "l / Right", "Focus the Proxies list", None),
+    bind(
+        Main,
+        "'",
+        "Type-ahead: jump to a group or proxy by name",
+        Some(("'", "Jump")),
+    ),
+    bind(Main, "/", "Search the proxy list", Some(("/", "Search"))),
+    bind(
+        Main,
+        "o",
+        "Cycle the proxy list's order",
+        Some(("o", "Sort")),
+    ),
+    bind(Main, "f", "Cycle the proxy list's provider filter", None),
+    bind(
+        Main,
+        "S",
+        "Switch between saved servers",
+        Some(("S", "Servers")),
+    ),
+    bind(
+        Main,
+        "Enter",
+        "Select group, or switch to the selected proxy; on the group's current choice that is a group itself, open it",
+        Some(("l/Enter", "Select")),
+    ),
+    bind(
+        Main,
+        "Esc",
+        "In the Proxies list, back up to the group a nested one was opened from",
+        None,
+    ),
+    bind(
+        Main,
+        "z",
+        "Revert the group to its previous proxy",
+        Some(("z", "Revert")),
+    ),
+    bind(
+        Main,
+        "r",
+        "Refresh; in the Proxies list also delay-test the group",
+        Some(("r", "Refresh+Test")),
+    ),
+    bind(
+        Main,
+        "d",
+        "Delay-test the selected group only",
+        Some(("d", "Test Group")),
+    ),
+    bind(
+        Main,
+        "t",
+        "Connectivity test; in the Proxies list, test the group",
+        Some(("t", "Test")),
+    ),
+    bind(Main, "m", "Mark the selected proxy for comparison", None),
+    bind(Main, "C", "Compare the two marked proxies", None),
+    bind(
+        Main,
+        "F",
+        "Flush the selected Smart group's statistics",
+        None,
+    ),
+    bind(Main, "F1-F11", "Jump to the group bound to the key", None),
+    bind(Main, "i", "Proxy info", Some(("i", "Info"))),
+    bind(Main, "c", "API capabilities report", Some(("c", "Caps"))),
+    bind(Main, "u", "Copy the external dashboard URL", None),
+    bind(Main, "T", "Traffic history from disk", None),
+    bind(Main, "N", "Past messages and errors", None),
+    bind(
+        Main,
+        "P",
+        "Changes queued while the controller was unreachable",
+        None,
+    ),
+    bind(Main, "n", "Notes for this controller", None),
+    bind(
+        Main,
+        ":",
+        "Command palette: select <group> <proxy>, restart, flush fakeip|dns, request <method> <path> [body], dns <name> [type], bulk <groups> <proxies>",
+        None,
+    ),
+    bind(
+        Main,
+        "Space",
+        "Leader: start a chord (listed below; set in Settings)",
+        Some(("Space", "Chords")),
+    ),
+    bind(Main, "s", "Open Settings", Some(("s", "Settings"))),
+    bind(Main, "F12", "Debug console", None),
+    bind(Groups, "p", "Pin/unpin the group", None),
+    bind(
+        Groups,
+        "K / J",
+        "Move the group up/down among pinned groups",
+        None,
+    ),
+    bind(Groups, "x", "Hide/unhide the group", None),
+    bind(Groups, "H", "Show/hide hidden groups", None),
+    bind(
+        Groups,
+        "L",
+        "Lock/unlock the group: switching it then asks first",
+        None,
+    ),
+    bind(
+        Groups,
+        "B",
+        "Bind the group to a free function key, or unbind it",
+        None,
+    ),
+    bind(
+        ProxySearch,
+        "Up / Down",
+        "Move between matches",
+        Some(("Up/Down", "Nav")),
+    ),
+    bind(
+        ProxySearch,
+        "Enter",
+        "Jump to the match in the full list",
+        Some(("Enter", "Jump")),
+    ),
+    bind(
+        ProxySearch,
+        "Esc",
+        "Cancel the search",
+        Some(("Esc", "Cancel")),
+    ),
+    bind(
+        Connections,
+        "Esc",
+        "Back to the main view",
+        Some(("Esc", "Proxies")),
+    ),
+    bind(
+        Connections,
+        "Enter",
+        "Connection detail",
+        Some(("Enter", "Detail")),
+    ),
+    bind(
+        Connections,
+        "x / Delete",
+        "Close the connection",
+        Some(("x", "Close")),
+    ),
+    bind(Connections, "X", "Close all connections", None),
+    bind(
+        Connections,
+        "F1-F9",
+        "Toggle connection filter presets",
+        Some(("F1-F9", "Toggle Filter")),
+    ),
+    bind(
+        Connections,
+        "v",
+        "Switch to the next column set",
+        Some(("v", "Columns")),
+    ),
+    bind(
+        Connections,
+        "f",
+        "Map fake-ip addresses back to their domains",
+        Some(("f", "Fake-IP")),
+    ),
+    bind(Connections, "r", "Refresh", Some(("r", "Refresh"))),
+    bind(
+        Rules,
+        "/",
+        "Search rules; Esc clears the search",
+        Some(("/", "Search")),
+    ),
+    bind(
+        Rules,
+        "p",
+        "Go to the rule provider of a RULE-SET rule",
+        Some(("p", "Go to Provider")),
+    ),
+    bind(Rules, "r", "Refresh", Some(("r", "Refresh"))),
+    bind(
+        Logs,
+        "Esc",
+        "Back to the main view",
+        Some(("Esc", "Proxies")),
+    ),
+    bind(Logs, "j / k", "Scroll back through the log", None),
+    bind(
+        Logs,
+        "G / End",
+        "Follow new lines again",
+        Some(("G", "Follow")),
+    ),
+    bind(
+        Logs,
+        "L",
+        "Cycle the level: debug, info, warning, error",
+        Some(("L", "Level")),
+    ),
+    bind(
+        Logs,
+        "p",
+        "Pause or resume; lines keep arriving meanwhile",
+        Some(("p", "Pause")),
+    ),
+    bind(Logs, "c", "Clear the log", Some(("c", "Clear"))),
+    bind(
+        RuleProviders,
+        "Esc",
+        "Back to Rules",
+        Some(("Esc", "Rules")),
+    ),
+    bind(
+        RuleProviders,
+        "u",
+        "Update the provider",
+        Some(("u", "Update")),
+    ),
+    bind(RuleProviders, "r", "Refresh", Some(("r", "Refresh"))),
+    bind(
+        ProxyProviders,
+        "Esc",
+        "Back to the main view",
+        Some(("Esc", "Proxies")),
+    ),
+    bind(
+        ProxyProviders,
+        "u",
+        "Update the provider",
+        Some(("u", "Update")),
+    ),
+    bind(
+        ProxyProviders,
+        "t",
+        "Health-check the provider",
+        Some(("t", "Health Check")),
+    ),
+    bind(
+        ProxyProviders,
+        "a",
+        "Cycle the auto-update interval",
+        Some(("a", "Auto-update")),
+    ),
+    bind(ProxyProviders, "r", "Refresh", Some(("r", "Refresh"))),
+    bind(
+        Overview,
+        "Esc",
+        "Back to the main view",
+        Some(("Esc", "Proxies")),
+    ),
+    bind(
+        Overview,
+        "t",
+        "Run the connectivity test",
+        Some(("t", "Test")),
+    ),
+    bind(
+        Overview,
+        "T",
+        "Traffic history from disk",
+        Some(("T", "History")),
+    ),
+    bind(
+        Overview,
+        "r",
+        "Refresh the config and core version",
+        Some(("r", "Refresh")),
+    ),
+    bind(
+        Settings,
+        "Esc / q / s",
+        "Close Settings",
+        Some(("Esc/q", "Back")),
+    ),
+    bind(Settings, "j / k", "Navigate", Some(("j/k", "Nav"))),
+    bind(
+        Settings,
+        "Enter",
+        "Edit or toggle; core settings are staged",
+        Some(("Enter", "Change/Edit")),
+    ),
+    bind(
+        Settings,
+        "+ / - (Right / Left)",
+        "Adjust a numeric setting",
+        Some(("+/-", "Adjust")),
+    ),
+    bind(
+        Settings,
+        "a",
+        "Review and apply staged changes",
+        Some(("a", "Apply")),
+    ),
+    bind(
+        Settings,
+        "D",
+        "Discard staged changes",
+        Some(("D", "Discard")),
+    ),
+    bind(
+        Settings,
+        "v",
+        "Browse the full core config",
+        Some(("v", "View All")),
+    ),
+    bind(
+        ConfigReview,
+        "Enter / y",
+        "Apply as one change",
+        Some(("Enter/y", "Apply as one change")),
+    ),
+    bind(
+        ConfigReview,
+        "Esc / n",
+        "Keep editing",
+        Some(("Esc/n", "Keep editing")),
+    ),
+    bind(ConfigTree, "Esc / q / v", "Back", Some(("Esc/q/v", "Back"))),
+    bind(ConfigTree, "j / k", "Navigate", Some(("j/k", "Nav"))),
+    bind(
+        ConfigTree,
+        "Enter / Space",
+        "Expand or collapse",
+        Some(("Enter/Space", "Expand/Collapse")),
+    ),
+    bind(Hosts, "Esc / q", "Back", Some(("Esc/q", "Back"))),
+    bind(Hosts, "j / k", "Navigate", Some(("j/k", "Nav"))),
+    bind(Hosts, "a", "Add domain=ip", Some(("a", "Add"))),
+    bind(Hosts, "Enter", "Edit", Some(("Enter", "Edit"))),
+    bind(Hosts, "d / Delete", "Delete", Some(("d", "Delete"))),
+    bind(Servers, "Esc / q / S", "Back", Some(("Esc/q", "Back"))),
+    bind(Servers, "j / k", "Navigate", Some(("j/k", "Nav"))),
+    bind(
+        Servers,
+        "Enter",
+        "Switch to the server",
+        Some(("Enter", "Switch")),
+    ),
+    bind(
+        Servers,
+        "a",
+        "Save the current URL and secret",
+        Some(("a", "Save current")),
+    ),
+    bind(Servers, "d", "Delete the server", Some(("d", "Delete"))),
+    bind(Queue, "Esc / q / P", "Back", Some(("Esc/q", "Back"))),
+    bind(Queue, "R", "Retry now", Some(("R", "Retry"))),
+    bind(Queue, "a", "Toggle auto-retry", Some(("a", "Auto-retry"))),
+    bind(Queue, "d / Delete", "Drop the change", Some(("d", "Drop"))),
+    bind(Comparison, "Esc / q", "Close", Some(("Esc/q", "Close"))),
+    bind(Comparison, "r", "Run again", Some(("r", "Run again"))),
+    bind(FakeIp, "j / k", "Scroll", Some(("j/k", "Scroll"))),
+    bind(
+        FakeIp,
+        "r",
+        "Rescan the connections and look the domains up again",
+        Some(("r", "Refresh")),
+    ),
+    bind(FakeIp, "Esc / q", "Close", Some(("Esc/q", "Close"))),
+    bind(Notes, "Enter", "New line", Some(("Enter", "New line"))),
+    bind(
+        Notes,
+        "Esc",
+        "Save and close",
+        Some(("Esc", "Save & close")),
+    ),
+    bind(Debug, "Esc / q / F12", "Close", Some(("Esc/q/F12", "Back"))),
+    bind(Debug, "j / k", "Navigate", Some(("j/k", "Nav"))),
+    bind(
+        Debug,
+        "e / :",
+        "Send a request, e.g. GET /version",
+        Some(("e/:", "New Request")),
+    ),
+    bind(Editing, "Enter", "Save", Some(("Enter", "Save"))),
+    bind(Editing, "Esc", "Cancel", Some(("Esc", "Cancel"))),
+    bind(
+        Editing,
+        "Tab",
+        "Complete names in the command palette",
+        None,
+    ),
+    bind(Confirm, "y", "Yes, go ahead", Some(("y", "Yes"))),
+    bind(Confirm, "n / Esc", "No, leave it", Some(("n/Esc", "No"))),
+    bind(
+        Confirm,
+        "Left / Right / Tab",
+        "Move between Yes and No",
+        Some(("←/→", "Choose")),
+    ),
+    bind(
+        Confirm,
+        "Enter",
+        "Answer with the highlighted choice",
+        Some(("Enter", "Answer")),
+    ),
+    bind(
+        Response,
+        "Esc / q / Enter",
+        "Close",
+        Some(("Esc/q", "Close")),
+    ),
+    bind(Response, "j / k", "Scroll", Some(("j/k", "Scroll"))),
+    bind(Response, "PgUp / PgDn", "Scroll by a page", None),
+    bind(Response, "c", "Copy the body", Some(("c", "Copy"))),
+    bind(Dns, "Enter", "Look up the name", Some(("Enter", "Query"))),
+    bind(
+        Dns,
+        "Tab / Shift+Tab",
+        "Change the record type",
+        Some(("Tab", "Type")),
+    ),
+    bind(Dns, "Esc", "Close", Some(("Esc", "Close"))),
+    bind(
+        TrafficHistory,
+        "Tab / h / l",
+        "Change the range",
+        Some(("Tab/h/l", "Range")),
+    ),
+    bind(
+        TrafficHistory,
+        "r",
+        "Read the history again",
+        Some(("r", "Reload")),
+    ),
+    bind(TrafficHistory, "Esc / q", "Close", Some(("Esc/q", "Close"))),
+    bind(
+        BulkSelect,
+        "Enter / y",
+        "Switch every listed group",
+        Some(("Enter", "Apply")),
+    ),
+    bind(BulkSelect, "Esc / n", "Cancel", Some(("Esc", "Cancel"))),
+    bind(BulkSelect, "j / k", "Scroll", Some(("j/k", "Scroll"))),
+    bind(Pin, "p", "Pin the node", Some(("p", "Pin"))),
+    bind(
+        Pin,
+        "a",
+        "Let the core pick the node again",
+        Some(("a", "Auto")),
+    ),
+    bind(
+        Pin,
+        "Left / Right / Tab",
+        "Move between the choices",
+        Some(("←/→", "Choose")),
+    ),
+    bind(
+        Pin,
+        "Enter",
+        "Apply the highlighted choice",
+        Some(("Enter", "Apply")),
+    ),
+    bind(Pin, "Esc / q", "Cancel", Some(("Esc", "Cancel"))),
+    bind(Notifications, "j / k", "Scroll", Some(("j/k", "Scroll"))),
+    bind(
+        Notifications,
+        "c",
+        "Clear the history",
+        Some(("c", "Clear")),
+    ),
+    bind(Notifications, "Esc / q", "Close", Some(("Esc/q", "Close"))),
+    bind(Changelog, "j / k", "Scroll", Some(("j/k", "Scroll"))),
+    bind(Changelog, "PgUp / PgDn", "Scroll by a page", None),
+    bind(
+        Changelog,
+        "Esc / q / Enter",
+        "Close; it won't show again",
+        Some(("Esc/q", "Close")),
+    ),
+];
+
+/// The default binding a plain `key` press has in the tab views, where the
+/// leader key is read.
+pub fn view_binding(key: char) -> Option<&'static Binding> {
+    const VIEWS: &[KeyContext] = &[
+        Global,
+        Main,
+        Groups,
+        Connections,
+        Rules,
+        Logs,
+        RuleProviders,
+        ProxyProviders,
+        Overview,
+    ];
+    KEYMAP
+        .iter()
+        .filter(|binding| VIEWS.contains(&binding.context))
+        .find(|binding| {
+            binding.keys.split(" / ").any(|keys| {
+                let mut chars = keys.chars();
+                match (chars.next(), chars.next(), chars.next(), chars.next()) {
+                    (Some(only), None, _, _) => only == key,
+                    // A range such as 1-6
+                    (Some(first), Some('-'), Some(last), None) => (first..=last).contains(&key),
+                    _ => false,
+                }
+            })
+        })
+}
+
+/// The status bar hints of `contexts`, in table order.
+pub fn hints(contexts: &[KeyContext]) -> String {
+    hints_with(contexts, |keys| keys.to_string())
+}
+
+/// The status bar hints of the Groups and Proxies lists, with each default
+/// key the `keymap` setting moved shown as its first replacement.
+pub fn main_hints(keymap: &BTreeMap<Action, String>) -> String {
+    let rebound = |key: &str| {
+        Action::ALL
+            .into_iter()
+            .find(|action| action.default_keys().split_whitespace().any(|k| k == key))
+            .and_then(|action| keymap.get(&action))
+            .and_then(|keys| keys.split_whitespace().next())
+            .unwrap_or(key)
+            .to_string()
+    };
+    hints_with(&[KeyContext::Global, KeyContext::Main], |keys| {
+        // Compact hints pair keys with a slash, as in `j/k` or `[/]`
+        if keys == "/" {
+            return rebound(keys);
+        }
+        keys.split('/').map(rebound).collect::<Vec<_>>().join("/")
+    })
+}
+
+fn hints_with(contexts: &[KeyContext], keys: impl Fn(&str) -> String) -> String {
+    contexts
+        .iter()
+        .flat_map(|context| KEYMAP.iter().filter(move |b| b.context == *context))
+        .filter_map(|binding| binding.hint)
+        .map(|(hint_keys, label)| format!("{}: {}", keys(hint_keys), label))
+        .collect::<Vec<_>>()
+        .join(" | ")
+}
+
+/// What a key does in the Groups and Proxies lists. The `keymap` setting
+/// rebinds these by name; the main loop looks the key up in `Keys` and
+/// dispatches on the action rather than on the key itself.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Quit,
+    TypeAhead,
+    Search,
+    Refresh,
+    Test,
+    TestGroup,
+    Palette,
+    RevertGroup,
+    PinGroup,
+    MoveGroupUp,
+    MoveGroupDown,
+    HideGroup,
+    LockGroup,
+    BindGroup,
+    ShowHidden,
+    ProviderFilter,
+    Sort,
+    FlushSmart,
+    Info,
+    CompareMark,
+    Compare,
+    Capabilities,
+    Servers,
+    Settings,
+    Notes,
+    Queue,
+    CopyDashboardUrl,
+    TrafficHistory,
+    Notifications,
+    Debug,
+    Down,
+    Up,
+    NextGroup,
+    PreviousGroup,
+    Open,
+    Back,
+    Select,
+}
+
+impl Action {
+    pub const ALL: [Action; 37] = [
+        Action::Quit,
+        Action::TypeAhead,
+        Action::Search,
+        Action::Refresh,
+        Action::Test,
+        Action::TestGroup,
+        Action::Palette,
+        Action::RevertGroup,
+        Action::PinGroup,
+        Action::MoveGroupUp,
+        Action::MoveGroupDown,
+        Action::HideGroup,
+        Action::LockGroup,
+        Action::BindGroup,
+        Action::ShowHidden,
+        Action::ProviderFilter,
+        Action::Sort,
+        Action::FlushSmart,
+        Action::Info,
+        Action::CompareMark,
+        Action::Compare,
+        Action::Capabilities,
+        Action::Servers,
+        Action::Settings,
+        Action::Notes,
+        Action::Queue,
+        Action::CopyDashboardUrl,
+        Action::TrafficHistory,
+        Action::Notifications,
+        Action::Debug,
+        Action::Down,
+        Action::Up,
+        Action::NextGroup,
+        Action::PreviousGroup,
+        Action::Open,
+        Action::Back,
+        Action::Select,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::TypeAhead => "type_ahead",
+            Action::Search => "search",
+            Action::Refresh => "refresh",
+            Action::Test => "test",
+            Action::TestGroup => "test_group",
+            Action::Palette => "palette",
+            Action::RevertGroup => "revert_group",
+            Action::PinGroup => "pin_group",
+            Action::MoveGroupUp => "move_group_up",
+            Action::MoveGroupDown => "move_group_down",
+            Action::HideGroup => "hide_group",
+            Action::LockGroup => "lock_group",
+            Action::BindGroup => "bind_group",
+            Action::ShowHidden => "show_hidden",
+            Action::ProviderFilter => "provider_filter",
+            Action::Sort => "sort",
+            Action::FlushSmart => "flush_smart",
+            Action::Info => "info",
+            Action::CompareMark => "compare_mark",
+            Action::Compare => "compare",
+            Action::Capabilities => "capabilities",
+            Action::Servers => "servers",
+            Action::Settings => "settings",
+            Action::Notes => "notes",
+            Action::Queue => "queue",
+            Action::CopyDashboardUrl => "copy_dashboard_url",
+            Action::TrafficHistory => "traffic_history",
+            Action::Notifications => "notifications",
+            Action::Debug => "debug",
+            Action::Down => "down",
+            Action::Up => "up",
+            Action::NextGroup => "next_group",
+            Action::PreviousGroup => "previous_group",
+            Action::Open => "open",
+            Action::Back => "back",
+            Action::Select => "select",
+        }
+    }
+
+    /// The keys bound when `keymap` leaves the action out, in `parse_key`
+    /// syntax.
+    pub fn default_keys(self) -> &'static str {
+        match self {
+            Action::Quit => "q",
+            Action::TypeAhead => "'",
+            Action::Search => "/",
+            Action::Refresh => "r",
+            Action::Test => "t",
+            Action::TestGroup => "d",
+            Action::Palette => ":",
+            Action::RevertGroup => "z",
+            Action::PinGroup => "p",
+            Action::MoveGroupUp => "K",
+            Action::MoveGroupDown => "J",
+            Action::HideGroup => "x",
+            Action::LockGroup => "L",
+            Action::BindGroup => "B",
+            Action::ShowHidden => "H",
+            Action::ProviderFilter => "f",
+            Action::Sort => "o",
+            Action::FlushSmart => "F",
+            Action::Info => "i",
+            Action::CompareMark => "m",
+            Action::Compare => "C",
+            Action::Capabilities => "c",
+            Action::Servers => "S",
+            Action::Settings => "s",
+            Action::Notes => "n",
+            Action::Queue => "P",
+            Action::CopyDashboardUrl => "u",
+            Action::TrafficHistory => "T",
+            Action::Notifications => "N",
+            Action::Debug => "F12",
+            Action::Down => "j Down",
+            Action::Up => "k Up",
+            Action::NextGroup => "]",
+            Action::PreviousGroup => "[",
+            Action::Open => "l Right",
+            Action::Back => "h Left Esc",
+            Action::Select => "Enter",
+        }
+    }
+}
+
+/// Reads one key such as `j`, `Enter`, `F5` or `Ctrl+d`. Names are
+/// case-insensitive; a single character is taken as typed, so `K` is
+/// Shift+k.
+pub fn parse_key(spec: &str) -> Result<(KeyCode, KeyModifiers), String> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut rest = spec;
+    loop {
+        let lower = rest.to_lowercase();
+        if rest.len() > 1 && (lower.starts_with("ctrl+") || lower.starts_with("ctrl-")) {
+            modifiers |= KeyModifiers::CONTROL;
+            rest = &rest[5..];
+        } else if rest.len() > 1 && (lower.starts_with("alt+") || lower.starts_with("alt-")) {
+            modifiers |= KeyModifiers::ALT;
+            rest = &rest[4..];
+        } else {
+            break;
+        }
+    }
+
+    let mut chars = rest.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Ok((KeyCode::Char(c), modifiers));
+    }
+    let code = match rest.to_lowercase().as_str() {
+        "space" => KeyCode::Char(' '),
+        "enter" => KeyCode::Enter,
+        "esc" => KeyCode::Esc,
+        "tab" => KeyCode::Tab,
+        "backspace" => KeyCode::Backspace,
+        "delete" => KeyCode::Delete,
+        "insert" => KeyCode::Insert,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        name => match name.strip_prefix('f').and_then(|n| n.parse().ok()) {
+            Some(n @ 1..=12) => KeyCode::F(n),
+            _ => return Err(format!("unknown key \"{}\"", spec)),
+        },
+    };
+    Ok((code, modifiers))
+}
+
+/// The Groups and Proxies keys: the defaults with `keymap` applied.
+pub struct Keys(HashMap<(KeyCode, KeyModifiers), Action>);
+
+impl Default for Keys {
+    fn default() -> Self {
+        Self::new(&BTreeMap::new()).0
+    }
+}
+
+impl Keys {
+    /// Binds each action to its keys from `keymap`, or to its defaults. A key
+    /// named for one action in `keymap` is taken from whichever action had it
+    /// by default. Keys that don't parse are skipped and come back as
+    /// problems.
+    pub fn new(keymap: &BTreeMap<Action, String>) -> (Self, Vec<String>) {
+        let mut problems = Vec::new();
+        let mut keys = HashMap::new();
+        // Defaults first, so the user's bindings win any clash
+        let ordered = Action::ALL
+            .iter()
+            .filter(|action| !keymap.contains_key(action))
+            .map(|action| (*action, action.default_keys()))
+            .chain(keymap.iter().map(|(action, spec)| (*action, spec.as_str())));
+        for (action, specs) in ordered {
+            for spec in specs.split_whitespace() {
+                match parse_key(spec) {
+                    Ok(key) => {
+                        keys.insert(key, action);
+                    }
+                    Err(e) => problems.push(format!("keymap {}: {}", action.name(), e)),
+                }
+            }
+        }
+        (Self(keys), problems)
+    }
+
+    /// The action bound to `key`. Shift is part of the character for letters
+    /// and symbols, so only Ctrl and Alt are compared.
+    pub fn action(&self, key: &KeyEvent) -> Option<Action> {
+        let modifiers = key.modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT);
+        self.0.get(&(key.code, modifiers)).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_key_reads_names_and_modifiers() {
+        assert_eq!(parse_key("j"), Ok((KeyCode::Char('j'), KeyModifiers::NONE)));
+        assert_eq!(parse_key("K"), Ok((KeyCode::Char('K'), KeyModifiers::NONE)));
+        assert_eq!(parse_key("+"), Ok((KeyCode::Char('+'), KeyModifiers::NONE)));
+        assert_eq!(
+            parse_key("Space"),
+            Ok((KeyCode::Char(' '), KeyModifiers::NONE))
+        );
+        assert_eq!(
+            parse_key("PageDown"),
+            Ok((KeyCode::PageDown, KeyModifiers::NONE))
+        );
+        assert_eq!(parse_key("F5"), Ok((KeyCode::F(5), KeyModifiers::NONE)));
+        assert_eq!(
+            parse_key("Ctrl+d"),
+            Ok((KeyCode::Char('d'), KeyModifiers::CONTROL))
+        );
+        assert_eq!(
+            parse_key("ctrl-alt-Enter"),
+            Ok((KeyCode::Enter, KeyModifiers::CONTROL | KeyModifiers::ALT))
+        );
+    }
+
+    #[test]
+    fn view_binding_finds_single_keys_and_ranges() {
+        let action = |key| view_binding(key).map(|binding| binding.action);
+        assert_eq!(action('j'), Some("Next item; hold to scroll faster"));
+        assert_eq!(action('T'), Some("Traffic history from disk"));
+        assert!(action('4').is_some());
+        assert_eq!(action(' '), None);
+        assert_eq!(action('e'), None);
+    }
+
+    #[test]
+    fn parse_key_rejects_unknown_names() {
+        assert!(parse_key("F13").is_err());
+        assert!(parse_key("Hyper").is_err());
+        assert!(parse_key("").is_err());
+    }
+}
@@ -0,0 +1,4 @@
+//! The parts of mihomot that work without the terminal UI, for scripts and
+//! other tools.
+
+pub mod api;
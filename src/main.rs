@@ -1,17 +1,47 @@
-use anyhow::Result;
-use clap::Parser;
+use anyhow::{Context, Result, anyhow, bail};
+use base64::Engine;
+use clap::{Parser, Subcommand};
 use crossterm::{
-    event::{self, Event, KeyCode, KeyEventKind},
+    event::{Event, EventStream, KeyCode, KeyEventKind, KeyModifiers},
     execute,
     terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
 };
+use futures_util::StreamExt;
 use ratatui::DefaultTerminal;
-use std::io::stdout;
+use std::collections::BTreeMap;
+use std::io::{Write, stdout};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
 
 mod app;
+mod changelog;
+mod cli;
+mod crash;
+mod export;
+mod ipc;
+mod keymap;
+mod serve;
+mod theme;
 mod ui;
+mod update;
 
-use app::{App, ConfigEntry, Focus};
+use app::{
+    App, AppEvent, ConfigEntry, ConfirmAction, DNS_QUERY_TYPES, Focus, GROUP_HOTKEY_LIMIT, Job,
+    LeaderAction, Load, Modal, NotificationLevel, ProviderAction, StartStep, StartupAction, Tab,
+    TrafficRange, leader_key_name,
+};
+use keymap::Action;
+
+/// Shows the error of a fallible call in the status bar instead of discarding it.
+macro_rules! report {
+    ($app:expr, $context:expr, $result:expr) => {
+        if let Err(e) = $result {
+            $app.error(format!("{}: {:#}", $context, e));
+        }
+    };
+}
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -23,301 +53,1973 @@ struct Args {
     /// Temporary API Secret to use
     #[arg(short = 'S', long)]
     secret: Option<String>,
+
+    /// Compact one- or two-line display for small terminal splits
+    #[arg(long)]
+    mini: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Download the latest release from GitHub and replace this binary
+    SelfUpdate {
+        /// Only check whether a newer release exists
+        #[arg(long)]
+        check: bool,
+    },
+    /// Push traffic and delay metrics to statsd or InfluxDB without the TUI
+    Export {
+        /// `statsd://host:port`, or an InfluxDB write URL such as
+        /// `http://host:8086/api/v2/write?org=home&bucket=mihomo`
+        target: String,
+        /// InfluxDB API token
+        #[arg(long)]
+        token: Option<String>,
+        /// Seconds between pushes
+        #[arg(long, default_value_t = 10)]
+        interval: u64,
+    },
+    /// Serve a status page (mode, selections, traffic) over HTTP, for
+    /// checking on the proxies from a phone without the controller secret
+    Serve {
+        /// Address to listen on; `0.0.0.0:8080` opens it to the LAN
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        listen: String,
+        /// Only show status; required, as it is the only mode so far
+        #[arg(long)]
+        readonly: bool,
+        /// Seconds between polls of the controller
+        #[arg(long, default_value_t = 5)]
+        interval: u64,
+    },
+    /// Inspect proxy groups without the TUI
+    Proxies {
+        #[command(subcommand)]
+        command: cli::ProxiesCommand,
+    },
+    /// Switch GROUP to PROXY
+    Select { group: String, proxy: String },
+    /// Delay-test every proxy in GROUP and print the results
+    Test {
+        group: String,
+        /// Print JSON instead of tab-separated text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Read or change the core config
+    Config {
+        #[command(subcommand)]
+        command: cli::ConfigCommand,
+    },
+    /// Send a command to the running TUI: `group <name>`, `test [group]`,
+    /// `select <group> <proxy>`, `refresh` or `ping`
+    Ctl {
+        #[arg(required = true, trailing_var_arg = true)]
+        command: Vec<String>,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
 
+    match args.command {
+        Some(Command::SelfUpdate { check }) => return update::self_update(check).await,
+        Some(Command::Export {
+            target,
+            token,
+            interval,
+        }) => return export::run(args.url, args.secret, &target, token, interval).await,
+        Some(Command::Serve {
+            listen,
+            readonly,
+            interval,
+        }) => return serve::run(args.url, args.secret, &listen, readonly, interval).await,
+        Some(Command::Proxies { command }) => {
+            return cli::proxies(args.url, args.secret, command).await;
+        }
+        Some(Command::Select { group, proxy }) => {
+            return cli::select(args.url, args.secret, &group, &proxy).await;
+        }
+        Some(Command::Test { group, json }) => {
+            return cli::test(args.url, args.secret, &group, json).await;
+        }
+        Some(Command::Config { command }) => {
+            return cli::config(args.url, args.secret, command).await;
+        }
+        Some(Command::Ctl { command }) => {
+            let message = ipc::send(&command.join(" ")).await?;
+            if !message.is_empty() {
+                println!("{}", message);
+            }
+            return Ok(());
+        }
+        None => {}
+    }
+
+    // A second TUI would fight the first over settings.json, so point at
+    // `ctl` instead; remote control is a bonus, so failing to listen is fine
+    let mut ipc = None;
+    if ipc::send("ping").await.is_ok() {
+        if !start_another_instance()? {
+            return Ok(());
+        }
+    } else {
+        ipc = ipc::Server::bind().ok();
+    }
+
     // Setup terminal
     enable_raw_mode()?;
     let mut stdout = stdout();
     execute!(stdout, EnterAlternateScreen)?;
+    crash::install();
     let mut terminal = ratatui::init();
 
     // Create app and fetch initial data
     let mut app = App::new(args.url, args.secret);
-    let _ = app.fetch_proxies().await;
-    let _ = app.fetch_config().await;
-    app.trigger_latency_test();
+    app.mini = args.mini;
+    app.load_latency_history();
+    app.load_keymap();
+    report!(app, "Prune traffic history", app.prune_traffic_history());
+    // The rest of startup runs from the loop once these have answered
+    app.probe_capabilities(startup_loads(&app.app_settings.startup_actions));
+    app.check_latest_core_release();
+    report!(app, "Record version", app.check_upgrade());
 
-    let app_result = run_app(&mut terminal, &mut app).await;
+    let input = read_input(app.events_tx.clone());
+    let app_result = run_app(&mut terminal, &mut app, ipc.as_mut()).await;
+    input.abort();
+    let saved = app.save_latency_history().and(app.flush_traffic_history());
 
     // Restore terminal
     ratatui::restore();
     execute!(stdout, LeaveAlternateScreen)?;
     disable_raw_mode()?;
 
-    app_result
+    app_result.and(saved)
+}
+
+/// Asks whether to start anyway while another instance is running.
+fn start_another_instance() -> Result<bool> {
+    println!(
+        "mihomot is already running. Control it with `mihomot ctl`, e.g.\n  \
+         mihomot ctl group Proxy\n  mihomot ctl test\n  mihomot ctl select Proxy HK-01"
+    );
+    print!("Start another instance anyway? [y/N] ");
+    stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(answer.trim().eq_ignore_ascii_case("y"))
+}
+
+/// Runs one `mihomot ctl` command against the live TUI and returns the
+/// message printed by `ctl`.
+fn run_ctl(app: &mut App, line: &str) -> Result<String> {
+    let (command, arg) = line
+        .split_once(' ')
+        .map_or((line, ""), |(command, arg)| (command, arg.trim()));
+    match command {
+        "ping" => Ok("pong".to_string()),
+        "group" => {
+            if !app.jump_to_group(arg) {
+                bail!("No group named {:?}", arg);
+            }
+            Ok(format!("Showing {}", arg))
+        }
+        "test" => {
+            if !arg.is_empty() && !app.jump_to_group(arg) {
+                bail!("No group named {:?}", arg);
+            }
+            let Some(group) = app.get_selected_group_name().cloned() else {
+                bail!("No group is selected");
+            };
+            app.trigger_group_latency_test();
+            Ok(format!("Testing {}", group))
+        }
+        "select" => {
+            if let Modal::Confirm { .. } = app.modal {
+                bail!("mihomot is waiting for an answer to a confirmation");
+            }
+            let mark = app.notification_mark();
+            app.run_command(line)?;
+            if let Some(error) = app
+                .notifications_since(mark)
+                .find(|n| n.level == NotificationLevel::Error)
+            {
+                bail!(error.message.clone());
+            }
+            if let Modal::Confirm { .. } = app.modal {
+                return Ok("The group is locked; confirm the switch in mihomot".to_string());
+            }
+            Ok(app
+                .notifications_since(mark)
+                .last()
+                .map(|n| n.message.clone())
+                .unwrap_or_default())
+        }
+        "refresh" => {
+            app.load(Load::Proxies);
+            Ok("Refreshing".to_string())
+        }
+        _ => bail!(
+            "Unknown command {:?}; use group, test, select, refresh or ping",
+            command
+        ),
+    }
+}
+
+/// What `startup_actions` loads once the controller has been probed.
+fn startup_loads(actions: &[StartupAction]) -> Vec<Load> {
+    let mut loads = Vec::new();
+    if actions.contains(&StartupAction::FetchProxies) || actions.contains(&StartupAction::GroupTest)
+    {
+        loads.extend([Load::Proxies, Load::ProxyProviders]);
+    }
+    if actions.contains(&StartupAction::FetchConfig) {
+        loads.push(Load::Config);
+    }
+    loads
+}
+
+/// Whether the probe and `startup_loads` have all answered.
+fn startup_answered(app: &App) -> bool {
+    !app.jobs.contains(&Job::Probe)
+        && !startup_loads(&app.app_settings.startup_actions)
+            .iter()
+            .any(|load| app.loading.contains(load))
+}
+
+/// The rest of `startup_actions`, then `on_start`, once `startup_loads` are in.
+fn finish_startup(app: &mut App) {
+    let actions = app.app_settings.startup_actions.clone();
+    if actions.contains(&StartupAction::FetchProxies) || actions.contains(&StartupAction::GroupTest)
+    {
+        app.select_current_proxy();
+    }
+    if actions.contains(&StartupAction::ConnectivityTest) {
+        app.trigger_latency_test();
+    }
+    if actions.contains(&StartupAction::GroupTest) {
+        app.trigger_group_latency_test();
+    }
+    run_on_start(app);
+}
+
+/// Replays `on_start` in order, stopping at the first step that fails, since
+/// later ones usually build on it.
+fn run_on_start(app: &mut App) {
+    for step in app.app_settings.on_start.clone() {
+        if let Err(e) = run_start_step(app, &step) {
+            app.error(format!("on_start {:?}: {:#}", step, e));
+            return;
+        }
+    }
+}
+
+fn run_start_step(app: &mut App, step: &str) -> Result<()> {
+    match StartStep::parse(step).map_err(|e| anyhow!(e))? {
+        StartStep::Group(group) => {
+            if !app.jump_to_group(&group) {
+                bail!("No group named {:?}", group);
+            }
+        }
+        StartStep::TestGroup(group) => {
+            if let Some(group) = group
+                && !app.jump_to_group(&group)
+            {
+                bail!("No group named {:?}", group);
+            }
+            app.trigger_group_latency_test();
+        }
+        StartStep::OpenTab(focus) => open_view(app, focus),
+        StartStep::Command(line) => {
+            let mark = app.notification_mark();
+            app.run_command(&line)?;
+            if let Some(error) = app
+                .notifications_since(mark)
+                .find(|n| n.level == NotificationLevel::Error)
+            {
+                bail!(error.message.clone());
+            }
+        }
+        StartStep::Leader(action) => run_leader_action(app, action),
+    }
+    Ok(())
+}
+
+/// How long low-power mode waits without input before pausing streams.
+const LOW_POWER_IDLE_AFTER: Duration = Duration::from_secs(60);
+
+/// Flags set from signal handlers: a SIGTSTP sent by the shell or `kill`
+/// (raw mode swallows Ctrl+Z itself) and a SIGCONT after any stop.
+struct SuspendSignals {
+    stop: Arc<AtomicBool>,
+    resumed: Arc<AtomicBool>,
+}
+
+impl SuspendSignals {
+    fn register() -> Result<Self> {
+        let signals = SuspendSignals {
+            stop: Arc::new(AtomicBool::new(false)),
+            resumed: Arc::new(AtomicBool::new(false)),
+        };
+        #[cfg(unix)]
+        {
+            use signal_hook::consts::{SIGCONT, SIGTSTP};
+            signal_hook::flag::register(SIGTSTP, signals.stop.clone())?;
+            signal_hook::flag::register(SIGCONT, signals.resumed.clone())?;
+        }
+        Ok(signals)
+    }
+}
+
+/// Hands the terminal back to the shell and stops the process until `fg`.
+/// Background tasks are stopped along with it; the streams are dropped so
+/// they reconnect cleanly instead of reading stale sockets.
+fn suspend(terminal: &mut DefaultTerminal, app: &mut App, signals: &SuspendSignals) -> Result<()> {
+    #[cfg(unix)]
+    {
+        app.stop_connections_stream();
+        app.stop_traffic_monitor();
+        app.stop_logs_stream();
+        ratatui::restore();
+        // SIGTSTP is handled above, so stop with the uncatchable signal instead
+        signal_hook::low_level::raise(signal_hook::consts::SIGSTOP)?;
+        reacquire_terminal(terminal)?;
+        // The run loop restarts the connections and logs streams by itself
+        app.start_traffic_monitor();
+        // The SIGCONT that woke us is handled already
+        signals.resumed.store(false, Ordering::Relaxed);
+    }
+    #[cfg(not(unix))]
+    let _ = (terminal, app, signals);
+    Ok(())
+}
+
+/// Re-enters raw mode and the alternate screen and forces a full redraw,
+/// since the shell may have reset the terminal while we were stopped.
+fn reacquire_terminal(terminal: &mut DefaultTerminal) -> Result<()> {
+    enable_raw_mode()?;
+    execute!(stdout(), EnterAlternateScreen)?;
+    terminal.clear()?;
+    Ok(())
 }
 
-async fn run_app(terminal: &mut DefaultTerminal, app: &mut App) -> Result<()> {
-    loop {
+/// Forwards terminal input to the run loop, which waits on it together with
+/// everything else in `App::events_rx`.
+fn read_input(tx: mpsc::Sender<AppEvent>) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut events = EventStream::new();
+        while let Some(Ok(event)) = events.next().await {
+            if tx.send(AppEvent::Input(event)).await.is_err() {
+                return;
+            }
+        }
+    })
+}
+
+async fn run_app(
+    terminal: &mut DefaultTerminal,
+    app: &mut App,
+    mut ipc: Option<&mut ipc::Server>,
+) -> Result<()> {
+    let mut last_connections_fetch = Instant::now();
+    let mut last_retry = Instant::now();
+    let mut last_drift_check = Instant::now();
+    let mut last_ping: Option<Instant> = None;
+    let mut last_core_poll = Instant::now();
+    let mut last_input = Instant::now();
+    let mut last_stale_check = Instant::now();
+    let mut last_provider_schedule = Instant::now();
+    let mut last_auto_refresh = Instant::now();
+    let mut starting = true;
+    let signals = SuspendSignals::register()?;
+
+    'events: loop {
+        // Low-power mode redraws and polls less often, and pauses streaming
+        // entirely once nothing has been pressed for a while
+        let low_power = app.low_power_active();
+        app.idle = low_power && last_input.elapsed() >= LOW_POWER_IDLE_AFTER;
+        let (tick, poll_every, drift_every, ping_every) = if app.idle {
+            (
+                Duration::from_secs(1),
+                Duration::MAX,
+                Duration::MAX,
+                Duration::MAX,
+            )
+        } else if low_power {
+            (
+                Duration::from_millis(500),
+                Duration::from_secs(5),
+                Duration::from_secs(60),
+                Duration::from_secs(30),
+            )
+        } else {
+            (
+                Duration::from_millis(100),
+                Duration::from_secs(1),
+                Duration::from_secs(10),
+                Duration::from_secs(5),
+            )
+        };
+        if app.idle {
+            app.stop_traffic_monitor();
+        }
+
+        if signals.stop.swap(false, Ordering::Relaxed) {
+            suspend(terminal, app, &signals)?;
+        }
+        if signals.resumed.swap(false, Ordering::Relaxed) {
+            // Also covers stops we couldn't intercept, such as SIGSTOP
+            reacquire_terminal(terminal)?;
+        }
+
+        if starting && startup_answered(app) {
+            starting = false;
+            finish_startup(app);
+        }
+
+        crash::update(app);
         terminal.draw(|f| ui::draw(f, app))?;
+        if std::mem::take(&mut app.bell_pending) {
+            let mut out = stdout();
+            write!(out, "\x07")?;
+            out.flush()?;
+        }
+
+        // Keep connections live while the view or the wide layout's summary
+        // shows them, streaming over the WebSocket and polling only while it
+        // is unavailable
+        if app.shows_connections() && !app.idle {
+            app.start_connections_stream();
+            if !app.connections_live.load(Ordering::Relaxed)
+                && last_connections_fetch.elapsed() >= poll_every
+            {
+                app.load(Load::Connections);
+                last_connections_fetch = Instant::now();
+            }
+        } else {
+            app.stop_connections_stream();
+        }
 
-        // Check for real latency updates
-        if let Ok(status) = app.real_latency_rx.try_recv() {
-            app.real_latency_status = status;
+        // Logs are only streamed while their view is open
+        if app.current_view() == Focus::Logs && !app.idle {
+            app.start_logs_stream();
+        } else {
+            app.stop_logs_stream();
         }
 
-        // Check for proxy latency updates
-        while let Ok((name, latency)) = app.proxy_test_rx.try_recv() {
-            app.proxy_latency.insert(name, Some(latency));
+        // Replay changes queued while the controller was unreachable
+        if app.auto_retry
+            && !app.mutation_queue.is_empty()
+            && last_retry.elapsed() >= Duration::from_secs(5)
+        {
+            app.retry_queued();
+            last_retry = Instant::now();
         }
 
-        // Check for traffic updates
-        while let Ok(traffic) = app.traffic_rx.try_recv() {
-            app.on_traffic(traffic);
+        if matches!(app.focus, Focus::Groups | Focus::Proxies) && !app.idle {
+            app.auto_test_selected_group();
         }
 
-        if event::poll(std::time::Duration::from_millis(100))?
-            && let Event::Key(key) = event::read()?
-            && key.kind == KeyEventKind::Press
+        // Re-test the selected group once its delay results have gone stale
+        if app.app_settings.auto_retest_stale
+            && !app.idle
+            && matches!(app.focus, Focus::Groups | Focus::Proxies)
+            && last_stale_check.elapsed() >= Duration::from_secs(30)
         {
-            if app.is_editing {
-                match key.code {
-                    KeyCode::Esc => {
-                        app.is_editing = false;
-                    }
-                    KeyCode::Enter => {
-                        let _ = commit_edit(app).await;
-                        app.is_editing = false;
+            if app.selected_group_needs_retest() {
+                app.trigger_group_latency_test();
+            }
+            last_stale_check = Instant::now();
+        }
+
+        // Time the controller link itself, apart from proxy delays
+        if last_ping.is_none_or(|at| at.elapsed() >= ping_every) {
+            app.ping_controller();
+            last_ping = Some(Instant::now());
+        }
+
+        // Follow a restart or upgrade until the core answers again
+        if app.core_operation_waiting() && last_core_poll.elapsed() >= Duration::from_secs(1) {
+            app.poll_core_version();
+            last_core_poll = Instant::now();
+        }
+
+        // Detect config changes made by other dashboards or scripts
+        if !app.config_drift && last_drift_check.elapsed() >= drift_every {
+            app.check_config_drift();
+            last_drift_check = Instant::now();
+        }
+
+        // Refresh what `r` would on the configured interval
+        let refresh_every = Duration::from_secs(app.app_settings.auto_refresh);
+        if !refresh_every.is_zero() && !app.idle && last_auto_refresh.elapsed() >= refresh_every {
+            app.auto_refresh();
+            last_auto_refresh = Instant::now();
+        }
+
+        // Refresh subscriptions on the app's own schedule, also while idle
+        if last_provider_schedule.elapsed() >= Duration::from_secs(60) {
+            app.update_due_providers();
+            last_provider_schedule = Instant::now();
+        }
+
+        // Sleep until something happens; the tick only keeps the timers
+        // above and animations going
+        let ctl = async {
+            match ipc.as_mut() {
+                Some(server) => server.rx.recv().await,
+                None => std::future::pending().await,
+            }
+        };
+        let mut event = tokio::select! {
+            Some(event) = app.events_rx.recv() => event,
+            Some(request) = ctl => {
+                // Commands from `mihomot ctl`
+                let reply = match run_ctl(app, &request.line) {
+                    Ok(message) => format!("ok {}", message),
+                    Err(e) => format!("error: {:#}", e),
+                };
+                let _ = request.reply.send(reply);
+                continue;
+            }
+            _ = tokio::time::sleep(tick) => continue,
+        };
+
+        // Apply everything else already waiting before drawing again
+        let key = loop {
+            match event {
+                AppEvent::Input(Event::Key(key)) if key.kind == KeyEventKind::Press => break key,
+                AppEvent::Input(_) => {}
+                event => app.on_event(event),
+            }
+            match app.events_rx.try_recv() {
+                Ok(next) => event = next,
+                Err(_) => continue 'events,
+            }
+        };
+
+        crash::record_key(app, &key);
+        last_input = Instant::now();
+        if app.idle {
+            app.idle = false;
+            app.start_traffic_monitor();
+        }
+
+        if key.code == KeyCode::Char('z') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            suspend(terminal, app, &signals)?;
+            continue;
+        }
+
+        // Mini mode has no room for views or popups, so only a few keys apply
+        if app.mini {
+            match key.code {
+                KeyCode::Char('q') => return Ok(()),
+                KeyCode::Char('r') => {
+                    app.load(Load::Proxies);
+                    app.load(Load::Config);
+                }
+                KeyCode::Char('t') => app.trigger_latency_test(),
+                KeyCode::Char('m') => app.mini = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        if let Modal::Confirm { yes, .. } = &mut app.modal {
+            match key.code {
+                KeyCode::Char('y') | KeyCode::Char('Y') => app.answer_confirm(true),
+                KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Char('q') | KeyCode::Esc => {
+                    app.answer_confirm(false);
+                }
+                KeyCode::Left
+                | KeyCode::Right
+                | KeyCode::Tab
+                | KeyCode::Char('h')
+                | KeyCode::Char('l') => *yes = !*yes,
+                KeyCode::Enter => {
+                    let yes = *yes;
+                    app.answer_confirm(yes);
+                }
+                _ => {}
+            }
+            continue;
+        }
+
+        if let Modal::Response(response) = &app.modal {
+            match key.code {
+                KeyCode::Esc | KeyCode::Char('q') | KeyCode::Enter => {
+                    app.modal = Modal::None;
+                    app.popup_scroll = 0;
+                }
+                KeyCode::Char('c') => {
+                    report!(app, "Copy", copy_to_clipboard(&response.body));
+                    app.info("Response body copied".to_string());
+                }
+                KeyCode::Char('j') | KeyCode::Down => app.scroll_popup_down(),
+                KeyCode::Char('k') | KeyCode::Up => app.scroll_popup_up(),
+                KeyCode::PageDown | KeyCode::Char(' ') => {
+                    app.popup_scroll = app.popup_scroll.saturating_add(10);
+                }
+                KeyCode::PageUp => app.popup_scroll = app.popup_scroll.saturating_sub(10),
+                KeyCode::Char('g') | KeyCode::Home => app.popup_scroll = 0,
+                _ => {}
+            }
+            continue;
+        }
+
+        if let Modal::Dns(tool) = &mut app.modal {
+            match key.code {
+                KeyCode::Esc => app.modal = Modal::None,
+                KeyCode::Enter => app.run_dns_query(),
+                KeyCode::Tab => {
+                    tool.record_type = (tool.record_type + 1) % DNS_QUERY_TYPES.len();
+                }
+                KeyCode::BackTab => {
+                    tool.record_type = tool
+                        .record_type
+                        .checked_sub(1)
+                        .unwrap_or(DNS_QUERY_TYPES.len() - 1);
+                }
+                KeyCode::Backspace => {
+                    tool.input.pop();
+                }
+                KeyCode::Char(c) => tool.input.push(c),
+                _ => {}
+            }
+            continue;
+        }
+
+        if let Modal::TrafficHistory(history) = &app.modal {
+            let range = history.range;
+            match key.code {
+                KeyCode::Esc | KeyCode::Char('q') => app.modal = Modal::None,
+                KeyCode::Tab | KeyCode::Right | KeyCode::Char('l') => {
+                    app.open_traffic_history(range.next());
+                }
+                KeyCode::BackTab | KeyCode::Left | KeyCode::Char('h') => {
+                    app.open_traffic_history(range.previous());
+                }
+                KeyCode::Char('r') => app.open_traffic_history(range),
+                _ => {}
+            }
+            continue;
+        }
+
+        if let Modal::Changelog(_) = app.modal {
+            match key.code {
+                KeyCode::Esc | KeyCode::Char('q') | KeyCode::Enter => {
+                    app.modal = Modal::None;
+                    app.popup_scroll = 0;
+                }
+                KeyCode::Char('j') | KeyCode::Down => app.scroll_popup_down(),
+                KeyCode::Char('k') | KeyCode::Up => app.scroll_popup_up(),
+                KeyCode::PageDown | KeyCode::Char(' ') => {
+                    app.popup_scroll = app.popup_scroll.saturating_add(10);
+                }
+                KeyCode::PageUp => app.popup_scroll = app.popup_scroll.saturating_sub(10),
+                KeyCode::Char('g') | KeyCode::Home => app.popup_scroll = 0,
+                _ => {}
+            }
+            continue;
+        }
+
+        if let Modal::FakeIp(_) = app.modal {
+            match key.code {
+                KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('f') => {
+                    app.modal = Modal::None;
+                    app.popup_scroll = 0;
+                }
+                KeyCode::Char('r') => app.open_fake_ip_map(),
+                KeyCode::Char('j') | KeyCode::Down => app.scroll_popup_down(),
+                KeyCode::Char('k') | KeyCode::Up => app.scroll_popup_up(),
+                KeyCode::PageDown | KeyCode::Char(' ') => {
+                    app.popup_scroll = app.popup_scroll.saturating_add(10);
+                }
+                KeyCode::PageUp => app.popup_scroll = app.popup_scroll.saturating_sub(10),
+                KeyCode::Char('g') | KeyCode::Home => app.popup_scroll = 0,
+                _ => {}
+            }
+            continue;
+        }
+
+        if let Modal::Notifications = app.modal {
+            match key.code {
+                KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('N') => {
+                    app.modal = Modal::None;
+                    app.popup_scroll = 0;
+                }
+                KeyCode::Char('c') => {
+                    app.notifications.clear();
+                    app.popup_scroll = 0;
+                }
+                KeyCode::Char('j') | KeyCode::Down => app.scroll_popup_down(),
+                KeyCode::Char('k') | KeyCode::Up => app.scroll_popup_up(),
+                KeyCode::PageDown | KeyCode::Char(' ') => {
+                    app.popup_scroll = app.popup_scroll.saturating_add(10);
+                }
+                KeyCode::PageUp => app.popup_scroll = app.popup_scroll.saturating_sub(10),
+                KeyCode::Char('g') | KeyCode::Home => app.popup_scroll = 0,
+                _ => {}
+            }
+            continue;
+        }
+
+        if let Modal::Pin(choice) = &mut app.modal {
+            match key.code {
+                KeyCode::Char('p') => app.answer_pin(false),
+                KeyCode::Char('a') => app.answer_pin(true),
+                KeyCode::Left
+                | KeyCode::Right
+                | KeyCode::Tab
+                | KeyCode::Char('h')
+                | KeyCode::Char('l') => choice.unpin = !choice.unpin,
+                KeyCode::Enter => {
+                    let unpin = choice.unpin;
+                    app.answer_pin(unpin);
+                }
+                KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('n') => {
+                    app.modal = Modal::None;
+                }
+                _ => {}
+            }
+            continue;
+        }
+
+        if let Modal::BulkSelect(_) = app.modal {
+            match key.code {
+                KeyCode::Enter | KeyCode::Char('y') => app.apply_bulk_selection(),
+                KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('n') => {
+                    app.modal = Modal::None;
+                    app.popup_scroll = 0;
+                }
+                KeyCode::Char('j') | KeyCode::Down => app.scroll_popup_down(),
+                KeyCode::Char('k') | KeyCode::Up => app.scroll_popup_up(),
+                _ => {}
+            }
+            continue;
+        }
+
+        if let Some(draft) = &mut app.notes_draft {
+            match key.code {
+                KeyCode::Esc => report!(app, "Save notes", app.close_notes()),
+                KeyCode::Enter => draft.push('\n'),
+                KeyCode::Backspace => {
+                    draft.pop();
+                }
+                KeyCode::Char(c) => draft.push(c),
+                _ => {}
+            }
+            continue;
+        }
+
+        if app.is_editing {
+            match key.code {
+                KeyCode::Esc => {
+                    app.is_editing = false;
+                    app.command_palette = false;
+                }
+                KeyCode::Tab if app.command_palette => {
+                    let (completed, candidates) = app.complete_command(&app.editing_value);
+                    app.editing_value = completed;
+                    if !candidates.is_empty() {
+                        app.info(candidates.join(" | "));
                     }
-                    KeyCode::Backspace => {
-                        app.editing_value.pop();
+                }
+                KeyCode::Enter => {
+                    if app.command_palette {
+                        let line = app.editing_value.clone();
+                        report!(app, "Command", app.run_command(&line));
+                        app.command_palette = false;
+                    } else if let Focus::Debug = app.focus {
+                        let line = app.editing_value.clone();
+                        app.run_debug_request(&line);
+                    } else if let Focus::Rules = app.focus {
+                        let query = app.editing_value.clone();
+                        app.set_rules_query(&query);
+                    } else if let Modal::Hosts = app.modal {
+                        let line = app.editing_value.clone();
+                        report!(app, "Add host", app.add_host(&line));
+                    } else if let Modal::Profiles = app.modal {
+                        let name = app.editing_value.clone();
+                        report!(app, "Save profile", app.save_current_profile(&name));
+                    } else {
+                        report!(app, "Save", commit_edit(app));
                     }
-                    KeyCode::Char(c) => {
-                        app.editing_value.push(c);
+                    app.is_editing = false;
+                }
+                KeyCode::Backspace => {
+                    app.editing_value.pop();
+                }
+                KeyCode::Char(c) => {
+                    app.editing_value.push(c);
+                }
+                _ => {}
+            }
+            continue;
+        }
+
+        if let Modal::Help = app.modal {
+            match key.code {
+                KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('?') => {
+                    app.modal = Modal::None;
+                    app.popup_scroll = 0;
+                }
+                KeyCode::Char('j') | KeyCode::Down => app.scroll_popup_down(),
+                KeyCode::Char('k') | KeyCode::Up => app.scroll_popup_up(),
+                KeyCode::PageDown | KeyCode::Char(' ') => {
+                    app.popup_scroll = app.popup_scroll.saturating_add(10);
+                }
+                KeyCode::PageUp => app.popup_scroll = app.popup_scroll.saturating_sub(10),
+                KeyCode::Char('g') | KeyCode::Home => app.popup_scroll = 0,
+                _ => {}
+            }
+            continue;
+        }
+        // Type-ahead and the proxy search take `?` as text
+        if key.code == KeyCode::Char('?')
+            && matches!(app.modal, Modal::None)
+            && app.type_ahead_text().is_none()
+            && app.proxy_search.is_none()
+        {
+            app.modal = Modal::Help;
+            app.popup_scroll = 0;
+            continue;
+        }
+
+        // A chord: the leader key, then a key from `leader_chords`
+        if app.leader_pending {
+            app.leader_pending = false;
+            if let KeyCode::Char(c) = key.code {
+                match app.app_settings.leader_chords.get(&c).copied() {
+                    Some(action) => run_leader_action(app, action),
+                    None => {
+                        app.info(format!(
+                            "No chord for {} {}",
+                            leader_key_name(app.app_settings.leader_key),
+                            c
+                        ));
                     }
-                    _ => {}
                 }
-                continue;
             }
+            continue;
+        }
+        if key.code == KeyCode::Char(app.app_settings.leader_key) && app.view_has_keys() {
+            app.leader_pending = true;
+            continue;
+        }
+        // The tab bar: number keys pick a tab, Tab and Shift+Tab step
+        // through the views
+        if app.view_has_keys() {
+            match key.code {
+                KeyCode::Tab => {
+                    cycle_view(app, true);
+                    continue;
+                }
+                KeyCode::BackTab => {
+                    cycle_view(app, false);
+                    continue;
+                }
+                KeyCode::Char(c @ '1'..='6') => {
+                    open_tab(app, Tab::ALL[c as usize - '1' as usize]);
+                    continue;
+                }
+                _ => {}
+            }
+        }
 
-            if app.show_info_popup {
-                match key.code {
-                    KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('i') => {
-                        app.show_info_popup = false;
-                        app.popup_scroll = 0;
+        if let Modal::ConfigTree = app.modal {
+            match key.code {
+                KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('v') => {
+                    app.modal = Modal::None;
+                }
+                KeyCode::Char('j') | KeyCode::Down => app.next_config_tree_row(),
+                KeyCode::Char('k') | KeyCode::Up => app.previous_config_tree_row(),
+                KeyCode::Enter | KeyCode::Char(' ') | KeyCode::Char('l') | KeyCode::Char('h') => {
+                    app.toggle_config_tree_node()
+                }
+                _ => {}
+            }
+        } else if let Modal::ConfigReview = app.modal {
+            match key.code {
+                KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('n') => {
+                    app.modal = Modal::None;
+                }
+                KeyCode::Enter | KeyCode::Char('y') => {
+                    app.modal = Modal::None;
+                    app.apply_staged_config();
+                }
+                _ => {}
+            }
+        } else if app.comparison.is_some() {
+            match key.code {
+                KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('C') => app.stop_comparison(),
+                KeyCode::Char('r') => app.start_comparison(),
+                _ => {}
+            }
+        } else if let Modal::Queue = app.modal {
+            match key.code {
+                KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('P') => {
+                    app.modal = Modal::None;
+                }
+                KeyCode::Char('j') | KeyCode::Down => app.next_queued(),
+                KeyCode::Char('k') | KeyCode::Up => app.previous_queued(),
+                KeyCode::Char('R') => app.retry_queued(),
+                KeyCode::Char('a') => app.auto_retry = !app.auto_retry,
+                KeyCode::Char('d') | KeyCode::Delete => app.drop_selected_queued(),
+                _ => {}
+            }
+        } else if let Modal::Capabilities = app.modal {
+            if let KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('c') = key.code {
+                app.modal = Modal::None;
+            }
+        } else if let Modal::Info = app.modal {
+            match key.code {
+                KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('i') => {
+                    app.modal = Modal::None;
+                    app.popup_scroll = 0;
+                }
+                KeyCode::Char('j') | KeyCode::Down => app.scroll_popup_down(),
+                KeyCode::Char('k') | KeyCode::Up => app.scroll_popup_up(),
+                _ => {}
+            }
+        } else if let Focus::Debug = app.focus {
+            match key.code {
+                KeyCode::Esc | KeyCode::Char('q') | KeyCode::F(12) => {
+                    app.focus = app.previous_focus.clone();
+                }
+                KeyCode::Char('j') | KeyCode::Down => app.next_debug_entry(),
+                KeyCode::Char('k') | KeyCode::Up => app.previous_debug_entry(),
+                KeyCode::Char(':') | KeyCode::Char('e') => {
+                    app.is_editing = true;
+                    app.editing_value = "GET /version".to_string();
+                }
+                _ => {}
+            }
+        } else if let Modal::Hosts = app.modal {
+            match key.code {
+                KeyCode::Esc | KeyCode::Char('q') => {
+                    app.modal = Modal::None;
+                }
+                KeyCode::Char('j') | KeyCode::Down => app.next_host(),
+                KeyCode::Char('k') | KeyCode::Up => app.previous_host(),
+                KeyCode::Char('a') => {
+                    app.is_editing = true;
+                    app.editing_value.clear();
+                    app.editing_host = None;
+                }
+                KeyCode::Enter => {
+                    if let Some((domain, target)) = app
+                        .hosts_state
+                        .selected()
+                        .and_then(|i| app.host_entries().get(i).cloned())
+                    {
+                        app.is_editing = true;
+                        app.editing_value = format!("{}={}", domain, target.replace(' ', ""));
+                        app.editing_host = Some(domain);
                     }
-                    KeyCode::Char('j') | KeyCode::Down => app.scroll_popup_down(),
-                    KeyCode::Char('k') | KeyCode::Up => app.scroll_popup_up(),
-                    _ => {}
                 }
-            } else if let Focus::Settings = app.focus {
-                match key.code {
-                    KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('s') => {
-                        app.focus = app.previous_focus.clone(); // Return to previous view
-                    }
-                    KeyCode::Char('j') | KeyCode::Down => app.next_setting(),
-                    KeyCode::Char('k') | KeyCode::Up => app.previous_setting(),
-                    KeyCode::Enter => {
-                        // Handle config change
-                        if let Some(idx) = app.settings_state.selected()
-                            && let Some(entry) = app.settings_items.get(idx).cloned()
-                        {
-                            match entry {
-                                ConfigEntry::MixedPort
-                                | ConfigEntry::BindAddress
-                                | ConfigEntry::BaseUrl
-                                | ConfigEntry::ApiSecret
-                                | ConfigEntry::TestUrl
-                                | ConfigEntry::TestTimeout => {
-                                    app.is_editing = true;
-                                    if let Some(config) = &app.config {
-                                        app.editing_value = match entry {
-                                            ConfigEntry::MixedPort => config.mixed_port.to_string(),
-                                            ConfigEntry::BindAddress => config.bind_address.clone(),
-                                            ConfigEntry::BaseUrl => {
-                                                app.app_settings.base_url.clone()
-                                            }
-                                            ConfigEntry::ApiSecret => {
-                                                app.app_settings.api_secret.clone()
-                                            }
-                                            ConfigEntry::TestUrl => {
-                                                app.app_settings.test_url.clone()
-                                            }
-                                            ConfigEntry::TestTimeout => {
-                                                app.app_settings.test_timeout.to_string()
-                                            }
-                                            _ => String::new(),
-                                        };
-                                    } else if matches!(
-                                        entry,
-                                        ConfigEntry::BaseUrl
-                                            | ConfigEntry::ApiSecret
-                                            | ConfigEntry::TestUrl
-                                            | ConfigEntry::TestTimeout
-                                    ) {
-                                        // Fallback if config is not loaded yet (e.g. wrong URL initially)
-                                        app.editing_value = match entry {
-                                            ConfigEntry::BaseUrl => {
-                                                app.app_settings.base_url.clone()
-                                            }
-                                            ConfigEntry::ApiSecret => {
-                                                app.app_settings.api_secret.clone()
-                                            }
-                                            ConfigEntry::TestUrl => {
-                                                app.app_settings.test_url.clone()
-                                            }
-                                            ConfigEntry::TestTimeout => {
-                                                app.app_settings.test_timeout.to_string()
-                                            }
-                                            _ => String::new(),
-                                        };
-                                    }
-                                }
-                                _ => {
-                                    let _ = handle_setting_change(app, entry).await;
+                KeyCode::Char('d') | KeyCode::Delete => {
+                    report!(app, "Remove host", app.remove_selected_host());
+                }
+                _ => {}
+            }
+        } else if let Modal::Profiles = app.modal {
+            match key.code {
+                KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('S') => {
+                    app.modal = Modal::None;
+                }
+                KeyCode::Char('j') | KeyCode::Down => app.next_profile(),
+                KeyCode::Char('k') | KeyCode::Up => app.previous_profile(),
+                KeyCode::Char('a') => {
+                    app.is_editing = true;
+                    app.editing_value = app
+                        .active_profile()
+                        .map(|p| p.name.clone())
+                        .unwrap_or_default();
+                }
+                KeyCode::Char('d') | KeyCode::Delete => {
+                    report!(app, "Remove profile", app.remove_selected_profile());
+                }
+                KeyCode::Enter => {
+                    app.modal = Modal::None;
+                    report!(app, "Switch server", app.switch_to_selected_profile());
+                }
+                _ => {}
+            }
+        } else if app.detail_connection_id.is_some() {
+            if let KeyCode::Esc | KeyCode::Char('q') | KeyCode::Enter = key.code {
+                app.close_connection_detail();
+            }
+        } else if let Focus::Connections = app.focus {
+            match key.code {
+                KeyCode::Char('q') => return Ok(()),
+                KeyCode::Esc => {
+                    app.focus = Focus::Groups;
+                }
+                KeyCode::Char('j') | KeyCode::Down => {
+                    for _ in 0..app.scroll_step() {
+                        app.next_connection();
+                    }
+                }
+                KeyCode::Char('k') | KeyCode::Up => {
+                    for _ in 0..app.scroll_step() {
+                        app.previous_connection();
+                    }
+                }
+                KeyCode::Char('r') => {
+                    app.load(Load::Connections);
+                    if app.config_drift {
+                        app.load(Load::Config);
+                    }
+                }
+                KeyCode::Enter => app.open_connection_detail(),
+                KeyCode::Char('x') | KeyCode::Delete => app.confirm_close_selected_connection(),
+                KeyCode::Char('X') if !app.connections.is_empty() => {
+                    app.confirm(ConfirmAction::CloseAllConnections);
+                }
+                KeyCode::F(key @ 1..=9) => {
+                    app.toggle_connection_filter(key as usize - 1);
+                }
+                KeyCode::Char('v') => app.cycle_connection_columns(),
+                KeyCode::Char('f') => app.open_fake_ip_map(),
+                KeyCode::Char('s') => {
+                    app.previous_focus = app.focus.clone();
+                    app.focus = Focus::Settings;
+                }
+                KeyCode::F(12) => {
+                    app.previous_focus = app.focus.clone();
+                    app.focus = Focus::Debug;
+                }
+                _ => {}
+            }
+        } else if let Focus::Rules = app.focus {
+            match key.code {
+                KeyCode::Char('q') => return Ok(()),
+                KeyCode::Esc => {
+                    if app.rules_query.is_empty() {
+                        app.focus = Focus::Groups;
+                    } else {
+                        app.set_rules_query("");
+                    }
+                }
+                KeyCode::Char('j') | KeyCode::Down => {
+                    for _ in 0..app.scroll_step() {
+                        app.next_rule();
+                    }
+                }
+                KeyCode::Char('k') | KeyCode::Up => {
+                    for _ in 0..app.scroll_step() {
+                        app.previous_rule();
+                    }
+                }
+                KeyCode::Char('/') => {
+                    app.is_editing = true;
+                    app.editing_value = app.rules_query.clone();
+                }
+                KeyCode::Char('p') => {
+                    app.jump_to_rule_provider();
+                }
+                KeyCode::Char('r') => {
+                    app.load(Load::Rules);
+                    if app.config_drift {
+                        app.load(Load::Config);
+                    }
+                }
+                KeyCode::Char('s') => {
+                    app.previous_focus = app.focus.clone();
+                    app.focus = Focus::Settings;
+                }
+                _ => {}
+            }
+        } else if let Focus::RuleProviders = app.focus {
+            match key.code {
+                KeyCode::Char('q') => return Ok(()),
+                KeyCode::Esc => {
+                    app.focus = Focus::Rules;
+                }
+                KeyCode::Char('j') | KeyCode::Down => app.next_rule_provider(),
+                KeyCode::Char('k') | KeyCode::Up => app.previous_rule_provider(),
+                KeyCode::Char('u') => app.start_provider_action(ProviderAction::UpdateRuleSet),
+                KeyCode::Char('r') => {
+                    app.load(Load::RuleProviders);
+                    if app.config_drift {
+                        app.load(Load::Config);
+                    }
+                }
+                KeyCode::Char('s') => {
+                    app.previous_focus = app.focus.clone();
+                    app.focus = Focus::Settings;
+                }
+                _ => {}
+            }
+        } else if let Focus::ProxyProviders = app.focus {
+            match key.code {
+                KeyCode::Char('q') => return Ok(()),
+                KeyCode::Esc => {
+                    app.focus = Focus::Groups;
+                }
+                KeyCode::Char('j') | KeyCode::Down => app.next_proxy_provider(),
+                KeyCode::Char('k') | KeyCode::Up => app.previous_proxy_provider(),
+                KeyCode::Char('u') => app.start_provider_action(ProviderAction::Update),
+                KeyCode::Char('t') => app.start_provider_action(ProviderAction::HealthCheck),
+                KeyCode::Char('a') => {
+                    report!(app, "Auto-update", app.cycle_provider_auto_update());
+                }
+                KeyCode::Char('r') => {
+                    app.load(Load::ProxyProviders);
+                    if app.config_drift {
+                        app.load(Load::Config);
+                    }
+                }
+                KeyCode::Char('s') => {
+                    app.previous_focus = app.focus.clone();
+                    app.focus = Focus::Settings;
+                }
+                _ => {}
+            }
+        } else if let Focus::Logs = app.focus {
+            match key.code {
+                KeyCode::Char('q') => return Ok(()),
+                KeyCode::Esc => app.focus = Focus::Groups,
+                KeyCode::Char('j') | KeyCode::Down => {
+                    for _ in 0..app.scroll_step() {
+                        app.next_log();
+                    }
+                }
+                KeyCode::Char('k') | KeyCode::Up => {
+                    for _ in 0..app.scroll_step() {
+                        app.previous_log();
+                    }
+                }
+                KeyCode::Char('G') | KeyCode::End => app.logs_state.select(None),
+                KeyCode::Char('L') => app.cycle_logs_level(),
+                KeyCode::Char('p') => app.toggle_logs_paused(),
+                KeyCode::Char('c') => {
+                    app.logs.clear();
+                    app.logs_held.clear();
+                    app.logs_dropped = 0;
+                    app.logs_state.select(None);
+                }
+                KeyCode::Char('s') => {
+                    app.previous_focus = app.focus.clone();
+                    app.focus = Focus::Settings;
+                }
+                _ => {}
+            }
+        } else if let Focus::Overview = app.focus {
+            match key.code {
+                KeyCode::Char('q') => return Ok(()),
+                KeyCode::Esc => app.focus = Focus::Groups,
+                KeyCode::Char('t') => app.trigger_latency_test(),
+                KeyCode::Char('T') => app.open_traffic_history(TrafficRange::Hour),
+                KeyCode::Char('r') => {
+                    app.load(Load::Config);
+                    app.load(Load::Version);
+                    app.check_latest_core_release();
+                }
+                KeyCode::Char('s') => {
+                    app.previous_focus = app.focus.clone();
+                    app.focus = Focus::Settings;
+                }
+                _ => {}
+            }
+        } else if let Focus::Settings = app.focus {
+            match key.code {
+                KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('s') => {
+                    app.focus = app.previous_focus.clone(); // Return to previous view
+                }
+                KeyCode::Char('j') | KeyCode::Down => app.next_setting(),
+                KeyCode::Char('k') | KeyCode::Up => app.previous_setting(),
+                KeyCode::Char('a') => {
+                    if app.staged_config.is_empty() {
+                        app.info("No pending changes".to_string());
+                    } else {
+                        app.modal = Modal::ConfigReview;
+                    }
+                }
+                KeyCode::Char('D') => app.staged_config.clear(),
+                KeyCode::Char('v') => app.open_config_tree(),
+                KeyCode::Char('+') | KeyCode::Char('=') | KeyCode::Right => {
+                    report!(app, "Change setting", app.step_selected_setting(true));
+                }
+                KeyCode::Char('-') | KeyCode::Left => {
+                    report!(app, "Change setting", app.step_selected_setting(false));
+                }
+                KeyCode::Enter => {
+                    // Handle config change
+                    if let Some(idx) = app.settings_state.selected()
+                        && let Some(entry) = app.settings_entries().get(idx).cloned()
+                    {
+                        match entry {
+                            ConfigEntry::MixedPort
+                            | ConfigEntry::Authentication
+                            | ConfigEntry::SkipAuthPrefixes
+                            | ConfigEntry::BindAddress
+                            | ConfigEntry::BaseUrl
+                            | ConfigEntry::ApiSecret
+                            | ConfigEntry::TestUrl
+                            | ConfigEntry::TestTimeout
+                            | ConfigEntry::DelayTestConcurrency
+                            | ConfigEntry::DelayTestTimeout
+                            | ConfigEntry::LatencyMaxAge
+                            | ConfigEntry::LatencyPrecision
+                            | ConfigEntry::LatencyGood
+                            | ConfigEntry::LatencyMedium
+                            | ConfigEntry::LatencyGaugeMin
+                            | ConfigEntry::LatencyGaugeMax
+                            | ConfigEntry::AutoRefresh
+                            | ConfigEntry::StartupActions
+                            | ConfigEntry::OnStart
+                            | ConfigEntry::LeaderKey
+                            | ConfigEntry::LeaderChords => {
+                                app.is_editing = true;
+                                if let Some(config) = app.effective_config() {
+                                    app.editing_value = match entry {
+                                        ConfigEntry::MixedPort => config.mixed_port.to_string(),
+                                        ConfigEntry::Authentication => {
+                                            config.authentication.join(", ")
+                                        }
+                                        ConfigEntry::SkipAuthPrefixes => {
+                                            config.skip_auth_prefixes.join(", ")
+                                        }
+                                        ConfigEntry::BindAddress => config.bind_address.clone(),
+                                        ConfigEntry::BaseUrl => app.app_settings.base_url.clone(),
+                                        ConfigEntry::ApiSecret => {
+                                            app.app_settings.api_secret.clone()
+                                        }
+                                        ConfigEntry::TestUrl => app.app_settings.test_url.clone(),
+                                        ConfigEntry::TestTimeout => {
+                                            app.app_settings.test_timeout.to_string()
+                                        }
+                                        ConfigEntry::DelayTestConcurrency => {
+                                            app.app_settings.delay_test_concurrency.to_string()
+                                        }
+                                        ConfigEntry::DelayTestTimeout => {
+                                            app.app_settings.delay_test_timeout.to_string()
+                                        }
+                                        ConfigEntry::LatencyMaxAge => {
+                                            app.app_settings.latency_max_age.to_string()
+                                        }
+                                        ConfigEntry::LatencyPrecision => {
+                                            app.app_settings.latency_precision.to_string()
+                                        }
+                                        ConfigEntry::LatencyGood => {
+                                            app.app_settings.latency_good.to_string()
+                                        }
+                                        ConfigEntry::LatencyMedium => {
+                                            app.app_settings.latency_medium.to_string()
+                                        }
+                                        ConfigEntry::LatencyGaugeMin => {
+                                            app.app_settings.latency_gauge_min.to_string()
+                                        }
+                                        ConfigEntry::LatencyGaugeMax => {
+                                            app.app_settings.latency_gauge_max.to_string()
+                                        }
+                                        ConfigEntry::AutoRefresh => {
+                                            app.app_settings.auto_refresh.to_string()
+                                        }
+                                        ConfigEntry::StartupActions => app.startup_actions_text(),
+                                        ConfigEntry::OnStart => app.on_start_text(),
+                                        ConfigEntry::LeaderKey => {
+                                            leader_key_name(app.app_settings.leader_key)
+                                        }
+                                        ConfigEntry::LeaderChords => app.leader_chords_text(),
+                                        _ => String::new(),
+                                    };
+                                } else if matches!(
+                                    entry,
+                                    ConfigEntry::BaseUrl
+                                        | ConfigEntry::ApiSecret
+                                        | ConfigEntry::TestUrl
+                                        | ConfigEntry::TestTimeout
+                                        | ConfigEntry::DelayTestConcurrency
+                                        | ConfigEntry::DelayTestTimeout
+                                        | ConfigEntry::LatencyMaxAge
+                                        | ConfigEntry::LatencyPrecision
+                                        | ConfigEntry::LatencyGood
+                                        | ConfigEntry::LatencyMedium
+                                        | ConfigEntry::LatencyGaugeMin
+                                        | ConfigEntry::LatencyGaugeMax
+                                        | ConfigEntry::AutoRefresh
+                                        | ConfigEntry::StartupActions
+                                        | ConfigEntry::OnStart
+                                        | ConfigEntry::LeaderKey
+                                        | ConfigEntry::LeaderChords
+                                ) {
+                                    // Fallback if config is not loaded yet (e.g. wrong URL initially)
+                                    app.editing_value = match entry {
+                                        ConfigEntry::BaseUrl => app.app_settings.base_url.clone(),
+                                        ConfigEntry::ApiSecret => {
+                                            app.app_settings.api_secret.clone()
+                                        }
+                                        ConfigEntry::TestUrl => app.app_settings.test_url.clone(),
+                                        ConfigEntry::TestTimeout => {
+                                            app.app_settings.test_timeout.to_string()
+                                        }
+                                        ConfigEntry::DelayTestConcurrency => {
+                                            app.app_settings.delay_test_concurrency.to_string()
+                                        }
+                                        ConfigEntry::DelayTestTimeout => {
+                                            app.app_settings.delay_test_timeout.to_string()
+                                        }
+                                        ConfigEntry::LatencyMaxAge => {
+                                            app.app_settings.latency_max_age.to_string()
+                                        }
+                                        ConfigEntry::LatencyPrecision => {
+                                            app.app_settings.latency_precision.to_string()
+                                        }
+                                        ConfigEntry::LatencyGood => {
+                                            app.app_settings.latency_good.to_string()
+                                        }
+                                        ConfigEntry::LatencyMedium => {
+                                            app.app_settings.latency_medium.to_string()
+                                        }
+                                        ConfigEntry::LatencyGaugeMin => {
+                                            app.app_settings.latency_gauge_min.to_string()
+                                        }
+                                        ConfigEntry::LatencyGaugeMax => {
+                                            app.app_settings.latency_gauge_max.to_string()
+                                        }
+                                        ConfigEntry::AutoRefresh => {
+                                            app.app_settings.auto_refresh.to_string()
+                                        }
+                                        ConfigEntry::StartupActions => app.startup_actions_text(),
+                                        ConfigEntry::OnStart => app.on_start_text(),
+                                        ConfigEntry::LeaderKey => {
+                                            leader_key_name(app.app_settings.leader_key)
+                                        }
+                                        ConfigEntry::LeaderChords => app.leader_chords_text(),
+                                        _ => String::new(),
+                                    };
                                 }
                             }
+                            _ => {
+                                report!(app, "Change setting", handle_setting_change(app, entry));
+                            }
                         }
                     }
-                    _ => {}
                 }
-            } else {
+                _ => {}
+            }
+        } else {
+            // Type-ahead takes printable keys until it times out or is ended
+            if app.type_ahead_text().is_some() {
                 match key.code {
-                    KeyCode::Char('q') => return Ok(()),
-                    KeyCode::Char('r') => {
-                        if let Focus::Proxies = app.focus {
-                            app.trigger_group_latency_test();
-                        }
-                        let _ = app.fetch_proxies().await;
-                        let _ = app.fetch_config().await;
+                    KeyCode::Char(c) => {
+                        app.type_ahead_push(Some(c));
+                        continue;
+                    }
+                    KeyCode::Backspace => {
+                        app.type_ahead_push(None);
+                        continue;
+                    }
+                    KeyCode::Esc => {
+                        app.stop_type_ahead();
+                        continue;
+                    }
+                    _ => app.stop_type_ahead(),
+                }
+            }
+            // The proxy search takes printable keys until Enter or Esc
+            if app.proxy_search.is_some() {
+                match key.code {
+                    KeyCode::Char(c) => app.proxy_search_push(Some(c)),
+                    KeyCode::Backspace => app.proxy_search_push(None),
+                    KeyCode::Down => app.next_proxy(),
+                    KeyCode::Up => app.previous_proxy(),
+                    KeyCode::Enter => app.finish_proxy_search(true),
+                    KeyCode::Esc => app.finish_proxy_search(false),
+                    _ => {}
+                }
+                continue;
+            }
+            // Group hotkeys fill the F keys that `keymap` leaves free
+            let Some(action) = app.keys.action(&key) else {
+                if let KeyCode::F(n) = key.code
+                    && (1..=GROUP_HOTKEY_LIMIT).contains(&n)
+                {
+                    app.jump_to_group_hotkey(n);
+                }
+                continue;
+            };
+            match action {
+                Action::Quit => return Ok(()),
+                Action::TypeAhead => app.start_type_ahead(),
+                Action::Search => {
+                    if let Focus::Groups = app.focus {
+                        app.select_current_proxy();
                     }
-                    KeyCode::Char('t') => {
+                    app.focus = Focus::Proxies;
+                    app.start_proxy_search();
+                }
+                Action::Refresh => {
+                    if let Focus::Proxies = app.focus {
+                        app.trigger_group_latency_test();
+                    }
+                    app.load(Load::Proxies);
+                    app.load(Load::ProxyProviders);
+                    app.load(Load::Config);
+                    app.load(Load::Version);
+                    app.check_latest_core_release();
+                }
+                Action::Test => {
+                    if let Focus::Proxies = app.focus {
+                        app.trigger_group_latency_test();
+                    } else {
                         app.trigger_latency_test();
                     }
-                    KeyCode::Char('s') => {
-                        app.previous_focus = app.focus.clone();
-                        app.focus = Focus::Settings;
+                }
+                Action::Palette => {
+                    app.command_palette = true;
+                    app.is_editing = true;
+                    app.editing_value.clear();
+                }
+                Action::RevertGroup => app.revert_selected_group(),
+                Action::TestGroup => {
+                    // Delay test only, without refetching proxies/config
+                    app.trigger_group_latency_test();
+                }
+                Action::PinGroup if app.focus == Focus::Groups => {
+                    report!(app, "Pin group", app.toggle_pin_selected_group());
+                }
+                Action::MoveGroupUp if app.focus == Focus::Groups => {
+                    report!(app, "Move group", app.move_selected_group(true));
+                }
+                Action::MoveGroupDown if app.focus == Focus::Groups => {
+                    report!(app, "Move group", app.move_selected_group(false));
+                }
+                Action::HideGroup if app.focus == Focus::Groups => {
+                    report!(app, "Hide group", app.toggle_hide_selected_group());
+                }
+                Action::LockGroup if app.focus == Focus::Groups => {
+                    report!(app, "Lock group", app.toggle_lock_selected_group());
+                }
+                Action::BindGroup if app.focus == Focus::Groups => {
+                    report!(app, "Bind group", app.toggle_hotkey_selected_group());
+                }
+                Action::ProviderFilter => app.cycle_provider_filter(),
+                Action::Sort => app.cycle_proxy_sort(),
+                Action::Servers => app.open_profiles(),
+                Action::FlushSmart => app.confirm_flush_smart_group(),
+                Action::ShowHidden => {
+                    app.show_hidden_groups = !app.show_hidden_groups;
+                    app.rebuild_group_names();
+                }
+                Action::Settings => {
+                    app.previous_focus = app.focus.clone();
+                    app.focus = Focus::Settings;
+                }
+                Action::Info => {
+                    if let Focus::Proxies = app.focus {
+                        app.modal = Modal::Info;
+                    }
+                }
+                Action::CompareMark if app.focus == Focus::Proxies => {
+                    app.toggle_compare_mark();
+                }
+                Action::Compare => app.start_comparison(),
+                Action::Capabilities => {
+                    app.modal = Modal::Capabilities;
+                }
+                Action::Notes => app.open_notes(),
+                Action::Queue => {
+                    app.modal = Modal::Queue;
+                    if app.queue_state.selected().is_none() && !app.mutation_queue.is_empty() {
+                        app.queue_state.select(Some(0));
+                    }
+                }
+                Action::CopyDashboardUrl => match app.dashboard_url() {
+                    Some(url) => {
+                        report!(app, "Copy", copy_to_clipboard(&url));
+                        app.info(format!("Dashboard URL copied: {}", url));
                     }
-                    KeyCode::Char('i') => {
-                        if let Focus::Proxies = app.focus {
-                            app.show_info_popup = true;
+                    None => app.info("No external UI configured".to_string()),
+                },
+                Action::TrafficHistory => app.open_traffic_history(TrafficRange::Hour),
+                Action::Notifications => app.open_notifications(),
+                Action::Debug => {
+                    app.previous_focus = app.focus.clone();
+                    app.focus = Focus::Debug;
+                }
+                Action::Down => {
+                    for _ in 0..app.scroll_step() {
+                        match app.focus {
+                            Focus::Groups => app.next_group(),
+                            Focus::Proxies => app.next_proxy(),
+                            _ => {}
                         }
                     }
-                    KeyCode::Down | KeyCode::Char('j') => match app.focus {
-                        Focus::Groups => app.next_group(),
-                        Focus::Proxies => app.next_proxy(),
-                        _ => {}
-                    },
-                    KeyCode::Up | KeyCode::Char('k') => match app.focus {
-                        Focus::Groups => app.previous_group(),
-                        Focus::Proxies => app.previous_proxy(),
-                        _ => {}
-                    },
-                    KeyCode::Right | KeyCode::Char('l') => {
-                        app.focus = Focus::Proxies;
+                }
+                Action::Up => {
+                    for _ in 0..app.scroll_step() {
+                        match app.focus {
+                            Focus::Groups => app.previous_group(),
+                            Focus::Proxies => app.previous_proxy(),
+                            _ => {}
+                        }
                     }
-                    KeyCode::Left | KeyCode::Char('h') | KeyCode::Esc => {
-                        app.focus = Focus::Groups;
+                }
+                // Switch groups without leaving the Proxies list
+                Action::NextGroup => app.next_group(),
+                Action::PreviousGroup => app.previous_group(),
+                Action::Open => {
+                    if let Focus::Groups = app.focus {
+                        app.select_current_proxy();
                     }
-                    KeyCode::Enter => {
-                        if let Focus::Proxies = app.focus {
-                            if let Some(group_name) = app.get_selected_group_name()
-                                && let Some(proxy_name) = app.get_selected_proxy_name()
-                            {
-                                let g_name = group_name.clone();
-                                let p_name = proxy_name.clone();
-                                let _ = app.select_proxy(&g_name, &p_name).await;
-                                let _ = app.fetch_proxies().await;
-                            }
-                        } else {
-                            app.focus = Focus::Proxies;
+                    app.focus = Focus::Proxies;
+                }
+                Action::Back if app.focus == Focus::Proxies && !app.group_trail.is_empty() => {
+                    app.leave_nested_group();
+                }
+                Action::Back => {
+                    app.focus = Focus::Groups;
+                }
+                Action::Select => {
+                    if let Focus::Proxies = app.focus {
+                        if !app.enter_nested_group()
+                            && let Some(group_name) = app.get_selected_group_name()
+                            && let Some(proxy_name) = app.get_selected_proxy_name()
+                        {
+                            let g_name = group_name.clone();
+                            let p_name = proxy_name.clone();
+                            app.select_proxy(&g_name, &p_name);
                         }
+                    } else {
+                        app.select_current_proxy();
+                        app.focus = Focus::Proxies;
                     }
-                    _ => {}
                 }
+                _ => {}
             }
         }
     }
 }
 
-async fn handle_setting_change(app: &mut App, entry: ConfigEntry) -> Result<()> {
-    if let Some(config) = &app.config {
-        match entry {
-            ConfigEntry::Mode => {
-                let new_mode = match config.mode.as_str() {
-                    "rule" => "global",
-                    "global" => "direct",
-                    _ => "rule",
-                };
-                app.update_config(serde_json::json!({ "mode": new_mode }))
-                    .await?;
-            }
-            ConfigEntry::Tun => {
-                let new_state = !config.tun.enable;
-                app.update_config(serde_json::json!({ "tun": { "enable": new_state } }))
-                    .await?;
-            }
-            ConfigEntry::LogLevel => {
-                let new_level = match config.log_level.as_str() {
-                    "info" => "warning",
-                    "warning" => "error",
-                    "error" => "debug",
-                    "debug" => "silent",
-                    _ => "info",
-                };
-                app.update_config(serde_json::json!({ "log-level": new_level }))
-                    .await?;
-            }
-            ConfigEntry::AllowLan => {
-                let new_state = !config.allow_lan;
-                app.update_config(serde_json::json!({ "allow-lan": new_state }))
-                    .await?;
+/// The views in Tab order; both provider views are stops of the Providers tab.
+const VIEW_ORDER: [Focus; 7] = [
+    Focus::Groups,
+    Focus::Connections,
+    Focus::Rules,
+    Focus::Logs,
+    Focus::RuleProviders,
+    Focus::ProxyProviders,
+    Focus::Overview,
+];
+
+/// Moves to the next or previous view in `VIEW_ORDER` and loads its data.
+fn cycle_view(app: &mut App, forward: bool) {
+    let current = match app.focus {
+        Focus::Proxies => Focus::Groups,
+        ref focus => focus.clone(),
+    };
+    let i = VIEW_ORDER.iter().position(|f| *f == current).unwrap_or(0);
+    let next = if forward {
+        (i + 1) % VIEW_ORDER.len()
+    } else {
+        (i + VIEW_ORDER.len() - 1) % VIEW_ORDER.len()
+    };
+    open_view(app, VIEW_ORDER[next].clone());
+}
+
+/// Opens `tab`. Picking the Providers tab again switches between its views.
+fn open_tab(app: &mut App, tab: Tab) {
+    let focus = match tab {
+        Tab::Proxies if matches!(app.focus, Focus::Groups | Focus::Proxies) => return,
+        Tab::Proxies => Focus::Groups,
+        Tab::Connections => Focus::Connections,
+        Tab::Rules => Focus::Rules,
+        Tab::Logs => Focus::Logs,
+        Tab::Providers if app.focus == Focus::RuleProviders => Focus::ProxyProviders,
+        Tab::Providers => Focus::RuleProviders,
+        Tab::Overview => Focus::Overview,
+    };
+    open_view(app, focus);
+}
+
+/// Runs the action bound to a leader chord.
+fn run_leader_action(app: &mut App, action: LeaderAction) {
+    match action {
+        LeaderAction::TestAll => {
+            app.test_all_groups();
+            app.info(format!("Testing {} groups", app.group_names.len()));
+        }
+        LeaderAction::TestGroup => app.trigger_group_latency_test(),
+        LeaderAction::ConnectivityTest => app.trigger_latency_test(),
+        LeaderAction::Refresh => {
+            app.load(Load::Proxies);
+            app.load(Load::ProxyProviders);
+            app.load(Load::Config);
+            app.load(Load::Version);
+            app.check_latest_core_release();
+        }
+        LeaderAction::Groups => app.focus = Focus::Groups,
+        LeaderAction::Connections => open_view(app, Focus::Connections),
+        LeaderAction::Rules => open_view(app, Focus::Rules),
+        LeaderAction::RuleProviders => open_view(app, Focus::RuleProviders),
+        LeaderAction::ProxyProviders => open_view(app, Focus::ProxyProviders),
+        LeaderAction::Logs => open_view(app, Focus::Logs),
+        LeaderAction::Overview => open_view(app, Focus::Overview),
+        LeaderAction::Settings | LeaderAction::Debug => {
+            app.previous_focus = app.focus.clone();
+            app.focus = match action {
+                LeaderAction::Settings => Focus::Settings,
+                _ => Focus::Debug,
+            };
+        }
+        LeaderAction::Servers => app.open_profiles(),
+        LeaderAction::Palette => {
+            app.command_palette = true;
+            app.is_editing = true;
+            app.editing_value.clear();
+        }
+        LeaderAction::Notes => app.open_notes(),
+        LeaderAction::Dns => app.open_dns_tool(),
+        LeaderAction::TrafficHistory => app.open_traffic_history(TrafficRange::Hour),
+        LeaderAction::Notifications => app.open_notifications(),
+    }
+}
+
+fn open_view(app: &mut App, focus: Focus) {
+    app.focus = focus;
+    match app.focus {
+        Focus::Connections => app.load(Load::Connections),
+        Focus::Rules => app.load(Load::Rules),
+        Focus::RuleProviders => app.load(Load::RuleProviders),
+        Focus::ProxyProviders => app.load(Load::ProxyProviders),
+        _ => {}
+    }
+}
+
+/// Copies text through the OSC 52 escape sequence, which also works over SSH.
+fn copy_to_clipboard(text: &str) -> Result<()> {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(text);
+    let mut out = stdout();
+    write!(out, "\x1b]52;c;{}\x07", encoded)?;
+    out.flush()?;
+    Ok(())
+}
+
+fn handle_setting_change(app: &mut App, entry: ConfigEntry) -> Result<()> {
+    // Core entries need the config; the app's own work without it
+    match (entry, app.effective_config()) {
+        (ConfigEntry::LowPower, _) => {
+            app.app_settings.low_power = app.app_settings.low_power.next();
+            app.save_app_settings()?;
+        }
+        (ConfigEntry::Alerts, _) => {
+            app.app_settings.alerts = app.app_settings.alerts.next();
+            app.save_app_settings()?;
+        }
+        (ConfigEntry::Accent, _) => app.cycle_accent()?,
+        (ConfigEntry::DnsQuery, _) => app.open_dns_tool(),
+        (ConfigEntry::CoreRestart, _) => app.confirm(ConfirmAction::RestartCore),
+        (ConfigEntry::CoreUpgrade, _) => app.confirm(ConfirmAction::UpgradeCore),
+        (ConfigEntry::Theme, _) => {
+            app.app_settings.theme = app.app_settings.theme.next();
+            app.save_app_settings()?;
+        }
+        (ConfigEntry::Layout, _) => {
+            app.app_settings.layout = app.app_settings.layout.next();
+            app.save_app_settings()?;
+        }
+        (ConfigEntry::LatencyFormat, _) => {
+            app.app_settings.latency_format = app.app_settings.latency_format.next();
+            app.save_app_settings()?;
+        }
+        (ConfigEntry::AutoTestGroups, _) => {
+            app.app_settings.auto_test_groups = !app.app_settings.auto_test_groups;
+            app.save_app_settings()?;
+        }
+        (ConfigEntry::AutoRetestStale, _) => {
+            app.app_settings.auto_retest_stale = !app.app_settings.auto_retest_stale;
+            app.save_app_settings()?;
+        }
+        (ConfigEntry::Mode, Some(config)) => {
+            let new_mode = match config.mode.as_str() {
+                "rule" => "global",
+                "global" => "direct",
+                _ => "rule",
+            };
+            app.stage_config(serde_json::json!({ "mode": new_mode }));
+        }
+        (ConfigEntry::Tun, Some(config)) => {
+            let new_state = !config.tun.enable;
+            app.stage_config(serde_json::json!({ "tun": { "enable": new_state } }));
+        }
+        (ConfigEntry::LogLevel, Some(config)) => {
+            let new_level = match config.log_level.as_str() {
+                "info" => "warning",
+                "warning" => "error",
+                "error" => "debug",
+                "debug" => "silent",
+                _ => "info",
+            };
+            app.stage_config(serde_json::json!({ "log-level": new_level }));
+        }
+        (ConfigEntry::AllowLan, Some(config)) => {
+            let new_state = !config.allow_lan;
+            app.stage_config(serde_json::json!({ "allow-lan": new_state }));
+            if new_state && config.authentication.is_empty() {
+                app.info(
+                    "LAN clients won't need a password; set LAN Authentication before applying"
+                        .to_string(),
+                );
             }
-            ConfigEntry::Ipv6 => {
-                let new_state = !config.ipv6;
-                app.update_config(serde_json::json!({ "ipv6": new_state }))
-                    .await?;
+        }
+        (ConfigEntry::Ipv6, Some(config)) => {
+            let new_state = !config.ipv6;
+            app.stage_config(serde_json::json!({ "ipv6": new_state }));
+        }
+        (ConfigEntry::Hosts, Some(config)) => {
+            app.modal = Modal::Hosts;
+            if app.hosts_state.selected().is_none() && !config.hosts.is_empty() {
+                app.hosts_state.select(Some(0));
             }
-            _ => {}
         }
+        _ => {}
     }
     Ok(())
 }
 
-async fn commit_edit(app: &mut App) -> Result<()> {
+fn commit_edit(app: &mut App) -> Result<()> {
     if let Some(idx) = app.settings_state.selected()
-        && let Some(entry) = app.settings_items.get(idx).cloned()
+        && let Some(entry) = app.settings_entries().get(idx).cloned()
     {
+        let value = app.editing_value.trim().to_string();
         match entry {
             ConfigEntry::MixedPort => {
-                if let Ok(port) = app.editing_value.parse::<u16>() {
-                    app.update_config(serde_json::json!({ "mixed-port": port }))
-                        .await?;
+                let port = value
+                    .parse::<u16>()
+                    .with_context(|| format!("Invalid port {:?}", value))?;
+                app.stage_config(serde_json::json!({ "mixed-port": port }));
+            }
+            ConfigEntry::Authentication => {
+                let users: Vec<&str> = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|user| !user.is_empty())
+                    .collect();
+                if let Some(bad) = users
+                    .iter()
+                    .find(|user| user.split_once(':').is_none_or(|(name, _)| name.is_empty()))
+                {
+                    bail!("Expected user:password, got {:?}", bad);
                 }
+                app.stage_config(serde_json::json!({ "authentication": users }));
+            }
+            ConfigEntry::SkipAuthPrefixes => {
+                let prefixes: Vec<&str> = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|prefix| !prefix.is_empty())
+                    .collect();
+                if let Some(bad) = prefixes.iter().find(|prefix| !is_ip_prefix(prefix)) {
+                    bail!("Invalid prefix {:?}, expected e.g. 192.168.1.0/24", bad);
+                }
+                app.stage_config(serde_json::json!({ "skip-auth-prefixes": prefixes }));
             }
             ConfigEntry::BindAddress => {
-                app.update_config(serde_json::json!({ "bind-address": app.editing_value }))
-                    .await?;
+                app.stage_config(serde_json::json!({ "bind-address": value }));
             }
             ConfigEntry::BaseUrl => {
-                app.app_settings.base_url = app.editing_value.clone();
-                let _ = app.save_app_settings();
-                let _ = app.fetch_proxies().await;
-                let _ = app.fetch_config().await;
+                app.stop_connections_stream();
+                app.stop_traffic_monitor();
+                app.stop_logs_stream();
+                app.app_settings.base_url = value;
+                report!(app, "Save settings", app.save_app_settings());
+                app.probe_capabilities(vec![Load::Proxies, Load::ProxyProviders, Load::Config]);
             }
             ConfigEntry::ApiSecret => {
-                app.app_settings.api_secret = app.editing_value.clone();
-                let _ = app.save_app_settings();
-                let _ = app.fetch_proxies().await;
-                let _ = app.fetch_config().await;
+                app.stop_connections_stream();
+                app.stop_traffic_monitor();
+                app.stop_logs_stream();
+                app.app_settings.api_secret = value;
+                report!(app, "Save settings", app.save_app_settings());
+                app.probe_capabilities(vec![Load::Proxies, Load::ProxyProviders, Load::Config]);
             }
             ConfigEntry::TestUrl => {
-                app.app_settings.test_url = app.editing_value.clone();
-                let _ = app.save_app_settings();
+                app.app_settings.test_url = value;
+                report!(app, "Save settings", app.save_app_settings());
                 app.trigger_latency_test();
             }
             ConfigEntry::TestTimeout => {
-                if let Ok(timeout) = app.editing_value.parse::<u64>() {
-                    app.app_settings.test_timeout = timeout;
-                    let _ = app.save_app_settings();
+                app.app_settings.test_timeout = value
+                    .parse::<u64>()
+                    .with_context(|| format!("Invalid timeout {:?}", value))?;
+                app.save_app_settings()?;
+            }
+            ConfigEntry::DelayTestConcurrency => {
+                let limit = value
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|limit| *limit > 0)
+                    .ok_or_else(|| anyhow!("Invalid parallelism {:?}", value))?;
+                app.app_settings.delay_test_concurrency = limit;
+                app.save_app_settings()?;
+            }
+            ConfigEntry::DelayTestTimeout => {
+                app.app_settings.delay_test_timeout = value
+                    .parse::<u64>()
+                    .with_context(|| format!("Invalid timeout {:?}", value))?;
+                app.save_app_settings()?;
+            }
+            ConfigEntry::LatencyMaxAge => {
+                app.app_settings.latency_max_age = value
+                    .parse::<u64>()
+                    .with_context(|| format!("Invalid age {:?}", value))?;
+                app.save_app_settings()?;
+            }
+            ConfigEntry::AutoRefresh => {
+                app.app_settings.auto_refresh = value
+                    .parse::<u64>()
+                    .with_context(|| format!("Invalid interval {:?}", value))?;
+                app.save_app_settings()?;
+            }
+            ConfigEntry::LatencyPrecision => {
+                let step = value
+                    .parse::<u64>()
+                    .ok()
+                    .filter(|step| *step > 0)
+                    .ok_or_else(|| anyhow!("Invalid precision {:?}", value))?;
+                app.app_settings.latency_precision = step;
+                app.save_app_settings()?;
+            }
+            ConfigEntry::LatencyGood | ConfigEntry::LatencyMedium => {
+                let ms = value
+                    .parse::<u64>()
+                    .with_context(|| format!("Invalid delay {:?}", value))?;
+                let settings = &mut app.app_settings;
+                let (good, medium) = match entry {
+                    ConfigEntry::LatencyGood => (ms, settings.latency_medium),
+                    _ => (settings.latency_good, ms),
+                };
+                if good >= medium {
+                    bail!(
+                        "The fast cutoff ({} ms) must be below the medium one ({} ms)",
+                        good,
+                        medium
+                    );
                 }
+                settings.latency_good = good;
+                settings.latency_medium = medium;
+                app.save_app_settings()?;
+            }
+            ConfigEntry::LatencyGaugeMin | ConfigEntry::LatencyGaugeMax => {
+                let ms = value
+                    .parse::<u64>()
+                    .with_context(|| format!("Invalid delay {:?}", value))?;
+                let settings = &mut app.app_settings;
+                let (min, max) = match entry {
+                    ConfigEntry::LatencyGaugeMin => (ms, settings.latency_gauge_max),
+                    _ => (settings.latency_gauge_min, ms),
+                };
+                if min >= max {
+                    bail!(
+                        "The gauge's full delay ({} ms) must be below its empty one ({} ms)",
+                        min,
+                        max
+                    );
+                }
+                settings.latency_gauge_min = min;
+                settings.latency_gauge_max = max;
+                app.save_app_settings()?;
+            }
+            ConfigEntry::StartupActions => {
+                let mut actions = Vec::new();
+                for name in value.split(',').map(str::trim) {
+                    if name.is_empty() || name == "none" {
+                        continue;
+                    }
+                    let action = StartupAction::ALL
+                        .into_iter()
+                        .find(|a| a.name() == name)
+                        .ok_or_else(|| anyhow!("Unknown startup action {:?}", name))?;
+                    actions.push(action);
+                }
+                app.app_settings.startup_actions = actions;
+                app.save_app_settings()?;
+            }
+            ConfigEntry::OnStart => {
+                let steps = StartStep::split_list(&value);
+                for step in &steps {
+                    StartStep::parse(step).map_err(|e| anyhow!(e))?;
+                }
+                app.app_settings.on_start = steps;
+                app.save_app_settings()?;
+            }
+            ConfigEntry::LeaderKey => {
+                let key = match value.trim() {
+                    "space" | "" => ' ',
+                    key => {
+                        let mut chars = key.chars();
+                        match (chars.next(), chars.next()) {
+                            (Some(key), None) => key,
+                            _ => bail!("The leader key must be one character or space"),
+                        }
+                    }
+                };
+                // The leader is read before the views see a key, so it would
+                // shadow the key's own binding everywhere
+                if let Some(action) = app.keys.action(&KeyCode::Char(key).into()) {
+                    bail!(
+                        "{} is bound to {} already",
+                        leader_key_name(key),
+                        action.name()
+                    );
+                }
+                if let Some(binding) = keymap::view_binding(key) {
+                    bail!(
+                        "{} already does \"{}\" in {}",
+                        leader_key_name(key),
+                        binding.action,
+                        binding.context.title()
+                    );
+                }
+                app.app_settings.leader_key = key;
+                app.save_app_settings()?;
+            }
+            ConfigEntry::LeaderChords => {
+                let mut chords = BTreeMap::new();
+                for chord in value.split(',').map(str::trim) {
+                    if chord.is_empty() || chord == "none" {
+                        continue;
+                    }
+                    let (key, name) = chord
+                        .split_once('=')
+                        .ok_or_else(|| anyhow!("Expected key=action, got {:?}", chord))?;
+                    let mut chars = key.trim().chars();
+                    let (Some(key), None) = (chars.next(), chars.next()) else {
+                        bail!("Chord keys are one character, got {:?}", key.trim());
+                    };
+                    let action = LeaderAction::ALL
+                        .into_iter()
+                        .find(|a| a.name() == name.trim())
+                        .ok_or_else(|| anyhow!("Unknown chord action {:?}", name.trim()))?;
+                    chords.insert(key, action);
+                }
+                app.app_settings.leader_chords = chords;
+                app.save_app_settings()?;
             }
             _ => {}
         }
     }
     Ok(())
 }
+
+/// Whether `prefix` is an address with a prefix length, e.g. `10.0.0.0/8` or `fd00::/8`.
+fn is_ip_prefix(prefix: &str) -> bool {
+    let Some((addr, len)) = prefix.split_once('/') else {
+        return false;
+    };
+    let max = match addr.parse::<std::net::IpAddr>() {
+        Ok(std::net::IpAddr::V4(_)) => 32,
+        Ok(std::net::IpAddr::V6(_)) => 128,
+        Err(_) => return false,
+    };
+    len.parse::<u8>().is_ok_and(|len| len <= max)
+}
@@ -0,0 +1,272 @@
+//! `mihomot serve`: a small status page for people who should see how the
+//! proxies are doing but not hold the controller's secret. The controller is
+//! polled in the background and every request is answered from the latest
+//! snapshot, so visitors never reach the controller themselves.
+
+use anyhow::{Context, Result, bail};
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::app::{App, LatencyTier, TrafficRate, format_bytes};
+
+/// Visitors get this long to send their request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Header lines read before a request is refused.
+const HEADER_LIMIT: usize = 100;
+
+/// What the page shows, as of the last poll.
+#[derive(Serialize, Clone, Default)]
+struct Status {
+    /// Unix seconds of the last successful poll.
+    updated: u64,
+    /// Why the last poll failed; the rest is from the one before.
+    error: Option<String>,
+    mode: Option<String>,
+    core_version: Option<String>,
+    /// Bytes per second since the previous poll; unknown after the first.
+    download_rate: Option<u64>,
+    upload_rate: Option<u64>,
+    download_total: u64,
+    upload_total: u64,
+    connections: usize,
+    groups: Vec<GroupStatus>,
+}
+
+#[derive(Serialize, Clone)]
+struct GroupStatus {
+    name: String,
+    #[serde(rename = "type")]
+    kind: String,
+    now: Option<String>,
+    /// Last delay of the proxy the group ends up using, in milliseconds.
+    delay: Option<u64>,
+    /// How `delay` is colored, by the `latency_good` and `latency_medium`
+    /// settings.
+    #[serde(skip)]
+    tier: Option<LatencyTier>,
+}
+
+/// Serves the status page on `listen` and refreshes it every `interval`
+/// seconds until interrupted.
+pub async fn run(
+    url: Option<String>,
+    secret: Option<String>,
+    listen: &str,
+    readonly: bool,
+    interval: u64,
+) -> Result<()> {
+    // Spelled out so a later mode that can switch proxies never starts
+    // from an old command line
+    if !readonly {
+        bail!("serve only has a read-only mode so far; pass --readonly");
+    }
+    let listener = TcpListener::bind(listen)
+        .await
+        .with_context(|| format!("Failed to listen on {}", listen))?;
+
+    let mut app = App::new(url, secret);
+    let interval = interval.max(1);
+    let status = Arc::new(Mutex::new(Status::default()));
+    println!(
+        "Serving the status of {} on http://{}/ (JSON at /status.json), refreshed every {}s",
+        app.app_settings.base_url, listen, interval
+    );
+
+    let served = status.clone();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let status = served.clone();
+            tokio::spawn(async move {
+                let _ =
+                    tokio::time::timeout(REQUEST_TIMEOUT, answer(stream, status, interval)).await;
+            });
+        }
+    });
+
+    let mut ticker = tokio::time::interval(Duration::from_secs(interval));
+    let mut rate = TrafficRate::default();
+    loop {
+        ticker.tick().await;
+        // A failed poll keeps the last snapshot and says why
+        match poll(&mut app, &mut rate).await {
+            Ok(fresh) => *status.lock().unwrap_or_else(|e| e.into_inner()) = fresh,
+            Err(e) => {
+                eprintln!("serve: {:#}", e);
+                status.lock().unwrap_or_else(|e| e.into_inner()).error = Some(format!("{:#}", e));
+            }
+        }
+    }
+}
+
+async fn poll(app: &mut App, rate: &mut TrafficRate) -> Result<Status> {
+    app.fetch_proxies().await?;
+    app.fetch_connections().await?;
+    app.fetch_config().await?;
+    app.fetch_version().await?;
+
+    let rates = rate.update(app.download_total, app.upload_total);
+
+    // Hidden groups stay hidden here too
+    let groups = app
+        .group_names
+        .iter()
+        .filter_map(|name| {
+            let item = app.proxies.get(name)?;
+            let delay = app.group_latency(name);
+            Some(GroupStatus {
+                name: name.clone(),
+                kind: item.proxy_type.clone().unwrap_or_default(),
+                now: item.now.clone(),
+                delay,
+                tier: delay.map(|ms| app.latency_tier(ms)),
+            })
+        })
+        .collect();
+
+    Ok(Status {
+        updated: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs()),
+        error: None,
+        mode: app.config.as_ref().map(|c| c.mode.clone()),
+        core_version: app.capabilities.version.as_ref().map(|v| v.version.clone()),
+        download_rate: rates.map(|(down, _)| down),
+        upload_rate: rates.map(|(_, up)| up),
+        download_total: app.download_total,
+        upload_total: app.upload_total,
+        connections: app.connections.len(),
+        groups,
+    })
+}
+
+/// Answers one request: `/` as HTML, `/status.json` as JSON. Anything but
+/// GET is refused, whatever the path.
+async fn answer(stream: TcpStream, status: Arc<Mutex<Status>>, interval: u64) -> Result<()> {
+    let (read, mut write) = stream.into_split();
+    let mut reader = BufReader::new(read);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    for _ in 0..HEADER_LIMIT {
+        let mut header = String::new();
+        if reader.read_line(&mut header).await? == 0 || header.trim().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let path = path.split('?').next().unwrap_or(path);
+    let status = status.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    let (code, content_type, body) = match (method, path) {
+        ("GET", "/") => (
+            "200 OK",
+            "text/html; charset=utf-8",
+            html(&status, interval),
+        ),
+        ("GET", "/status.json") => (
+            "200 OK",
+            "application/json",
+            serde_json::to_string_pretty(&status)?,
+        ),
+        ("GET", _) => ("404 Not Found", "text/plain", "Not found\n".to_string()),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "This page is read-only\n".to_string(),
+        ),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+         Cache-Control: no-store\r\nConnection: close\r\n\r\n{}",
+        code,
+        content_type,
+        body.len(),
+        body
+    );
+    write.write_all(response.as_bytes()).await?;
+    write.shutdown().await?;
+    Ok(())
+}
+
+/// A phone-sized page that reloads itself every `interval` seconds.
+fn html(status: &Status, interval: u64) -> String {
+    let mut page = String::new();
+    let _ = write!(
+        page,
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\
+         <meta http-equiv=\"refresh\" content=\"{}\"><title>mihomo status</title>\
+         <style>body{{font-family:sans-serif;margin:1em;max-width:40em}}\
+         table{{border-collapse:collapse;width:100%}}td,th{{padding:.3em;text-align:left;\
+         border-bottom:1px solid #ddd}}.fast{{color:#2a2}}.medium{{color:#b80}}\
+         .slow,.error{{color:#c22}}.muted{{color:#888}}</style></head><body>\n",
+        interval
+    );
+    page.push_str("<h1>mihomo status</h1>\n");
+    if let Some(error) = &status.error {
+        let _ = writeln!(
+            page,
+            "<p class=\"error\">Controller unreachable: {}</p>",
+            escape(error)
+        );
+    }
+    if status.updated == 0 {
+        page.push_str("<p class=\"muted\">Waiting for the first reading…</p></body></html>\n");
+        return page;
+    }
+
+    let bytes = |n| format_bytes(n, '.');
+    let rate = |rate: Option<u64>| rate.map_or("-".to_string(), |r| format!("{}/s", bytes(r)));
+    let _ = writeln!(
+        page,
+        "<p>Mode <b>{}</b> · {} connections<br>↓ {} ↑ {} <span class=\"muted\">(total ↓ {} ↑ {})</span></p>",
+        escape(status.mode.as_deref().unwrap_or("?")),
+        status.connections,
+        rate(status.download_rate),
+        rate(status.upload_rate),
+        bytes(status.download_total),
+        bytes(status.upload_total)
+    );
+
+    page.push_str("<table><tr><th>Group</th><th>Using</th><th>Delay</th></tr>\n");
+    for group in &status.groups {
+        let (class, delay) = match (group.tier, group.delay) {
+            (Some(LatencyTier::Fast), Some(ms)) => ("fast", format!("{} ms", ms)),
+            (Some(LatencyTier::Medium), Some(ms)) => ("medium", format!("{} ms", ms)),
+            (Some(LatencyTier::Slow), Some(ms)) => ("slow", format!("{} ms", ms)),
+            _ => ("muted", "-".to_string()),
+        };
+        let _ = writeln!(
+            page,
+            "<tr><td>{} <span class=\"muted\">{}</span></td><td>{}</td><td class=\"{}\">{}</td></tr>",
+            escape(&group.name),
+            escape(&group.kind),
+            escape(group.now.as_deref().unwrap_or("-")),
+            class,
+            delay
+        );
+    }
+    page.push_str("</table>\n");
+    let _ = writeln!(
+        page,
+        "<p class=\"muted\">Core {} · updated <span id=\"t\" data-at=\"{}\"></span></p>\
+         <script>var t=document.getElementById('t');\
+         t.textContent=new Date(t.dataset.at*1000).toLocaleTimeString();</script>",
+        escape(status.core_version.as_deref().unwrap_or("?")),
+        status.updated
+    );
+    page.push_str("</body></html>\n");
+    page
+}
+
+/// Names come from the core config, so anything could be in them.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
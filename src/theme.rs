@@ -0,0 +1,170 @@
+//! Color schemes. Everything drawn in `ui.rs` takes its colors from the
+//! `Theme` picked in the settings; per-controller accents stay separate.
+
+use ratatui::style::Color;
+use serde::{Deserialize, Serialize};
+
+/// The colors of one scheme, by what they mark rather than their hue.
+pub struct Theme {
+    /// Fills the whole screen; `Reset` keeps the terminal's own.
+    pub background: Color,
+    pub foreground: Color,
+    /// Field labels and section headings.
+    pub label: Color,
+    /// Table headers, keys and warnings.
+    pub highlight: Color,
+    pub success: Color,
+    pub error: Color,
+    /// Informational accents: prompts, popup borders, the selected list entry.
+    pub info: Color,
+    /// Markers such as group hotkeys and traffic events.
+    pub marker: Color,
+    /// Primary text where it's colored explicitly.
+    pub text: Color,
+    /// Secondary text, slightly receded.
+    pub subtle: Color,
+    /// Hints and details that should stay out of the way.
+    pub muted: Color,
+    /// Borders of unfocused panels.
+    pub border: Color,
+    /// Text drawn on a colored background, such as banners.
+    pub on_highlight: Color,
+    /// Background of popups and overlays.
+    pub popup_bg: Color,
+    /// Background of the lighter info popups.
+    pub panel_bg: Color,
+    /// Background of the selected table row.
+    pub selection_bg: Color,
+    /// Delays by the thresholds of `latency_color`.
+    pub latency_fast: Color,
+    pub latency_medium: Color,
+    pub latency_slow: Color,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ThemeName {
+    /// The terminal's own palette, for dark backgrounds.
+    #[default]
+    Dark,
+    Light,
+    Gruvbox,
+    Nord,
+}
+
+impl ThemeName {
+    pub fn next(self) -> Self {
+        match self {
+            ThemeName::Dark => ThemeName::Light,
+            ThemeName::Light => ThemeName::Gruvbox,
+            ThemeName::Gruvbox => ThemeName::Nord,
+            ThemeName::Nord => ThemeName::Dark,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ThemeName::Dark => "dark",
+            ThemeName::Light => "light",
+            ThemeName::Gruvbox => "gruvbox",
+            ThemeName::Nord => "nord",
+        }
+    }
+
+    pub fn theme(self) -> &'static Theme {
+        match self {
+            ThemeName::Dark => &DARK,
+            ThemeName::Light => &LIGHT,
+            ThemeName::Gruvbox => &GRUVBOX,
+            ThemeName::Nord => &NORD,
+        }
+    }
+}
+
+const DARK: Theme = Theme {
+    background: Color::Reset,
+    foreground: Color::Reset,
+    label: Color::Blue,
+    highlight: Color::Yellow,
+    success: Color::Green,
+    error: Color::Red,
+    info: Color::Cyan,
+    marker: Color::Magenta,
+    text: Color::White,
+    subtle: Color::Gray,
+    muted: Color::DarkGray,
+    border: Color::White,
+    on_highlight: Color::Black,
+    popup_bg: Color::Black,
+    panel_bg: Color::DarkGray,
+    selection_bg: Color::DarkGray,
+    latency_fast: Color::Green,
+    latency_medium: Color::Yellow,
+    latency_slow: Color::Red,
+};
+
+const LIGHT: Theme = Theme {
+    background: Color::Rgb(250, 250, 250),
+    foreground: Color::Black,
+    label: Color::Rgb(0, 80, 160),
+    highlight: Color::Rgb(160, 90, 0),
+    success: Color::Rgb(0, 128, 0),
+    error: Color::Rgb(180, 0, 0),
+    info: Color::Rgb(0, 110, 140),
+    marker: Color::Rgb(140, 0, 140),
+    text: Color::Black,
+    subtle: Color::Rgb(70, 70, 70),
+    muted: Color::Rgb(130, 130, 130),
+    border: Color::Rgb(90, 90, 90),
+    on_highlight: Color::White,
+    popup_bg: Color::Rgb(240, 240, 240),
+    panel_bg: Color::Rgb(220, 220, 220),
+    selection_bg: Color::Rgb(205, 215, 235),
+    latency_fast: Color::Rgb(0, 128, 0),
+    latency_medium: Color::Rgb(160, 90, 0),
+    latency_slow: Color::Rgb(180, 0, 0),
+};
+
+const GRUVBOX: Theme = Theme {
+    background: Color::Rgb(0x28, 0x28, 0x28),
+    foreground: Color::Rgb(0xeb, 0xdb, 0xb2),
+    label: Color::Rgb(0x83, 0xa5, 0x98),
+    highlight: Color::Rgb(0xfa, 0xbd, 0x2f),
+    success: Color::Rgb(0xb8, 0xbb, 0x26),
+    error: Color::Rgb(0xfb, 0x49, 0x34),
+    info: Color::Rgb(0x8e, 0xc0, 0x7c),
+    marker: Color::Rgb(0xd3, 0x86, 0x9b),
+    text: Color::Rgb(0xeb, 0xdb, 0xb2),
+    subtle: Color::Rgb(0xa8, 0x99, 0x84),
+    muted: Color::Rgb(0x92, 0x83, 0x74),
+    border: Color::Rgb(0xa8, 0x99, 0x84),
+    on_highlight: Color::Rgb(0x28, 0x28, 0x28),
+    popup_bg: Color::Rgb(0x1d, 0x20, 0x21),
+    panel_bg: Color::Rgb(0x3c, 0x38, 0x36),
+    selection_bg: Color::Rgb(0x50, 0x49, 0x45),
+    latency_fast: Color::Rgb(0xb8, 0xbb, 0x26),
+    latency_medium: Color::Rgb(0xfe, 0x80, 0x19),
+    latency_slow: Color::Rgb(0xfb, 0x49, 0x34),
+};
+
+const NORD: Theme = Theme {
+    background: Color::Rgb(0x2e, 0x34, 0x40),
+    foreground: Color::Rgb(0xd8, 0xde, 0xe9),
+    label: Color::Rgb(0x81, 0xa1, 0xc1),
+    highlight: Color::Rgb(0xeb, 0xcb, 0x8b),
+    success: Color::Rgb(0xa3, 0xbe, 0x8c),
+    error: Color::Rgb(0xbf, 0x61, 0x6a),
+    info: Color::Rgb(0x88, 0xc0, 0xd0),
+    marker: Color::Rgb(0xb4, 0x8e, 0xad),
+    text: Color::Rgb(0xec, 0xef, 0xf4),
+    subtle: Color::Rgb(0xd8, 0xde, 0xe9),
+    muted: Color::Rgb(0x61, 0x6e, 0x88),
+    border: Color::Rgb(0x4c, 0x56, 0x6a),
+    on_highlight: Color::Rgb(0x2e, 0x34, 0x40),
+    popup_bg: Color::Rgb(0x3b, 0x42, 0x52),
+    panel_bg: Color::Rgb(0x43, 0x4c, 0x5e),
+    selection_bg: Color::Rgb(0x4c, 0x56, 0x6a),
+    latency_fast: Color::Rgb(0xa3, 0xbe, 0x8c),
+    latency_medium: Color::Rgb(0xd0, 0x87, 0x70),
+    latency_slow: Color::Rgb(0xbf, 0x61, 0x6a),
+};
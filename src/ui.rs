@@ -4,303 +4,3796 @@ use ratatui::{
     style::{Color, Modifier, Style},
     text::{Line, Span},
     widgets::{
-        Block, Borders, Cell, Clear, Gauge, List, ListItem, Paragraph, Row, Sparkline, Table, Wrap,
+        Block, Borders, Cell, Clear, Gauge, List, ListItem, ListState, Paragraph, Row, Sparkline,
+        Table, Wrap,
     },
 };
 
-use crate::app::{App, ConfigEntry, Focus};
+use mihomot::api::{dns_rcode_name, dns_type_name};
+use std::sync::atomic::Ordering;
+use std::time::Instant;
+
+use crate::app::{
+    App, BulkOutcome, BulkSelection, COMPARE_ROUNDS, Comparison, ConfigEntry, ConfirmAction,
+    Connection, ConnectionColumn, CoreFeature, CoreOperationKind, CoreOperationStatus,
+    DNS_QUERY_TYPES, DelayStats, DnsTool, EndpointStatus, FakeIpMap, Focus, Job, LOG_LEVELS,
+    LatencyFormat, LatencyTier, LatencyTrend, LayoutPreset, Load, Modal, Notification,
+    NotificationLevel, Panel, PinChoice, ProxySort, RawResponse, Tab, TrafficEventKind,
+    TrafficHistory, TrafficRange, can_pin, fuzzy_match, leader_key_name,
+};
+use crate::changelog;
+use crate::keymap::{KEYMAP, KeyContext, hints, main_hints};
+use crate::theme::Theme;
 
 pub fn draw(f: &mut Frame, app: &mut App) {
+    let theme = app.theme();
+    f.render_widget(
+        Block::default().style(Style::default().bg(theme.background).fg(theme.foreground)),
+        f.area(),
+    );
+    if app.mini {
+        draw_mini(f, app);
+        draw_flash(f, app);
+        return;
+    }
+
+    let unsupported = app.capabilities.unsupported_features();
+    let banner_height = u16::from(!unsupported.is_empty()) + u16::from(app.config_drift);
+
     let chunks = Layout::default()
         .direction(Direction::Vertical)
-        .constraints([Constraint::Min(0), Constraint::Length(1)])
-        .split(f.area());
-
-    let main_chunks = Layout::default()
-        .direction(Direction::Horizontal)
         .constraints([
-            Constraint::Percentage(20), // Groups
-            Constraint::Percentage(40), // Proxies
-            Constraint::Percentage(40), // Overview
+            Constraint::Length(banner_height),
+            Constraint::Length(1),
+            Constraint::Min(0),
+            Constraint::Length(1),
         ])
+        .split(f.area());
+
+    let banners = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(1); 2])
         .split(chunks[0]);
+    let mut banner_slot = banners.iter();
+    if app.config_drift
+        && let Some(area) = banner_slot.next()
+    {
+        draw_drift_banner(f, app.theme(), *area);
+    }
+    if !unsupported.is_empty()
+        && let Some(area) = banner_slot.next()
+    {
+        draw_compat_banner(f, app, &unsupported, *area);
+    }
 
-    draw_groups(f, app, main_chunks[0]);
-    draw_proxies(f, app, main_chunks[1]);
-    draw_overview(f, app, main_chunks[2]);
-    draw_status_bar(f, app, chunks[1]);
+    draw_tab_bar(f, app, chunks[1]);
+    match app.current_view() {
+        Focus::Connections => draw_connections(f, app, chunks[2]),
+        Focus::Rules => draw_rules(f, app, chunks[2]),
+        Focus::Logs => draw_logs(f, app, chunks[2]),
+        Focus::RuleProviders => draw_rule_providers(f, app, chunks[2]),
+        Focus::ProxyProviders => draw_proxy_providers(f, app, chunks[2]),
+        Focus::Overview => draw_overview_tab(f, app, chunks[2]),
+        _ => draw_main(f, app, chunks[2]),
+    }
+    draw_status_bar(f, app, chunks[3]);
 
     if let Focus::Settings = app.focus {
         draw_settings(f, app);
     }
 
-    if app.show_info_popup {
-        draw_info_popup(f, app);
+    if let Focus::Debug = app.focus {
+        draw_debug(f, app);
     }
 
-    if app.is_editing {
-        draw_input_popup(f, app);
+    if app.detail_connection_id.is_some() {
+        draw_connection_popup(f, app);
     }
-}
 
-fn draw_groups(f: &mut Frame, app: &mut App, area: Rect) {
-    let items: Vec<ListItem> = app
-        .group_names
-        .iter()
-        .map(|name| ListItem::new(Line::from(name.as_str())))
-        .collect();
+    if let Some(comparison) = &app.comparison {
+        draw_comparison_popup(f, app.theme(), comparison);
+    }
 
-    let title = "Groups";
-    let border_color = if let Focus::Groups = app.focus {
-        Color::Yellow
-    } else {
-        Color::White
-    };
+    // Hosts and servers are edited in the input popup, so it goes on top
+    match &app.modal {
+        Modal::Help => draw_help_popup(f, app),
+        Modal::Info => draw_info_popup(f, app),
+        Modal::Capabilities => draw_capabilities_popup(f, app),
+        Modal::Queue => draw_queue_popup(f, app),
+        Modal::ConfigReview => draw_config_review(f, app),
+        Modal::ConfigTree => draw_config_tree(f, app),
+        Modal::Hosts => draw_hosts_popup(f, app),
+        Modal::Profiles => draw_profiles_popup(f, app),
+        Modal::Confirm { action, yes } => draw_confirm_popup(f, app, action, *yes),
+        Modal::Response(response) => draw_response_popup(f, app, response),
+        Modal::Dns(tool) => draw_dns_popup(f, app, tool),
+        Modal::TrafficHistory(history) => draw_traffic_history_popup(f, app, history),
+        Modal::BulkSelect(selection) => draw_bulk_select_popup(f, app, selection),
+        Modal::Pin(choice) => draw_pin_popup(f, app, choice),
+        Modal::Notifications => draw_notifications_popup(f, app),
+        Modal::Changelog(previous) => draw_changelog_popup(f, app, previous),
+        Modal::FakeIp(map) => draw_fake_ip_popup(f, app, map),
+        Modal::None => {}
+    }
 
-    let block = Block::default()
-        .borders(Borders::ALL)
-        .title(title)
-        .border_style(Style::default().fg(border_color));
+    if app.is_editing {
+        draw_input_popup(f, app);
+    }
 
-    let list = List::new(items)
-        .block(block)
-        .highlight_style(
-            Style::default()
-                .add_modifier(Modifier::BOLD)
-                .fg(Color::Cyan),
-        )
-        .highlight_symbol("> ");
+    if let Some(draft) = &app.notes_draft {
+        draw_notes_popup(f, app, draft);
+    }
+    draw_toasts(f, app, chunks[3]);
+    draw_flash(f, app);
+}
 
-    f.render_stateful_widget(list, area, &mut app.group_state);
+fn notification_color(theme: &Theme, level: NotificationLevel) -> Color {
+    match level {
+        NotificationLevel::Info => theme.success,
+        NotificationLevel::Warn => theme.highlight,
+        NotificationLevel::Error => theme.error,
+    }
 }
 
-fn draw_proxies(f: &mut Frame, app: &mut App, area: Rect) {
-    let border_color = if let Focus::Proxies = app.focus {
-        Color::Yellow
+/// `message (×3)` for a notification that came in several times.
+fn notification_text(notification: &Notification) -> String {
+    if notification.count > 1 {
+        format!("{} (×{})", notification.message, notification.count)
     } else {
-        Color::White
+        notification.message.clone()
+    }
+}
+
+/// The live notifications, stacked right above the status bar with the
+/// newest at the bottom.
+fn draw_toasts(f: &mut Frame, app: &App, status_bar: Rect) {
+    let theme = app.theme();
+    let toasts: Vec<&Notification> = app.toasts().collect();
+    if toasts.is_empty() {
+        return;
+    }
+    let lines: Vec<Line> = toasts
+        .iter()
+        .rev()
+        .map(|toast| {
+            let color = notification_color(theme, toast.level);
+            Line::from(vec![
+                Span::styled(
+                    format!(" {} ", toast.level.label()),
+                    Style::default()
+                        .fg(theme.on_highlight)
+                        .bg(color)
+                        .add_modifier(Modifier::BOLD),
+                ),
+                Span::styled(
+                    format!(" {} ", notification_text(toast)),
+                    Style::default().fg(color),
+                ),
+            ])
+        })
+        .collect();
+    let height = (lines.len() as u16).min(status_bar.y);
+    let width = lines
+        .iter()
+        .map(|line| line.width() as u16)
+        .max()
+        .unwrap_or(0)
+        .min(status_bar.width.saturating_mul(3) / 4);
+    let area = Rect {
+        x: status_bar.right().saturating_sub(width),
+        y: status_bar.y - height,
+        width,
+        height,
     };
+    f.render_widget(Clear, area);
+    f.render_widget(
+        Paragraph::new(lines).style(Style::default().bg(theme.popup_bg)),
+        area,
+    );
+}
+
+/// New features and changed default keys of each release since `previous`.
+fn draw_changelog_popup(f: &mut Frame, app: &App, previous: &str) {
+    let theme = app.theme();
+    let area = f.area();
+    let popup_area = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage(15),
+            Constraint::Percentage(70),
+            Constraint::Percentage(15),
+        ])
+        .split(area)[1];
+    let popup_area = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(15),
+            Constraint::Percentage(70),
+            Constraint::Percentage(15),
+        ])
+        .split(popup_area)[1];
+
+    f.render_widget(Clear, popup_area);
 
     let block = Block::default()
+        .title(format!(
+            " What's New: {} -> {} ",
+            previous,
+            env!("CARGO_PKG_VERSION")
+        ))
+        .title_alignment(ratatui::layout::Alignment::Center)
         .borders(Borders::ALL)
-        .title("Proxies")
-        .border_style(Style::default().fg(border_color));
+        .border_style(Style::default().fg(theme.info))
+        .style(Style::default().bg(theme.popup_bg));
 
-    if app.group_names.is_empty() {
-        f.render_widget(Paragraph::new("No groups found").block(block), area);
-        return;
+    let heading = Style::default()
+        .fg(theme.highlight)
+        .add_modifier(Modifier::BOLD);
+    let mut text = Vec::new();
+    for release in changelog::since(previous) {
+        if !text.is_empty() {
+            text.push(Line::from(""));
+        }
+        text.push(Line::from(Span::styled(
+            format!("mihomot {}", release.version),
+            heading,
+        )));
+        text.extend(
+            release
+                .features
+                .iter()
+                .map(|feature| Line::from(format!("  • {}", feature))),
+        );
+        let changes = release.key_changes();
+        if changes.is_empty() {
+            continue;
+        }
+        text.push(Line::from(Span::styled(
+            "  Default keys:",
+            Style::default().fg(theme.label),
+        )));
+        for change in changes {
+            let keys = match (change.before, change.after, change.was) {
+                (None, Some(after), _) => Span::styled(
+                    format!("{} (new)", after),
+                    Style::default().fg(theme.success),
+                ),
+                (Some(_), Some(after), Some(was)) => Span::styled(
+                    format!("{} (was: {})", after, was),
+                    Style::default().fg(theme.highlight),
+                ),
+                (Some(before), Some(after), None) => Span::styled(
+                    format!("{} -> {}", before, after),
+                    Style::default().fg(theme.highlight),
+                ),
+                (Some(before), None, _) => Span::styled(
+                    format!("{} (removed)", before),
+                    Style::default().fg(theme.error),
+                ),
+                (None, None, _) => Span::raw(""),
+            };
+            text.push(Line::from(vec![
+                Span::styled(
+                    format!("    {}: ", change.context.title()),
+                    Style::default().fg(theme.muted),
+                ),
+                Span::raw(format!("{}  ", change.action)),
+                keys,
+            ]));
+        }
     }
 
-    let group_idx = app.group_state.selected().unwrap_or(0);
-    let group_name_opt = app.group_names.get(group_idx).cloned();
+    let p = Paragraph::new(text)
+        .block(block)
+        .wrap(Wrap { trim: false })
+        .scroll((app.popup_scroll, 0));
+    f.render_widget(p, popup_area);
+}
 
-    if let Some(group_name) = group_name_opt {
-        if let Some(group) = app.proxies.get(&group_name) {
-            if let Some(all) = &group.all {
-                let rows: Vec<Row> = all
-                    .iter()
-                    .map(|name| {
-                        let mut style = Style::default();
-                        if let Some(now) = &group.now
-                            && now == name
-                        {
-                            style = style.fg(Color::Green);
-                        }
+/// Everything still kept, newest first, with how long ago it came.
+fn draw_notifications_popup(f: &mut Frame, app: &App) {
+    let theme = app.theme();
+    let area = f.area();
+    let popup_area = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage(15),
+            Constraint::Percentage(70),
+            Constraint::Percentage(15),
+        ])
+        .split(area)[1];
+    let popup_area = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(10),
+            Constraint::Percentage(80),
+            Constraint::Percentage(10),
+        ])
+        .split(popup_area)[1];
 
-                        // Latency
-                        let latency = app.proxy_latency.get(name).copied().flatten();
-                        let (lat_str, lat_style) = if let Some(ms) = latency {
-                            let s = format!("{} ms", ms);
-                            let c = if ms < 200 {
-                                Color::Green
-                            } else if ms < 500 {
-                                Color::Yellow
-                            } else {
-                                Color::Red
-                            };
-                            (s, Style::default().fg(c))
-                        } else {
-                            ("-".to_string(), Style::default().fg(Color::Gray))
-                        };
+    f.render_widget(Clear, popup_area);
 
-                        Row::new(vec![
-                            Cell::from(name.as_str()).style(style),
-                            Cell::from(lat_str).style(lat_style),
-                        ])
-                    })
-                    .collect();
+    let block = Block::default()
+        .title(format!(" Notifications ({}) ", app.notifications.len()))
+        .title_alignment(ratatui::layout::Alignment::Center)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.info))
+        .style(Style::default().bg(theme.popup_bg));
 
-                let table = Table::new(rows, [Constraint::Percentage(70), Constraint::Length(10)])
-                    .block(block)
-                    .row_highlight_style(
+    let text: Vec<Line> = if app.notifications.is_empty() {
+        vec![Line::from(Span::styled(
+            "Nothing yet",
+            Style::default().fg(theme.muted),
+        ))]
+    } else {
+        app.notifications
+            .iter()
+            .rev()
+            .map(|notification| {
+                let ago = notification.at.elapsed().unwrap_or_default();
+                Line::from(vec![
+                    Span::styled(
+                        format!("{:>7} ago ", format_duration(ago)),
+                        Style::default().fg(theme.muted),
+                    ),
+                    Span::styled(
+                        format!("{:<6}", notification.level.label()),
                         Style::default()
-                            .add_modifier(Modifier::BOLD)
-                            .bg(Color::DarkGray),
-                    )
-                    .highlight_symbol(">> ");
+                            .fg(notification_color(theme, notification.level))
+                            .add_modifier(Modifier::BOLD),
+                    ),
+                    Span::raw(notification_text(notification)),
+                ])
+            })
+            .collect()
+    };
 
-                f.render_stateful_widget(table, area, &mut app.proxy_state);
-            } else {
-                f.render_widget(
-                    Paragraph::new("No proxies in this group").block(block),
-                    area,
-                );
-            }
-        } else {
-            f.render_widget(Paragraph::new("Group not found").block(block), area);
-        }
-    } else {
-        f.render_widget(Paragraph::new("Select a group").block(block), area);
-    }
+    let p = Paragraph::new(text)
+        .block(block)
+        .wrap(Wrap { trim: false })
+        .scroll((app.popup_scroll, 0));
+    f.render_widget(p, popup_area);
 }
 
-fn draw_overview(f: &mut Frame, app: &App, area: Rect) {
-    let block = Block::default().borders(Borders::ALL).title("Overview");
+/// Inverts everything drawn while a flash alert lasts.
+fn draw_flash(f: &mut Frame, app: &App) {
+    if app.flash_until.is_some_and(|until| Instant::now() < until) {
+        let area = f.area();
+        f.buffer_mut()
+            .set_style(area, Style::default().add_modifier(Modifier::REVERSED));
+    }
+}
 
-    let inner_area = block.inner(area);
-    f.render_widget(block, area);
+/// The views by number, the current one in the controller's accent.
+fn draw_tab_bar(f: &mut Frame, app: &App, area: Rect) {
+    let theme = app.theme();
+    let current = Tab::of(&app.current_view());
+    let mut spans = Vec::new();
+    for (i, tab) in Tab::ALL.into_iter().enumerate() {
+        let style = if Some(tab) == current {
+            Style::default()
+                .fg(theme.on_highlight)
+                .bg(app.accent())
+                .add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().fg(theme.subtle)
+        };
+        spans.push(Span::styled(format!(" {} {} ", i + 1, tab.label()), style));
+        spans.push(Span::raw(" "));
+    }
+    f.render_widget(Paragraph::new(Line::from(spans)), area);
+}
 
-    let chunks = Layout::default()
+/// The answer to a raw request, its status line above the body.
+fn draw_response_popup(f: &mut Frame, app: &App, response: &RawResponse) {
+    let theme = app.theme();
+    let area = f.area();
+    let popup_area = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
-            Constraint::Length(6), // Info
-            Constraint::Length(3), // Google Test
-            Constraint::Min(0),    // Charts
+            Constraint::Percentage(10),
+            Constraint::Percentage(80),
+            Constraint::Percentage(10),
         ])
-        .margin(1)
-        .split(inner_area);
+        .split(area)[1];
+    let popup_area = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(15),
+            Constraint::Percentage(70),
+            Constraint::Percentage(15),
+        ])
+        .split(popup_area)[1];
 
-    // 1. Info
-    let mut info_text = vec![];
-    if let Some(config) = &app.config {
-        info_text.push(Line::from(vec![
-            Span::styled("Mode: ", Style::default().fg(Color::Blue)),
-            Span::raw(&config.mode),
-        ]));
-        info_text.push(Line::from(vec![
-            Span::styled("Mixed Port: ", Style::default().fg(Color::Blue)),
-            Span::raw(config.mixed_port.to_string()),
-        ]));
-        info_text.push(Line::from(vec![
-            Span::styled("TUN: ", Style::default().fg(Color::Blue)),
+    f.render_widget(Clear, popup_area);
+
+    let block = Block::default()
+        .title(format!(" {} ", response.request))
+        .title_alignment(ratatui::layout::Alignment::Center)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.info))
+        .style(Style::default().bg(theme.popup_bg));
+
+    let (status, color) = match &response.status {
+        Ok(code) if (200..300).contains(code) => (code.to_string(), theme.success),
+        Ok(code) => (code.to_string(), theme.error),
+        Err(e) => (e.clone(), theme.error),
+    };
+    let mut text = vec![
+        Line::from(vec![
+            Span::styled("Status: ", Style::default().fg(theme.label)),
             Span::styled(
-                if config.tun.enable {
-                    "Enabled"
-                } else {
-                    "Disabled"
-                },
-                Style::default().fg(if config.tun.enable {
-                    Color::Green
-                } else {
-                    Color::Red
-                }),
+                status,
+                Style::default().fg(color).add_modifier(Modifier::BOLD),
             ),
-        ]));
-        if let Some(stack) = &config.tun.stack {
-            info_text.push(Line::from(vec![
-                Span::styled("TUN Stack: ", Style::default().fg(Color::DarkGray)),
-                Span::raw(stack),
-            ]));
-        }
+            Span::styled(
+                format!("  {} ms", response.elapsed.as_millis()),
+                Style::default().fg(theme.muted),
+            ),
+        ]),
+        Line::from(""),
+    ];
+    if response.body.is_empty() {
+        text.push(Line::from(Span::styled(
+            "(empty body)",
+            Style::default().fg(theme.muted),
+        )));
     } else {
-        info_text.push(Line::from("Loading config..."));
+        text.extend(
+            response
+                .body
+                .lines()
+                .map(|line| Line::from(line.to_string())),
+        );
     }
 
-    f.render_widget(Paragraph::new(info_text), chunks[0]);
-
-    // 2. Connection Test (Latency)
-    let (latency_label, latency_color, percent) = match &app.real_latency_status {
-        crate::app::RealLatencyStatus::Pending => ("Idle".to_string(), Color::Gray, 0),
-        crate::app::RealLatencyStatus::Testing => ("Testing...".to_string(), Color::Yellow, 0),
-        crate::app::RealLatencyStatus::Success(ms) => {
-            let color = if *ms < 200 {
-                Color::Green
-            } else if *ms < 500 {
-                Color::Yellow
-            } else {
-                Color::Red
-            };
-            (
-                format!("{} ms", ms),
-                color,
-                (1000.0 / (*ms as f64).max(10.0) * 100.0).min(100.0) as u16,
-            )
-        }
-        crate::app::RealLatencyStatus::Failed(msg) => (format!("Err: {}", msg), Color::Red, 100),
-    };
-
-    let gauge = Gauge::default()
-        .block(Block::default().title("Test Latency").borders(Borders::ALL))
-        .gauge_style(Style::default().fg(latency_color))
-        .percent(percent)
-        .label(latency_label);
-
-    f.render_widget(gauge, chunks[1]);
+    let p = Paragraph::new(text)
+        .block(block)
+        .scroll((app.popup_scroll, 0));
+    f.render_widget(p, popup_area);
+}
 
-    // 3. Charts (Sparklines)
-    let chart_chunks = Layout::default()
+/// The preview of a `bulk` command: one line per matching group.
+fn draw_bulk_select_popup(f: &mut Frame, app: &App, selection: &BulkSelection) {
+    let theme = app.theme();
+    let area = f.area();
+    let popup_area = Layout::default()
         .direction(Direction::Vertical)
-        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
-        .split(chunks[2]);
-
-    let width = chart_chunks[0].width.saturating_sub(2) as usize;
+        .constraints([
+            Constraint::Percentage(15),
+            Constraint::Percentage(70),
+            Constraint::Percentage(15),
+        ])
+        .split(area)[1];
+    let popup_area = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(15),
+            Constraint::Percentage(70),
+            Constraint::Percentage(15),
+        ])
+        .split(popup_area)[1];
 
-    // Download
-    let down_speed = format_speed(app.current_down);
-    let down_title = format!("Download: {}/s", down_speed);
-    let down_data: Vec<u64> = app
-        .traffic_history_down
+    f.render_widget(Clear, popup_area);
+
+    let block = Block::default()
+        .title(format!(
+            " Fastest {} in {} ",
+            selection.proxies, selection.groups
+        ))
+        .title_alignment(ratatui::layout::Alignment::Center)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.info))
+        .style(Style::default().bg(theme.popup_bg));
+
+    let switches = selection.switches();
+    let mut text = vec![
+        Line::from(Span::styled(
+            match (selection.changes.len(), switches) {
+                (0, _) => "No group matches".to_string(),
+                (_, 0) => "Nothing to switch".to_string(),
+                (_, 1) => "Switch 1 group?".to_string(),
+                (_, n) => format!("Switch {} groups?", n),
+            },
+            Style::default()
+                .fg(theme.highlight)
+                .add_modifier(Modifier::BOLD),
+        )),
+        Line::from(""),
+    ];
+    let width = selection
+        .changes
         .iter()
-        .rev()
-        .take(width)
-        .rev()
-        .cloned()
-        .collect();
+        .map(|c| c.group.chars().count())
+        .max()
+        .unwrap_or(0);
+    for change in &selection.changes {
+        let mut spans = vec![Span::styled(
+            format!("{:<width$}  ", change.group, width = width),
+            Style::default().fg(theme.label),
+        )];
+        let now = change.now.clone().unwrap_or_else(|| "-".to_string());
+        match &change.outcome {
+            BulkOutcome::Switch { proxy, delay } => {
+                spans.push(Span::styled(now, Style::default().fg(theme.subtle)));
+                spans.push(Span::raw(" → "));
+                spans.push(Span::styled(
+                    proxy.clone(),
+                    Style::default()
+                        .fg(theme.success)
+                        .add_modifier(Modifier::BOLD),
+                ));
+                spans.push(Span::styled(
+                    format!(" ({} ms)", delay),
+                    Style::default().fg(latency_color(app, *delay)),
+                ));
+            }
+            BulkOutcome::Unchanged { delay } => {
+                spans.push(Span::styled(now, Style::default().fg(theme.subtle)));
+                spans.push(Span::styled(
+                    format!(" ({} ms, already the fastest)", delay),
+                    Style::default().fg(theme.muted),
+                ));
+            }
+            BulkOutcome::Skip(reason) => spans.push(Span::styled(
+                format!("skipped: {}", reason),
+                Style::default().fg(theme.muted),
+            )),
+        }
+        if change.locked && matches!(change.outcome, BulkOutcome::Switch { .. }) {
+            spans.push(Span::styled(
+                "  locked",
+                Style::default().fg(theme.highlight),
+            ));
+        }
+        text.push(Line::from(spans));
+    }
+
+    let p = Paragraph::new(text)
+        .block(block)
+        .scroll((app.popup_scroll, 0));
+    f.render_widget(p, popup_area);
+}
+
+/// The DNS tool: the name being typed, then the last lookup's records.
+fn draw_dns_popup(f: &mut Frame, app: &App, tool: &DnsTool) {
+    let theme = app.theme();
+    let area = f.area();
+    let popup_area = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage(20),
+            Constraint::Percentage(60),
+            Constraint::Percentage(20),
+        ])
+        .split(area)[1];
+    let popup_area = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(15),
+            Constraint::Percentage(70),
+            Constraint::Percentage(15),
+        ])
+        .split(popup_area)[1];
+
+    f.render_widget(Clear, popup_area);
+
+    let block = Block::default()
+        .title(" DNS Query ")
+        .title(loading_title(app, &[], &[Job::DnsQuery]))
+        .title_alignment(ratatui::layout::Alignment::Center)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.info))
+        .style(Style::default().bg(theme.popup_bg));
+
+    let mut text = vec![
+        Line::from(vec![
+            Span::styled("Name: ", Style::default().fg(theme.label)),
+            Span::raw(format!("{}_", tool.input)),
+        ]),
+        Line::from(vec![
+            Span::styled("Type: ", Style::default().fg(theme.label)),
+            Span::styled(
+                DNS_QUERY_TYPES[tool.record_type],
+                Style::default()
+                    .fg(theme.highlight)
+                    .add_modifier(Modifier::BOLD),
+            ),
+        ]),
+        Line::from(""),
+    ];
+
+    let Some(lookup) = &tool.lookup else {
+        text.push(Line::from(Span::styled(
+            "Type a domain and press Enter",
+            Style::default().fg(theme.muted),
+        )));
+        f.render_widget(Paragraph::new(text).block(block), popup_area);
+        return;
+    };
+    text.push(Line::from(vec![
+        Span::styled(
+            format!("{} {}", lookup.record_type, lookup.name),
+            Style::default().fg(theme.text),
+        ),
+        Span::styled(
+            format!("  {} ms", lookup.elapsed.as_millis()),
+            Style::default().fg(theme.muted),
+        ),
+    ]));
+    match &lookup.answer {
+        Err(e) => text.push(Line::from(Span::styled(
+            e.clone(),
+            Style::default().fg(theme.error),
+        ))),
+        Ok(answer) => {
+            let status_color = if answer.status == 0 {
+                theme.success
+            } else {
+                theme.error
+            };
+            text.push(Line::from(vec![
+                Span::styled("Status: ", Style::default().fg(theme.label)),
+                Span::styled(
+                    dns_rcode_name(answer.status),
+                    Style::default().fg(status_color),
+                ),
+            ]));
+            text.push(Line::from(vec![
+                Span::styled("Answered by: ", Style::default().fg(theme.label)),
+                Span::raw(lookup.resolver.clone()),
+            ]));
+            text.push(Line::from(""));
+
+            // An empty answer still says something through its SOA record
+            let (heading, records) = if answer.answer.is_empty() {
+                ("Authority", &answer.authority)
+            } else {
+                ("Answer", &answer.answer)
+            };
+            if records.is_empty() {
+                text.push(Line::from(Span::styled(
+                    "(no records)",
+                    Style::default().fg(theme.muted),
+                )));
+            } else {
+                text.push(Line::from(Span::styled(
+                    heading,
+                    Style::default().fg(theme.label),
+                )));
+                let width = records.iter().map(|r| r.name.len()).max().unwrap_or(0);
+                for record in records {
+                    text.push(Line::from(vec![
+                        Span::raw(format!("{:<width$}  ", record.name, width = width)),
+                        Span::styled(
+                            format!("{:<6}", dns_type_name(record.record_type)),
+                            Style::default().fg(theme.info),
+                        ),
+                        Span::styled(
+                            format!("{:>6}s  ", record.ttl),
+                            Style::default().fg(theme.muted),
+                        ),
+                        Span::styled(record.data.clone(), Style::default().fg(theme.text)),
+                    ]));
+                }
+            }
+        }
+    }
+
+    let p = Paragraph::new(text).block(block).wrap(Wrap { trim: false });
+    f.render_widget(p, popup_area);
+}
+
+/// Stored traffic of the current controller over a chosen range, with totals.
+fn draw_traffic_history_popup(f: &mut Frame, app: &App, history: &TrafficHistory) {
+    let theme = app.theme();
+    let area = f.area();
+    let popup_area = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage(15),
+            Constraint::Percentage(70),
+            Constraint::Percentage(15),
+        ])
+        .split(area)[1];
+    let popup_area = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(10),
+            Constraint::Percentage(80),
+            Constraint::Percentage(10),
+        ])
+        .split(popup_area)[1];
+
+    f.render_widget(Clear, popup_area);
+
+    let block = Block::default()
+        .title(" Traffic History ")
+        .title_alignment(ratatui::layout::Alignment::Center)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.info))
+        .style(Style::default().bg(theme.popup_bg));
+    let inner = block.inner(popup_area);
+    f.render_widget(block, popup_area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(4),
+            Constraint::Fill(1),
+            Constraint::Fill(1),
+            Constraint::Length(1),
+        ])
+        .split(inner);
+
+    let mut tabs = Vec::new();
+    for range in TrafficRange::ALL {
+        let style = if range == history.range {
+            Style::default()
+                .fg(theme.highlight)
+                .add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().fg(theme.muted)
+        };
+        tabs.push(Span::styled(format!(" {} ", range.label()), style));
+    }
+
+    let (total_up, total_down) = history.totals();
+    let span_minutes = history.range.span().as_secs() / 60;
+    let busiest = history.minutes.iter().max_by_key(|m| m.up + m.down);
+    let mut text = vec![
+        Line::from(tabs),
+        Line::from(vec![
+            Span::styled("Download: ", Style::default().fg(theme.label)),
+            Span::styled(
+                format_bytes(app, total_down),
+                Style::default().fg(theme.success),
+            ),
+            Span::styled("  Upload: ", Style::default().fg(theme.label)),
+            Span::styled(
+                format_bytes(app, total_up),
+                Style::default().fg(theme.highlight),
+            ),
+            Span::styled("  Total: ", Style::default().fg(theme.label)),
+            Span::raw(format_bytes(app, total_up + total_down)),
+        ]),
+    ];
+    let mut coverage = vec![Span::styled(
+        format!(
+            "Recorded {} of {} minutes",
+            history.minutes.len(),
+            span_minutes
+        ),
+        Style::default().fg(theme.muted),
+    )];
+    if let Some(minute) = busiest {
+        coverage.push(Span::styled(
+            format!(
+                "  Busiest minute: {}/s on average",
+                format_speed(app, (minute.up + minute.down) / 60)
+            ),
+            Style::default().fg(theme.muted),
+        ));
+    }
+    text.push(Line::from(coverage));
+    f.render_widget(Paragraph::new(text), chunks[0]);
+
+    if history.minutes.is_empty() {
+        let p = Paragraph::new(Span::styled(
+            "Nothing stored for this range yet; traffic is saved a minute at a time while mihomot runs",
+            Style::default().fg(theme.muted),
+        ))
+        .wrap(Wrap { trim: false });
+        f.render_widget(p, chunks[1]);
+        return;
+    }
+
+    let (up, down) = history.buckets(chunks[1].width.saturating_sub(2) as usize);
     let down_sparkline = Sparkline::default()
         .block(
             Block::default()
-                .title(down_title)
+                .title("Download")
                 .borders(Borders::TOP | Borders::LEFT | Borders::RIGHT),
         )
-        .data(&down_data)
-        .style(Style::default().fg(Color::Green));
-    f.render_widget(down_sparkline, chart_chunks[0]);
+        .data(&down)
+        .style(Style::default().fg(theme.success));
+    f.render_widget(down_sparkline, chunks[1]);
+    let up_sparkline = Sparkline::default()
+        .block(Block::default().title("Upload").borders(Borders::ALL))
+        .data(&up)
+        .style(Style::default().fg(theme.highlight));
+    f.render_widget(up_sparkline, chunks[2]);
+
+    let start = match history.range {
+        TrafficRange::Hour => "-1h",
+        TrafficRange::Day => "-24h",
+        TrafficRange::Week => "-7d",
+    };
+    let gap = (chunks[3].width as usize).saturating_sub(start.len() + "now".len() + 2);
+    let axis = Line::from(Span::styled(
+        format!(" {}{}now ", start, " ".repeat(gap)),
+        Style::default().fg(theme.muted),
+    ));
+    f.render_widget(Paragraph::new(axis), chunks[3]);
+}
+
+fn draw_confirm_popup(f: &mut Frame, app: &App, action: &ConfirmAction, yes: bool) {
+    let theme = app.theme();
+    let area = f.area();
+    let popup_area = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Fill(1),
+            Constraint::Length(6),
+            Constraint::Fill(1),
+        ])
+        .split(area)[1];
+
+    let popup_area = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(25),
+            Constraint::Percentage(50),
+            Constraint::Percentage(25),
+        ])
+        .split(popup_area)[1];
+
+    f.render_widget(Clear, popup_area);
+
+    let block = Block::default()
+        .title(" Confirm ")
+        .title_alignment(ratatui::layout::Alignment::Center)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.highlight))
+        .style(Style::default().bg(theme.popup_bg));
+
+    let text = vec![
+        Line::from(action.prompt()),
+        Line::from(""),
+        Line::from(vec![
+            popup_button(theme, " Yes (y) ".to_string(), yes),
+            Span::raw("   "),
+            popup_button(theme, " No (n) ".to_string(), !yes),
+        ]),
+    ];
+    let paragraph = Paragraph::new(text)
+        .block(block)
+        .alignment(ratatui::layout::Alignment::Center)
+        .wrap(Wrap { trim: true });
+    f.render_widget(paragraph, popup_area);
+}
+
+fn popup_button(theme: &Theme, label: String, selected: bool) -> Span<'static> {
+    if selected {
+        Span::styled(
+            label,
+            Style::default()
+                .fg(theme.on_highlight)
+                .bg(theme.highlight)
+                .add_modifier(Modifier::BOLD),
+        )
+    } else {
+        Span::styled(label, Style::default().fg(theme.subtle))
+    }
+}
+
+/// Pin a node of a `url-test` or `fallback` group, or let the core pick again.
+fn draw_pin_popup(f: &mut Frame, app: &App, choice: &PinChoice) {
+    let theme = app.theme();
+    let area = f.area();
+    let popup_area = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Fill(1),
+            Constraint::Length(7),
+            Constraint::Fill(1),
+        ])
+        .split(area)[1];
+    let popup_area = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(25),
+            Constraint::Percentage(50),
+            Constraint::Percentage(25),
+        ])
+        .split(popup_area)[1];
+
+    f.render_widget(Clear, popup_area);
+
+    let block = Block::default()
+        .title(format!(" {} ", choice.group))
+        .title_alignment(ratatui::layout::Alignment::Center)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.highlight))
+        .style(Style::default().bg(theme.popup_bg));
+
+    let state = match &choice.pinned {
+        Some(pinned) => format!("This {} group is pinned to {}.", choice.kind, pinned),
+        None => format!("The core picks the node of this {} group.", choice.kind),
+    };
+    let text = vec![
+        Line::from(state),
+        Line::from(Span::styled(
+            "A pinned node stays in use until the group returns to auto.",
+            Style::default().fg(theme.muted),
+        )),
+        Line::from(""),
+        Line::from(vec![
+            popup_button(theme, format!(" Pin {} (p) ", choice.proxy), !choice.unpin),
+            Span::raw("   "),
+            popup_button(theme, " Return to auto (a) ".to_string(), choice.unpin),
+        ]),
+    ];
+    let paragraph = Paragraph::new(text)
+        .block(block)
+        .alignment(ratatui::layout::Alignment::Center)
+        .wrap(Wrap { trim: true });
+    f.render_widget(paragraph, popup_area);
+}
+
+/// Every binding in `KEYMAP`, grouped by where it applies.
+fn draw_help_popup(f: &mut Frame, app: &App) {
+    let theme = app.theme();
+    let area = f.area();
+    let popup_area = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage(10),
+            Constraint::Percentage(80),
+            Constraint::Percentage(10),
+        ])
+        .split(area)[1];
+    let popup_area = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(20),
+            Constraint::Percentage(60),
+            Constraint::Percentage(20),
+        ])
+        .split(popup_area)[1];
+
+    f.render_widget(Clear, popup_area);
+
+    let block = Block::default()
+        .title(" Keys ")
+        .title_alignment(ratatui::layout::Alignment::Center)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.info))
+        .style(Style::default().bg(theme.popup_bg));
+
+    let mut text = vec![];
+    for context in KeyContext::ALL {
+        if !text.is_empty() {
+            text.push(Line::from(""));
+        }
+        text.push(Line::from(Span::styled(
+            context.title(),
+            Style::default()
+                .fg(theme.highlight)
+                .add_modifier(Modifier::BOLD),
+        )));
+        for binding in KEYMAP.iter().filter(|b| b.context == context) {
+            text.push(Line::from(vec![
+                Span::styled(
+                    format!("  {:<22}", binding.keys),
+                    Style::default().fg(theme.info),
+                ),
+                Span::raw(binding.action),
+            ]));
+        }
+    }
+
+    // Chords come from the settings rather than the fixed table
+    text.push(Line::from(""));
+    text.push(Line::from(Span::styled(
+        "Leader Chords",
+        Style::default()
+            .fg(theme.highlight)
+            .add_modifier(Modifier::BOLD),
+    )));
+    let leader = leader_key_name(app.app_settings.leader_key);
+    for (key, action) in &app.app_settings.leader_chords {
+        text.push(Line::from(vec![
+            Span::styled(
+                format!("  {:<22}", format!("{} {}", leader, key)),
+                Style::default().fg(theme.info),
+            ),
+            Span::raw(action.label()),
+        ]));
+    }
+
+    // The table above lists the defaults; these replace some of them
+    if !app.app_settings.keymap.is_empty() {
+        text.push(Line::from(""));
+        text.push(Line::from(Span::styled(
+            "Custom Keys (Groups and Proxies)",
+            Style::default()
+                .fg(theme.highlight)
+                .add_modifier(Modifier::BOLD),
+        )));
+        for (action, keys) in &app.app_settings.keymap {
+            let keys = if keys.trim().is_empty() {
+                "(none)"
+            } else {
+                keys.as_str()
+            };
+            text.push(Line::from(vec![
+                Span::styled(format!("  {:<22}", keys), Style::default().fg(theme.info)),
+                Span::raw(format!("{} (was {})", action.name(), action.default_keys())),
+            ]));
+        }
+    }
+
+    let p = Paragraph::new(text)
+        .block(block)
+        .scroll((app.popup_scroll, 0));
+    f.render_widget(p, popup_area);
+}
+
+/// One line of state, plus a status/help line when the terminal has room.
+fn draw_mini(f: &mut Frame, app: &App) {
+    let theme = app.theme();
+    let area = f.area();
+    let label = Style::default().fg(theme.label);
+
+    let mut spans = Vec::new();
+    if let Some(config) = &app.config {
+        spans.push(Span::styled(
+            config.mode.clone(),
+            Style::default().add_modifier(Modifier::BOLD),
+        ));
+        spans.push(Span::raw(" │ "));
+    }
+    if let Some(group) = app.get_selected_group_name() {
+        let now = app
+            .proxies
+            .get(group)
+            .and_then(|g| g.now.as_deref())
+            .unwrap_or("-");
+        spans.push(Span::styled(format!("{} ", group), label));
+        spans.push(Span::raw(now.to_string()));
+        if let Some(ms) = app.group_latency(group) {
+            let slowest = app
+                .group_names
+                .iter()
+                .filter_map(|name| app.group_latency(name))
+                .max()
+                .unwrap_or(ms);
+            spans.push(Span::styled(
+                format!(" {}", format_latency(app, ms, slowest)),
+                Style::default().fg(latency_color(app, ms)),
+            ));
+        }
+        spans.push(Span::raw(" │ "));
+    }
+    spans.push(Span::styled("↓ ", Style::default().fg(theme.success)));
+    spans.push(Span::raw(format!(
+        "{}/s ",
+        format_speed(app, app.current_down)
+    )));
+    spans.push(Span::styled("↑ ", Style::default().fg(theme.highlight)));
+    spans.push(Span::raw(format!(
+        "{}/s",
+        format_speed(app, app.current_up)
+    )));
+    spans.push(Span::raw(" │ "));
+    spans.push(match &app.real_latency_status {
+        crate::app::RealLatencyStatus::Success(ms) => Span::styled(
+            format!("test {}ms", ms),
+            Style::default().fg(latency_color(app, *ms)),
+        ),
+        crate::app::RealLatencyStatus::Failed(_) => {
+            Span::styled("test failed", Style::default().fg(theme.error))
+        }
+        crate::app::RealLatencyStatus::Testing => Span::raw("testing..."),
+        crate::app::RealLatencyStatus::Pending => Span::raw("test -"),
+    });
+
+    let mut lines = vec![Line::from(spans)];
+    if area.height >= 2 {
+        lines.push(if let Some(toast) = app.toasts().next() {
+            Line::from(Span::styled(
+                notification_text(toast),
+                Style::default().fg(notification_color(theme, toast.level)),
+            ))
+        } else {
+            Line::from(Span::styled(
+                "q: Quit | r: Refresh | t: Test | m: Full UI",
+                Style::default().fg(theme.muted),
+            ))
+        });
+    }
+    f.render_widget(Paragraph::new(lines), area);
+}
+
+fn draw_main(f: &mut Frame, app: &mut App, area: Rect) {
+    if app.app_settings.layout == LayoutPreset::Wide {
+        draw_main_wide(f, app, area);
+        return;
+    }
+    let main_chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(30), // Groups
+            Constraint::Percentage(70), // Proxies
+        ])
+        .split(area);
+
+    draw_groups(f, app, main_chunks[0]);
+    draw_proxies(f, app, main_chunks[1]);
+}
+
+/// The Overview tab: the Overview with the connections summary beside it.
+fn draw_overview_tab(f: &mut Frame, app: &App, area: Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(65), Constraint::Percentage(35)])
+        .split(area);
+    draw_overview(f, app, chunks[0]);
+    draw_connections_summary(f, app, chunks[1]);
+}
+
+/// Groups above Proxies on the left, monitoring on the right.
+fn draw_main_wide(f: &mut Frame, app: &mut App, area: Rect) {
+    let halves = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(area);
+    let left = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(35), Constraint::Percentage(65)])
+        .split(halves[0]);
+    let right = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(65), Constraint::Percentage(35)])
+        .split(halves[1]);
+
+    draw_groups(f, app, left[0]);
+    draw_proxies(f, app, left[1]);
+    draw_overview(f, app, right[0]);
+    draw_connections_summary(f, app, right[1]);
+}
+
+/// Open connections summed up by proxy chain and rule, and the busiest hosts,
+/// from `App::connection_stats`.
+fn draw_connections_summary(f: &mut Frame, app: &App, area: Rect) {
+    let theme = app.theme();
+    let stats = &app.connection_stats;
+    let mut title = vec![Span::raw(format!("Connections ({}) ", stats.total))];
+    if app.connections_live.load(Ordering::Relaxed) {
+        title.push(Span::styled("● live ", Style::default().fg(theme.success)));
+    }
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(Line::from(title))
+        .title(loading_title(
+            app,
+            &[Load::Connections],
+            &[Job::CloseConnections],
+        ));
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(1),
+            Constraint::Percentage(50),
+            Constraint::Min(0),
+        ])
+        .split(inner);
+    let top = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(rows[1]);
+
+    let muted = Style::default().fg(theme.muted);
+    f.render_widget(
+        Paragraph::new(Line::from(vec![
+            Span::raw(format!(
+                "{} open, {} TCP, {} UDP",
+                stats.total, stats.tcp, stats.udp
+            )),
+            Span::styled(
+                format!(
+                    "  total ↓ {} ↑ {}",
+                    format_speed(app, app.download_total),
+                    format_speed(app, app.upload_total)
+                ),
+                muted,
+            ),
+        ])),
+        rows[0],
+    );
+
+    let heading = Style::default().fg(theme.label);
+    let section = |name: &str, area: Rect, lines: Vec<Line<'static>>| {
+        let mut text = vec![Line::from(Span::styled(name.to_string(), heading))];
+        text.extend(
+            lines
+                .into_iter()
+                .take(area.height.saturating_sub(1) as usize),
+        );
+        Paragraph::new(text)
+    };
+
+    let chains = stats
+        .by_chain
+        .iter()
+        .map(|(chain, count, bytes)| {
+            Line::from(vec![
+                Span::raw(format!("{:>4} ", count)),
+                Span::raw(chain.clone()),
+                Span::styled(format!("  {}", format_speed(app, *bytes)), muted),
+            ])
+        })
+        .collect();
+    f.render_widget(section("By chain", top[0], chains), top[0]);
+
+    let rules = stats
+        .by_rule
+        .iter()
+        .map(|(rule, count)| Line::from(format!("{:>4} {}", count, rule)))
+        .collect();
+    f.render_widget(section("By rule", top[1], rules), top[1]);
+
+    let hosts = stats
+        .top_hosts
+        .iter()
+        .map(|(host, bytes)| Line::from(format!("{:>9} {}", format_speed(app, *bytes), host)))
+        .collect();
+    f.render_widget(section("Top hosts", rows[2], hosts), rows[2]);
+}
+
+fn draw_drift_banner(f: &mut Frame, theme: &Theme, area: Rect) {
+    let text = Line::from(vec![
+        Span::styled("Notice: ", Style::default().add_modifier(Modifier::BOLD)),
+        Span::raw("config changed externally — press r to sync"),
+    ]);
+
+    f.render_widget(
+        Paragraph::new(text).style(Style::default().bg(theme.info).fg(theme.on_highlight)),
+        area,
+    );
+}
+
+fn draw_compat_banner(f: &mut Frame, app: &App, unsupported: &[CoreFeature], area: Rect) {
+    let theme = app.theme();
+    let version = app
+        .capabilities
+        .version
+        .as_ref()
+        .map(|v| v.version.as_str())
+        .unwrap_or("?");
+    let features = unsupported
+        .iter()
+        .map(|feature| {
+            let (major, minor, patch) = feature.min_version();
+            format!("{} (>= v{}.{}.{})", feature.label(), major, minor, patch)
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let text = Line::from(vec![
+        Span::styled(
+            "Warning: ",
+            Style::default()
+                .fg(theme.on_highlight)
+                .add_modifier(Modifier::BOLD),
+        ),
+        Span::raw(format!(
+            "core {} is too old for {}; {}",
+            version,
+            features,
+            match (
+                unsupported.contains(&CoreFeature::GroupDelay),
+                unsupported.len() > 1,
+            ) {
+                (true, true) => "those tools are hidden and groups are tested proxy by proxy",
+                (true, false) => "groups are tested proxy by proxy",
+                _ => "those tools are hidden",
+            }
+        )),
+    ]);
+
+    f.render_widget(
+        Paragraph::new(text).style(Style::default().bg(theme.highlight).fg(theme.on_highlight)),
+        area,
+    );
+}
+
+fn draw_groups(f: &mut Frame, app: &mut App, area: Rect) {
+    let theme = app.theme();
+    // Inside the borders and the highlight symbol
+    let row_width = area.width.saturating_sub(4) as usize;
+    let items: Vec<ListItem> = app
+        .group_names
+        .iter()
+        .map(|name| {
+            // Stale results no longer say much about the group's health
+            let color = app
+                .group_latency(name)
+                .filter(|_| !app.is_group_latency_stale(name))
+                .map(|ms| latency_color(app, ms))
+                .unwrap_or(theme.subtle);
+            let mut style = Style::default().fg(color);
+            if app.is_group_hidden(name) {
+                style = style.add_modifier(Modifier::DIM | Modifier::ITALIC);
+            }
+            let marker = if app.is_group_pinned(name) {
+                "★ "
+            } else {
+                ""
+            };
+            let mut spans = vec![Span::raw(format!("{}{}", marker, name))];
+            if app.is_group_locked(name) {
+                spans.push(Span::styled(
+                    " locked",
+                    Style::default().fg(theme.highlight),
+                ));
+            }
+            if let Some(key) = app.group_hotkey(name) {
+                spans.push(Span::styled(
+                    format!(" F{}", key),
+                    Style::default().fg(theme.marker),
+                ));
+            }
+            // The core picks the node of these unless one is pinned
+            if let Some(kind) = app.auto_group_kind(name) {
+                spans.push(Span::styled(
+                    format!(" {}", kind),
+                    Style::default().fg(theme.muted),
+                ));
+                if app.pinned_proxy(name).is_some() {
+                    spans.push(Span::styled(" pinned", Style::default().fg(theme.marker)));
+                }
+            }
+            // The active node's recent delays, right-aligned when there's room
+            if let Some(proxy) = app.resolve_group_proxy(name) {
+                let trend = latency_trend_line(app, proxy, GROUP_TREND_WIDTH);
+                let used = Line::from(spans.clone()).width();
+                if !trend.spans.is_empty() && used + 1 + trend.width() <= row_width {
+                    spans.push(Span::raw(" ".repeat(row_width - used - trend.width())));
+                    spans.extend(trend.spans);
+                }
+            }
+            ListItem::new(Line::from(spans)).style(style)
+        })
+        .collect();
+
+    let title = if app.show_hidden_groups {
+        "Groups (showing hidden)"
+    } else {
+        "Groups"
+    };
+    let border_color = if let Focus::Groups = app.focus {
+        app.accent()
+    } else {
+        theme.border
+    };
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(title)
+        .title(loading_title(app, &[Load::Proxies], &[Job::Probe]))
+        .border_style(Style::default().fg(border_color));
+
+    if let Some(error) = app
+        .load_errors
+        .get(&Panel::Proxies)
+        .filter(|_| app.group_names.is_empty())
+    {
+        draw_load_error(f, theme, block, error, area);
+        return;
+    }
+
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(Style::default().add_modifier(Modifier::BOLD).fg(theme.info))
+        .highlight_symbol("> ");
+
+    f.render_stateful_widget(list, area, &mut app.group_state);
+}
+
+fn draw_proxies(f: &mut Frame, app: &mut App, area: Rect) {
+    let theme = app.theme();
+    let border_color = if let Focus::Proxies = app.focus {
+        app.accent()
+    } else {
+        theme.border
+    };
+
+    let mut title = match &app.provider_filter {
+        Some(provider) => format!("Proxies [{}]", provider),
+        None => "Proxies".to_string(),
+    };
+    if let Some(crumbs) = app.group_breadcrumbs() {
+        title.push_str(&format!(": {}", crumbs));
+    }
+    if app.proxy_sort != ProxySort::Original {
+        title.push_str(&format!(" by {}", app.proxy_sort.label()));
+    }
+    if let Some(query) = &app.proxy_search {
+        title.push_str(&format!(" /{}", query));
+    }
+    if let Some(weights) = app
+        .get_selected_group_name()
+        .and_then(|name| app.smart_weights.get(name))
+    {
+        match weights {
+            Ok(_) => title.push_str(" (Smart, F: flush)"),
+            Err(e) => title.push_str(&format!(" (Smart, weights unavailable: {})", e)),
+        }
+    }
+    if let Some(group) = app.get_selected_group_name()
+        && let Some(kind) = app.auto_group_kind(group)
+    {
+        match app.pinned_proxy(group) {
+            Some(pinned) => title.push_str(&format!(" ({}, pinned to {}", kind, pinned)),
+            None if can_pin(kind) => title.push_str(&format!(" ({}, Enter: pin", kind)),
+            None => title.push_str(&format!(" ({}, read-only", kind)),
+        }
+        if let Some((previous, at)) = app.auto_switches.get(group) {
+            let ago = at.elapsed().unwrap_or_default();
+            title.push_str(&format!(", was {} {} ago", previous, format_duration(ago)));
+        }
+        title.push(')');
+    }
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(title)
+        .title(loading_title(app, &[Load::Proxies], &[Job::Probe]))
+        .border_style(Style::default().fg(border_color));
+
+    if app.group_names.is_empty() {
+        match app.load_errors.get(&Panel::Proxies) {
+            Some(error) => draw_load_error(f, theme, block, error, area),
+            None => f.render_widget(Paragraph::new("No groups found").block(block), area),
+        }
+        return;
+    }
+
+    let group_idx = app.group_state.selected().unwrap_or(0);
+    let group_name_opt = app.group_names.get(group_idx).cloned();
+
+    if let Some(group_name) = group_name_opt {
+        if let Some(group) = app.proxies.get(&group_name) {
+            if group.all.is_some() {
+                let visible = app.visible_proxies();
+                let slowest = visible
+                    .iter()
+                    .filter_map(|name| app.proxy_latency.get(name).copied().flatten())
+                    .max()
+                    .unwrap_or(0);
+                let rows: Vec<Row> = visible
+                    .iter()
+                    .map(|name| {
+                        let mut style = Style::default();
+                        let is_active = group.now.as_ref() == Some(name);
+                        if is_active {
+                            style = style.fg(theme.success);
+                        }
+                        let marker = if app.is_switch_pending(&group_name, name) {
+                            format!("{} ", spinner_frame())
+                        } else if is_active {
+                            "● ".to_string()
+                        } else {
+                            "  ".to_string()
+                        };
+
+                        // Latency
+                        let latency = app.proxy_latency.get(name).copied().flatten();
+                        let (lat_str, lat_style) = if let Some(ms) = latency {
+                            if app.is_latency_stale(name) {
+                                (
+                                    format!("~{}", format_latency(app, ms, slowest)),
+                                    Style::default().fg(theme.muted).add_modifier(Modifier::DIM),
+                                )
+                            } else {
+                                let s = format_latency(app, ms, slowest);
+                                (s, Style::default().fg(latency_color(app, ms)))
+                            }
+                        } else if let Some(failure) = app.delay_failures.get(name) {
+                            (failure.label(), Style::default().fg(theme.error))
+                        } else {
+                            ("-".to_string(), Style::default().fg(theme.subtle))
+                        };
+
+                        // Smart groups show the node weight in place of the provider
+                        let origin = match app.smart_weights.get(&group_name) {
+                            Some(Ok(weights)) => weights
+                                .get(name)
+                                .map(|w| format!("w {:.2}", w))
+                                .unwrap_or_default(),
+                            Some(Err(_)) => "w ?".to_string(),
+                            None => app.proxy_origin.get(name).cloned().unwrap_or_default(),
+                        };
+
+                        let mark = match app.marked_proxies.iter().position(|p| p == name) {
+                            Some(i) => format!("  [{}]", ['A', 'B'][i]),
+                            None => String::new(),
+                        };
+
+                        // Matched characters stand out while searching
+                        let matched = app
+                            .proxy_search
+                            .as_ref()
+                            .and_then(|query| fuzzy_match(name, query))
+                            .unwrap_or_default();
+                        let mut spans = vec![Span::raw(marker)];
+                        spans.extend(name.chars().enumerate().map(|(i, c)| {
+                            if matched.contains(&i) {
+                                Span::styled(
+                                    c.to_string(),
+                                    Style::default()
+                                        .fg(theme.highlight)
+                                        .add_modifier(Modifier::UNDERLINED),
+                                )
+                            } else {
+                                Span::raw(c.to_string())
+                            }
+                        }));
+                        spans.push(Span::raw(mark));
+
+                        Row::new(vec![
+                            Cell::from(Line::from(spans)).style(style),
+                            Cell::from(origin).style(Style::default().fg(theme.muted)),
+                            Cell::from(latency_trend_line(app, name, TREND_WIDTH)),
+                            Cell::from(lat_str).style(lat_style),
+                        ])
+                    })
+                    .collect();
+
+                let table = Table::new(
+                    rows,
+                    [
+                        Constraint::Percentage(50),
+                        Constraint::Percentage(20),
+                        Constraint::Length(TREND_WIDTH as u16 + 2),
+                        Constraint::Length(10),
+                    ],
+                )
+                .block(block)
+                .row_highlight_style(
+                    Style::default()
+                        .add_modifier(Modifier::BOLD)
+                        .bg(theme.selection_bg),
+                )
+                .highlight_symbol(">> ");
+
+                f.render_stateful_widget(table, area, &mut app.proxy_state);
+            } else {
+                f.render_widget(
+                    Paragraph::new("No proxies in this group").block(block),
+                    area,
+                );
+            }
+        } else {
+            f.render_widget(Paragraph::new("Group not found").block(block), area);
+        }
+    } else {
+        f.render_widget(Paragraph::new("Select a group").block(block), area);
+    }
+}
+
+/// Shown in place of an empty list when loading it failed.
+fn draw_load_error(f: &mut Frame, theme: &Theme, block: Block, error: &str, area: Rect) {
+    let text = vec![
+        Line::from(Span::styled(
+            error.to_string(),
+            Style::default().fg(theme.error),
+        )),
+        Line::from(""),
+        Line::from(vec![
+            Span::raw("press "),
+            Span::styled(
+                " r ",
+                Style::default()
+                    .fg(theme.on_highlight)
+                    .bg(theme.highlight)
+                    .add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" to retry"),
+        ]),
+    ];
+    f.render_widget(
+        Paragraph::new(text).block(block).wrap(Wrap { trim: true }),
+        area,
+    );
+}
+
+fn draw_overview(f: &mut Frame, app: &App, area: Rect) {
+    let theme = app.theme();
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title("Overview")
+        .title(loading_title(
+            app,
+            &[Load::Config, Load::Version],
+            &[Job::Probe, Job::ApplyConfig],
+        ));
+
+    let inner_area = block.inner(area);
+    f.render_widget(block, area);
+
+    let memory_height = if app.memory.is_some() { 3 } else { 0 };
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(8),             // Info
+            Constraint::Length(3),             // Google Test
+            Constraint::Length(memory_height), // Memory
+            Constraint::Min(0),                // Charts
+        ])
+        .margin(1)
+        .split(inner_area);
+
+    // 1. Info
+    let mut info_text = vec![];
+    if let Some(version) = &app.capabilities.version {
+        let mut line = vec![
+            Span::styled("Core: ", Style::default().fg(theme.label)),
+            Span::raw(version.describe()),
+        ];
+        match (app.core_outdated(), &app.latest_core_release) {
+            (Some(true), Some(latest)) => line.push(Span::styled(
+                format!("  {} available", latest),
+                Style::default().fg(theme.highlight),
+            )),
+            (Some(false), _) => {
+                line.push(Span::styled("  latest", Style::default().fg(theme.success)))
+            }
+            // Alpha builds have no comparable version
+            (None, Some(latest)) => line.push(Span::styled(
+                format!("  release {}", latest),
+                Style::default().fg(theme.muted),
+            )),
+            _ => {}
+        }
+        info_text.push(Line::from(line));
+    }
+    if let Some(config) = &app.config {
+        info_text.push(Line::from(vec![
+            Span::styled("Mode: ", Style::default().fg(theme.label)),
+            Span::raw(&config.mode),
+        ]));
+        info_text.push(Line::from(vec![
+            Span::styled("Mixed Port: ", Style::default().fg(theme.label)),
+            Span::raw(config.mixed_port.to_string()),
+        ]));
+        info_text.push(Line::from(vec![
+            Span::styled("TUN: ", Style::default().fg(theme.label)),
+            Span::styled(
+                if config.tun.enable {
+                    "Enabled"
+                } else {
+                    "Disabled"
+                },
+                Style::default().fg(if config.tun.enable {
+                    theme.success
+                } else {
+                    theme.error
+                }),
+            ),
+        ]));
+        if let Some(stack) = &config.tun.stack {
+            info_text.push(Line::from(vec![
+                Span::styled("TUN Stack: ", Style::default().fg(theme.muted)),
+                Span::raw(stack),
+            ]));
+        }
+        if let Some(controller) = &config.external_controller {
+            info_text.push(Line::from(vec![
+                Span::styled("Controller: ", Style::default().fg(theme.label)),
+                Span::raw(controller),
+            ]));
+        }
+        if let Some(ui) = config.external_ui.as_ref().filter(|ui| !ui.is_empty()) {
+            info_text.push(Line::from(vec![
+                Span::styled("External UI: ", Style::default().fg(theme.label)),
+                Span::raw(ui),
+                Span::styled(" (u: copy URL)", Style::default().fg(theme.muted)),
+            ]));
+        }
+    } else {
+        info_text.push(Line::from("Loading config..."));
+    }
+
+    f.render_widget(Paragraph::new(info_text), chunks[0]);
+
+    // 2. Connection Test (Latency)
+    let (latency_label, latency_color, percent) = match &app.real_latency_status {
+        crate::app::RealLatencyStatus::Pending => ("Idle".to_string(), theme.subtle, 0),
+        crate::app::RealLatencyStatus::Testing => ("Testing...".to_string(), theme.highlight, 0),
+        crate::app::RealLatencyStatus::Success(ms) => (
+            format!("{} ms", ms),
+            latency_color(app, *ms),
+            app.latency_gauge_percent(*ms),
+        ),
+        crate::app::RealLatencyStatus::Failed(msg) => (format!("Err: {}", msg), theme.error, 100),
+    };
+
+    let gauge = Gauge::default()
+        .block(Block::default().title("Test Latency").borders(Borders::ALL))
+        .gauge_style(Style::default().fg(latency_color))
+        .percent(percent)
+        .label(latency_label);
+
+    f.render_widget(gauge, chunks[1]);
+
+    // 3. Core memory
+    if let Some(memory) = app.memory {
+        let (label, ratio) = if memory.oslimit > 0 {
+            (
+                format!(
+                    "{} / {}",
+                    format_speed(app, memory.inuse),
+                    format_speed(app, memory.oslimit)
+                ),
+                (memory.inuse as f64 / memory.oslimit as f64).min(1.0),
+            )
+        } else {
+            (
+                format!("{} (no OS limit)", format_speed(app, memory.inuse)),
+                0.0,
+            )
+        };
+        let color = if ratio >= 0.9 {
+            theme.error
+        } else if ratio >= 0.7 {
+            theme.highlight
+        } else {
+            theme.info
+        };
+        let gauge = Gauge::default()
+            .block(Block::default().title("Core Memory").borders(Borders::ALL))
+            .gauge_style(Style::default().fg(color))
+            .ratio(ratio)
+            .label(label);
+        f.render_widget(gauge, chunks[2]);
+    }
+
+    // 4. Charts (Sparklines)
+    let chart_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage(50),
+            Constraint::Length(1), // Event markers
+            Constraint::Percentage(50),
+            Constraint::Length(1), // Legend
+        ])
+        .split(chunks[3]);
+
+    let width = chart_chunks[0].width.saturating_sub(2) as usize;
+
+    // Download
+    let down_data: Vec<u64> = app
+        .traffic_history_down
+        .iter()
+        .rev()
+        .take(width)
+        .rev()
+        .cloned()
+        .collect();
+    let down_title = format!(
+        "Download: {}/s{}",
+        format_speed(app, app.current_down),
+        window_stats(app, &down_data)
+    );
+    let down_sparkline = Sparkline::default()
+        .block(
+            Block::default()
+                .title(down_title)
+                .borders(Borders::TOP | Borders::LEFT | Borders::RIGHT),
+        )
+        .data(&down_data)
+        .style(Style::default().fg(theme.success));
+    f.render_widget(down_sparkline, chart_chunks[0]);
+
+    // Upload
+    let up_data: Vec<u64> = app
+        .traffic_history_up
+        .iter()
+        .rev()
+        .take(width)
+        .rev()
+        .cloned()
+        .collect();
+    let up_title = format!(
+        "Upload: {}/s{}",
+        format_speed(app, app.current_up),
+        window_stats(app, &up_data)
+    );
+    let up_sparkline = Sparkline::default()
+        .block(
+            Block::default()
+                .title(up_title)
+                .borders(Borders::BOTTOM | Borders::LEFT | Borders::RIGHT),
+        )
+        .data(&up_data)
+        .style(Style::default().fg(theme.highlight));
+    f.render_widget(up_sparkline, chart_chunks[2]);
+
+    // Event markers line up with the newest samples at the right edge
+    let mut markers: Vec<Span> = vec![Span::raw(" "); width];
+    for event in &app.traffic_events {
+        let age = app.traffic_samples.saturating_sub(event.sample) as usize;
+        if let Some(col) = width.checked_sub(age + 1) {
+            let (symbol, color) = event_marker(theme, event.kind);
+            markers[col] = Span::styled(symbol, Style::default().fg(color));
+        }
+    }
+    markers.insert(0, Span::raw("│"));
+    markers.push(Span::raw("│"));
+    f.render_widget(Paragraph::new(Line::from(markers)), chart_chunks[1]);
+
+    let mut legend = Vec::new();
+    for kind in [
+        TrafficEventKind::ProxySwitch,
+        TrafficEventKind::ModeChange,
+        TrafficEventKind::CoreRestart,
+        TrafficEventKind::Reconnect,
+        TrafficEventKind::LatencyAlert,
+    ] {
+        let (symbol, color) = event_marker(theme, kind);
+        legend.push(Span::styled(symbol, Style::default().fg(color)));
+        legend.push(Span::styled(
+            format!(" {}  ", event_label(kind)),
+            Style::default().fg(theme.muted),
+        ));
+    }
+    if let Some(last) = app.traffic_events.back() {
+        legend.push(Span::styled(
+            format!("last: {}", last.label),
+            Style::default().fg(theme.subtle),
+        ));
+    }
+    f.render_widget(Paragraph::new(Line::from(legend)), chart_chunks[3]);
+}
+
+/// Peak and average over the samples actually received in the visible window,
+/// ignoring the zero padding the history starts with.
+fn window_stats(app: &App, data: &[u64]) -> String {
+    let received = app.traffic_samples;
+    let samples = &data[data.len().saturating_sub(received as usize)..];
+    let Some(peak) = samples.iter().max() else {
+        return String::new();
+    };
+    let average = samples.iter().sum::<u64>() / samples.len() as u64;
+    format!(
+        "  peak {}/s  avg {}/s",
+        format_speed(app, *peak),
+        format_speed(app, average)
+    )
+}
+
+fn event_marker(theme: &Theme, kind: TrafficEventKind) -> (&'static str, Color) {
+    match kind {
+        TrafficEventKind::ProxySwitch => ("▲", theme.info),
+        TrafficEventKind::ModeChange => ("◆", theme.marker),
+        TrafficEventKind::CoreRestart => ("✖", theme.error),
+        TrafficEventKind::Reconnect => ("○", theme.muted),
+        TrafficEventKind::LatencyAlert => ("!", theme.highlight),
+    }
+}
+
+fn event_label(kind: TrafficEventKind) -> &'static str {
+    match kind {
+        TrafficEventKind::ProxySwitch => "switch",
+        TrafficEventKind::ModeChange => "mode",
+        TrafficEventKind::CoreRestart => "restart",
+        TrafficEventKind::Reconnect => "reconnect",
+        TrafficEventKind::LatencyAlert => "latency",
+    }
+}
+
+fn draw_connections(f: &mut Frame, app: &mut App, area: Rect) {
+    let theme = app.theme();
+    let visible = app.visible_connections();
+
+    let mut title = vec![Span::raw(format!(
+        "Connections ({}/{}) ",
+        visible.len(),
+        app.connections.len()
+    ))];
+    if app.connections_live.load(Ordering::Relaxed) {
+        title.push(Span::styled("● live ", Style::default().fg(theme.success)));
+    } else {
+        title.push(Span::styled("polling ", Style::default().fg(theme.muted)));
+    }
+    title.push(Span::styled(
+        format!("cols: {} ", app.current_connection_columns().0),
+        Style::default().fg(theme.info),
+    ));
+    for (i, filter) in app
+        .app_settings
+        .connection_filters
+        .iter()
+        .enumerate()
+        .take(9)
+    {
+        let style = if app.active_connection_filters.contains(&i) {
+            Style::default().fg(theme.on_highlight).bg(theme.highlight)
+        } else {
+            Style::default().fg(theme.muted)
+        };
+        title.push(Span::styled(format!("[F{}:{}]", i + 1, filter.name), style));
+        title.push(Span::raw(" "));
+    }
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(Line::from(title))
+        .title(loading_title(
+            app,
+            &[Load::Connections],
+            &[Job::CloseConnections],
+        ))
+        .title_bottom(format!(
+            " Total ↓ {} ↑ {} ",
+            format_speed(app, app.download_total),
+            format_speed(app, app.upload_total)
+        ))
+        .border_style(Style::default().fg(app.accent()));
+
+    if let Some(error) = app
+        .load_errors
+        .get(&Panel::Connections)
+        .filter(|_| app.connections.is_empty())
+    {
+        draw_load_error(f, theme, block, error, area);
+        return;
+    }
+
+    let columns = app.current_connection_columns().1;
+    let header = Row::new(columns.iter().map(|column| column.title())).style(
+        Style::default()
+            .fg(theme.highlight)
+            .add_modifier(Modifier::BOLD),
+    );
+
+    let rows: Vec<Row> = visible
+        .iter()
+        .map(|conn| {
+            Row::new(
+                columns
+                    .iter()
+                    .map(|column| Cell::from(connection_cell(app, conn, *column))),
+            )
+        })
+        .collect();
+
+    let widths: Vec<Constraint> = columns
+        .iter()
+        .map(|column| match column {
+            ConnectionColumn::Host | ConnectionColumn::Chain | ConnectionColumn::Rule => {
+                Constraint::Fill(3)
+            }
+            ConnectionColumn::Process | ConnectionColumn::Sniff | ConnectionColumn::Source => {
+                Constraint::Fill(2)
+            }
+            ConnectionColumn::Inbound => Constraint::Fill(1),
+            ConnectionColumn::Network => Constraint::Length(4),
+            ConnectionColumn::Type => Constraint::Length(8),
+            ConnectionColumn::DownloadSpeed | ConnectionColumn::UploadSpeed => {
+                Constraint::Length(12)
+            }
+            ConnectionColumn::Download | ConnectionColumn::Upload => Constraint::Length(10),
+            ConnectionColumn::Time => Constraint::Length(7),
+        })
+        .collect();
+
+    let table = Table::new(rows, widths)
+        .header(header)
+        .block(block)
+        .row_highlight_style(
+            Style::default()
+                .add_modifier(Modifier::BOLD)
+                .bg(theme.selection_bg),
+        )
+        .highlight_symbol(">> ");
+
+    f.render_stateful_widget(table, area, &mut app.connections_state);
+}
+
+fn connection_cell(app: &App, conn: &Connection, column: ConnectionColumn) -> String {
+    let rate = |pick: fn(&(u64, u64)) -> u64| {
+        app.connection_rates
+            .get(&conn.id)
+            .map(|rates| format!("{}/s", format_speed(app, pick(rates))))
+            .unwrap_or_default()
+    };
+    match column {
+        ConnectionColumn::Host => conn.target(),
+        ConnectionColumn::Process => conn.metadata.process.clone(),
+        ConnectionColumn::Network => conn.metadata.network.clone(),
+        ConnectionColumn::Chain => conn.chain_text(),
+        ConnectionColumn::Rule => conn.rule_text(),
+        ConnectionColumn::Sniff => conn.metadata.sniff_host.clone(),
+        ConnectionColumn::Source if conn.metadata.source_ip.is_empty() => String::new(),
+        ConnectionColumn::Source => {
+            format!("{}:{}", conn.metadata.source_ip, conn.metadata.source_port)
+        }
+        ConnectionColumn::Inbound => conn.metadata.inbound_name.clone(),
+        ConnectionColumn::Type => conn.metadata.conn_type.clone(),
+        ConnectionColumn::DownloadSpeed => rate(|rates| rates.0),
+        ConnectionColumn::UploadSpeed => rate(|rates| rates.1),
+        ConnectionColumn::Download => format_bytes(app, conn.download),
+        ConnectionColumn::Upload => format_bytes(app, conn.upload),
+        ConnectionColumn::Time => conn.duration().map(format_duration).unwrap_or_default(),
+    }
+}
+
+fn draw_rules(f: &mut Frame, app: &mut App, area: Rect) {
+    let theme = app.theme();
+    let visible = app.visible_rules();
+
+    let mut title = format!("Rules ({}/{})", visible.len(), app.rules.len());
+    if !app.rules_query.is_empty() {
+        title.push_str(&format!(" filter: {}", app.rules_query));
+    }
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(title)
+        .title(loading_title(app, &[Load::Rules], &[Job::OpenProvider]))
+        .border_style(Style::default().fg(app.accent()));
+
+    if let Some(error) = app
+        .load_errors
+        .get(&Panel::Rules)
+        .filter(|_| app.rules.is_empty())
+    {
+        draw_load_error(f, theme, block, error, area);
+        return;
+    }
+
+    let header = Row::new(vec!["#", "Type", "Payload", "Proxy"]).style(
+        Style::default()
+            .fg(theme.highlight)
+            .add_modifier(Modifier::BOLD),
+    );
+
+    let rows: Vec<Row> = visible
+        .iter()
+        .map(|rule| {
+            let payload_style = if rule.rule_type.eq_ignore_ascii_case("RuleSet") {
+                Style::default().fg(theme.info)
+            } else {
+                Style::default()
+            };
+            Row::new(vec![
+                Cell::from((rule.index + 1).to_string()).style(Style::default().fg(theme.muted)),
+                Cell::from(rule.rule_type.clone()),
+                Cell::from(rule.payload.clone()).style(payload_style),
+                Cell::from(rule.proxy.clone()),
+            ])
+        })
+        .collect();
+
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(5),
+            Constraint::Length(16),
+            Constraint::Percentage(60),
+            Constraint::Percentage(30),
+        ],
+    )
+    .header(header)
+    .block(block)
+    .row_highlight_style(
+        Style::default()
+            .add_modifier(Modifier::BOLD)
+            .bg(theme.selection_bg),
+    )
+    .highlight_symbol(">> ");
+
+    f.render_stateful_widget(table, area, &mut app.rules_state);
+}
+
+/// The core's log, newest at the bottom; it follows new lines until one is
+/// selected.
+fn draw_logs(f: &mut Frame, app: &mut App, area: Rect) {
+    let theme = app.theme();
+    let mut title = vec![Span::raw(format!(
+        "Logs ({}, {} and above) ",
+        app.logs.len(),
+        LOG_LEVELS[app.logs_level]
+    ))];
+    if app.logs_paused {
+        let mut paused = format!("paused, {} new", app.logs_held.len());
+        if app.logs_dropped > 0 {
+            paused.push_str(&format!(", {} dropped", app.logs_dropped));
+        }
+        title.push(Span::styled(
+            paused + " ",
+            Style::default().fg(theme.highlight),
+        ));
+    } else if app.logs_state.selected().is_some() {
+        title.push(Span::styled(
+            "scrolled ",
+            Style::default().fg(theme.highlight),
+        ));
+    }
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(Line::from(title))
+        .border_style(Style::default().fg(app.accent()));
+
+    if app.logs.is_empty() {
+        let text = if app.capabilities.supports("/logs") {
+            "Waiting for the core to log something"
+        } else {
+            "The core has no /logs API"
+        };
+        let p = Paragraph::new(Span::styled(text, Style::default().fg(theme.muted))).block(block);
+        f.render_widget(p, area);
+        return;
+    }
+
+    let items: Vec<ListItem> = app
+        .logs
+        .iter()
+        .map(|line| {
+            let color = match line.level.as_str() {
+                "debug" => theme.muted,
+                "warning" => theme.highlight,
+                "error" => theme.error,
+                _ => theme.info,
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(format!("{:<8}", line.level), Style::default().fg(color)),
+                Span::raw(line.payload.clone()),
+            ]))
+        })
+        .collect();
+    // Following draws as if the newest line were selected, without marking it
+    let following = app.logs_state.selected().is_none();
+    let (mut state, highlight) = if following {
+        let state = ListState::default().with_selected(Some(app.logs.len() - 1));
+        (state, Style::default())
+    } else {
+        (
+            app.logs_state.clone(),
+            Style::default().bg(theme.selection_bg),
+        )
+    };
+    let list = List::new(items).block(block).highlight_style(highlight);
+    f.render_stateful_widget(list, area, &mut state);
+    if !following {
+        app.logs_state = state;
+    }
+}
+
+fn draw_rule_providers(f: &mut Frame, app: &mut App, area: Rect) {
+    let theme = app.theme();
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+        .split(area);
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!("Rule Providers ({})", app.rule_providers.len()))
+        .title(loading_title(app, &[Load::RuleProviders], &[]))
+        .border_style(Style::default().fg(app.accent()));
+
+    if let Some(error) = app
+        .load_errors
+        .get(&Panel::RuleProviders)
+        .filter(|_| app.rule_providers.is_empty())
+    {
+        draw_load_error(f, theme, block, error, chunks[0]);
+        draw_rule_provider_preview(f, app, chunks[1]);
+        return;
+    }
+
+    let header = Row::new(vec!["Name", "Behavior", "Vehicle", "Rules", "Updated"]).style(
+        Style::default()
+            .fg(theme.highlight)
+            .add_modifier(Modifier::BOLD),
+    );
+
+    let rows: Vec<Row> = app
+        .rule_providers
+        .iter()
+        .map(|provider| {
+            let updated = format_timestamp(&provider.updated_at);
+            let name = if app.rule_provider_updates.contains(&provider.name) {
+                format!("{} {}", spinner_frame(), provider.name)
+            } else {
+                provider.name.clone()
+            };
+            Row::new(vec![
+                Cell::from(name),
+                Cell::from(provider.behavior.clone()),
+                Cell::from(provider.vehicle_type.clone()),
+                Cell::from(provider.rule_count.to_string()),
+                Cell::from(updated),
+            ])
+        })
+        .collect();
+
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Percentage(30),
+            Constraint::Length(10),
+            Constraint::Length(8),
+            Constraint::Length(8),
+            Constraint::Min(19),
+        ],
+    )
+    .header(header)
+    .block(block)
+    .row_highlight_style(
+        Style::default()
+            .add_modifier(Modifier::BOLD)
+            .bg(theme.selection_bg),
+    )
+    .highlight_symbol(">> ");
+
+    f.render_stateful_widget(table, chunks[0], &mut app.rule_providers_state);
+    draw_rule_provider_preview(f, app, chunks[1]);
+}
+
+fn draw_proxy_providers(f: &mut Frame, app: &mut App, area: Rect) {
+    let theme = app.theme();
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!("Proxy Providers ({})", app.proxy_providers.len()))
+        .title(loading_title(
+            app,
+            &[Load::ProxyProviders],
+            &[Job::UpdateProviders],
+        ))
+        .border_style(Style::default().fg(app.accent()));
+
+    if let Some(error) = app
+        .load_errors
+        .get(&Panel::ProxyProviders)
+        .filter(|_| app.proxy_providers.is_empty())
+    {
+        draw_load_error(f, theme, block, error, area);
+        return;
+    }
+
+    let header = Row::new(vec![
+        "Name", "Vehicle", "Proxies", "Updated", "Usage", "Expires", "Auto", "",
+    ])
+    .style(
+        Style::default()
+            .fg(theme.highlight)
+            .add_modifier(Modifier::BOLD),
+    );
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let rows: Vec<Row> = app
+        .proxy_providers
+        .iter()
+        .map(|provider| {
+            let updated = format_timestamp(&provider.updated_at);
+            let (usage, expires) = match &provider.subscription_info {
+                Some(info) => {
+                    const GB: f64 = 1024.0 * 1024.0 * 1024.0;
+                    let used = (info.upload + info.download) as f64 / GB;
+                    let usage = if info.total > 0 {
+                        format!(
+                            "{} / {} GB",
+                            format_decimal(app, used),
+                            format_decimal(app, info.total as f64 / GB)
+                        )
+                    } else {
+                        format!("{} GB", format_decimal(app, used))
+                    };
+                    let expires = match info.expire {
+                        0 => "never".to_string(),
+                        t if t <= now => "expired".to_string(),
+                        t => format!("in {}d", (t - now) / 86400),
+                    };
+                    (usage, expires)
+                }
+                None => (String::new(), String::new()),
+            };
+            let auto = app
+                .app_settings
+                .provider_auto_update
+                .get(&provider.name)
+                .map(|hours| format!("{}h", hours))
+                .unwrap_or_default();
+            // A running action, or else how the last update went
+            let (status, status_color) = match app.provider_actions.get(&provider.name) {
+                Some(action) => (
+                    format!("{} {}", spinner_frame(), action.label()),
+                    theme.info,
+                ),
+                None => match app.provider_update_results.get(&provider.name) {
+                    Some((at, result)) => {
+                        let ago = format_duration(at.elapsed().unwrap_or_default());
+                        match result {
+                            Ok(()) => (format!("updated {} ago", ago), theme.muted),
+                            Err(_) => (format!("failed {} ago", ago), theme.error),
+                        }
+                    }
+                    None => (String::new(), theme.info),
+                },
+            };
+            Row::new(vec![
+                Cell::from(provider.name.clone()),
+                Cell::from(provider.vehicle_type.clone()),
+                Cell::from(provider.proxies.len().to_string()),
+                Cell::from(updated),
+                Cell::from(usage),
+                Cell::from(expires),
+                Cell::from(auto),
+                Cell::from(status).style(Style::default().fg(status_color)),
+            ])
+        })
+        .collect();
+
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Percentage(25),
+            Constraint::Length(8),
+            Constraint::Length(8),
+            Constraint::Length(20),
+            Constraint::Length(18),
+            Constraint::Length(8),
+            Constraint::Length(5),
+            Constraint::Min(14),
+        ],
+    )
+    .header(header)
+    .block(block)
+    .row_highlight_style(
+        Style::default()
+            .add_modifier(Modifier::BOLD)
+            .bg(theme.selection_bg),
+    )
+    .highlight_symbol(">> ");
+
+    f.render_stateful_widget(table, area, &mut app.proxy_providers_state);
+}
+
+fn draw_rule_provider_preview(f: &mut Frame, app: &App, area: Rect) {
+    let theme = app.theme();
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title("Contents")
+        .border_style(Style::default().fg(app.accent()));
+
+    let lines: Vec<Line> = match &app.rule_provider_preview {
+        None => vec![Line::from("No provider selected")],
+        Some((_, Err(reason))) => vec![
+            Line::from(Span::styled(
+                "Preview unavailable",
+                Style::default().fg(theme.muted),
+            )),
+            Line::from(reason.clone()),
+        ],
+        Some((_, Ok(preview))) => {
+            let mut lines = vec![
+                Line::from(Span::styled(
+                    preview.path.display().to_string(),
+                    Style::default().fg(theme.muted),
+                )),
+                Line::from(vec![
+                    Span::styled(
+                        format!("{} entries", preview.total),
+                        Style::default().add_modifier(Modifier::BOLD),
+                    ),
+                    Span::raw(format!(
+                        ": {} domains, {} IPs, {} other",
+                        preview.domains,
+                        preview.ips,
+                        preview.total - preview.domains - preview.ips
+                    )),
+                ]),
+                Line::default(),
+            ];
+            lines.extend(preview.entries.iter().map(|e| Line::from(e.clone())));
+            if preview.total > preview.entries.len() {
+                lines.push(Line::from(Span::styled(
+                    format!("… {} more", preview.total - preview.entries.len()),
+                    Style::default().fg(theme.muted),
+                )));
+            }
+            lines
+        }
+    };
+
+    f.render_widget(
+        Paragraph::new(lines)
+            .block(block)
+            .wrap(Wrap { trim: false }),
+        area,
+    );
+}
+
+fn draw_connection_popup(f: &mut Frame, app: &App) {
+    let theme = app.theme();
+    let area = f.area();
+    let popup_area = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage(15),
+            Constraint::Percentage(70),
+            Constraint::Percentage(15),
+        ])
+        .split(area)[1];
+
+    let popup_area = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(15),
+            Constraint::Percentage(70),
+            Constraint::Percentage(15),
+        ])
+        .split(popup_area)[1];
+
+    f.render_widget(Clear, popup_area);
+
+    let block = Block::default()
+        .title(" Connection Detail ")
+        .title_alignment(ratatui::layout::Alignment::Center)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.info))
+        .style(Style::default().bg(theme.popup_bg));
+    let inner_area = block.inner(popup_area);
+    f.render_widget(block, popup_area);
+
+    let Some(conn) = app.detail_connection() else {
+        f.render_widget(Paragraph::new("Connection closed"), inner_area);
+        return;
+    };
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(9), Constraint::Min(0)])
+        .split(inner_area);
+
+    let field = |label: &'static str, value: String| {
+        Line::from(vec![
+            Span::styled(format!("{:<10}", label), Style::default().fg(theme.label)),
+            Span::raw(value),
+        ])
+    };
+    let text = vec![
+        field("Target", conn.target()),
+        field(
+            "Process",
+            if conn.metadata.process_path.is_empty() {
+                conn.metadata.process.clone()
+            } else {
+                conn.metadata.process_path.clone()
+            },
+        ),
+        field("Network", conn.metadata.network.clone()),
+        field(
+            "Chain",
+            conn.chains
+                .iter()
+                .rev()
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(" > "),
+        ),
+        field("Rule", format!("{} {}", conn.rule, conn.rule_payload)),
+        field("Started", format_timestamp(&conn.start)),
+        field(
+            "Total",
+            format!(
+                "↓ {}  ↑ {}",
+                format_speed(app, conn.download),
+                format_speed(app, conn.upload)
+            ),
+        ),
+    ];
+    f.render_widget(Paragraph::new(text), chunks[0]);
+
+    let chart_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(chunks[1]);
+    let width = chart_chunks[0].width.saturating_sub(2) as usize;
+
+    for (i, (label, history, color)) in [
+        ("Download", &app.connection_rate_down, theme.success),
+        ("Upload", &app.connection_rate_up, theme.highlight),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let current = history.back().copied().unwrap_or(0);
+        let data: Vec<u64> = history.iter().rev().take(width).rev().cloned().collect();
+        let sparkline = Sparkline::default()
+            .block(
+                Block::default()
+                    .title(format!("{}: {}/s", label, format_speed(app, current)))
+                    .borders(Borders::TOP),
+            )
+            .data(&data)
+            .style(Style::default().fg(color));
+        f.render_widget(sparkline, chart_chunks[i]);
+    }
+}
+
+/// Braille spinner driven by wall-clock time, so it animates at the redraw rate.
+fn spinner_frame() -> char {
+    const FRAMES: [char; 8] = ['⣾', '⣽', '⣻', '⢿', '⡿', '⣟', '⣯', '⣷'];
+    let millis = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    FRAMES[(millis / 100 % FRAMES.len() as u128) as usize]
+}
+
+/// A spinner at the right of a panel's title while any of `loads` or `jobs` runs.
+fn loading_title(app: &App, loads: &[Load], jobs: &[Job]) -> Line<'static> {
+    if loads.iter().any(|load| app.loading.contains(load))
+        || jobs.iter().any(|job| app.jobs.contains(job))
+    {
+        Line::from(format!(" {} ", spinner_frame())).right_aligned()
+    } else {
+        Line::default()
+    }
+}
+
+fn latency_color(app: &App, ms: u64) -> Color {
+    let theme = app.theme();
+    match app.latency_tier(ms) {
+        LatencyTier::Fast => theme.latency_fast,
+        LatencyTier::Medium => theme.latency_medium,
+        LatencyTier::Slow => theme.latency_slow,
+    }
+}
+
+/// Renders a delay in the user's chosen format; bars are scaled to `slowest`.
+fn format_latency(app: &App, ms: u64, slowest: u64) -> String {
+    match app.app_settings.latency_format {
+        LatencyFormat::Ms => {
+            let step = app.app_settings.latency_precision.max(1);
+            format!("{} ms", (ms + step / 2) / step * step)
+        }
+        LatencyFormat::Bar => {
+            const WIDTH: u64 = 8;
+            let filled = (ms * WIDTH)
+                .div_ceil(slowest.max(ms).max(1))
+                .clamp(1, WIDTH);
+            format!(
+                "{}{}",
+                "█".repeat(filled as usize),
+                "░".repeat((WIDTH - filled) as usize)
+            )
+        }
+        LatencyFormat::Grade => match app.latency_tier(ms) {
+            LatencyTier::Fast => "A",
+            LatencyTier::Medium => "B",
+            LatencyTier::Slow => "C",
+        }
+        .to_string(),
+    }
+}
+
+/// Recent results shown in the trend column.
+const TREND_WIDTH: usize = 8;
+
+/// Results in the sparkline next to each group, which has less room.
+const GROUP_TREND_WIDTH: usize = 5;
+
+/// A sparkline of a proxy's recent delays, failures as a red `×`, followed by
+/// an arrow for where the latest result went.
+fn latency_trend_line(app: &App, name: &str, width: usize) -> Line<'static> {
+    let theme = app.theme();
+    const LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let Some(history) = app.latency_history.get(name) else {
+        return Line::default();
+    };
+    let recent: Vec<u64> = history
+        .iter()
+        .skip(history.len().saturating_sub(width))
+        .map(|(_, delay)| *delay)
+        .collect();
+    let slowest = recent.iter().copied().max().unwrap_or(0).max(1);
+    let mut spans: Vec<Span> = recent
+        .iter()
+        .map(|&delay| match delay {
+            0 => Span::styled("×", Style::default().fg(theme.error)),
+            ms => {
+                let level = ((ms * 7).div_ceil(slowest) as usize).min(7);
+                Span::styled(LEVELS[level].to_string(), Style::default().fg(theme.muted))
+            }
+        })
+        .collect();
+    let arrow = match app.latency_trend(name) {
+        Some(LatencyTrend::Slower) => Span::styled(" ↑", Style::default().fg(theme.error)),
+        Some(LatencyTrend::Faster) => Span::styled(" ↓", Style::default().fg(theme.success)),
+        Some(LatencyTrend::Steady) => Span::styled(" →", Style::default().fg(theme.subtle)),
+        Some(LatencyTrend::Flaky) => Span::styled(" !", Style::default().fg(theme.highlight)),
+        None => Span::raw(""),
+    };
+    spans.push(arrow);
+    Line::from(spans)
+}
+
+/// The core's version, or how the last `kind` operation went while it's
+/// the latest one.
+fn core_operation_text(app: &App, kind: CoreOperationKind) -> String {
+    let version = app
+        .capabilities
+        .version
+        .as_ref()
+        .map_or("unknown version".to_string(), |v| v.version.clone());
+    let Some(op) = app.core_operation.as_ref().filter(|op| op.kind == kind) else {
+        return version;
+    };
+    let elapsed = format_duration(op.started.elapsed());
+    match &op.status {
+        CoreOperationStatus::Sending => match kind {
+            CoreOperationKind::Restart => format!("requesting... ({})", elapsed),
+            CoreOperationKind::Upgrade => format!("downloading... ({})", elapsed),
+        },
+        CoreOperationStatus::Waiting { went_down: false } => {
+            format!("waiting for the core ({})", elapsed)
+        }
+        CoreOperationStatus::Waiting { went_down: true } => {
+            format!("core down, waiting ({})", elapsed)
+        }
+        CoreOperationStatus::Done { version, took } => {
+            format!("{} (back after {})", version, format_duration(*took))
+        }
+        CoreOperationStatus::Failed(e) => format!("failed: {}", e),
+    }
+}
+
+/// Compact elapsed time: `42s`, `3m05s`, `2h07m`.
+fn format_duration(duration: std::time::Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..60 => format!("{}s", secs),
+        60..3600 => format!("{}m{:02}s", secs / 60, secs % 60),
+        _ => format!("{}h{:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+fn format_speed(app: &App, bytes: u64) -> String {
+    if bytes < 1024 {
+        format!("{} B", bytes)
+    } else if bytes < 1024 * 1024 {
+        format!("{} KB", format_decimal(app, bytes as f64 / 1024.0))
+    } else {
+        format!(
+            "{} MB",
+            format_decimal(app, bytes as f64 / (1024.0 * 1024.0))
+        )
+    }
+}
+
+/// Amounts of data, up to gigabytes.
+fn format_bytes(app: &App, bytes: u64) -> String {
+    crate::app::format_bytes(bytes, app.decimal_separator)
+}
+
+/// One decimal place, with the locale's decimal separator.
+fn format_decimal(app: &App, value: f64) -> String {
+    format!("{:.1}", value).replace('.', &app.decimal_separator.to_string())
+}
+
+/// Trims an RFC 3339 timestamp to `YYYY-MM-DD HH:MM:SS`.
+fn format_timestamp(text: &str) -> String {
+    text.get(..19)
+        .map(|t| t.replace('T', " "))
+        .unwrap_or_else(|| text.to_string())
+}
+
+fn draw_settings(f: &mut Frame, app: &mut App) {
+    let theme = app.theme();
+    let area = f.area();
+    // Center a 70% x 50% block
+    let popup_area = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage(25),
+            Constraint::Percentage(50),
+            Constraint::Percentage(25),
+        ])
+        .split(area)[1];
+
+    let popup_area = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(15),
+            Constraint::Percentage(70),
+            Constraint::Percentage(15),
+        ])
+        .split(popup_area)[1];
+
+    f.render_widget(Clear, popup_area);
+
+    let title = if app.staged_config.is_empty() {
+        " Configuration ".to_string()
+    } else {
+        format!(
+            " Configuration ({} pending — a: review & apply, D: discard) ",
+            app.staged_changes().len()
+        )
+    };
+    let block = Block::default()
+        .title(title)
+        .title_alignment(ratatui::layout::Alignment::Center)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.info).add_modifier(Modifier::BOLD))
+        .style(Style::default().bg(theme.popup_bg));
+
+    let header_style = Style::default()
+        .fg(theme.highlight)
+        .add_modifier(Modifier::BOLD)
+        .bg(theme.selection_bg);
+
+    let selected_style = Style::default()
+        .add_modifier(Modifier::REVERSED)
+        .fg(theme.info);
+
+    let header = Row::new(vec!["Setting", "Current Value", "Action"])
+        .style(header_style)
+        .height(1)
+        .bottom_margin(1);
+
+    // Show staged edits in place of the values loaded from the core
+    let config = app.effective_config();
+    let rows: Vec<Row> = app
+        .settings_entries()
+        .iter()
+        .map(|item| {
+            let (label, value, mut action) = match item {
+                ConfigEntry::BaseUrl => {
+                    ("App: Base URL", app.app_settings.base_url.clone(), "Edit")
+                }
+                ConfigEntry::ApiSecret => (
+                    "App: API Secret",
+                    if app.app_settings.api_secret.is_empty() {
+                        "<none>".to_string()
+                    } else {
+                        "******".to_string()
+                    },
+                    "Edit",
+                ),
+                ConfigEntry::TestUrl => {
+                    ("App: Test URL", app.app_settings.test_url.clone(), "Edit")
+                }
+                ConfigEntry::TestTimeout => (
+                    "App: Test Timeout (ms)",
+                    app.app_settings.test_timeout.to_string(),
+                    "Edit",
+                ),
+                ConfigEntry::DelayTestConcurrency => (
+                    "App: Delay Test Parallelism",
+                    app.app_settings.delay_test_concurrency.to_string(),
+                    "Edit",
+                ),
+                ConfigEntry::DelayTestTimeout => (
+                    "App: Delay Test Timeout (ms)",
+                    app.app_settings.delay_test_timeout.to_string(),
+                    "Edit",
+                ),
+                ConfigEntry::LatencyMaxAge => (
+                    "App: Delay Results Stale After (s)",
+                    app.app_settings.latency_max_age.to_string(),
+                    "Edit (0: never)",
+                ),
+                ConfigEntry::AutoRefresh => (
+                    "App: Auto-refresh Every (s)",
+                    app.app_settings.auto_refresh.to_string(),
+                    "Edit (0: off)",
+                ),
+                ConfigEntry::AutoRetestStale => (
+                    "App: Re-test Stale Results",
+                    if app.app_settings.auto_retest_stale {
+                        "Enabled"
+                    } else {
+                        "Disabled"
+                    }
+                    .to_string(),
+                    "Toggle",
+                ),
+                ConfigEntry::AutoTestGroups => (
+                    "App: Test Groups When First Selected",
+                    if app.app_settings.auto_test_groups {
+                        "Enabled"
+                    } else {
+                        "Disabled"
+                    }
+                    .to_string(),
+                    "Toggle",
+                ),
+                ConfigEntry::LatencyFormat => (
+                    "App: Delay Format",
+                    app.app_settings.latency_format.label().to_string(),
+                    "Cycle (ms/bar/grade)",
+                ),
+                ConfigEntry::Theme => (
+                    "App: Theme",
+                    app.app_settings.theme.label().to_string(),
+                    "Cycle (dark/light/gruvbox/nord)",
+                ),
+                ConfigEntry::Layout => (
+                    "App: Layout",
+                    app.app_settings.layout.label().to_string(),
+                    "Cycle (classic/wide)",
+                ),
+                ConfigEntry::LatencyPrecision => (
+                    "App: Delay Rounding (ms)",
+                    app.app_settings.latency_precision.to_string(),
+                    "Edit",
+                ),
+                ConfigEntry::LatencyGood => (
+                    "App: Fast Delay Below (ms)",
+                    app.app_settings.latency_good.to_string(),
+                    "Edit",
+                ),
+                ConfigEntry::LatencyMedium => (
+                    "App: Medium Delay Below (ms)",
+                    app.app_settings.latency_medium.to_string(),
+                    "Edit",
+                ),
+                ConfigEntry::LatencyGaugeMin => (
+                    "App: Test Gauge Full At (ms)",
+                    app.app_settings.latency_gauge_min.to_string(),
+                    "Edit",
+                ),
+                ConfigEntry::LatencyGaugeMax => (
+                    "App: Test Gauge Empty At (ms)",
+                    app.app_settings.latency_gauge_max.to_string(),
+                    "Edit",
+                ),
+                ConfigEntry::Accent => (
+                    "App: Accent (this controller)",
+                    app.accent().to_string(),
+                    "Cycle",
+                ),
+                ConfigEntry::StartupActions => (
+                    "App: Startup Actions",
+                    app.startup_actions_text(),
+                    "Edit (comma-separated or none)",
+                ),
+                ConfigEntry::OnStart => (
+                    "App: On Start",
+                    app.on_start_text(),
+                    "Edit (step or step:argument, comma-separated)",
+                ),
+                ConfigEntry::LeaderKey => (
+                    "App: Leader Key",
+                    leader_key_name(app.app_settings.leader_key),
+                    "Edit (one key or space)",
+                ),
+                ConfigEntry::LeaderChords => (
+                    "App: Leader Chords",
+                    app.leader_chords_text(),
+                    "Edit (key=action, comma-separated)",
+                ),
+                ConfigEntry::LowPower => {
+                    let mode = app.app_settings.low_power;
+                    let state = if app.low_power_active() {
+                        "active"
+                    } else {
+                        "inactive"
+                    };
+                    (
+                        "App: Low-Power Mode",
+                        format!("{} ({})", mode.label(), state),
+                        "Cycle (Auto/On/Off)",
+                    )
+                }
+                ConfigEntry::Alerts => (
+                    "App: Critical Alerts",
+                    app.app_settings.alerts.label().to_string(),
+                    "Cycle (Off/Bell/Flash/Both)",
+                ),
+                ConfigEntry::Mode => {
+                    let val = config
+                        .as_ref()
+                        .map(|c| c.mode.as_str())
+                        .unwrap_or("Unknown");
+                    ("Mode", val.to_string(), "Cycle (Rule/Global/Direct)")
+                }
+                ConfigEntry::Tun => {
+                    let val = config.as_ref().map(|c| c.tun.enable).unwrap_or(false);
+                    (
+                        "TUN Mode",
+                        if val { "Enabled" } else { "Disabled" }.to_string(),
+                        "Toggle",
+                    )
+                }
+                ConfigEntry::MixedPort => {
+                    let val = config.as_ref().map(|c| c.mixed_port).unwrap_or(0);
+                    ("Mixed Port", val.to_string(), "Edit")
+                }
+                ConfigEntry::LogLevel => {
+                    let val = config
+                        .as_ref()
+                        .map(|c| c.log_level.as_str())
+                        .unwrap_or("info");
+                    ("Log Level", val.to_string(), "Cycle")
+                }
+                ConfigEntry::AllowLan => {
+                    let val = config.as_ref().map(|c| c.allow_lan).unwrap_or(false);
+                    (
+                        "Allow LAN",
+                        if val { "True" } else { "False" }.to_string(),
+                        "Toggle",
+                    )
+                }
+                ConfigEntry::Authentication => {
+                    // Only user names; passwords show up when editing
+                    let users: Vec<&str> = config
+                        .iter()
+                        .flat_map(|c| &c.authentication)
+                        .map(|user| user.split_once(':').map_or(user.as_str(), |(name, _)| name))
+                        .collect();
+                    let val = if users.is_empty() {
+                        "None: LAN clients need no password".to_string()
+                    } else {
+                        users.join(", ")
+                    };
+                    (
+                        "LAN Authentication",
+                        val,
+                        "Edit (user:pass, comma-separated)",
+                    )
+                }
+                ConfigEntry::SkipAuthPrefixes => {
+                    let val = config
+                        .as_ref()
+                        .map(|c| c.skip_auth_prefixes.join(", "))
+                        .filter(|val| !val.is_empty())
+                        .unwrap_or_else(|| "none".to_string());
+                    (
+                        "LAN Skip-Auth Prefixes",
+                        val,
+                        "Edit (CIDRs, comma-separated)",
+                    )
+                }
+                ConfigEntry::BindAddress => {
+                    let val = config
+                        .as_ref()
+                        .map(|c| c.bind_address.as_str())
+                        .unwrap_or("*");
+                    ("Bind Address", val.to_string(), "Edit")
+                }
+                ConfigEntry::Ipv6 => {
+                    let val = config.as_ref().map(|c| c.ipv6).unwrap_or(false);
+                    (
+                        "IPv6",
+                        if val { "Enabled" } else { "Disabled" }.to_string(),
+                        "Toggle",
+                    )
+                }
+                ConfigEntry::Hosts => {
+                    let count = config.as_ref().map(|c| c.hosts.len()).unwrap_or(0);
+                    ("DNS Hosts", format!("{} entries", count), "Manage")
+                }
+                ConfigEntry::DnsQuery => {
+                    let servers = config
+                        .as_ref()
+                        .and_then(|c| c.dns.as_ref())
+                        .filter(|dns| dns.enable && !dns.nameserver.is_empty())
+                        .map_or_else(|| "-".to_string(), |dns| dns.nameserver.join(", "));
+                    ("DNS: Query Tool", servers, "Open")
+                }
+                ConfigEntry::CoreRestart => (
+                    "Core: Restart",
+                    core_operation_text(app, CoreOperationKind::Restart),
+                    "Restart (asks first)",
+                ),
+                ConfigEntry::CoreUpgrade => (
+                    "Core: Upgrade",
+                    core_operation_text(app, CoreOperationKind::Upgrade),
+                    "Upgrade (asks first)",
+                ),
+            };
+            let is_core_entry = !matches!(
+                item,
+                ConfigEntry::BaseUrl
+                    | ConfigEntry::ApiSecret
+                    | ConfigEntry::TestUrl
+                    | ConfigEntry::TestTimeout
+                    | ConfigEntry::DelayTestConcurrency
+                    | ConfigEntry::DelayTestTimeout
+                    | ConfigEntry::LatencyMaxAge
+                    | ConfigEntry::AutoRefresh
+                    | ConfigEntry::AutoRetestStale
+                    | ConfigEntry::AutoTestGroups
+                    | ConfigEntry::LatencyFormat
+                    | ConfigEntry::LatencyPrecision
+                    | ConfigEntry::LatencyGood
+                    | ConfigEntry::LatencyMedium
+                    | ConfigEntry::LatencyGaugeMin
+                    | ConfigEntry::LatencyGaugeMax
+                    | ConfigEntry::Layout
+                    | ConfigEntry::Theme
+                    | ConfigEntry::StartupActions
+                    | ConfigEntry::OnStart
+                    | ConfigEntry::LeaderKey
+                    | ConfigEntry::LeaderChords
+                    | ConfigEntry::LowPower
+                    | ConfigEntry::Alerts
+                    | ConfigEntry::Accent
+                    | ConfigEntry::DnsQuery
+                    | ConfigEntry::CoreRestart
+                    | ConfigEntry::CoreUpgrade
+            );
+            if is_core_entry && !app.capabilities.supports("/configs") {
+                action = "Unsupported by core";
+            }
+            let value = match item.config_key() {
+                Some(key) if app.staged_config.contains_key(key) => format!("{} *", value),
+                _ => value,
+            };
+            // Open LAN access without a password is worth flagging
+            let value_color = match item {
+                ConfigEntry::Authentication
+                    if config.as_ref().is_some_and(|c| c.authentication.is_empty()) =>
+                {
+                    theme.error
+                }
+                ConfigEntry::CoreRestart | ConfigEntry::CoreUpgrade
+                    if app.core_operation.as_ref().is_some_and(|op| {
+                        matches!(op.status, CoreOperationStatus::Failed(_))
+                            && (op.kind == CoreOperationKind::Restart)
+                                == (*item == ConfigEntry::CoreRestart)
+                    }) =>
+                {
+                    theme.error
+                }
+                _ => theme.text,
+            };
+
+            Row::new(vec![
+                Cell::from(label).style(
+                    Style::default()
+                        .fg(theme.label)
+                        .add_modifier(Modifier::BOLD),
+                ),
+                Cell::from(value).style(Style::default().fg(value_color)),
+                Cell::from(action).style(
+                    Style::default()
+                        .fg(theme.subtle)
+                        .add_modifier(Modifier::ITALIC),
+                ),
+            ])
+            .height(1)
+        })
+        .collect();
+
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Percentage(30),
+            Constraint::Percentage(30),
+            Constraint::Percentage(40),
+        ],
+    )
+    .header(header)
+    .block(block)
+    .row_highlight_style(selected_style)
+    .highlight_symbol(">> ");
+
+    f.render_stateful_widget(table, popup_area, &mut app.settings_state);
+}
+
+fn draw_debug(f: &mut Frame, app: &mut App) {
+    let theme = app.theme();
+    let area = f.area();
+    let popup_area = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage(5),
+            Constraint::Percentage(90),
+            Constraint::Percentage(5),
+        ])
+        .split(area)[1];
+
+    let popup_area = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(5),
+            Constraint::Percentage(90),
+            Constraint::Percentage(5),
+        ])
+        .split(popup_area)[1];
+
+    f.render_widget(Clear, popup_area);
+
+    let block = Block::default()
+        .title(" API Debug Console ")
+        .title(loading_title(app, &[], &[Job::Request]))
+        .title_alignment(ratatui::layout::Alignment::Center)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.marker))
+        .style(Style::default().bg(theme.popup_bg));
+    let inner_area = block.inner(popup_area);
+    f.render_widget(block, popup_area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(40), Constraint::Percentage(60)])
+        .split(inner_area);
+
+    // Newest first
+    let entries: Vec<_> = app
+        .api_log
+        .lock()
+        .map(|log| log.iter().rev().cloned().collect())
+        .unwrap_or_default();
+
+    let header = Row::new(vec!["Method", "Status", "Time", "URL"]).style(
+        Style::default()
+            .fg(theme.highlight)
+            .add_modifier(Modifier::BOLD),
+    );
+
+    let rows: Vec<Row> = entries
+        .iter()
+        .map(|entry| {
+            let (status, color) = match &entry.status {
+                Ok(code) if entry.decode_error.is_some() => (code.to_string(), theme.highlight),
+                Ok(code) if *code < 400 => (code.to_string(), theme.success),
+                Ok(code) => (code.to_string(), theme.error),
+                Err(_) => ("ERR".to_string(), theme.error),
+            };
+            Row::new(vec![
+                Cell::from(entry.method.clone()),
+                Cell::from(status).style(Style::default().fg(color)),
+                Cell::from(format!("{} ms", entry.elapsed.as_millis())),
+                Cell::from(entry.url.clone()),
+            ])
+        })
+        .collect();
+
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(7),
+            Constraint::Length(6),
+            Constraint::Length(9),
+            Constraint::Min(0),
+        ],
+    )
+    .header(header)
+    .block(Block::default().borders(Borders::BOTTOM))
+    .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+    .highlight_symbol(">> ");
+
+    f.render_stateful_widget(table, chunks[0], &mut app.debug_state);
+
+    let mut text = vec![];
+    if let Some(entry) = app.debug_state.selected().and_then(|i| entries.get(i)) {
+        text.push(Line::from(vec![
+            Span::styled(
+                format!("{} ", entry.method),
+                Style::default().fg(theme.highlight),
+            ),
+            Span::raw(entry.url.clone()),
+        ]));
+        if let Some(body) = &entry.request_body {
+            text.push(Line::from(Span::styled(
+                "Request body:",
+                Style::default().fg(theme.label),
+            )));
+            text.push(Line::from(body.clone()));
+        }
+        match &entry.status {
+            Ok(code) => text.push(Line::from(vec![
+                Span::styled("Response: ", Style::default().fg(theme.label)),
+                Span::raw(format!("{} in {} ms", code, entry.elapsed.as_millis())),
+            ])),
+            Err(e) => text.push(Line::from(vec![
+                Span::styled("Error: ", Style::default().fg(theme.error)),
+                Span::raw(e.clone()),
+            ])),
+        }
+        if let Some(e) = &entry.decode_error {
+            text.push(Line::from(Span::styled(
+                e.clone(),
+                Style::default().fg(theme.error),
+            )));
+        }
+        for line in entry.response_body.lines() {
+            text.push(Line::from(line.to_string()));
+        }
+    } else if entries.is_empty() {
+        text.push(Line::from("No requests recorded yet. Press e to send one."));
+    } else {
+        text.push(Line::from("Select a request to inspect it."));
+    }
+
+    f.render_widget(Paragraph::new(text).wrap(Wrap { trim: false }), chunks[1]);
+}
+
+fn draw_hosts_popup(f: &mut Frame, app: &mut App) {
+    let theme = app.theme();
+    let area = f.area();
+    let popup_area = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage(20),
+            Constraint::Percentage(60),
+            Constraint::Percentage(20),
+        ])
+        .split(area)[1];
+
+    let popup_area = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(20),
+            Constraint::Percentage(60),
+            Constraint::Percentage(20),
+        ])
+        .split(popup_area)[1];
+
+    f.render_widget(Clear, popup_area);
+
+    let block = Block::default()
+        .title(" DNS Hosts ")
+        .title_alignment(ratatui::layout::Alignment::Center)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.info))
+        .style(Style::default().bg(theme.popup_bg));
+
+    let entries = app.host_entries();
+    if entries.is_empty() {
+        f.render_widget(
+            Paragraph::new("No hosts overrides. Press a to add one.").block(block),
+            popup_area,
+        );
+        return;
+    }
+
+    let items: Vec<ListItem> = entries
+        .iter()
+        .map(|(domain, target)| {
+            ListItem::new(Line::from(vec![
+                Span::styled(domain.clone(), Style::default().fg(theme.label)),
+                Span::raw(" -> "),
+                Span::raw(target.clone()),
+            ]))
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .highlight_symbol(">> ");
+
+    f.render_stateful_widget(list, popup_area, &mut app.hosts_state);
+}
+
+fn draw_profiles_popup(f: &mut Frame, app: &mut App) {
+    let theme = app.theme();
+    let area = f.area();
+    let popup_area = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage(20),
+            Constraint::Percentage(60),
+            Constraint::Percentage(20),
+        ])
+        .split(area)[1];
+
+    let popup_area = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(20),
+            Constraint::Percentage(60),
+            Constraint::Percentage(20),
+        ])
+        .split(popup_area)[1];
+
+    f.render_widget(Clear, popup_area);
+
+    let block = Block::default()
+        .title(" Servers ")
+        .title_alignment(ratatui::layout::Alignment::Center)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.info))
+        .style(Style::default().bg(theme.popup_bg));
+
+    let profiles = &app.app_settings.profiles;
+    if profiles.is_empty() {
+        f.render_widget(
+            Paragraph::new("No saved servers. Press a to save the current one.").block(block),
+            popup_area,
+        );
+        return;
+    }
+
+    let items: Vec<ListItem> = profiles
+        .iter()
+        .map(|profile| {
+            let active = profile.base_url == app.app_settings.base_url;
+            let marker = if active { "● " } else { "  " };
+            ListItem::new(Line::from(vec![
+                Span::styled(marker, Style::default().fg(theme.success)),
+                Span::styled(
+                    profile.name.clone(),
+                    Style::default().fg(app.accent_for(&profile.base_url)),
+                ),
+                Span::raw("  "),
+                Span::styled(profile.base_url.clone(), Style::default().fg(theme.muted)),
+            ]))
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .highlight_symbol(">> ");
+
+    f.render_stateful_widget(list, popup_area, &mut app.profiles_state);
+}
+
+fn draw_input_popup(f: &mut Frame, app: &mut App) {
+    let theme = app.theme();
+    let area = f.area();
+    let popup_area = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage(40),
+            Constraint::Length(3), // Input box height
+            Constraint::Percentage(40),
+        ])
+        .split(area)[1];
+
+    let popup_area = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(30),
+            Constraint::Percentage(40),
+            Constraint::Percentage(30),
+        ])
+        .split(popup_area)[1];
+
+    f.render_widget(Clear, popup_area);
+
+    let title = if app.command_palette {
+        "Command: select <group> <proxy> (Tab to Complete, Enter to Run)"
+    } else if let Focus::Debug = app.focus {
+        "Request: METHOD /path [json] (Enter to Send, Esc to Cancel)"
+    } else if let Focus::Rules = app.focus {
+        "Search Rules (Enter to Apply, Esc to Cancel)"
+    } else if let Modal::Hosts = app.modal {
+        "Host: domain=ip[,ip] (Enter to Save, Esc to Cancel)"
+    } else if let Modal::Profiles = app.modal {
+        "Server name for the current URL and secret (Enter to Save, Esc to Cancel)"
+    } else {
+        "Edit Value (Enter to Save, Esc to Cancel)"
+    };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(title)
+        .style(Style::default().bg(theme.label).fg(theme.text));
+
+    let p = Paragraph::new(app.editing_value.clone()).block(block);
+
+    f.render_widget(p, popup_area);
+}
+
+/// One line per fake-ip address: the domain it stands for, its real
+/// addresses and how many connections use it.
+fn draw_fake_ip_popup(f: &mut Frame, app: &App, map: &FakeIpMap) {
+    let theme = app.theme();
+    let area = f.area();
+    let popup_area = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage(15),
+            Constraint::Percentage(70),
+            Constraint::Percentage(15),
+        ])
+        .split(area)[1];
+    let popup_area = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(10),
+            Constraint::Percentage(80),
+            Constraint::Percentage(10),
+        ])
+        .split(popup_area)[1];
+
+    f.render_widget(Clear, popup_area);
+
+    let pending = map.entries.iter().any(|entry| entry.real.is_none());
+    let block = Block::default()
+        .title(format!(" Fake-IP Map: {} ", map.range))
+        .title(if pending {
+            Line::from(format!(" {} ", spinner_frame())).right_aligned()
+        } else {
+            Line::default()
+        })
+        .title_alignment(ratatui::layout::Alignment::Center)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.info))
+        .style(Style::default().bg(theme.popup_bg));
+
+    let mut text = Vec::new();
+    if !map.fake_ip_mode {
+        text.push(Line::from(Span::styled(
+            "The core's DNS isn't in fake-ip mode, or its config isn't loaded",
+            Style::default().fg(theme.highlight),
+        )));
+        text.push(Line::from(""));
+    }
+    if map.entries.is_empty() {
+        text.push(Line::from(Span::styled(
+            "No open connection goes to a fake-ip address",
+            Style::default().fg(theme.muted),
+        )));
+    } else {
+        text.push(Line::from(Span::styled(
+            format!(
+                "{:<18}{:<36}{:<6}{}",
+                "Fake IP", "Domain", "Conns", "Resolves to"
+            ),
+            Style::default()
+                .fg(theme.label)
+                .add_modifier(Modifier::BOLD),
+        )));
+    }
+    for entry in &map.entries {
+        let domain = if entry.domain.is_empty() {
+            Span::styled(format!("{:<36}", "?"), Style::default().fg(theme.muted))
+        } else {
+            Span::styled(
+                format!("{:<36}", entry.domain),
+                Style::default().fg(theme.text),
+            )
+        };
+        let real = match &entry.real {
+            None => Span::styled("looking up…", Style::default().fg(theme.muted)),
+            Some(Ok(addresses)) if addresses.is_empty() => {
+                Span::styled("no answer", Style::default().fg(theme.muted))
+            }
+            Some(Ok(addresses)) => {
+                Span::styled(addresses.join(", "), Style::default().fg(theme.success))
+            }
+            Some(Err(e)) => Span::styled(e.clone(), Style::default().fg(theme.error)),
+        };
+        text.push(Line::from(vec![
+            Span::styled(
+                format!("{:<18}", entry.ip),
+                Style::default().fg(theme.highlight),
+            ),
+            domain,
+            Span::raw(format!("{:<6}", entry.connections)),
+            real,
+        ]));
+    }
+
+    let p = Paragraph::new(text)
+        .block(block)
+        .scroll((app.popup_scroll, 0));
+    f.render_widget(p, popup_area);
+}
+
+fn draw_info_popup(f: &mut Frame, app: &App) {
+    let theme = app.theme();
+    let area = f.area();
+    let popup_area = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage(25),
+            Constraint::Percentage(50),
+            Constraint::Percentage(25),
+        ])
+        .split(area)[1];
+
+    let popup_area = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(25),
+            Constraint::Percentage(50),
+            Constraint::Percentage(25),
+        ])
+        .split(popup_area)[1];
+
+    f.render_widget(Clear, popup_area);
+
+    let block = Block::default()
+        .title("Proxy Information")
+        .borders(Borders::ALL)
+        .style(Style::default().bg(theme.panel_bg));
+
+    let mut text = vec![];
+
+    if let Some(proxy_name) = app.get_selected_proxy_name() {
+        text.push(Line::from(vec![
+            Span::styled("Name: ", Style::default().fg(theme.highlight)),
+            Span::from(proxy_name.clone()),
+        ]));
+
+        if let Some(item) = app.proxies.get(&proxy_name) {
+            if let Some(ptype) = &item.proxy_type {
+                text.push(Line::from(vec![
+                    Span::styled("Type: ", Style::default().fg(theme.highlight)),
+                    Span::from(ptype.clone()),
+                ]));
+            }
+            if let Some(origin) = app.proxy_origin.get(&proxy_name) {
+                text.push(Line::from(vec![
+                    Span::styled("Provider: ", Style::default().fg(theme.highlight)),
+                    Span::from(origin.clone()),
+                ]));
+            }
+            if let Some(failure) = app.delay_failures.get(&proxy_name) {
+                text.push(Line::from(vec![
+                    Span::styled("Last test: ", Style::default().fg(theme.highlight)),
+                    Span::styled(failure.describe(), Style::default().fg(theme.error)),
+                ]));
+            }
+
+            // Render extra fields pretty-printed
+            let extra_json = serde_json::to_string_pretty(&item.extra).unwrap_or_default();
+            let lines: Vec<String> = extra_json.lines().map(|s| s.to_string()).collect();
+            for line in lines {
+                text.push(Line::from(line));
+            }
+        } else {
+            text.push(Line::from("Details not found (Recursive group?)"));
+        }
+    } else {
+        text.push(Line::from("No proxy selected"));
+    }
+
+    let p = Paragraph::new(text)
+        .block(block)
+        .wrap(Wrap { trim: false })
+        .scroll((app.popup_scroll, 0));
+
+    f.render_widget(p, popup_area);
+}
+
+fn draw_comparison_popup(f: &mut Frame, theme: &Theme, comparison: &Comparison) {
+    let area = f.area();
+    let popup_area = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage(25),
+            Constraint::Percentage(50),
+            Constraint::Percentage(25),
+        ])
+        .split(area)[1];
+
+    let popup_area = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(20),
+            Constraint::Percentage(60),
+            Constraint::Percentage(20),
+        ])
+        .split(popup_area)[1];
+
+    f.render_widget(Clear, popup_area);
+
+    let done = comparison.rounds_done();
+    let title = if done < COMPARE_ROUNDS {
+        format!(
+            "Compare: round {}/{} {}",
+            done + 1,
+            COMPARE_ROUNDS,
+            spinner_frame()
+        )
+    } else {
+        format!("Compare: {} rounds", COMPARE_ROUNDS)
+    };
+    let mut block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .style(Style::default().bg(theme.panel_bg));
+
+    let stats = [comparison.stats(0), comparison.stats(1)];
+    let metric = |label: &str, value: fn(&DelayStats) -> u64| {
+        let mut cells =
+            vec![Cell::from(label.to_string()).style(Style::default().fg(theme.highlight))];
+        for side in &stats {
+            cells.push(Cell::from(match side {
+                Some(s) => format!("{} ms", value(s)),
+                None => "-".to_string(),
+            }));
+        }
+        Row::new(cells)
+    };
+    let mut rows = vec![
+        metric("Min", |s| s.min),
+        metric("Avg", |s| s.avg),
+        metric("Median", |s| s.median),
+        metric("Max", |s| s.max),
+        metric("Jitter", |s| s.jitter),
+    ];
+    let mut failed = vec![Cell::from("Failed").style(Style::default().fg(theme.highlight))];
+    for results in &comparison.results {
+        let count = results.iter().filter(|r| r.is_none()).count();
+        failed.push(Cell::from(format!("{}/{}", count, results.len())));
+    }
+    rows.push(Row::new(failed));
+
+    // Name the winner once every round is in
+    if done == COMPARE_ROUNDS {
+        let verdict = match (&stats[0], &stats[1]) {
+            (Some(a), Some(b)) if a.avg != b.avg => {
+                let (winner, margin) = if a.avg < b.avg {
+                    (&comparison.proxies[0], b.avg - a.avg)
+                } else {
+                    (&comparison.proxies[1], a.avg - b.avg)
+                };
+                format!("{} is faster by {} ms on average", winner, margin)
+            }
+            (Some(_), Some(_)) => "Both average the same".to_string(),
+            (Some(_), None) => format!("{} never answered", comparison.proxies[1]),
+            (None, Some(_)) => format!("{} never answered", comparison.proxies[0]),
+            (None, None) => "Neither proxy answered".to_string(),
+        };
+        block = block.title_bottom(Span::styled(
+            format!(" {} ", verdict),
+            Style::default()
+                .fg(theme.success)
+                .add_modifier(Modifier::BOLD),
+        ));
+    }
+
+    let header = Row::new(vec![
+        Cell::from(""),
+        Cell::from(format!("[A] {}", comparison.proxies[0])),
+        Cell::from(format!("[B] {}", comparison.proxies[1])),
+    ])
+    .style(Style::default().add_modifier(Modifier::BOLD));
+
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(8),
+            Constraint::Percentage(45),
+            Constraint::Percentage(45),
+        ],
+    )
+    .header(header)
+    .block(block);
+    f.render_widget(table, popup_area);
+}
+
+fn draw_notes_popup(f: &mut Frame, app: &App, draft: &str) {
+    let theme = app.theme();
+    let area = f.area();
+    let popup_area = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage(20),
+            Constraint::Percentage(60),
+            Constraint::Percentage(20),
+        ])
+        .split(area)[1];
+
+    let popup_area = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(20),
+            Constraint::Percentage(60),
+            Constraint::Percentage(20),
+        ])
+        .split(popup_area)[1];
+
+    f.render_widget(Clear, popup_area);
+
+    let block = Block::default()
+        .title(format!("Notes ({})", app.app_settings.base_url))
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(app.accent()))
+        .style(Style::default().bg(theme.panel_bg));
+
+    let mut lines: Vec<Line> = draft.split('\n').map(Line::from).collect();
+    if let Some(last) = lines.last_mut() {
+        last.push_span(Span::styled("▏", Style::default().fg(theme.highlight)));
+    }
+    // Keep the cursor line in view once the notes outgrow the popup
+    let height = popup_area.height.saturating_sub(2) as usize;
+    let scroll = lines.len().saturating_sub(height) as u16;
+
+    f.render_widget(
+        Paragraph::new(lines)
+            .block(block)
+            .wrap(Wrap { trim: false })
+            .scroll((scroll, 0)),
+        popup_area,
+    );
+}
+
+fn draw_capabilities_popup(f: &mut Frame, app: &App) {
+    let theme = app.theme();
+    let area = f.area();
+    let popup_area = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage(30),
+            Constraint::Percentage(40),
+            Constraint::Percentage(30),
+        ])
+        .split(area)[1];
+
+    let popup_area = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(25),
+            Constraint::Percentage(50),
+            Constraint::Percentage(25),
+        ])
+        .split(popup_area)[1];
+
+    f.render_widget(Clear, popup_area);
+
+    let block = Block::default()
+        .title("API Capabilities")
+        .borders(Borders::ALL)
+        .style(Style::default().bg(theme.panel_bg));
 
-    // Upload
-    let up_speed = format_speed(app.current_up);
-    let up_title = format!("Upload: {}/s", up_speed);
-    let up_data: Vec<u64> = app
-        .traffic_history_up
-        .iter()
-        .rev()
-        .take(width)
-        .rev()
-        .cloned()
-        .collect();
-    let up_sparkline = Sparkline::default()
-        .block(
-            Block::default()
-                .title(up_title)
-                .borders(Borders::BOTTOM | Borders::LEFT | Borders::RIGHT),
-        )
-        .data(&up_data)
-        .style(Style::default().fg(Color::Yellow));
-    f.render_widget(up_sparkline, chart_chunks[1]);
-}
+    let caps = &app.capabilities;
+    let mut text = vec![];
 
-fn format_speed(bytes: u64) -> String {
-    if bytes < 1024 {
-        format!("{} B", bytes)
-    } else if bytes < 1024 * 1024 {
-        format!("{:.1} KB", bytes as f64 / 1024.0)
+    if !caps.checked {
+        text.push(Line::from("Not checked yet"));
     } else {
-        format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+        let core = caps
+            .version
+            .as_ref()
+            .map_or("Unknown".to_string(), |v| v.describe());
+        text.push(Line::from(vec![
+            Span::styled("Core: ", Style::default().fg(theme.highlight)),
+            Span::raw(core),
+        ]));
+        text.push(Line::from(""));
+
+        for (path, status) in &caps.endpoints {
+            let (mark, detail, color) = match status {
+                EndpointStatus::Available => ("OK  ", String::new(), theme.success),
+                EndpointStatus::Unsupported(code) => {
+                    ("N/A ", format!(" (disabled, {})", code), theme.error)
+                }
+                EndpointStatus::Failed(msg) => ("ERR ", format!(" ({})", msg), theme.highlight),
+            };
+            text.push(Line::from(vec![
+                Span::styled(mark, Style::default().fg(color)),
+                Span::raw(*path),
+                Span::styled(detail, Style::default().fg(theme.subtle)),
+            ]));
+        }
+
+        text.push(Line::from(""));
+        for feature in CoreFeature::ALL {
+            let (major, minor, patch) = feature.min_version();
+            let (mark, color) = if caps.supports_feature(feature) {
+                ("OK  ", theme.success)
+            } else {
+                ("OLD ", theme.error)
+            };
+            text.push(Line::from(vec![
+                Span::styled(mark, Style::default().fg(color)),
+                Span::raw(feature.label()),
+                Span::styled(
+                    format!(" (requires v{}.{}.{})", major, minor, patch),
+                    Style::default().fg(theme.subtle),
+                ),
+            ]));
+        }
     }
+
+    f.render_widget(Paragraph::new(text).block(block), popup_area);
 }
 
-fn draw_settings(f: &mut Frame, app: &mut App) {
+fn draw_queue_popup(f: &mut Frame, app: &mut App) {
+    let theme = app.theme();
     let area = f.area();
-    // Center a 70% x 50% block
     let popup_area = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
@@ -313,269 +3806,364 @@ fn draw_settings(f: &mut Frame, app: &mut App) {
     let popup_area = Layout::default()
         .direction(Direction::Horizontal)
         .constraints([
-            Constraint::Percentage(15),
-            Constraint::Percentage(70),
-            Constraint::Percentage(15),
+            Constraint::Percentage(20),
+            Constraint::Percentage(60),
+            Constraint::Percentage(20),
         ])
         .split(popup_area)[1];
 
     f.render_widget(Clear, popup_area);
 
     let block = Block::default()
-        .title(" Configuration ")
+        .title(format!(
+            " Pending Changes (auto-retry: {}) ",
+            if app.auto_retry { "on" } else { "off" }
+        ))
+        .title(loading_title(app, &[], &[Job::Retry]))
         .title_alignment(ratatui::layout::Alignment::Center)
         .borders(Borders::ALL)
-        .border_style(
-            Style::default()
-                .fg(Color::Cyan)
-                .add_modifier(Modifier::BOLD),
-        )
-        .style(Style::default().bg(Color::Black));
-
-    let header_style = Style::default()
-        .fg(Color::Yellow)
-        .add_modifier(Modifier::BOLD)
-        .bg(Color::DarkGray);
-
-    let selected_style = Style::default()
-        .add_modifier(Modifier::REVERSED)
-        .fg(Color::LightCyan);
+        .border_style(Style::default().fg(theme.highlight))
+        .style(Style::default().bg(theme.popup_bg));
 
-    let header = Row::new(vec!["Setting", "Current Value", "Action"])
-        .style(header_style)
-        .height(1)
-        .bottom_margin(1);
+    if app.mutation_queue.is_empty() {
+        f.render_widget(
+            Paragraph::new("No pending changes").block(block),
+            popup_area,
+        );
+        return;
+    }
 
-    let rows: Vec<Row> = app
-        .settings_items
+    let items: Vec<ListItem> = app
+        .mutation_queue
         .iter()
-        .map(|item| {
-            let (label, value, action) = match item {
-                ConfigEntry::BaseUrl => {
-                    ("App: Base URL", app.app_settings.base_url.clone(), "Edit")
-                }
-                ConfigEntry::ApiSecret => (
-                    "App: API Secret",
-                    if app.app_settings.api_secret.is_empty() {
-                        "<none>".to_string()
-                    } else {
-                        "******".to_string()
-                    },
-                    "Edit",
-                ),
-                ConfigEntry::TestUrl => {
-                    ("App: Test URL", app.app_settings.test_url.clone(), "Edit")
-                }
-                ConfigEntry::TestTimeout => (
-                    "App: Test Timeout (ms)",
-                    app.app_settings.test_timeout.to_string(),
-                    "Edit",
-                ),
-                ConfigEntry::Mode => {
-                    let val = app
-                        .config
-                        .as_ref()
-                        .map(|c| c.mode.as_str())
-                        .unwrap_or("Unknown");
-                    ("Mode", val.to_string(), "Cycle (Rule/Global/Direct)")
-                }
-                ConfigEntry::Tun => {
-                    let val = app.config.as_ref().map(|c| c.tun.enable).unwrap_or(false);
-                    (
-                        "TUN Mode",
-                        if val { "Enabled" } else { "Disabled" }.to_string(),
-                        "Toggle",
-                    )
-                }
-                ConfigEntry::MixedPort => {
-                    let val = app.config.as_ref().map(|c| c.mixed_port).unwrap_or(0);
-                    ("Mixed Port", val.to_string(), "Edit")
-                }
-                ConfigEntry::LogLevel => {
-                    let val = app
-                        .config
-                        .as_ref()
-                        .map(|c| c.log_level.as_str())
-                        .unwrap_or("info");
-                    ("Log Level", val.to_string(), "Cycle")
-                }
-                ConfigEntry::AllowLan => {
-                    let val = app.config.as_ref().map(|c| c.allow_lan).unwrap_or(false);
-                    (
-                        "Allow LAN",
-                        if val { "True" } else { "False" }.to_string(),
-                        "Toggle",
-                    )
-                }
-                ConfigEntry::BindAddress => {
-                    let val = app
-                        .config
-                        .as_ref()
-                        .map(|c| c.bind_address.as_str())
-                        .unwrap_or("*");
-                    ("Bind Address", val.to_string(), "Edit")
-                }
-                ConfigEntry::Ipv6 => {
-                    let val = app.config.as_ref().map(|c| c.ipv6).unwrap_or(false);
-                    (
-                        "IPv6",
-                        if val { "Enabled" } else { "Disabled" }.to_string(),
-                        "Toggle",
-                    )
-                }
-            };
-
-            Row::new(vec![
-                Cell::from(label).style(
-                    Style::default()
-                        .fg(Color::Blue)
-                        .add_modifier(Modifier::BOLD),
-                ),
-                Cell::from(value).style(Style::default().fg(Color::White)),
-                Cell::from(action).style(
-                    Style::default()
-                        .fg(Color::Gray)
-                        .add_modifier(Modifier::ITALIC),
-                ),
+        .map(|queued| {
+            ListItem::new(vec![
+                Line::from(queued.mutation.describe()),
+                Line::from(Span::styled(
+                    format!("  {} attempt(s), last: {}", queued.attempts, queued.error),
+                    Style::default().fg(theme.muted),
+                )),
             ])
-            .height(1)
         })
         .collect();
 
-    let table = Table::new(
-        rows,
-        [
-            Constraint::Percentage(30),
-            Constraint::Percentage(30),
-            Constraint::Percentage(40),
-        ],
-    )
-    .header(header)
-    .block(block)
-    .row_highlight_style(selected_style)
-    .highlight_symbol(">> ");
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .highlight_symbol(">> ");
 
-    f.render_stateful_widget(table, popup_area, &mut app.settings_state);
+    f.render_stateful_widget(list, popup_area, &mut app.queue_state);
 }
 
-fn draw_input_popup(f: &mut Frame, app: &mut App) {
+fn draw_config_tree(f: &mut Frame, app: &mut App) {
+    let theme = app.theme();
     let area = f.area();
     let popup_area = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
-            Constraint::Percentage(40),
-            Constraint::Length(3), // Input box height
-            Constraint::Percentage(40),
+            Constraint::Percentage(10),
+            Constraint::Percentage(80),
+            Constraint::Percentage(10),
         ])
         .split(area)[1];
 
     let popup_area = Layout::default()
         .direction(Direction::Horizontal)
         .constraints([
-            Constraint::Percentage(30),
-            Constraint::Percentage(40),
-            Constraint::Percentage(30),
+            Constraint::Percentage(15),
+            Constraint::Percentage(70),
+            Constraint::Percentage(15),
         ])
         .split(popup_area)[1];
 
     f.render_widget(Clear, popup_area);
 
     let block = Block::default()
+        .title(" Core Config (read-only) ")
+        .title_alignment(ratatui::layout::Alignment::Center)
         .borders(Borders::ALL)
-        .title("Edit Value (Enter to Save, Esc to Cancel)")
-        .style(Style::default().bg(Color::Blue).fg(Color::White));
+        .border_style(Style::default().fg(theme.info))
+        .style(Style::default().bg(theme.popup_bg));
 
-    let p = Paragraph::new(app.editing_value.clone()).block(block);
+    if app.config.is_none() {
+        f.render_widget(Paragraph::new("Config not loaded").block(block), popup_area);
+        return;
+    }
 
-    f.render_widget(p, popup_area);
+    // Top-level keys that can be changed from the Settings table
+    let editable: Vec<&str> = app
+        .settings_items
+        .iter()
+        .filter_map(|item| item.config_key())
+        .collect();
+
+    let items: Vec<ListItem> = app
+        .config_tree_rows()
+        .into_iter()
+        .map(|row| {
+            let indent = "  ".repeat(row.depth);
+            let key_style = if row.depth == 0 && editable.contains(&row.key.as_str()) {
+                Style::default().fg(theme.info).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(theme.label)
+            };
+            let line = match row.value {
+                Some(value) => Line::from(vec![
+                    Span::raw(format!("{}  ", indent)),
+                    Span::styled(row.key, key_style),
+                    Span::raw(": "),
+                    Span::styled(value, Style::default().fg(theme.text)),
+                ]),
+                None => Line::from(vec![
+                    Span::raw(format!(
+                        "{}{} ",
+                        indent,
+                        if row.expanded { "▾" } else { "▸" }
+                    )),
+                    Span::styled(row.key, key_style),
+                ]),
+            };
+            ListItem::new(line)
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+    f.render_stateful_widget(list, popup_area, &mut app.config_tree_state);
 }
 
-fn draw_info_popup(f: &mut Frame, app: &App) {
+fn draw_config_review(f: &mut Frame, app: &App) {
+    let theme = app.theme();
     let area = f.area();
     let popup_area = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
-            Constraint::Percentage(25),
-            Constraint::Percentage(50),
-            Constraint::Percentage(25),
+            Constraint::Percentage(30),
+            Constraint::Percentage(40),
+            Constraint::Percentage(30),
         ])
         .split(area)[1];
 
     let popup_area = Layout::default()
         .direction(Direction::Horizontal)
         .constraints([
-            Constraint::Percentage(25),
-            Constraint::Percentage(50),
-            Constraint::Percentage(25),
+            Constraint::Percentage(20),
+            Constraint::Percentage(60),
+            Constraint::Percentage(20),
         ])
         .split(popup_area)[1];
 
     f.render_widget(Clear, popup_area);
 
     let block = Block::default()
-        .title("Proxy Information")
+        .title(" Apply Changes? ")
+        .title_alignment(ratatui::layout::Alignment::Center)
         .borders(Borders::ALL)
-        .style(Style::default().bg(Color::DarkGray));
-
-    let mut text = vec![];
-
-    if let Some(proxy_name) = app.get_selected_proxy_name() {
-        text.push(Line::from(vec![
-            Span::styled("Name: ", Style::default().fg(Color::Yellow)),
-            Span::from(proxy_name.clone()),
-        ]));
-
-        if let Some(item) = app.proxies.get(&proxy_name) {
-            if let Some(ptype) = &item.proxy_type {
-                text.push(Line::from(vec![
-                    Span::styled("Type: ", Style::default().fg(Color::Yellow)),
-                    Span::from(ptype.clone()),
-                ]));
-            }
+        .border_style(Style::default().fg(theme.highlight))
+        .style(Style::default().bg(theme.popup_bg));
 
-            // Render extra fields pretty-printed
-            let extra_json = serde_json::to_string_pretty(&item.extra).unwrap_or_default();
-            let lines: Vec<String> = extra_json.lines().map(|s| s.to_string()).collect();
-            for line in lines {
-                text.push(Line::from(line));
-            }
-        } else {
-            text.push(Line::from("Details not found (Recursive group?)"));
-        }
-    } else {
-        text.push(Line::from("No proxy selected"));
-    }
+    let rows: Vec<Row> = app
+        .staged_changes()
+        .into_iter()
+        .map(|(key, old, new)| {
+            Row::new(vec![
+                Cell::from(key).style(Style::default().fg(theme.label)),
+                Cell::from(old).style(Style::default().fg(theme.error)),
+                Cell::from(new).style(Style::default().fg(theme.success)),
+            ])
+        })
+        .collect();
 
-    let p = Paragraph::new(text)
-        .block(block)
-        .wrap(Wrap { trim: false })
-        .scroll((app.popup_scroll, 0));
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Percentage(34),
+            Constraint::Percentage(33),
+            Constraint::Percentage(33),
+        ],
+    )
+    .header(
+        Row::new(vec!["Setting", "Current", "New"]).style(
+            Style::default()
+                .fg(theme.highlight)
+                .add_modifier(Modifier::BOLD),
+        ),
+    )
+    .block(block);
 
-    f.render_widget(p, popup_area);
+    f.render_widget(table, popup_area);
 }
 
 fn draw_status_bar(f: &mut Frame, app: &App, area: Rect) {
-    let text = if let Some(err) = &app.error {
+    let theme = app.theme();
+    let mut text = if let Some(text) = app.type_ahead_text() {
+        Line::from(vec![
+            Span::styled("Jump to: ", Style::default().fg(theme.info)),
+            Span::raw(format!("{}_", text)),
+        ])
+    } else if let Some(query) = &app.proxy_search {
+        Line::from(vec![
+            Span::styled("Search: ", Style::default().fg(theme.info)),
+            Span::raw(format!("{}_", query)),
+            Span::styled(
+                format!("  ({})", hints(&[KeyContext::ProxySearch])),
+                Style::default().fg(theme.muted),
+            ),
+        ])
+    } else if let Modal::Confirm { .. } = app.modal {
+        Line::from(hints(&[KeyContext::Confirm]))
+    } else if let Modal::Response(_) = app.modal {
+        Line::from(hints(&[KeyContext::Response]))
+    } else if let Modal::Dns(_) = app.modal {
+        Line::from(hints(&[KeyContext::Dns]))
+    } else if let Modal::TrafficHistory(_) = app.modal {
+        Line::from(hints(&[KeyContext::TrafficHistory]))
+    } else if let Modal::Notifications = app.modal {
+        Line::from(hints(&[KeyContext::Notifications]))
+    } else if let Modal::Changelog(_) = app.modal {
+        Line::from(hints(&[KeyContext::Changelog]))
+    } else if let Modal::FakeIp(_) = app.modal {
+        Line::from(hints(&[KeyContext::FakeIp]))
+    } else if let Modal::BulkSelect(_) = app.modal {
+        Line::from(hints(&[KeyContext::BulkSelect]))
+    } else if let Modal::Pin(_) = app.modal {
+        Line::from(hints(&[KeyContext::Pin]))
+    } else if app.leader_pending {
+        let chords: Vec<String> = app
+            .app_settings
+            .leader_chords
+            .iter()
+            .map(|(key, action)| format!("{}: {}", key, action.label()))
+            .collect();
         Line::from(vec![
             Span::styled(
-                "Error: ",
-                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+                format!("{} … ", leader_key_name(app.app_settings.leader_key)),
+                Style::default().fg(theme.info),
             ),
-            Span::styled(err, Style::default().fg(Color::Red)),
+            Span::raw(chords.join(" | ")),
         ])
+    } else if let Modal::Help = app.modal {
+        Line::from("Esc/q/?: Close | j/k: Scroll | PgUp/PgDn: Page")
+    } else if app.comparison.is_some() {
+        Line::from(hints(&[KeyContext::Comparison]))
+    } else if app.notes_draft.is_some() {
+        Line::from(format!(
+            "Notes: Type to write | {}",
+            hints(&[KeyContext::Notes])
+        ))
     } else if app.is_editing {
-        Line::from("Editing: Type to input | Enter: Save | Esc: Cancel")
+        Line::from(format!(
+            "Editing: Type to input | {}",
+            hints(&[KeyContext::Editing])
+        ))
+    } else if let Modal::ConfigTree = app.modal {
+        Line::from(hints(&[KeyContext::ConfigTree]))
+    } else if let Modal::ConfigReview = app.modal {
+        Line::from(hints(&[KeyContext::ConfigReview]))
+    } else if let Modal::Hosts = app.modal {
+        Line::from(hints(&[KeyContext::Hosts]))
+    } else if let Modal::Profiles = app.modal {
+        Line::from(hints(&[KeyContext::Servers]))
+    } else if let Modal::Queue = app.modal {
+        Line::from(hints(&[KeyContext::Queue]))
     } else {
-        match app.focus {
-            Focus::Settings => Line::from("Esc/q: Back | j/k: Nav | Enter: Change/Edit | s: Close"),
-            _ => Line::from(
-                "q: Quit | j/k: Nav | l/Enter: Select | r: Refresh | t: Test | s: Settings | i: Info",
-            ),
+        let context = match app.focus {
+            Focus::Settings => KeyContext::Settings,
+            Focus::Debug => KeyContext::Debug,
+            Focus::Connections => KeyContext::Connections,
+            Focus::Rules => KeyContext::Rules,
+            Focus::RuleProviders => KeyContext::RuleProviders,
+            Focus::ProxyProviders => KeyContext::ProxyProviders,
+            Focus::Logs => KeyContext::Logs,
+            Focus::Overview => KeyContext::Overview,
+            Focus::Groups | Focus::Proxies => KeyContext::Main,
+        };
+        match context {
+            KeyContext::Settings | KeyContext::Debug => Line::from(hints(&[context])),
+            KeyContext::Main => Line::from(main_hints(&app.app_settings.keymap)),
+            _ => Line::from(hints(&[KeyContext::Global, context])),
         }
     };
 
+    // Name the controller in its accent color so backends can't be confused
+    let host = match app.active_profile() {
+        Some(profile) => profile.name.as_str(),
+        None => app
+            .app_settings
+            .base_url
+            .split("://")
+            .last()
+            .unwrap_or_default()
+            .trim_end_matches('/'),
+    };
+    text.spans.insert(
+        0,
+        Span::styled(
+            format!(" {} ", host),
+            Style::default().fg(theme.on_highlight).bg(app.accent()),
+        ),
+    );
+    text.spans.insert(1, Span::raw(" "));
+
+    let mut rate = Line::default();
+    if let Some(label) = app.jobs.iter().rev().find_map(|job| job.label()) {
+        rate.push_span(Span::styled(
+            format!("{} {} ", spinner_frame(), label),
+            Style::default().fg(theme.info),
+        ));
+    }
+    if app.idle {
+        rate.push_span(Span::styled(
+            "idle, streams paused ",
+            Style::default().fg(theme.muted),
+        ));
+    } else if app.low_power_active() {
+        rate.push_span(Span::styled("low power ", Style::default().fg(theme.muted)));
+    }
+    if !app.mutation_queue.is_empty() {
+        rate.push_span(Span::styled(
+            format!("{} queued (P) ", app.mutation_queue.len()),
+            Style::default().fg(theme.highlight),
+        ));
+    }
+    match &app.controller_rtt {
+        Some(Ok(ms)) => {
+            rate.push_span(Span::styled("api ", Style::default().fg(theme.muted)));
+            rate.push_span(Span::styled(
+                format!("{} ms ", ms),
+                Style::default().fg(latency_color(app, *ms)),
+            ));
+        }
+        Some(Err(e)) => rate.push_span(Span::styled(
+            format!("api {} ", e),
+            Style::default().fg(theme.error),
+        )),
+        None => {}
+    }
+    rate.extend(vec![
+        Span::styled("↓ ", Style::default().fg(theme.success)),
+        Span::raw(format!("{}/s ", format_speed(app, app.current_down))),
+        Span::styled("↑ ", Style::default().fg(theme.highlight)),
+        Span::raw(format!("{}/s", format_speed(app, app.current_up))),
+    ]);
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Min(0),
+            Constraint::Length(rate.width() as u16 + 1),
+        ])
+        .split(area);
+
     f.render_widget(
-        Paragraph::new(text).style(Style::default().fg(Color::DarkGray)),
-        area,
+        Paragraph::new(text).style(Style::default().fg(theme.muted)),
+        chunks[0],
+    );
+    f.render_widget(
+        Paragraph::new(rate)
+            .alignment(ratatui::layout::Alignment::Right)
+            .style(Style::default().fg(theme.subtle)),
+        chunks[1],
     );
 }
@@ -0,0 +1,150 @@
+use anyhow::{Context, Result, anyhow, bail};
+use reqwest::Client;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+
+use crate::app::parse_version;
+
+const RELEASES_URL: &str = "https://api.github.com/repos/nkanf-dev/mihomot/releases/latest";
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+/// Release binaries are published as `mihomot-<arch>-<os>[.exe]`.
+fn asset_name() -> String {
+    let suffix = if cfg!(windows) { ".exe" } else { "" };
+    format!(
+        "mihomot-{}-{}{}",
+        std::env::consts::ARCH,
+        std::env::consts::OS,
+        suffix
+    )
+}
+
+/// Checks the latest GitHub release and replaces the running executable with it.
+/// With `check_only`, only reports whether an update is available.
+pub async fn self_update(check_only: bool) -> Result<()> {
+    let client = Client::builder()
+        .user_agent(concat!("mihomot/", env!("CARGO_PKG_VERSION")))
+        .build()?;
+
+    let release: Release = client
+        .get(RELEASES_URL)
+        .send()
+        .await?
+        .error_for_status()
+        .context("Failed to query GitHub releases")?
+        .json()
+        .await?;
+
+    let current = env!("CARGO_PKG_VERSION");
+    let latest = parse_version(&release.tag_name)
+        .ok_or_else(|| anyhow!("Unrecognized release tag: {}", release.tag_name))?;
+    if parse_version(current).is_some_and(|v| v >= latest) {
+        println!("mihomot {} is up to date", current);
+        return Ok(());
+    }
+
+    println!("Update available: {} -> {}", current, release.tag_name);
+    if check_only {
+        return Ok(());
+    }
+
+    let name = asset_name();
+    let asset = release
+        .assets
+        .iter()
+        .find(|a| a.name == name)
+        .ok_or_else(|| anyhow!("No release binary for this platform ({})", name))?;
+    let expected = fetch_checksum(&client, &release, &name).await?;
+
+    println!("Downloading {}...", asset.name);
+    let binary = client
+        .get(&asset.browser_download_url)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+
+    let actual: String = Sha256::digest(&binary)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    if !actual.eq_ignore_ascii_case(&expected) {
+        bail!("Checksum mismatch: expected {}, got {}", expected, actual);
+    }
+
+    let exe = std::env::current_exe()?;
+    replace_executable(&exe, &binary)?;
+    println!("Updated {} to {}", exe.display(), release.tag_name);
+    Ok(())
+}
+
+/// Looks for `<asset>.sha256` first, then a combined `SHA256SUMS` file.
+async fn fetch_checksum(client: &Client, release: &Release, name: &str) -> Result<String> {
+    let sidecar = format!("{}.sha256", name);
+    let source = release
+        .assets
+        .iter()
+        .find(|a| a.name == sidecar)
+        .or_else(|| release.assets.iter().find(|a| a.name == "SHA256SUMS"))
+        .ok_or_else(|| anyhow!("Release has no checksum for {}, refusing to install", name))?;
+
+    let text = client
+        .get(&source.browser_download_url)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+
+    // Both formats use `<hex>  <filename>` lines; a sidecar may omit the filename
+    text.lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let hash = parts.next()?;
+            match parts.next() {
+                Some(file) if file.trim_start_matches('*') != name => None,
+                _ => Some(hash.to_string()),
+            }
+        })
+        .next()
+        .ok_or_else(|| anyhow!("Checksum for {} not found in {}", name, source.name))
+}
+
+/// Writes the new binary next to the current one and renames it into place,
+/// which works even while the old executable is running.
+fn replace_executable(exe: &Path, binary: &[u8]) -> Result<()> {
+    let staged = exe.with_extension("new");
+    fs::write(&staged, binary).with_context(|| format!("Failed to write {}", staged.display()))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(exe)?.permissions().mode();
+        fs::set_permissions(&staged, fs::Permissions::from_mode(mode))?;
+    }
+
+    // Windows refuses to overwrite a running executable but allows renaming it
+    #[cfg(windows)]
+    {
+        let old = exe.with_extension("old");
+        let _ = fs::remove_file(&old);
+        fs::rename(exe, &old)?;
+    }
+
+    fs::rename(&staged, exe).with_context(|| format!("Failed to replace {}", exe.display()))?;
+    Ok(())
+}